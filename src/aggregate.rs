@@ -0,0 +1,543 @@
+//! Aggregation stages of the exposition pipeline.
+//!
+//! The filtered snapshot is classified once into a [`Classified`] snapshot.
+//! Selection stages then decide which processes get series of their own
+//! (monitoring processes, the global cap, the subgroup share), and output
+//! stages write the per-process, session, subgroup and Top-N families from
+//! it. Processes without series of their own still count in the sums.
+//!
+//! [`Pipeline::with_aggregation`](crate::pipeline::Pipeline::with_aggregation)
+//! picks the stages from the configuration.
+
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use std::sync::Arc;
+
+use crate::cache::{ProcKey, ProcMem, ProcRates};
+use crate::config::Config;
+use crate::metrics::{
+    MemoryMetrics, PrivilegeLabels, ProcessLabels, SessionLabels, StateLabels, SubgroupLabels,
+    TopInfoLabels, TopLabels, TopWaitLabels,
+};
+use crate::pipeline::AggregateStage;
+use crate::privacy::public_name;
+use crate::process::{classify_process_with_config, CAPABILITIES};
+use crate::topn_hysteresis::TopNHysteresis;
+
+/// Group and subgroup of a classified process.
+pub type SubgroupKey = (Arc<str>, Arc<str>);
+
+/// The classified snapshot the aggregation stages work on.
+pub struct Classified<'s> {
+    /// The whole snapshot, classified or not
+    pub processes: &'s [ProcMem],
+    /// Classified processes in snapshot order, including the "other"
+    /// processes beyond `top_n_others`
+    pub classified: Vec<&'s ProcMem>,
+    /// Aggregated processes by group and subgroup
+    pub groups: HashMap<SubgroupKey, Vec<&'s ProcMem>>,
+    /// PIDs of processes counted in the sums but never exported as series
+    pub hidden: HashSet<u32>,
+    /// PIDs of processes eligible for per-process series
+    pub exported: HashSet<u32>,
+    /// Top-N of each subgroup in rank order, filled by [`TopN`]
+    pub top: HashMap<SubgroupKey, Vec<&'s ProcMem>>,
+    /// Number of processes exported as per-process series
+    pub exported_count: usize,
+    pub rates: &'s HashMap<ProcKey, ProcRates>,
+    pub uptime_seconds: &'s str,
+}
+
+impl<'s> Classified<'s> {
+    /// Classifies `processes` with `cfg`. Only the first `top_n_others`
+    /// processes of the "other" group are aggregated.
+    pub fn new(
+        processes: &'s [ProcMem],
+        rates: &'s HashMap<ProcKey, ProcRates>,
+        cfg: &Config,
+        uptime_seconds: &'s str,
+    ) -> Self {
+        let mut classified = Vec::new();
+        let mut groups: HashMap<SubgroupKey, Vec<&ProcMem>> = HashMap::new();
+        let mut other_exported = 0usize;
+        let other_limit = cfg.top_n_others.unwrap_or(10);
+
+        for p in processes {
+            let Some((group, subgroup)) = classify_process_with_config(p.classify_name(), cfg)
+            else {
+                continue;
+            };
+            classified.push(p);
+            if group.as_ref().eq_ignore_ascii_case("other") {
+                if other_exported >= other_limit {
+                    continue;
+                }
+                other_exported += 1;
+            }
+            groups.entry((group, subgroup)).or_default().push(p);
+        }
+
+        let exported = groups.values().flatten().map(|p| p.pid).collect();
+        Self {
+            processes,
+            classified,
+            groups,
+            hidden: HashSet::new(),
+            exported,
+            top: HashMap::new(),
+            exported_count: 0,
+            rates,
+            uptime_seconds,
+        }
+    }
+}
+
+/// Memory and CPU families enabled by the configuration.
+#[derive(Debug, Clone, Copy)]
+pub struct EnabledValues {
+    pub rss: bool,
+    pub pss: bool,
+    pub uss: bool,
+    pub cpu: bool,
+}
+
+impl EnabledValues {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            rss: cfg.enable_rss.unwrap_or(true),
+            // Kernels without Pss in smaps would only export zeros
+            pss: cfg.enable_pss.unwrap_or(true)
+                && (cfg.test_data_file.is_some() || CAPABILITIES.smaps_pss()),
+            uss: cfg.enable_uss.unwrap_or(true),
+            cpu: cfg.enable_cpu.unwrap_or(true),
+        }
+    }
+}
+
+/// Hides the exporter and monitoring agents (`hide_monitoring_processes`).
+pub struct HideMonitoring<'a> {
+    pub names: Vec<&'a str>,
+}
+
+impl AggregateStage for HideMonitoring<'_> {
+    fn name(&self) -> &'static str {
+        "hide_monitoring"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, _metrics: &MemoryMetrics) {
+        let own_pid = std::process::id();
+        let hidden: Vec<u32> = snapshot
+            .groups
+            .values()
+            .flatten()
+            .filter(|p| p.pid == own_pid || self.names.iter().any(|name| *name == &*p.name))
+            .map(|p| p.pid)
+            .collect();
+        for pid in hidden {
+            snapshot.exported.remove(&pid);
+            snapshot.hidden.insert(pid);
+        }
+    }
+}
+
+/// Keeps the per-process series of the largest processes by USS
+/// (`max_exported_processes`).
+pub struct MaxExported(pub usize);
+
+impl AggregateStage for MaxExported {
+    fn name(&self) -> &'static str {
+        "max_exported"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, _metrics: &MemoryMetrics) {
+        let mut by_uss: Vec<&ProcMem> = snapshot
+            .groups
+            .values()
+            .flatten()
+            .copied()
+            .filter(|p| snapshot.exported.contains(&p.pid))
+            .collect();
+        by_uss.sort_by_key(|p| std::cmp::Reverse(p.uss));
+        snapshot.exported = by_uss.iter().take(self.0).map(|p| p.pid).collect();
+    }
+}
+
+/// Keeps the per-process series of processes above `percent` of their
+/// subgroup's USS (`min_uss_percent_of_subgroup`).
+pub struct MinSubgroupShare {
+    pub percent: f64,
+}
+
+impl AggregateStage for MinSubgroupShare {
+    fn name(&self) -> &'static str {
+        "min_subgroup_share"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, _metrics: &MemoryMetrics) {
+        for list in snapshot.groups.values() {
+            let subgroup_uss: u64 = list.iter().map(|p| p.uss).sum();
+            let min_uss = subgroup_uss as f64 * self.percent / 100.0;
+            for p in list.iter().filter(|p| (p.uss as f64) <= min_uss) {
+                snapshot.exported.remove(&p.pid);
+            }
+        }
+    }
+}
+
+/// Writes the per-process families of the exported processes.
+pub struct ProcessSeries<'a> {
+    pub cfg: &'a Config,
+}
+
+impl AggregateStage for ProcessSeries<'_> {
+    fn name(&self) -> &'static str {
+        "process_series"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics) {
+        let proc_id_label = self.cfg.proc_id_label.unwrap_or(false);
+        for ((group, subgroup), list) in &snapshot.groups {
+            for p in list {
+                if !snapshot.exported.contains(&p.pid) {
+                    continue;
+                }
+                snapshot.exported_count += 1;
+                let pid_str = p.pid.to_string();
+                let name = public_name(&p.name, self.cfg);
+                let extracted = p.extra_labels.as_deref().unwrap_or_default();
+                let with_proc_id: Vec<String>;
+                let extra = if proc_id_label {
+                    with_proc_id = std::iter::once(p.key().to_string())
+                        .chain(extracted.iter().cloned())
+                        .collect();
+                    &with_proc_id[..]
+                } else {
+                    extracted
+                };
+                let labels = ProcessLabels {
+                    pid: &pid_str,
+                    name: &name,
+                    group,
+                    subgroup,
+                    uptime_in_seconds: snapshot.uptime_seconds,
+                    extra,
+                };
+
+                metrics.set_for_process(&labels, p, self.cfg);
+                if let Some(r) = snapshot.rates.get(&p.key()) {
+                    metrics.set_rates_for_process(&labels, r);
+                }
+            }
+        }
+    }
+}
+
+/// Sums the USS of classified processes per session or process group
+/// (`session_aggregation`).
+pub struct SessionSums<'a> {
+    /// "session" or "pgrp"
+    pub by: &'a str,
+    pub cfg: &'a Config,
+}
+
+impl AggregateStage for SessionSums<'_> {
+    fn name(&self) -> &'static str {
+        "session_sums"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics) {
+        // Session sums cover every classified process, not only the
+        // aggregated ones
+        let mut sessions: HashMap<u32, u64> = HashMap::new();
+        for p in &snapshot.classified {
+            let id = if self.by == "pgrp" { p.pgrp } else { p.session };
+            // Kernel threads belong to session/process group 0
+            if id != 0 {
+                *sessions.entry(id).or_default() += p.uss;
+            }
+        }
+
+        // The leader's PID equals the session/process group ID
+        let leaders: HashMap<u32, &str> = snapshot
+            .processes
+            .iter()
+            .filter(|p| sessions.contains_key(&p.pid))
+            .map(|p| (p.pid, &*p.name))
+            .collect();
+        for (id, uss_sum) in &sessions {
+            let leader = public_name(leaders.get(id).copied().unwrap_or(""), self.cfg);
+            let labels = SessionLabels {
+                aggregation: self.by,
+                id: &id.to_string(),
+                leader: &leader,
+            };
+            metrics.session_uss_sum.set(&labels, *uss_sum as f64);
+        }
+    }
+}
+
+/// Writes the memory, CPU and rate sums of each subgroup.
+pub struct SubgroupSums {
+    pub enabled: EnabledValues,
+}
+
+impl AggregateStage for SubgroupSums {
+    fn name(&self) -> &'static str {
+        "subgroup_sums"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics) {
+        for ((group, subgroup), list) in &snapshot.groups {
+            let mut rss_sum: u64 = 0;
+            let mut pss_sum: u64 = 0;
+            let mut uss_sum: u64 = 0;
+            let mut cpu_percent_sum: f64 = 0.0;
+            let mut cpu_time_sum: f64 = 0.0;
+            let mut rate_sums: Option<ProcRates> = None;
+
+            for p in list {
+                rss_sum += p.rss;
+                pss_sum += p.pss;
+                uss_sum += p.uss;
+                cpu_percent_sum += p.cpu_percent as f64;
+                cpu_time_sum += p.cpu_time_seconds as f64;
+                if let Some(r) = snapshot.rates.get(&p.key()) {
+                    rate_sums
+                        .get_or_insert_with(ProcRates::default)
+                        .accumulate(r);
+                }
+            }
+
+            let labels = SubgroupLabels {
+                group,
+                subgroup,
+                uptime_in_seconds: snapshot.uptime_seconds,
+            };
+            if let Some(sums) = &rate_sums {
+                metrics.set_rate_sums(&labels, sums);
+            }
+            if self.enabled.rss {
+                metrics.agg_rss_sum.set(&labels, rss_sum as f64);
+            }
+            if self.enabled.pss {
+                metrics.agg_pss_sum.set(&labels, pss_sum as f64);
+            }
+            if self.enabled.uss {
+                metrics.agg_uss_sum.set(&labels, uss_sum as f64);
+            }
+            if self.enabled.cpu {
+                metrics.agg_cpu_percent_sum.set(&labels, cpu_percent_sum);
+                metrics.agg_cpu_time_sum.set(&labels, cpu_time_sum);
+            }
+        }
+    }
+}
+
+/// Counts the root and fully capable processes of each subgroup
+/// (`enable_privilege_metrics`).
+pub struct PrivilegeCounts;
+
+impl AggregateStage for PrivilegeCounts {
+    fn name(&self) -> &'static str {
+        "privilege_counts"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics) {
+        for ((group, subgroup), list) in &snapshot.groups {
+            let privileges: Vec<_> = list.iter().filter_map(|p| p.privileges).collect();
+            for (privilege, count) in [
+                ("root", privileges.iter().filter(|p| p.is_root()).count()),
+                (
+                    "full_capabilities",
+                    privileges
+                        .iter()
+                        .filter(|p| p.has_full_capabilities())
+                        .count(),
+                ),
+            ] {
+                let labels = PrivilegeLabels {
+                    group,
+                    subgroup,
+                    privilege,
+                };
+                metrics.privileged_processes.set(&labels, count as f64);
+            }
+        }
+    }
+}
+
+/// Counts the processes of each subgroup by scheduler state.
+pub struct StateCounts;
+
+impl AggregateStage for StateCounts {
+    fn name(&self) -> &'static str {
+        "state_counts"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics) {
+        for ((group, subgroup), list) in &snapshot.groups {
+            // Counted states, other states such as idle kernel threads are left out
+            for state in ['R', 'S', 'D', 'Z'] {
+                let count = list.iter().filter(|p| p.state == state).count();
+                let labels = StateLabels {
+                    group,
+                    subgroup,
+                    state: &state.to_string(),
+                };
+                metrics.processes_by_state.set(&labels, count as f64);
+            }
+        }
+    }
+}
+
+/// Selects the Top-N of each subgroup by USS and writes their values and
+/// shares of the subgroup. With `hysteresis`, the Top-N of the scan with
+/// the given ID is selected with hysteresis.
+pub struct TopN<'a> {
+    pub cfg: &'a Config,
+    pub enabled: EnabledValues,
+    pub hysteresis: Option<(&'a TopNHysteresis, u64)>,
+}
+
+impl AggregateStage for TopN<'_> {
+    fn name(&self) -> &'static str {
+        "top_n"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics) {
+        let enabled = self.enabled;
+        let top_subgroup = self.cfg.top_n_subgroup.unwrap_or(3);
+        let top_others = self.cfg.top_n_others.unwrap_or(10);
+
+        for (key, list) in &snapshot.groups {
+            let (group, subgroup) = key;
+            let is_other_group = group.eq_ignore_ascii_case("other")
+                || group.eq_ignore_ascii_case("others")
+                || subgroup.eq_ignore_ascii_case("other")
+                || subgroup.eq_ignore_ascii_case("others");
+            let limit = if is_other_group {
+                std::cmp::max(1, top_others)
+            } else {
+                std::cmp::max(1, top_subgroup)
+            };
+
+            let rss_total = list.iter().map(|p| p.rss).sum::<u64>() as f64;
+            let pss_total = list.iter().map(|p| p.pss).sum::<u64>() as f64;
+            let uss_total = list.iter().map(|p| p.uss).sum::<u64>() as f64;
+            let cpu_total: f64 = list.iter().map(|p| p.cpu_time_seconds as f64).sum();
+
+            let mut ranked: Vec<&ProcMem> = list
+                .iter()
+                .copied()
+                .filter(|p| !snapshot.hidden.contains(&p.pid))
+                .collect();
+            ranked.sort_by_key(|p| std::cmp::Reverse(p.uss));
+            let top = match self.hysteresis {
+                Some((hysteresis, scan_id)) => hysteresis.select(scan_id, key, &ranked, limit),
+                None => ranked.iter().take(limit).copied().collect(),
+            };
+
+            for (rank, p) in top.iter().enumerate() {
+                let pid_s = p.pid.to_string();
+                let rank_s = (rank + 1).to_string();
+                let name = public_name(&p.name, self.cfg);
+                let labels = TopLabels {
+                    group,
+                    subgroup,
+                    rank: &rank_s,
+                    pid: &pid_s,
+                    name: &name,
+                    uptime_in_seconds: snapshot.uptime_seconds,
+                };
+
+                // Absolute Top-N values
+                if enabled.rss {
+                    metrics.top_rss.set(&labels, p.rss as f64);
+                }
+                if enabled.pss {
+                    metrics.top_pss.set(&labels, p.pss as f64);
+                }
+                if enabled.uss {
+                    metrics.top_uss.set(&labels, p.uss as f64);
+                }
+                if enabled.cpu {
+                    metrics.top_cpu_percent.set(&labels, p.cpu_percent as f64);
+                    metrics.top_cpu_time.set(&labels, p.cpu_time_seconds as f64);
+                }
+
+                // Percentage-of-subgroup values
+                if enabled.cpu && cpu_total > 0.0 {
+                    let pct = (p.cpu_time_seconds as f64 / cpu_total) * 100.0;
+                    metrics.top_cpu_percent_of_subgroup.set(&labels, pct);
+                }
+                if enabled.rss && rss_total > 0.0 {
+                    let pct = (p.rss as f64 / rss_total) * 100.0;
+                    metrics.top_rss_percent_of_subgroup.set(&labels, pct);
+                }
+                if enabled.pss && pss_total > 0.0 {
+                    let pct = (p.pss as f64 / pss_total) * 100.0;
+                    metrics.top_pss_percent_of_subgroup.set(&labels, pct);
+                }
+                if enabled.uss && uss_total > 0.0 {
+                    let pct = (p.uss as f64 / uss_total) * 100.0;
+                    metrics.top_uss_percent_of_subgroup.set(&labels, pct);
+                }
+            }
+            snapshot.top.insert(key.clone(), top);
+        }
+    }
+}
+
+/// Writes the value-free info series of the Top-N (`stable_topn`).
+pub struct TopInfo<'a> {
+    pub cfg: &'a Config,
+}
+
+impl AggregateStage for TopInfo<'_> {
+    fn name(&self) -> &'static str {
+        "top_info"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics) {
+        for ((group, subgroup), top) in &snapshot.top {
+            for (rank, p) in top.iter().enumerate() {
+                let info = TopInfoLabels {
+                    group,
+                    subgroup,
+                    rank: &(rank + 1).to_string(),
+                    pid: &p.pid.to_string(),
+                    name: &public_name(&p.name, self.cfg),
+                };
+                metrics.top_info.set(&info, 1.0);
+            }
+        }
+    }
+}
+
+/// Writes the scheduler state and wait channel of the Top-N
+/// (`enable_wchan_metrics`).
+pub struct TopWait<'a> {
+    pub cfg: &'a Config,
+}
+
+impl AggregateStage for TopWait<'_> {
+    fn name(&self) -> &'static str {
+        "top_wait"
+    }
+
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics) {
+        for ((group, subgroup), top) in &snapshot.top {
+            for (rank, p) in top.iter().enumerate() {
+                let wait = TopWaitLabels {
+                    group,
+                    subgroup,
+                    rank: &(rank + 1).to_string(),
+                    pid: &p.pid.to_string(),
+                    name: &public_name(&p.name, self.cfg),
+                    state: &p.state.to_string(),
+                    wchan: p.wchan.as_deref().unwrap_or(""),
+                };
+                metrics.top_wait_info.set(&wait, 1.0);
+            }
+        }
+    }
+}
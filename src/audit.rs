@@ -0,0 +1,200 @@
+//! Audit log of state-changing requests.
+//!
+//! With `audit_log` set, every request that changes runtime state (currently
+//! `PUT /-/loglevel`) appends one JSON line with who, when and what: time,
+//! request ID, source IP, authenticated principal, method, path, action,
+//! details and outcome. Rejected attempts are recorded too. The file is
+//! opened in append mode with mode 0600, synced after every record and never
+//! truncated or rotated by the exporter; use logrotate with `copytruncate` or
+//! a restart after moving it.
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::error;
+
+use crate::request_id::RequestId;
+
+/// Request details of an audit record, extracted from the request.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub request_id: String,
+    pub source_ip: Option<IpAddr>,
+    pub method: String,
+    pub path: String,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuditContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            request_id: parts
+                .extensions
+                .get::<RequestId>()
+                .map(|id| id.0.clone())
+                .unwrap_or_default(),
+            source_ip: parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|info| info.0.ip()),
+            method: parts.method.to_string(),
+            path: parts.uri.path().to_string(),
+        })
+    }
+}
+
+/// Result of an audited request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    /// Authentication failed
+    Denied,
+    /// The request was malformed
+    Invalid,
+    /// The change failed
+    Error,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    request_id: &'a str,
+    source_ip: Option<IpAddr>,
+    principal: &'a str,
+    method: &'a str,
+    path: &'a str,
+    action: &'a str,
+    detail: &'a str,
+    outcome: AuditOutcome,
+    status: u16,
+}
+
+/// Append-only audit log; a no-op when `audit_log` is not configured.
+#[derive(Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    /// Opens or creates the log at `path` for appending.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(path)?;
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    /// Opens the configured audit log, disabled if `path` is None.
+    pub fn from_path(path: Option<&Path>) -> io::Result<Self> {
+        path.map_or_else(|| Ok(Self::default()), Self::open)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Appends a record; failures are logged as errors.
+    pub fn record(
+        &self,
+        ctx: &AuditContext,
+        principal: &str,
+        action: &str,
+        detail: &str,
+        outcome: AuditOutcome,
+        status: u16,
+    ) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let record = AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            request_id: &ctx.request_id,
+            source_ip: ctx.source_ip,
+            principal,
+            method: &ctx.method,
+            path: &ctx.path,
+            action,
+            detail,
+            outcome,
+            status,
+        };
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to encode audit record: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        // One write per record keeps lines whole with O_APPEND
+        if let Err(e) = file.write_all(&line).and_then(|_| file.sync_data()) {
+            error!("Failed to write audit record for {}: {}", action, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_log_appends_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let ctx = AuditContext {
+            request_id: "abc".to_string(),
+            source_ip: Some("10.0.0.5".parse().unwrap()),
+            method: "PUT".to_string(),
+            path: "/-/loglevel".to_string(),
+        };
+
+        let log = AuditLog::open(&path).unwrap();
+        log.record(
+            &ctx,
+            "admin_token",
+            "set_log_level",
+            "info -> debug",
+            AuditOutcome::Success,
+            200,
+        );
+        drop(log);
+        let log = AuditLog::open(&path).unwrap();
+        log.record(
+            &ctx,
+            "anonymous",
+            "set_log_level",
+            "",
+            AuditOutcome::Denied,
+            401,
+        );
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["source_ip"], "10.0.0.5");
+        assert_eq!(lines[0]["detail"], "info -> debug");
+        assert_eq!(lines[1]["outcome"], "denied");
+        assert_eq!(lines[1]["status"], 401);
+
+        assert!(!AuditLog::default().is_enabled());
+    }
+}
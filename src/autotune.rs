@@ -0,0 +1,216 @@
+//! Scan concurrency auto-tuning.
+//!
+//! More scan threads are not always faster: on VMs whose /proc reads block
+//! on slow storage (swapped-out page tables, spinning disks), maximum
+//! parallelism only adds contention. With `scan_autotune` configured, scans
+//! run on a dedicated thread pool whose size is adjusted between scans by a
+//! simple hill climb on the scan throughput (entries per second), bounded by
+//! `min_threads` and `max_threads`. High system IO wait during a scan always
+//! steps the pool down. Pool threads apply the Landlock sandbox when they
+//! start; a pool built before the sandbox was applied is replaced.
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::config::ScanAutotuneConfig;
+use crate::sandbox::{landlock_active, restrict_thread};
+use crate::self_limits::renice_current_thread;
+use crate::system::{read_cpu_stats, CpuStat};
+
+/// Share of CPU time in IO wait during a scan above which the pool shrinks.
+pub const IOWAIT_HIGH: f64 = 0.25;
+/// Throughput changes below this fraction count as noise.
+const NOISE: f64 = 0.05;
+
+/// Next pool size from the last two scans.
+///
+/// Keeps moving in `direction` (+1 or -1) while throughput improves, turns
+/// around when it drops, and steps down while IO wait is high. Returns the
+/// new size and direction.
+pub fn next_threads(
+    current: usize,
+    direction: i8,
+    previous_throughput: Option<f64>,
+    throughput: f64,
+    iowait: f64,
+    (min, max): (usize, usize),
+) -> (usize, i8) {
+    let direction = if iowait > IOWAIT_HIGH {
+        -1
+    } else {
+        match previous_throughput {
+            Some(previous) if throughput < previous * (1.0 - NOISE) => -direction,
+            Some(previous) if throughput <= previous * (1.0 + NOISE) => 0,
+            _ => direction,
+        }
+    };
+    // Steps of a quarter, so large pools converge in a few scans
+    let step = (current / 4).max(1);
+    let next = match direction {
+        1 => current.saturating_add(step),
+        -1 => current.saturating_sub(step),
+        _ => current,
+    };
+    // A stable or bounded pool probes the other way next time
+    let next = next.clamp(min, max);
+    let direction = match direction {
+        0 if next == max => -1,
+        0 => 1,
+        d if next == current => -d,
+        d => d,
+    };
+    (next, direction)
+}
+
+/// Aggregate CPU times, for the IO wait share of a scan.
+pub fn total_cpu_stat() -> Option<CpuStat> {
+    read_cpu_stats().ok()?.get("cpu").copied()
+}
+
+struct TunerState {
+    threads: usize,
+    direction: i8,
+    last_throughput: Option<f64>,
+    pool: Option<Arc<ThreadPool>>,
+    /// The pool was built with the Landlock sandbox active
+    sandboxed: bool,
+}
+
+/// The scan thread pool and its tuning state.
+pub struct ScanTuner {
+    min: usize,
+    max: usize,
+    nice: Option<i32>,
+    state: Mutex<TunerState>,
+}
+
+impl ScanTuner {
+    pub fn new(cfg: &ScanAutotuneConfig, nice: Option<i32>) -> Self {
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let min = cfg.min_threads.unwrap_or(1).max(1);
+        let max = cfg.max_threads.unwrap_or(cpus).max(min);
+        Self {
+            min,
+            max,
+            nice,
+            state: Mutex::new(TunerState {
+                threads: max,
+                direction: -1,
+                last_throughput: None,
+                pool: None,
+                sandboxed: false,
+            }),
+        }
+    }
+
+    /// The pool for the next scan, rebuilt when its size changed.
+    pub fn pool(&self) -> Option<Arc<ThreadPool>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        // Threads started before the sandbox was applied are unrestricted
+        if !state.sandboxed && landlock_active() {
+            state.pool = None;
+        }
+        if state.pool.is_none() {
+            let nice = self.nice;
+            let builder = ThreadPoolBuilder::new()
+                .num_threads(state.threads)
+                .thread_name(|i| format!("scan-{}", i))
+                .start_handler(move |_| {
+                    restrict_thread();
+                    if let Some(nice) = nice {
+                        renice_current_thread(nice);
+                    }
+                });
+            match builder.build() {
+                Ok(pool) => {
+                    state.pool = Some(Arc::new(pool));
+                    state.sandboxed = landlock_active();
+                }
+                Err(e) => warn!("Failed to build scan thread pool: {}", e),
+            }
+        }
+        state.pool.clone()
+    }
+
+    /// Feeds back a finished scan and picks the pool size for the next one.
+    pub fn record(&self, entries: usize, elapsed: Duration, iowait: f64) {
+        let secs = elapsed.as_secs_f64();
+        if entries == 0 || secs <= 0.0 {
+            return;
+        }
+        let throughput = entries as f64 / secs;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (threads, direction) = next_threads(
+            state.threads,
+            state.direction,
+            state.last_throughput,
+            throughput,
+            iowait,
+            (self.min, self.max),
+        );
+        debug!(
+            "Scan of {} entries at {:.0}/s with {} threads (iowait {:.2}), next {}",
+            entries, throughput, state.threads, iowait, threads
+        );
+        if threads != state.threads {
+            state.threads = threads;
+            // Dropped once the running scan (if any) releases its handle
+            state.pool = None;
+        }
+        state.last_throughput = Some(throughput);
+        state.direction = direction;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: (usize, usize) = (1, 16);
+
+    #[test]
+    fn test_next_threads() {
+        // First scan: keep shrinking from the maximum
+        assert_eq!(next_threads(16, -1, None, 1000.0, 0.0, BOUNDS), (12, -1));
+        // Faster: continue
+        assert_eq!(
+            next_threads(12, -1, Some(1000.0), 1200.0, 0.0, BOUNDS),
+            (9, -1)
+        );
+        // Slower: turn around
+        assert_eq!(
+            next_threads(9, -1, Some(1200.0), 1000.0, 0.0, BOUNDS),
+            (11, 1)
+        );
+        // Unchanged: stay, probe upwards next time
+        assert_eq!(
+            next_threads(11, 1, Some(1000.0), 1010.0, 0.0, BOUNDS),
+            (11, 1)
+        );
+        // High IO wait always shrinks
+        assert_eq!(
+            next_threads(11, 1, Some(1000.0), 2000.0, 0.4, BOUNDS),
+            (9, -1)
+        );
+        // Bounds turn the direction around
+        assert_eq!(next_threads(1, -1, Some(100.0), 200.0, 0.0, BOUNDS), (1, 1));
+        assert_eq!(
+            next_threads(16, 1, Some(100.0), 200.0, 0.0, BOUNDS),
+            (16, -1)
+        );
+    }
+
+    #[test]
+    fn test_tuner_pool() {
+        let cfg = ScanAutotuneConfig {
+            min_threads: Some(2),
+            max_threads: Some(4),
+        };
+        let tuner = ScanTuner::new(&cfg, None);
+        assert_eq!(tuner.pool().unwrap().current_num_threads(), 4);
+        tuner.record(1000, Duration::from_secs(1), 0.5);
+        assert_eq!(tuner.pool().unwrap().current_num_threads(), 3);
+    }
+}
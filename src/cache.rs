@@ -4,26 +4,349 @@
 //! between collection intervals, along with metadata about the cache state.
 
 use ahash::AHashMap as HashMap;
+use std::fmt;
+use std::sync::Arc;
 use std::time::Instant;
 
+use crate::cgroup::{CgroupMemory, MemoryLimit};
+use crate::process::{DelayStats, IoPriority, MemoryValues, Privileges, RuleStats, SchedStat};
+
+/// Identity of a process across scans and restarts of the exporter.
+///
+/// A PID alone is reused by the kernel; together with the start time it
+/// names one process for as long as the host runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProcKey {
+    pub pid: u32,
+    /// Start time after boot in clock ticks
+    pub start_time_ticks: u64,
+}
+
+/// The `proc_id` label: PID and start time in hex, e.g. `1234.1a2b3c`.
+impl fmt::Display for ProcKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:x}", self.pid, self.start_time_ticks)
+    }
+}
+
+/// CPU usage of one thread of a process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadCpu {
+    pub tid: u32,
+    pub name: Arc<str>,
+    pub cpu_percent: f32,
+    pub cpu_time_seconds: f32,
+}
+
 /// Process memory and CPU metrics collected from /proc.
 #[derive(Debug, Clone)]
 pub struct ProcMem {
     pub pid: u32,
-    pub name: String,
+    /// Shared with the entry of the same process in the previous snapshot,
+    /// so snapshots and scrapes copy a pointer instead of the name
+    pub name: Arc<str>,
     pub rss: u64,
     pub pss: u64,
     pub uss: u64,
+    /// Swapped-out anonymous memory (smaps Swap)
+    pub swap: u64,
+    /// Memory locked with mlock(2) and friends (smaps Locked)
+    pub locked: u64,
+    /// Anonymous memory (smaps Anonymous); the rest of the RSS is file-backed
+    pub anonymous: u64,
+    /// Resident shared memory, tmpfs and System V (status RssShmem); None
+    /// unless `enable_shmem_metrics`
+    pub shmem: Option<u64>,
     pub cpu_percent: f32,
     pub cpu_time_seconds: f32,
+    /// Process start time after boot in clock ticks (detects PID reuse)
+    pub start_time_ticks: u64,
+    /// Total CPU time in clock ticks
+    pub cpu_ticks: u64,
+    /// Part of `cpu_ticks` spent running a guest's virtual CPU
+    pub guest_ticks: u64,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    /// Process group and session ID from /proc/<pid>/stat, 0 if unknown
+    pub pgrp: u32,
+    pub session: u32,
+    /// Nice value and scheduling policy (SCHED_*) from /proc/<pid>/stat
+    pub nice: i32,
+    pub sched_policy: u32,
+    /// Scheduler state from /proc/<pid>/stat (R, S, D, ...)
+    pub state: char,
+    /// I/O priority, only read with `enable_priority_metrics`
+    pub io_priority: Option<IoPriority>,
+    /// Effective UID and capabilities, only read with `enable_privilege_metrics`
+    pub privileges: Option<Privileges>,
+    /// Block IO and swap-in delays, only read with `enable_delay_metrics`
+    pub delays: Option<DelayStats>,
+    /// Scheduler run and wait times, only read with `enable_schedstat`
+    pub schedstat: Option<SchedStat>,
+    /// Resident bytes per NUMA node by node number, only read with
+    /// `enable_numa`; refreshed with the memory values
+    pub numa: Option<Arc<[(u32, u64)]>>,
+    /// Kernel function a process in uninterruptible sleep waits in, only
+    /// read with `enable_wchan_metrics`
+    pub wchan: Option<Arc<str>>,
+    /// Storage read/write counters, None if /proc/<pid>/io was not read
+    pub read_bytes: Option<u64>,
+    pub write_bytes: Option<u64>,
+    /// Read/write syscall counters, None if /proc/<pid>/io was not read
+    pub read_syscalls: Option<u64>,
+    pub write_syscalls: Option<u64>,
+    /// When rss/pss/uss were read; older than the snapshot for processes
+    /// whose memory scan was deferred by `refresh_every`
+    pub memory_updated: Instant,
+    /// Values of the `label_extractors` labels, None if nothing was extracted
+    pub extra_labels: Option<Arc<[String]>>,
+    /// Values of the `environ` variables, None if `environ` is not configured;
+    /// read once per process and carried over while the PID is not reused
+    pub environ: Option<Arc<[String]>>,
+    /// Value of `environ.classify_by` if it matches a subgroup rule
+    pub classify_as: Option<Arc<str>>,
+    /// Memory limit of the process' cgroup, only read with
+    /// `cgroups.process_limits`
+    pub memory_limit: Option<MemoryLimit>,
+    /// Busiest threads by CPU, only read with `per_thread` for processes
+    /// with more than one thread
+    pub threads: Option<Arc<[ThreadCpu]>>,
+}
+
+/// Per-second rates of a process between two snapshots.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProcRates {
+    pub cpu_seconds: f64,
+    pub minor_faults: f64,
+    pub major_faults: f64,
+    pub read_bytes: Option<f64>,
+    pub write_bytes: Option<f64>,
+    /// Growth of swapped-out memory, None if the memory was not re-read
+    pub swapped_bytes: Option<f64>,
+}
+
+impl ProcRates {
+    /// Adds another process' rates, e.g. for subgroup sums.
+    pub fn accumulate(&mut self, other: &ProcRates) {
+        let add = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+        };
+        self.cpu_seconds += other.cpu_seconds;
+        self.minor_faults += other.minor_faults;
+        self.major_faults += other.major_faults;
+        self.read_bytes = add(self.read_bytes, other.read_bytes);
+        self.write_bytes = add(self.write_bytes, other.write_bytes);
+        self.swapped_bytes = add(self.swapped_bytes, other.swapped_bytes);
+    }
+}
+
+impl ProcMem {
+    /// The stable identity of this process.
+    pub fn key(&self) -> ProcKey {
+        ProcKey {
+            pid: self.pid,
+            start_time_ticks: self.start_time_ticks,
+        }
+    }
+
+    /// The memory values of the last memory scan.
+    pub fn memory(&self) -> MemoryValues {
+        MemoryValues {
+            rss: self.rss,
+            pss: self.pss,
+            uss: self.uss,
+            swap: self.swap,
+            locked: self.locked,
+            anonymous: self.anonymous,
+        }
+    }
+
+    /// The name matched against the subgroup rules.
+    pub fn classify_name(&self) -> &str {
+        self.classify_as.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Computes per-second rates against the same process in an older snapshot.
+    ///
+    /// Returns None if the PID was reused by a different process or the
+    /// interval is empty. Counters that went backwards yield a rate of 0.
+    /// The swap rate uses the time between the two memory reads, since a
+    /// memory scan deferred by `refresh_every` carries over the old values.
+    pub fn rates_since(&self, previous: &ProcMem, seconds: f64, clk_tck: f64) -> Option<ProcRates> {
+        if seconds <= 0.0 || self.start_time_ticks != previous.start_time_ticks {
+            return None;
+        }
+
+        let rate = |now: u64, before: u64| now.saturating_sub(before) as f64 / seconds;
+        let io_rate = |now: Option<u64>, before: Option<u64>| Some(rate(now?, before?));
+        let swapped_bytes = self
+            .memory_updated
+            .checked_duration_since(previous.memory_updated)
+            .map(|elapsed| elapsed.as_secs_f64())
+            .filter(|&elapsed| elapsed > 0.0)
+            .map(|elapsed| self.swap.saturating_sub(previous.swap) as f64 / elapsed);
+
+        Some(ProcRates {
+            cpu_seconds: rate(self.cpu_ticks, previous.cpu_ticks) / clk_tck,
+            minor_faults: rate(self.minor_faults, previous.minor_faults),
+            major_faults: rate(self.major_faults, previous.major_faults),
+            read_bytes: io_rate(self.read_bytes, previous.read_bytes),
+            write_bytes: io_rate(self.write_bytes, previous.write_bytes),
+            swapped_bytes,
+        })
+    }
 }
 
 /// Cache state for storing process metrics with update timing information.
 #[derive(Clone, Default)]
 pub struct MetricsCache {
-    pub processes: HashMap<u32, ProcMem>,
+    pub processes: HashMap<ProcKey, ProcMem>,
     pub last_updated: Option<Instant>,
+    /// Unix time of the start of the last successful update in milliseconds
+    pub last_updated_unix_ms: Option<i64>,
     pub update_duration_seconds: f64,
     pub update_success: bool,
     pub is_updating: bool,
+    /// Snapshot before the current one, used for rate computation
+    pub previous_processes: HashMap<ProcKey, ProcMem>,
+    pub previous_updated: Option<Instant>,
+    /// When the scan of a restored snapshot ran, as seen from this process;
+    /// the baseline of rates in the first scan after a restart
+    pub restored_updated: Option<Instant>,
+    /// Number of completed scans, drives `refresh_every`; doubles as the
+    /// sequence number (`scan_id`) of the current snapshot
+    pub scan_cycle: u64,
+    /// Classification rule hits of the last scan
+    pub rule_stats: RuleStats,
+    /// Cgroup memory of the last scan (`cgroups`)
+    pub cgroups: Vec<CgroupMemory>,
+    /// Processes restored from `cache_snapshot` at startup, not yet
+    /// replaced by a scan
+    pub restored: bool,
+    /// The initial scan exceeded `startup_scan_timeout_seconds` and has not
+    /// completed yet
+    pub initializing: bool,
+}
+
+impl MetricsCache {
+    /// Seconds between the previous and the current snapshot.
+    pub fn snapshot_interval_seconds(&self) -> Option<f64> {
+        let current = self.last_updated?;
+        let previous = self.previous_updated?;
+        Some(current.checked_duration_since(previous)?.as_secs_f64())
+    }
+
+    /// Per-second rates of all current processes also present in the previous snapshot.
+    pub fn rates(&self, clk_tck: f64) -> HashMap<ProcKey, ProcRates> {
+        let Some(seconds) = self.snapshot_interval_seconds() else {
+            return HashMap::new();
+        };
+        self.processes
+            .iter()
+            .filter_map(|(key, p)| {
+                let previous = self.previous_processes.get(key)?;
+                Some((*key, p.rates_since(previous, seconds, clk_tck)?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proc_mem(start_time_ticks: u64, cpu_ticks: u64, read_bytes: Option<u64>) -> ProcMem {
+        ProcMem {
+            pid: 42,
+            name: "test".into(),
+            rss: 0,
+            pss: 0,
+            uss: 0,
+            swap: 0,
+            locked: 0,
+            anonymous: 0,
+            shmem: None,
+            cpu_percent: 0.0,
+            cpu_time_seconds: 0.0,
+            start_time_ticks,
+            cpu_ticks,
+            guest_ticks: 0,
+            minor_faults: cpu_ticks * 10,
+            major_faults: 0,
+            pgrp: 0,
+            session: 0,
+            nice: 0,
+            sched_policy: 0,
+            state: 'S',
+            io_priority: None,
+            privileges: None,
+            delays: None,
+            schedstat: None,
+            numa: None,
+            wchan: None,
+            read_bytes,
+            write_bytes: None,
+            read_syscalls: None,
+            write_syscalls: None,
+            memory_updated: Instant::now(),
+            extra_labels: None,
+            environ: None,
+            classify_as: None,
+            memory_limit: None,
+            threads: None,
+        }
+    }
+
+    #[test]
+    fn test_rates_since() {
+        let before = proc_mem(7, 100, Some(1000));
+        let now = proc_mem(7, 300, Some(5000));
+
+        let rates = now.rates_since(&before, 2.0, 100.0).expect("same process");
+        assert_eq!(rates.cpu_seconds, 1.0);
+        assert_eq!(rates.minor_faults, 1000.0);
+        assert_eq!(rates.read_bytes, Some(2000.0));
+        assert_eq!(rates.write_bytes, None);
+    }
+
+    #[test]
+    fn test_rates_since_pid_reuse_and_reset() {
+        let before = proc_mem(7, 300, None);
+        assert!(proc_mem(8, 400, None)
+            .rates_since(&before, 2.0, 100.0)
+            .is_none());
+        assert!(proc_mem(7, 400, None)
+            .rates_since(&before, 0.0, 100.0)
+            .is_none());
+
+        // Counters going backwards never produce negative rates
+        let rates = proc_mem(7, 100, None)
+            .rates_since(&before, 2.0, 100.0)
+            .unwrap();
+        assert_eq!(rates.cpu_seconds, 0.0);
+    }
+
+    #[test]
+    fn test_rates_since_swap() {
+        let mut before = proc_mem(7, 100, None);
+        before.swap = 8 * 1024 * 1024;
+        let mut now = before.clone();
+        assert_eq!(
+            now.rates_since(&before, 2.0, 100.0).unwrap().swapped_bytes,
+            None,
+            "memory scan deferred"
+        );
+
+        now.swap = 16 * 1024 * 1024;
+        now.memory_updated = before.memory_updated + std::time::Duration::from_secs(4);
+        let rates = now.rates_since(&before, 2.0, 100.0).unwrap();
+        assert_eq!(rates.swapped_bytes, Some(2.0 * 1024.0 * 1024.0));
+
+        // Swap-ins shrink the residency but never yield negative rates
+        let mut later = before.clone();
+        later.memory_updated = now.memory_updated + std::time::Duration::from_secs(1);
+        let rates = later.rates_since(&now, 1.0, 100.0).unwrap();
+        assert_eq!(rates.swapped_bytes, Some(0.0));
+    }
 }
@@ -0,0 +1,105 @@
+//! Cache-Control headers of HTTP responses.
+//!
+//! Responses built from the scan cache stay the same until the next scan, so
+//! they may be cached for the rest of `cache_ttl`: `max-age` is the time left
+//! until the cache expires. With collection on scrape every request may
+//! trigger a scan and they are marked `no-cache`, as are all other endpoints.
+//! Error responses are never stored.
+//!
+//! GET routes answer HEAD requests too; the /metrics endpoints and the other
+//! read endpoints answer them without building the body, so health-checking
+//! load balancers that probe with HEAD cost no more than a header.
+
+use axum::{
+    extract::{Request, State},
+    http::{header::CACHE_CONTROL, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Duration;
+
+use crate::collector::is_on_scrape;
+use crate::schedule::current_cache_ttl;
+use crate::state::SharedState;
+
+/// Endpoints answered from the scan cache; `/metrics` includes its schema
+/// and tenant variants.
+const CACHED_PATHS: &[&str] = &[
+    "/metrics",
+    "/api/v1/owners",
+    "/api/v1/unclassified",
+    "/export.csv",
+];
+
+fn is_cached_path(path: &str) -> bool {
+    CACHED_PATHS.contains(&path) || path.starts_with("/metrics/")
+}
+
+/// Cache-Control value of a successful response. `expires_in` is the time
+/// until the scan cache expires, None without a completed scan or with
+/// collection on scrape.
+fn cache_control(path: &str, expires_in: Option<Duration>) -> String {
+    match expires_in {
+        Some(left) if is_cached_path(path) => format!("max-age={}", left.as_secs()),
+        _ => "no-cache".to_string(),
+    }
+}
+
+/// Middleware answering HEAD without running the handler, for routes whose
+/// GET answer does not depend on the request and always succeeds.
+pub async fn answer_head(request: Request, next: Next) -> Response {
+    if request.method() == Method::HEAD {
+        return StatusCode::OK.into_response();
+    }
+    next.run(request).await
+}
+
+/// Middleware adding Cache-Control to responses without one.
+pub async fn set_cache_control(
+    State(state): State<SharedState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let mut response = next.run(request).await;
+    if response.headers().contains_key(CACHE_CONTROL) {
+        return response;
+    }
+
+    let value = if !response.status().is_success() {
+        "no-store".to_string()
+    } else if is_cached_path(&path) && !is_on_scrape(&state) {
+        let last_updated = state.cache.read().await.last_updated;
+        let ttl = Duration::from_secs(current_cache_ttl(&state.config));
+        cache_control(
+            &path,
+            last_updated.map(|updated| ttl.saturating_sub(updated.elapsed())),
+        )
+    } else {
+        cache_control(&path, None)
+    };
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        response.headers_mut().insert(CACHE_CONTROL, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_control() {
+        let left = Some(Duration::from_millis(12_500));
+        assert_eq!(cache_control("/metrics", left), "max-age=12");
+        assert_eq!(cache_control("/metrics/v2", left), "max-age=12");
+        assert_eq!(cache_control("/metrics/team-a", left), "max-age=12");
+        assert_eq!(cache_control("/export.csv", left), "max-age=12");
+        // Expired or not scanned yet
+        assert_eq!(cache_control("/metrics", Some(Duration::ZERO)), "max-age=0");
+        assert_eq!(cache_control("/metrics", None), "no-cache");
+        // Not answered from the scan cache
+        assert_eq!(cache_control("/health", left), "no-cache");
+        assert_eq!(cache_control("/metricsfoo", left), "no-cache");
+    }
+}
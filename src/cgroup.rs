@@ -0,0 +1,357 @@
+//! Per-cgroup memory accounting from the cgroup v2 hierarchy.
+//!
+//! With `cgroups` configured, every cache update walks the unified hierarchy
+//! down to `max_depth` levels below the root and reads `memory.current`,
+//! `memory.swap.current` and selected `memory.stat` fields of each cgroup.
+//! This is the kernel's own accounting, including page cache and kernel
+//! memory charged to a cgroup, which per-process RSS/PSS/USS cannot show.
+//!
+//! With `process_limits`, processes are also mapped to the collected cgroup
+//! closest to its `memory.max`, see [`CgroupLimits`]. With `unit_limits`, the
+//! cgroups of systemd units report their usage against their `memory.high`
+//! and `memory.max` (MemoryHigh= and MemoryMax= of the unit).
+
+use ahash::AHashMap as HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use herakles_proc_mem_exporter::CollectError;
+
+use crate::config::CgroupConfig;
+
+/// Mount point of the unified hierarchy.
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// Levels below the root walked by default.
+pub const DEFAULT_MAX_DEPTH: usize = 2;
+/// `memory.stat` fields exported by default, all in bytes.
+pub const DEFAULT_STAT_FIELDS: &[&str] = &[
+    "anon",
+    "file",
+    "kernel",
+    "kernel_stack",
+    "slab",
+    "sock",
+    "shmem",
+    "file_mapped",
+    "file_dirty",
+    "file_writeback",
+];
+/// Upper bound of collected cgroups per scan, so a runaway hierarchy cannot
+/// explode the series count.
+pub const MAX_CGROUPS: usize = 2000;
+/// Suffixes of systemd units that own a cgroup.
+const UNIT_SUFFIXES: &[&str] = &[".service", ".scope", ".slice", ".socket", ".mount", ".swap"];
+
+/// Memory accounting of one cgroup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CgroupMemory {
+    /// Path below the root, "/" for the root itself
+    pub path: String,
+    /// memory.current (not available for the root cgroup)
+    pub current_bytes: Option<u64>,
+    /// memory.swap.current (needs swap accounting)
+    pub swap_current_bytes: Option<u64>,
+    /// memory.max, None if unlimited ("max") or not available
+    pub max_bytes: Option<u64>,
+    /// memory.high, the throttling limit; None if unlimited or not available
+    pub high_bytes: Option<u64>,
+    /// Configured memory.stat fields present in the file, in config order
+    pub stat: Vec<(String, u64)>,
+}
+
+/// Walks the hierarchy and reads the memory files of every cgroup.
+///
+/// Cgroups removed during the walk are skipped; an unreadable root is an error.
+pub fn collect_cgroups(cfg: &CgroupConfig) -> Result<Vec<CgroupMemory>, CollectError> {
+    let root = cfg
+        .root
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CGROUP_ROOT));
+    let max_depth = cfg.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+    let default_fields: Vec<String>;
+    let fields = match &cfg.stat_fields {
+        Some(fields) => fields.as_slice(),
+        None => {
+            default_fields = DEFAULT_STAT_FIELDS.iter().map(|f| f.to_string()).collect();
+            &default_fields
+        }
+    };
+
+    let controllers = root.join("cgroup.controllers");
+    fs::metadata(&controllers).map_err(|e| CollectError::io(&controllers, e))?;
+
+    let mut cgroups = Vec::new();
+    let mut pending = vec![(root.clone(), 0usize)];
+    while let Some((dir, depth)) = pending.pop() {
+        if cgroups.len() >= MAX_CGROUPS {
+            break;
+        }
+        if let Some(cgroup) = read_cgroup(&root, &dir, fields)? {
+            cgroups.push(cgroup);
+        }
+        if depth < max_depth {
+            let mut children = child_cgroups(&dir)?;
+            // Reversed so the stack yields children in name order
+            children.sort_unstable_by(|a, b| b.cmp(a));
+            pending.extend(children.into_iter().map(|child| (child, depth + 1)));
+        }
+    }
+    Ok(cgroups)
+}
+
+fn child_cgroups(dir: &Path) -> Result<Vec<PathBuf>, CollectError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(CollectError::io(dir, e)),
+    };
+    Ok(entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+        .collect())
+}
+
+/// Reads one cgroup, None if it has no memory files (controller not enabled
+/// for it) or disappeared.
+fn read_cgroup(
+    root: &Path,
+    dir: &Path,
+    fields: &[String],
+) -> Result<Option<CgroupMemory>, CollectError> {
+    let current_bytes = read_u64(&dir.join("memory.current"))?;
+    let swap_current_bytes = read_u64(&dir.join("memory.swap.current"))?;
+    let max_bytes = read_limit(&dir.join("memory.max"))?;
+    let high_bytes = read_limit(&dir.join("memory.high"))?;
+    let stat_path = dir.join("memory.stat");
+    let stat = match fs::read_to_string(&stat_path) {
+        Ok(content) => parse_memory_stat(&content, fields),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(CollectError::io(&stat_path, e)),
+    };
+    if current_bytes.is_none() && swap_current_bytes.is_none() && stat.is_empty() {
+        return Ok(None);
+    }
+
+    let relative = dir.strip_prefix(root).unwrap_or(dir);
+    Ok(Some(CgroupMemory {
+        path: format!("/{}", relative.display()),
+        current_bytes,
+        swap_current_bytes,
+        max_bytes,
+        high_bytes,
+        stat,
+    }))
+}
+
+/// The systemd unit owning the cgroup at `path`, e.g. "nginx.service" for
+/// "/system.slice/nginx.service".
+pub fn systemd_unit(path: &str) -> Option<&str> {
+    let name = path.rsplit('/').next()?;
+    UNIT_SUFFIXES
+        .iter()
+        .any(|suffix| name.len() > suffix.len() && name.ends_with(suffix))
+        .then_some(name)
+}
+
+/// The cgroup limiting the memory of a process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryLimit {
+    /// Path of the limiting cgroup below the root
+    pub cgroup: Arc<str>,
+    /// memory.max of the limiting cgroup
+    pub max_bytes: u64,
+    /// memory.current of the limiting cgroup
+    pub current_bytes: u64,
+}
+
+/// Memory limits of the collected cgroups, by path.
+#[derive(Debug, Default)]
+pub struct CgroupLimits {
+    limits: HashMap<String, MemoryLimit>,
+}
+
+impl CgroupLimits {
+    pub fn new(cgroups: &[CgroupMemory]) -> Self {
+        let limits = cgroups
+            .iter()
+            .filter_map(|c| {
+                let limit = MemoryLimit {
+                    cgroup: Arc::from(c.path.as_str()),
+                    max_bytes: c.max_bytes.filter(|&max| max > 0)?,
+                    current_bytes: c.current_bytes?,
+                };
+                Some((c.path.clone(), limit))
+            })
+            .collect();
+        Self { limits }
+    }
+
+    /// The limit an OOM kill of a process in cgroup `path` is closest to:
+    /// of the cgroup and its collected ancestors, the one with the highest
+    /// usage relative to its memory.max. Limits of cgroups below `max_depth`
+    /// are not known.
+    pub fn for_cgroup(&self, path: &str) -> Option<MemoryLimit> {
+        let ancestors = std::iter::successors(Some(path), |p| match p.rfind('/') {
+            Some(0) if p.len() > 1 => Some("/"),
+            Some(i) if i > 0 => Some(&p[..i]),
+            _ => None,
+        });
+        ancestors
+            .filter_map(|p| self.limits.get(p))
+            .max_by(|a, b| {
+                let ratio = |l: &MemoryLimit| l.current_bytes as f64 / l.max_bytes as f64;
+                ratio(a).total_cmp(&ratio(b))
+            })
+            .cloned()
+    }
+}
+
+/// Path of the cgroup v2 hierarchy in /proc/<pid>/cgroup content ("0::<path>").
+pub fn parse_process_cgroup(content: &str) -> Option<&str> {
+    content.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// Reads the cgroup v2 path of a process.
+pub fn read_process_cgroup(proc_path: &Path) -> Result<Option<String>, CollectError> {
+    let path = proc_path.join("cgroup");
+    let content = fs::read_to_string(&path).map_err(|e| CollectError::io(&path, e))?;
+    Ok(parse_process_cgroup(&content).map(str::to_string))
+}
+
+/// Reads a memory.max or memory.high file, None if unlimited ("max").
+fn read_limit(path: &Path) -> Result<Option<u64>, CollectError> {
+    match fs::read_to_string(path) {
+        Ok(content) if content.trim() == "max" => Ok(None),
+        Ok(_) => read_u64(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(CollectError::io(path, e)),
+    }
+}
+
+fn read_u64(path: &Path) -> Result<Option<u64>, CollectError> {
+    match fs::read_to_string(path) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| CollectError::parse(path, "expected a byte count")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(CollectError::io(path, e)),
+    }
+}
+
+/// Picks `fields` from memory.stat content ("<field> <value>" per line).
+fn parse_memory_stat(content: &str, fields: &[String]) -> Vec<(String, u64)> {
+    fields
+        .iter()
+        .filter_map(|field| {
+            content.lines().find_map(|line| {
+                let (name, value) = line.split_once(' ')?;
+                (name == field)
+                    .then(|| value.trim().parse().ok())
+                    .flatten()
+                    .map(|value| (field.clone(), value))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, file: &str, content: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(file), content).unwrap();
+    }
+
+    #[test]
+    fn test_collect_cgroups() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        write(root, "cgroup.controllers", "cpu memory pids\n");
+        write(root, "memory.stat", "anon 1000\nfile 2000\n");
+        let system = root.join("system.slice");
+        write(&system, "memory.current", "4096\n");
+        write(&system, "memory.max", "8192\n");
+        write(&system, "memory.swap.current", "0\n");
+        write(&system, "memory.stat", "anon 3000\nfile 1000\nslab 96\n");
+        let nginx = system.join("nginx.service");
+        write(&nginx, "memory.current", "1024\n");
+        write(&nginx, "memory.max", "max\n");
+        write(&nginx, "memory.high", "2048\n");
+        write(&nginx.join("deep"), "memory.current", "512\n");
+        fs::create_dir_all(root.join("init.scope")).unwrap();
+
+        let cfg = CgroupConfig {
+            root: Some(root.to_path_buf()),
+            stat_fields: Some(vec!["anon".to_string(), "slab".to_string()]),
+            ..Default::default()
+        };
+        let cgroups = collect_cgroups(&cfg).unwrap();
+        let paths: Vec<&str> = cgroups.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["/", "/system.slice", "/system.slice/nginx.service"]);
+        assert_eq!(cgroups[0].current_bytes, None);
+        assert_eq!(cgroups[0].stat, [("anon".to_string(), 1000)]);
+        assert_eq!(cgroups[1].current_bytes, Some(4096));
+        assert_eq!(cgroups[1].swap_current_bytes, Some(0));
+        assert_eq!(cgroups[1].max_bytes, Some(8192));
+        assert_eq!(cgroups[2].max_bytes, None);
+        assert_eq!(cgroups[2].high_bytes, Some(2048));
+        assert_eq!(cgroups[1].high_bytes, None);
+        assert_eq!(
+            cgroups[1].stat,
+            [("anon".to_string(), 3000), ("slab".to_string(), 96)]
+        );
+
+        let cfg = CgroupConfig {
+            root: Some(root.join("missing")),
+            ..Default::default()
+        };
+        assert!(collect_cgroups(&cfg).is_err());
+    }
+
+    #[test]
+    fn test_cgroup_limits() {
+        let cgroup = |path: &str, current, max| CgroupMemory {
+            path: path.to_string(),
+            current_bytes: Some(current),
+            swap_current_bytes: None,
+            max_bytes: max,
+            high_bytes: None,
+            stat: Vec::new(),
+        };
+        let limits = CgroupLimits::new(&[
+            cgroup("/kubepods", 600, Some(1000)),
+            cgroup("/kubepods/pod1", 500, Some(550)),
+            cgroup("/kubepods/pod2", 100, None),
+            cgroup("/system.slice", 100, None),
+        ]);
+
+        // The tightest of the cgroup and its ancestors
+        let limit = limits.for_cgroup("/kubepods/pod1/ctr").unwrap();
+        assert_eq!(&*limit.cgroup, "/kubepods/pod1");
+        assert_eq!((limit.current_bytes, limit.max_bytes), (500, 550));
+        let limit = limits.for_cgroup("/kubepods/pod2/ctr").unwrap();
+        assert_eq!(&*limit.cgroup, "/kubepods");
+        assert!(limits.for_cgroup("/system.slice/nginx.service").is_none());
+        assert!(limits.for_cgroup("/").is_none());
+
+        assert_eq!(
+            parse_process_cgroup("12:pids:/init.scope\n0::/kubepods/pod1/ctr\n"),
+            Some("/kubepods/pod1/ctr")
+        );
+        assert_eq!(parse_process_cgroup("12:pids:/init.scope\n"), None);
+
+        assert_eq!(
+            systemd_unit("/system.slice/nginx.service"),
+            Some("nginx.service")
+        );
+        assert_eq!(systemd_unit("/user.slice"), Some("user.slice"));
+        assert_eq!(systemd_unit("/kubepods/pod1"), None);
+        assert_eq!(systemd_unit("/"), None);
+    }
+}
@@ -26,6 +26,15 @@ pub enum ConfigFormat {
     Toml,
 }
 
+/// Output format options for the dump command
+#[derive(Debug, Clone, ValueEnum)]
+pub enum DumpFormat {
+    /// One JSON object per process and scan
+    Jsonl,
+    /// Apache Parquet (requires the "parquet" feature)
+    Parquet,
+}
+
 /// Main CLI arguments structure
 #[derive(Parser, Debug)]
 #[command(
@@ -52,6 +61,14 @@ pub struct Args {
     #[arg(long)]
     pub bind: Option<IpAddr>,
 
+    /// Write the listening address to this file once serving (use with --port 0)
+    #[arg(long)]
+    pub ready_file: Option<PathBuf>,
+
+    /// Lock this PID file and refuse to start if another instance holds it
+    #[arg(long)]
+    pub pid_file: Option<PathBuf>,
+
     /// Log level
     #[arg(long, value_enum, default_value = "info")]
     pub log_level: LogLevel,
@@ -76,10 +93,14 @@ pub struct Args {
     #[arg(long, value_enum, default_value = "yaml")]
     pub config_format: ConfigFormat,
 
-    /// Validate config and exit (return code 1 on error)
+    /// Validate config and exit (return code 1 on error, including unknown keys)
     #[arg(long)]
     pub check_config: bool,
 
+    /// Treat unknown config file keys as errors instead of warnings
+    #[arg(long)]
+    pub strict_config: bool,
+
     /// Enable /debug/pprof endpoints
     #[arg(long)]
     pub debug: bool,
@@ -140,6 +161,18 @@ pub struct Args {
     #[arg(long)]
     pub top_n_others: Option<usize>,
 
+    /// Export only subgroup sums, Top-N and system metrics (per_process_metrics: false)
+    #[arg(long)]
+    pub aggregates_only: bool,
+
+    /// Maximum number of processes exported as per-process series (override config)
+    #[arg(long)]
+    pub max_exported_processes: Option<usize>,
+
+    /// Export only processes above this percentage of their subgroup's USS (override config)
+    #[arg(long, value_name = "PERCENT")]
+    pub min_uss_percent_of_subgroup: Option<f64>,
+
     /// Path to JSON test data file (uses synthetic data instead of /proc)
     #[arg(short = 't', long)]
     pub test_data_file: Option<PathBuf>,
@@ -155,6 +188,34 @@ pub struct Args {
     /// Path to TLS private key file (PEM format)
     #[arg(long)]
     pub tls_key: Option<PathBuf>,
+
+    /// Write the exposition atomically to this .prom file after every cache
+    /// update (node_exporter textfile collector)
+    #[arg(long)]
+    pub textfile_output: Option<PathBuf>,
+
+    /// Do not serve HTTP endpoints (use with --textfile-output)
+    #[arg(long)]
+    pub disable_http: bool,
+
+    /// Perform a single collection, print the metrics exposition and exit
+    #[arg(long)]
+    pub once: bool,
+
+    /// Write the --once exposition to this file instead of stdout
+    #[arg(long, requires = "once")]
+    pub output: Option<PathBuf>,
+}
+
+/// Subcommands of `config`
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print the JSON Schema of the configuration file
+    Schema {
+        /// Output file path (default: stdout)
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+    },
 }
 
 /// Subcommands for additional functionality
@@ -176,7 +237,11 @@ pub enum Commands {
     },
 
     /// Generate configuration files
+    #[command(args_conflicts_with_subcommands = true)]
     Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+
         /// Output file path
         #[arg(short = 'o', long)]
         output: Option<PathBuf>,
@@ -203,6 +268,22 @@ pub enum Commands {
         /// Output format
         #[arg(long, value_enum, default_value = "yaml")]
         format: ConfigFormat,
+
+        /// Compare one full collection against a baseline file and fail on deviations
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<PathBuf>,
+
+        /// Write the result of one full collection as a new baseline file
+        #[arg(long, value_name = "FILE", conflicts_with = "baseline")]
+        write_baseline: Option<PathBuf>,
+
+        /// Allowed absolute difference of process counts per subgroup
+        #[arg(long, default_value_t = 0)]
+        count_tolerance: usize,
+
+        /// Allowed relative difference of memory totals in percent
+        #[arg(long, default_value_t = 0.0)]
+        memory_tolerance: f64,
     },
 
     /// List available process subgroups
@@ -216,6 +297,36 @@ pub enum Commands {
         group: Option<String>,
     },
 
+    /// Write one or more scans to a file for offline analysis
+    Dump {
+        /// Output file path
+        #[arg(short = 'o', long)]
+        output: PathBuf,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "parquet")]
+        format: DumpFormat,
+
+        /// Number of scans to write
+        #[arg(short = 'n', long, default_value_t = 1)]
+        scans: usize,
+
+        /// Seconds between scans
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+    },
+
+    /// Check the local /health endpoint (exit 0 if healthy, 1 otherwise)
+    Healthcheck {
+        /// Request timeout in seconds
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+
+        /// Skip TLS certificate verification
+        #[arg(long)]
+        insecure: bool,
+    },
+
     /// Generate synthetic test data JSON file
     GenerateTestdata {
         /// Output file path
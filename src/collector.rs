@@ -0,0 +1,1119 @@
+//! Process collection for the metrics cache.
+//!
+//! This module scans /proc (or the configured test data), applies the name
+//! and USS filters and stores the result in the shared `MetricsCache`. It is
+//! driven either by the background refresh task or, with
+//! `collection_mode: on_scrape`, directly by the metrics handlers.
+
+use ahash::AHashMap as HashMap;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, field, info, info_span, instrument, trace, warn, Span};
+
+use herakles_proc_mem_exporter::CollectError;
+
+use crate::autotune::total_cpu_stat;
+use crate::cache::{ProcKey, ProcMem, ThreadCpu};
+use crate::cgroup::{collect_cgroups, read_process_cgroup, CgroupLimits};
+use crate::commands::generate::load_test_data_from_file;
+use crate::config::{Config, DEFAULT_TOP_N_THREADS};
+use crate::history::{history_enabled, HistoryPoint};
+use crate::privacy::{excluded_by_owner, process_owner, public_name, public_values};
+use crate::process::scanner::ProcEntry;
+use crate::process::{
+    classify_process_raw, parse_memory_for_process, parse_numa_maps, probe_capabilities,
+    read_io_priority, read_proc_environ, read_proc_io, read_proc_privileges, read_proc_schedstat,
+    read_proc_shmem, read_proc_stat, read_proc_threads, read_proc_wchan, read_process_cmdline,
+    read_process_name, rule_stats, should_include_process, update_cpu_stat, CapabilitySnapshot,
+    MaxProcessesBy, MemoryValues, ProcEntryChunks, ProcStat, ThreadStat, CAPABILITIES, CLK_TCK,
+    MAX_IO_BUFFER_BYTES, MAX_SMAPS_BUFFER_BYTES, MAX_SMAPS_ROLLUP_BUFFER_BYTES, SUBGROUPS,
+};
+use crate::sandbox;
+use crate::schedule::current_cache_ttl;
+use crate::self_limits::{read_self_resources, read_self_usage, ScanGuard};
+use crate::short_lived::SHORT_LIVED_SUBGROUP;
+use crate::state::SharedState;
+
+/// Collection mode running scans synchronously inside the metrics handlers.
+pub const COLLECTION_MODE_ON_SCRAPE: &str = "on_scrape";
+
+/// Returns true if scans are driven by scrapes instead of the background task.
+pub fn is_on_scrape(state: &SharedState) -> bool {
+    state.config.collection_mode.as_deref() == Some(COLLECTION_MODE_ON_SCRAPE)
+}
+
+/// Makes sure the cache is not older than the current cache_ttl before a scrape is served.
+///
+/// Only one scan runs at a time: concurrent scrapes wait on the scan lock and
+/// then find a fresh snapshot instead of scanning again.
+pub async fn ensure_fresh_cache(state: &SharedState) {
+    let _scan = state.scan_lock.lock().await;
+
+    let max_age = Duration::from_secs(current_cache_ttl(&state.config));
+    let fresh = state
+        .cache
+        .read()
+        .await
+        .last_updated
+        .is_some_and(|t| t.elapsed() < max_age);
+
+    if fresh {
+        debug!(
+            "Cache younger than {}s, serving snapshot",
+            max_age.as_secs()
+        );
+        return;
+    }
+
+    if let Err(e) = update_cache(state).await {
+        error!("On-scrape cache update failed: {}", e);
+    }
+}
+
+/// Records a self limit enforcement event in metrics and health stats.
+fn record_self_limit_event(state: &SharedState, action: &str) {
+    state.self_limit_events.with_label_values(&[action]).inc();
+    state.health_stats.record_self_limit_event();
+}
+
+/// Counts a collection error by its category and the file it came from.
+pub fn record_collect_error(state: &SharedState, source: &str, err: &CollectError) {
+    state
+        .collect_errors
+        .with_label_values(&[err.category(), source])
+        .inc();
+}
+
+/// Processes that appeared and disappeared between two snapshots.
+///
+/// A PID reused by a new process counts as an exit and a start.
+fn process_churn<'a>(
+    previous: &'a HashMap<ProcKey, ProcMem>,
+    current: &'a [ProcMem],
+) -> (Vec<&'a ProcMem>, Vec<&'a ProcMem>) {
+    let current_keys: HashSet<ProcKey> = current.iter().map(ProcMem::key).collect();
+    let started = current
+        .iter()
+        .filter(|p| !previous.contains_key(&p.key()))
+        .collect();
+    let exited = previous
+        .iter()
+        .filter(|(key, _)| !current_keys.contains(key))
+        .map(|(_, p)| p)
+        .collect();
+    (started, exited)
+}
+
+/// Counts process starts and exits since the previous scan per subgroup.
+fn record_process_churn(
+    state: &SharedState,
+    previous: &HashMap<ProcKey, ProcMem>,
+    current: &[ProcMem],
+) {
+    let (started, exited) = process_churn(previous, current);
+    for (counter, processes) in [
+        (&state.process_starts, started),
+        (&state.process_exits, exited),
+    ] {
+        for p in processes {
+            let (group, subgroup) = classify_process_raw(p.classify_name());
+            counter
+                .with_label_values(&[group.as_ref(), subgroup.as_ref()])
+                .inc();
+        }
+    }
+}
+
+/// Attributes processes that exited before this scan saw them to the
+/// `short_lived` pseudo-subgroup of their group.
+fn record_short_lived(state: &SharedState) {
+    let mut peaks: HashMap<Arc<str>, u64> = HashMap::new();
+    for p in state.short_lived.scan_completed() {
+        let (group, _) = classify_process_raw(&p.name);
+        let labels = [group.as_ref(), SHORT_LIVED_SUBGROUP];
+        state.short_lived_processes.with_label_values(&labels).inc();
+        state
+            .short_lived_cpu_seconds
+            .with_label_values(&labels)
+            .inc_by(p.cpu_ticks as f64 / *CLK_TCK);
+        let peak = peaks.entry(group).or_default();
+        *peak = (*peak).max(p.peak_rss_bytes);
+    }
+
+    state.short_lived_peak_rss.reset();
+    for (group, peak) in peaks {
+        state
+            .short_lived_peak_rss
+            .with_label_values(&[group.as_ref(), SHORT_LIVED_SUBGROUP])
+            .set(peak as f64);
+    }
+}
+
+/// Probes /proc capabilities, logs changes and updates the capability gauges.
+fn refresh_capabilities(state: &SharedState) {
+    let current = probe_capabilities("/proc");
+    let previous = CAPABILITIES.store(current);
+
+    for ((feature, available), (_, was_available)) in
+        current.features().into_iter().zip(previous.features())
+    {
+        state
+            .capability
+            .with_label_values(&[feature])
+            .set(if available { 1.0 } else { 0.0 });
+        if available != was_available {
+            info!(
+                "/proc capability {} changed: available={}",
+                feature, available
+            );
+        }
+    }
+
+    log_own_processes_only(previous, current);
+}
+
+/// Logs switches into and out of the hidepid degraded mode, in which only
+/// the exporter's own processes are scanned.
+pub fn log_own_processes_only(previous: CapabilitySnapshot, current: CapabilitySnapshot) {
+    if current.own_processes_only() && !previous.own_processes_only() {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        let uid = unsafe { libc::geteuid() };
+        warn!(
+            "/proc is mounted with hidepid: exporting only processes of uid {} and system metrics. \
+             Run as root or add the exporter to the group of the hidepid gid= mount option to see all processes",
+            uid
+        );
+    } else if previous.own_processes_only() && !current.own_processes_only() {
+        info!("Processes of other users are visible again, scanning all processes");
+    }
+}
+
+/// Returns the `refresh_every` interval for a process, preferring a
+/// "group/subgroup" entry over a plain group entry.
+fn refresh_every_for(cfg: &Config, name: &str) -> u64 {
+    let Some(refresh) = &cfg.refresh_every else {
+        return 1;
+    };
+    let (group, subgroup) = classify_process_raw(name);
+    refresh
+        .get(&format!("{}/{}", group, subgroup))
+        .or_else(|| refresh.get(group.as_ref()))
+        .copied()
+        .unwrap_or(1)
+}
+
+/// Reads the `environ` variables of a process and the name it is classified
+/// by, reusing the previous scan's values while the PID is not reused.
+fn environ_for(
+    state: &SharedState,
+    entry: &ProcEntry,
+    previous: Option<&PreviousProcess>,
+) -> (Option<Arc<[String]>>, Option<Arc<str>>) {
+    let Some(cfg) = &state.config.environ else {
+        return (None, None);
+    };
+    if let Some(p) = previous.filter(|p| p.environ.is_some()) {
+        return (p.environ.clone(), p.classify_as.clone());
+    }
+
+    let values: Arc<[String]> = match read_proc_environ(&entry.proc_path, &cfg.variables) {
+        Ok(values) => values.into(),
+        Err(e) => {
+            // Unreadable environments count as all variables unset until the PID is reused
+            trace!("Failed to read environ for pid {}: {}", entry.pid, e);
+            record_collect_error(state, "environ", &e);
+            vec![String::new(); cfg.variables.len()].into()
+        }
+    };
+    let classify_as = cfg
+        .classify_by
+        .as_ref()
+        .and_then(|var| cfg.variables.iter().position(|v| v == var))
+        .map(|i| values[i].as_str())
+        .filter(|value| SUBGROUPS.contains_key(*value))
+        .map(Arc::from);
+    (Some(values), classify_as)
+}
+
+/// Returns true if the memory scan of a process is skipped in this cycle.
+///
+/// The PID offsets the cycle so deferred processes are spread evenly over the
+/// scans instead of all being read every Nth scan.
+fn memory_scan_deferred(every: u64, cycle: u64, pid: u32) -> bool {
+    every > 1 && !(cycle + pid as u64).is_multiple_of(every)
+}
+
+/// CPU usage of the `top_n_threads` busiest threads of a process, by CPU
+/// percent and then total CPU time, as the first scan has no percentages.
+fn busiest_threads(state: &SharedState, threads: Vec<ThreadStat>) -> Arc<[ThreadCpu]> {
+    let mut threads: Vec<ThreadCpu> = threads
+        .into_iter()
+        .map(|t| {
+            let key = ProcKey {
+                pid: t.tid,
+                start_time_ticks: t.stat.start_time,
+            };
+            let cpu = update_cpu_stat(
+                key,
+                t.stat.cpu_ticks() as f64 / *CLK_TCK,
+                &state.thread_cpu_cache,
+            );
+            ThreadCpu {
+                tid: t.tid,
+                name: public_name(&t.name, &state.config).into(),
+                cpu_percent: cpu.cpu_percent as f32,
+                cpu_time_seconds: cpu.cpu_time_seconds as f32,
+            }
+        })
+        .collect();
+    threads.sort_by(|a, b| {
+        b.cpu_percent
+            .total_cmp(&a.cpu_percent)
+            .then(b.cpu_time_seconds.total_cmp(&a.cpu_time_seconds))
+            .then(a.tid.cmp(&b.tid))
+    });
+    threads.truncate(state.config.top_n_threads.unwrap_or(DEFAULT_TOP_N_THREADS));
+    threads.into()
+}
+
+/// Processes counted by a scan, updated from the scan workers.
+#[derive(Default)]
+struct ScanCounts {
+    included: AtomicUsize,
+    skipped: AtomicUsize,
+    denied: AtomicUsize,
+    hidden: AtomicUsize,
+    zombie: AtomicUsize,
+    reused: AtomicUsize,
+}
+
+/// What a scan reuses of a process from the previous snapshot.
+struct PreviousProcess {
+    name: Arc<str>,
+    memory: MemoryValues,
+    memory_updated: Instant,
+    numa: Option<Arc<[(u32, u64)]>>,
+    environ: Option<Arc<[String]>>,
+    classify_as: Option<Arc<str>>,
+}
+
+impl From<&ProcMem> for PreviousProcess {
+    fn from(p: &ProcMem) -> Self {
+        Self {
+            name: p.name.clone(),
+            memory: p.memory(),
+            memory_updated: p.memory_updated,
+            numa: p.numa.clone(),
+            environ: p.environ.clone(),
+            classify_as: p.classify_as.clone(),
+        }
+    }
+}
+
+/// Scans /proc, blocking the calling thread until all workers are done.
+///
+/// `start` is the time of the scan recorded for freshly read memory values.
+fn scan_proc(
+    state: &SharedState,
+    previous: &HashMap<ProcKey, PreviousProcess>,
+    cycle: u64,
+    limits: Option<&CgroupLimits>,
+    scan_guard: &ScanGuard,
+    counts: &ScanCounts,
+    start: Instant,
+) -> Vec<ProcMem> {
+    let min_uss_bytes = state.config.min_uss_kb.unwrap_or(0) * 1024;
+    let enable_rates = state.config.enable_rates.unwrap_or(false);
+    let enable_io = state.config.enable_io_metrics.unwrap_or(false);
+    let enable_priority = state.config.enable_priority_metrics.unwrap_or(false);
+    let enable_privileges = state.config.enable_privilege_metrics.unwrap_or(false);
+    let enable_shmem = state.config.enable_shmem_metrics.unwrap_or(false);
+    let enable_schedstat = state.config.enable_schedstat.unwrap_or(false);
+    let enable_wchan = state.config.enable_wchan_metrics.unwrap_or(false);
+    let per_thread = state.config.per_thread.unwrap_or(false);
+    let enable_numa = state.config.enable_numa.unwrap_or(false);
+
+    // Under hidepid only the exporter's own processes can be read
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let own_uid = CAPABILITIES
+        .snapshot()
+        .own_processes_only()
+        .then(|| unsafe { libc::geteuid() });
+
+    // Synchronous phase, the span must not be held across an await
+    let scan_span = info_span!("scan_proc", entries = field::Empty).entered();
+    let chunks = ProcEntryChunks::new(
+        "/proc",
+        state.config.max_processes,
+        MaxProcessesBy::from_config(&state.config),
+    );
+    let entry_count = AtomicUsize::new(0);
+    if chunks.capped() > 0 {
+        debug!(
+            "max_processes: {} processes left out of the scan",
+            chunks.capped()
+        );
+    }
+    state.processes_capped.set(chunks.capped() as f64);
+
+    // Steal over the same interval as the CPU percent of the processes
+    if state.config.enable_cpu.unwrap_or(true) {
+        match state.scan_cpu_cache.calculate_usage_ratios() {
+            Ok(ratios) => {
+                if let Some(total) = ratios.get("cpu") {
+                    state.cpu_steal_ratio.set(total.steal);
+                }
+            }
+            Err(e) => record_collect_error(state, "system_stat", &e),
+        }
+    }
+
+    let scan_entry = |entry: &ProcEntry| -> Option<ProcMem> {
+        if own_uid.is_some_and(|uid| process_owner(&entry.proc_path) != Some(uid)) {
+            counts.hidden.fetch_add(1, Ordering::Relaxed);
+            counts.skipped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        // Read ahead of the filters, zombies are counted system-wide
+        let stat = read_proc_stat(&entry.proc_path);
+        if matches!(&stat, Ok(stat) if stat.state == 'Z') {
+            counts.zombie.fetch_add(1, Ordering::Relaxed);
+        }
+        let name = match read_process_name(&entry.proc_path) {
+            Some(name) => name,
+            None => {
+                debug!("Skipping process {}: could not read name", entry.pid);
+                counts.skipped.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+
+        if !should_include_process(&name, &state.config) {
+            debug!("Skipping process {}: filtered by name config", name);
+            counts.skipped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        if excluded_by_owner(&entry.proc_path, &state.excluded_uids) {
+            debug!("Skipping process {}: excluded by owner", entry.pid);
+            counts.skipped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let stat = match stat {
+            Ok(stat) => stat,
+            Err(e) => {
+                debug!("Failed to read stat for pid {}: {}", entry.pid, e);
+                record_collect_error(state, "stat", &e);
+                ProcStat::default()
+            }
+        };
+        let key = ProcKey {
+            pid: entry.pid,
+            start_time_ticks: stat.start_time,
+        };
+        // The same process in the previous scan; never one that used the PID before
+        let previous_process = previous.get(&key);
+        let name: Arc<str> = match previous_process {
+            Some(p) if *p.name == *name => p.name.clone(),
+            _ => name.into(),
+        };
+        let cpu = update_cpu_stat(key, stat.cpu_ticks() as f64 / *CLK_TCK, &state.cpu_cache);
+
+        // Low-priority processes keep their last memory values between
+        // rescans
+        let reused = previous_process
+            .filter(|p| {
+                p.name == name
+                    && memory_scan_deferred(
+                        refresh_every_for(&state.config, &name),
+                        cycle,
+                        entry.pid,
+                    )
+            })
+            .map(|p| (p.memory, p.memory_updated));
+        let memory_reused = reused.is_some();
+
+        let memory = match reused {
+            Some(values) => {
+                counts.reused.fetch_add(1, Ordering::Relaxed);
+                Ok(values)
+            }
+            None => parse_memory_for_process(&entry.proc_path, &state.buffer_config)
+                .map(|values| (values, start)),
+        };
+
+        match memory {
+            Ok((
+                MemoryValues {
+                    rss,
+                    pss,
+                    uss,
+                    swap,
+                    locked,
+                    anonymous,
+                },
+                memory_updated,
+            )) => {
+                if uss < min_uss_bytes {
+                    debug!(
+                        "Skipping process {}: USS {} bytes below threshold {} bytes",
+                        name, uss, min_uss_bytes
+                    );
+                    counts.skipped.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+
+                debug!(
+                    "Including process {}: {} (RSS: {} MB, PSS: {} MB, USS: {} MB, CPU: {:.6}%)",
+                    entry.pid,
+                    name,
+                    rss / 1024 / 1024,
+                    pss / 1024 / 1024,
+                    uss / 1024 / 1024,
+                    cpu.cpu_percent
+                );
+
+                // IO counters cost an extra read, skipped unless exported
+                let io = if enable_rates || enable_io {
+                    read_proc_io(&entry.proc_path, state.buffer_config.io_kb)
+                        .map_err(|e| record_collect_error(state, "io", &e))
+                        .ok()
+                } else {
+                    None
+                };
+
+                let privileges = if enable_privileges {
+                    read_proc_privileges(&entry.proc_path)
+                        .map_err(|e| record_collect_error(state, "status", &e))
+                        .ok()
+                } else {
+                    None
+                };
+
+                let shmem = if enable_shmem {
+                    read_proc_shmem(&entry.proc_path)
+                        .map_err(|e| record_collect_error(state, "status", &e))
+                        .ok()
+                } else {
+                    None
+                };
+
+                let schedstat = if enable_schedstat {
+                    read_proc_schedstat(&entry.proc_path)
+                        .map_err(|e| record_collect_error(state, "schedstat", &e))
+                        .ok()
+                } else {
+                    None
+                };
+
+                let delays = state.taskstats.as_ref().and_then(|taskstats| {
+                    taskstats
+                        .delays(entry.pid)
+                        .map_err(|e| {
+                            record_collect_error(
+                                state,
+                                "taskstats",
+                                &CollectError::io("taskstats", e),
+                            )
+                        })
+                        .ok()
+                });
+
+                // numa_maps walks every page of the process, it is read
+                // only when the memory values are
+                let numa = if !enable_numa {
+                    None
+                } else if memory_reused {
+                    previous_process.and_then(|p| p.numa.clone())
+                } else {
+                    parse_numa_maps(
+                        &entry.proc_path.join("numa_maps"),
+                        state.buffer_config.smaps_kb,
+                    )
+                    .map_err(|e| record_collect_error(state, "numa_maps", &e))
+                    .ok()
+                    .map(Arc::from)
+                };
+
+                // A single thread is the process itself
+                let threads = if per_thread && stat.num_threads > 1 {
+                    read_proc_threads(&entry.proc_path)
+                        .map_err(|e| record_collect_error(state, "task", &e))
+                        .ok()
+                        .map(|threads| busiest_threads(state, threads))
+                } else {
+                    None
+                };
+
+                // Sleeping processes all have a wait channel, only hangs are of interest
+                let wchan = if enable_wchan && stat.state == 'D' {
+                    read_proc_wchan(&entry.proc_path)
+                        .map_err(|e| record_collect_error(state, "wchan", &e))
+                        .ok()
+                        .flatten()
+                        .map(Arc::from)
+                } else {
+                    None
+                };
+
+                let cmdline = if state.label_extractors.needs_cmdline() {
+                    read_process_cmdline(&entry.proc_path)
+                } else {
+                    None
+                };
+                let (environ, classify_as) = environ_for(state, entry, previous_process);
+                let extra_labels = state
+                    .label_extractors
+                    .extract(&name, cmdline.as_deref(), environ.as_deref())
+                    .map(|values| public_values(values, &state.config));
+                let memory_limit = limits.and_then(|limits| {
+                    read_process_cgroup(&entry.proc_path)
+                        .map_err(|e| record_collect_error(state, "cgroup", &e))
+                        .ok()
+                        .flatten()
+                        .and_then(|path| limits.for_cgroup(&path))
+                });
+
+                counts.included.fetch_add(1, Ordering::Relaxed);
+                Some(ProcMem {
+                    pid: entry.pid,
+                    name,
+                    rss,
+                    pss,
+                    uss,
+                    swap,
+                    locked,
+                    anonymous,
+                    shmem,
+                    cpu_percent: cpu.cpu_percent as f32,
+                    cpu_time_seconds: cpu.cpu_time_seconds as f32,
+                    start_time_ticks: stat.start_time,
+                    cpu_ticks: stat.cpu_ticks(),
+                    guest_ticks: stat.guest_time,
+                    minor_faults: stat.minor_faults,
+                    major_faults: stat.major_faults,
+                    pgrp: stat.pgrp.max(0) as u32,
+                    session: stat.session.max(0) as u32,
+                    nice: stat.nice,
+                    sched_policy: stat.policy,
+                    state: stat.state,
+                    io_priority: enable_priority
+                        .then(|| read_io_priority(entry.pid))
+                        .flatten(),
+                    privileges,
+                    delays,
+                    schedstat,
+                    numa,
+                    wchan,
+                    read_bytes: io.map(|io| io.read_bytes),
+                    write_bytes: io.map(|io| io.write_bytes),
+                    read_syscalls: io.map(|io| io.read_syscalls),
+                    write_syscalls: io.map(|io| io.write_syscalls),
+                    memory_updated,
+                    extra_labels,
+                    environ,
+                    classify_as,
+                    memory_limit,
+                    threads,
+                })
+            }
+            Err(e) if e.is_permission_denied() => {
+                record_collect_error(state, "memory", &e);
+                // Expected for foreign processes when unprivileged, summarized below
+                trace!("Skipping process {}: failed to parse memory: {}", name, e);
+                counts.denied.fetch_add(1, Ordering::Relaxed);
+                counts.skipped.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            Err(e) => {
+                debug!("Skipping process {}: failed to parse memory: {}", name, e);
+                record_collect_error(state, "memory", &e);
+                counts.skipped.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    };
+
+    // Chunks are listed while earlier ones are scanned; results are put
+    // back into PID order afterwards
+    let (scan_entry, entry_count) = (&scan_entry, &entry_count);
+    let skipped = &counts.skipped;
+    let scan = || {
+        let mut results: Vec<ProcMem> = chunks
+            .par_bridge()
+            .flat_map_iter(|chunk| {
+                chunk
+                    .into_iter()
+                    .filter(ProcEntry::is_scannable)
+                    .filter_map(move |entry| {
+                        entry_count.fetch_add(1, Ordering::Relaxed);
+                        if scan_guard.should_stop() {
+                            skipped.fetch_add(1, Ordering::Relaxed);
+                            return None;
+                        }
+                        let entry_start = Instant::now();
+                        let result = scan_entry(&entry);
+                        scan_guard.throttle(entry_start.elapsed());
+                        result
+                    })
+            })
+            .collect();
+        results.sort_unstable_by_key(|p| p.pid);
+        results
+    };
+
+    let results = match (
+        &state.scan_tuner,
+        state.scan_tuner.as_ref().and_then(|t| t.pool()),
+    ) {
+        (Some(tuner), Some(pool)) => {
+            let cpu_before = total_cpu_stat();
+            let scan_start = Instant::now();
+            let results = pool.install(scan);
+            let iowait = match (cpu_before, total_cpu_stat()) {
+                (Some(before), Some(after)) => after.ratios_since(&before).iowait,
+                _ => 0.0,
+            };
+            state.scan_threads.set(pool.current_num_threads() as f64);
+            tuner.record(
+                entry_count.load(Ordering::Relaxed),
+                scan_start.elapsed(),
+                iowait,
+            );
+            results
+        }
+        _ => {
+            state.scan_threads.set(rayon::current_num_threads() as f64);
+            scan()
+        }
+    };
+    debug!(
+        "Scanned {} process entries from /proc",
+        entry_count.load(Ordering::Relaxed)
+    );
+    scan_span.record("entries", entry_count.load(Ordering::Relaxed));
+    results
+}
+
+/// Cache update function.
+#[instrument(skip(state))]
+pub async fn update_cache(state: &SharedState) -> Result<(), CollectError> {
+    let start = Instant::now();
+    let start_unix_ms = chrono::Utc::now().timestamp_millis();
+    info!("Starting cache update");
+
+    // Mark cache as updating
+    {
+        let mut cache = state.cache.write().await;
+        cache.is_updating = true;
+        cache.update_success = false;
+        state.cache_updating.set(1.0);
+        debug!("Cache marked as updating (old snapshot still available)");
+    }
+
+    let min_uss_bytes = state.config.min_uss_kb.unwrap_or(0) * 1024;
+
+    // Enforce the exporter's own RSS cap before starting another scan
+    let scan_guard = ScanGuard::new(state.config.self_limits.as_ref());
+    if scan_guard.rss_exceeded_now() {
+        warn!("Exporter RSS above self_limits.max_rss_mb, skipping scan and keeping old snapshot");
+        record_self_limit_event(state, "scan_aborted");
+        state.health_stats.record_scan_failure();
+        let err = CollectError::ScanAborted("exporter RSS above self_limits.max_rss_mb".into());
+        record_collect_error(state, "scan", &err);
+        {
+            let mut cache = state.cache.write().await;
+            cache.is_updating = false;
+            state.cache_updating.set(0.0);
+        }
+        state.cache_ready.notify_waiters();
+        return Err(err);
+    }
+
+    refresh_capabilities(state);
+
+    let counts = ScanCounts::default();
+
+    // Collected before the scan, so processes can be mapped to their limits
+    let cgroups = match &state.config.cgroups {
+        Some(cfg) => collect_cgroups(cfg).unwrap_or_else(|e| {
+            warn!("Failed to collect cgroup memory: {}", e);
+            record_collect_error(state, "cgroup", &e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    let process_limits = state
+        .config
+        .cgroups
+        .as_ref()
+        .and_then(|cfg| cfg.process_limits)
+        .unwrap_or(false);
+    let limits = process_limits.then(|| CgroupLimits::new(&cgroups));
+
+    // Previous snapshot, source of memory values for deferred processes;
+    // copied so the read guard is not held during the scan
+    let (previous, cycle) = {
+        let cache = state.cache.read().await;
+        let previous: HashMap<ProcKey, PreviousProcess> = cache
+            .processes
+            .iter()
+            .map(|(key, p)| (*key, PreviousProcess::from(p)))
+            .collect();
+        (previous, cache.scan_cycle)
+    };
+
+    let (results, counts, scan_guard) = if let Some(test_file) = &state.config.test_data_file {
+        info!("Using test data from file: {}", test_file.display());
+
+        let test_data = match load_test_data_from_file(test_file) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to load test data: {}", e);
+                record_collect_error(state, "test_data", &e);
+                state.health_stats.record_scan_failure();
+                {
+                    let mut cache = state.cache.write().await;
+                    cache.is_updating = false;
+                    state.cache_updating.set(0.0);
+                }
+                return Err(e);
+            }
+        };
+
+        info!("Loaded {} test processes", test_data.processes.len());
+
+        let results = test_data
+            .processes
+            .into_iter()
+            .filter_map(|tp| {
+                if !should_include_process(&tp.name, &state.config) {
+                    debug!("Skipping process {}: filtered by name config", tp.name);
+                    counts.skipped.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+
+                if tp.uss < min_uss_bytes {
+                    debug!(
+                        "Skipping process {}: USS {} bytes below threshold {} bytes",
+                        tp.name, tp.uss, min_uss_bytes
+                    );
+                    counts.skipped.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+
+                debug!(
+                    "Including test process {}: {} (RSS: {} MB, PSS: {} MB, USS: {} MB, CPU: {:.6}%)",
+                    tp.pid,
+                    tp.name,
+                    tp.rss / 1024 / 1024,
+                    tp.pss / 1024 / 1024,
+                    tp.uss / 1024 / 1024,
+                    tp.cpu_percent
+                );
+
+                counts.included.fetch_add(1, Ordering::Relaxed);
+                let mut process = ProcMem::from(tp);
+                process.extra_labels = state
+                    .label_extractors
+                    .extract(&process.name, None, None)
+                    .map(|values| public_values(values, &state.config));
+                Some(process)
+            })
+            .collect();
+        (results, counts, scan_guard)
+    } else {
+        // The scan reads /proc synchronously; on a blocking thread it cannot
+        // stall the runtime serving scrapes
+        let scan_state = state.clone();
+        let span = Span::current();
+        let scan = sandbox::spawn_blocking(move || {
+            let results = span.in_scope(|| {
+                scan_proc(
+                    &scan_state,
+                    &previous,
+                    cycle,
+                    limits.as_ref(),
+                    &scan_guard,
+                    &counts,
+                    start,
+                )
+            });
+            (results, counts, scan_guard)
+        });
+        match scan.await {
+            Ok(scan) => scan,
+            Err(e) => {
+                error!("Scan task failed: {}", e);
+                let err = CollectError::ScanAborted(format!("scan task failed: {}", e));
+                record_collect_error(state, "scan", &err);
+                state.health_stats.record_scan_failure();
+                {
+                    let mut cache = state.cache.write().await;
+                    cache.is_updating = false;
+                    state.cache_updating.set(0.0);
+                }
+                state.cache_ready.notify_waiters();
+                return Err(err);
+            }
+        }
+    };
+
+    if scan_guard.was_truncated() {
+        warn!(
+            "Exporter RSS exceeded self_limits.max_rss_mb during scan, snapshot truncated to {} processes",
+            results.len()
+        );
+        record_self_limit_event(state, "scan_truncated");
+    }
+
+    let final_included = counts.included.load(Ordering::Relaxed);
+    let final_skipped = counts.skipped.load(Ordering::Relaxed);
+    let final_denied = counts.denied.load(Ordering::Relaxed);
+    let final_hidden = counts.hidden.load(Ordering::Relaxed);
+    state
+        .zombie_processes
+        .set(counts.zombie.load(Ordering::Relaxed) as f64);
+
+    if final_denied > 0 {
+        debug!(
+            "{} processes skipped: permission denied reading memory maps{}",
+            final_denied,
+            if CAPABILITIES.hidepid() {
+                " (/proc mounted with hidepid)"
+            } else {
+                ""
+            }
+        );
+    }
+    if final_hidden > 0 {
+        debug!(
+            "{} processes of other users skipped: hidden by hidepid",
+            final_hidden
+        );
+    }
+
+    debug!(
+        "Process filtering completed: {} included, {} skipped, {} memory values reused",
+        final_included,
+        final_skipped,
+        counts.reused.load(Ordering::Relaxed)
+    );
+
+    if results.is_empty() {
+        warn!("No processes matched filters after sorting");
+    }
+
+    let classify_span = info_span!("classify", processes = results.len()).entered();
+    let rule_stats = rule_stats(results.iter().map(|p| p.classify_name()));
+    state.unclassified.record_scan(
+        results
+            .iter()
+            .filter(|p| !SUBGROUPS.contains_key(p.classify_name())),
+    );
+    if state.config.enable_rule_hit_metrics.unwrap_or(false) {
+        state.rule_hits.reset();
+        for (rule, hits) in &rule_stats.hits {
+            let (group, subgroup) = classify_process_raw(rule);
+            state
+                .rule_hits
+                .with_label_values(&[group.as_ref(), subgroup.as_ref(), rule.as_ref()])
+                .set(*hits as f64);
+        }
+    }
+    drop(classify_span);
+
+    // Update cache with new data
+    {
+        let mut cache = state.cache.write().await;
+        // Keep the previous snapshot for rate computation at scrape time
+        let previous = std::mem::take(&mut cache.processes);
+        // The first scan has nothing to compare to, a truncated one would
+        // count the missing processes as exits
+        if cache.last_updated.is_some() && !scan_guard.was_truncated() {
+            record_process_churn(state, &previous, &results);
+        }
+        cache.previous_processes = previous;
+        // After a warm start, rates are computed against the restored scan
+        cache.previous_updated = cache.last_updated.or(cache.restored_updated.take());
+        for p in &results {
+            cache.processes.insert(p.key(), p.clone());
+        }
+        // Exited processes leave the CPU cache; a truncated scan did not
+        // see all survivors
+        if !scan_guard.was_truncated() {
+            state
+                .cpu_cache
+                .write()
+                .expect("cpu_cache write lock poisoned")
+                .retain(|key, _| cache.processes.contains_key(key));
+            // Threads not read in this scan exited, or their process did
+            state
+                .thread_cpu_cache
+                .write()
+                .expect("thread_cpu_cache write lock poisoned")
+                .retain(|_, entry| entry.last_updated >= start);
+        }
+
+        cache.scan_cycle += 1;
+        cache.rule_stats = rule_stats;
+        cache.cgroups = cgroups;
+        cache.update_duration_seconds = start.elapsed().as_secs_f64();
+        cache.update_success = true;
+        cache.last_updated = Some(start);
+        cache.last_updated_unix_ms = Some(start_unix_ms);
+        cache.is_updating = false;
+        cache.restored = false;
+        cache.initializing = false;
+
+        state.cache_updating.set(0.0);
+        state.cache_restored.set(0.0);
+        state.initializing.set(0.0);
+    }
+
+    state.cache_ready.notify_waiters();
+
+    if state.config.short_lived.is_some() {
+        record_short_lived(state);
+    }
+
+    if history_enabled(&state.config) {
+        let point = HistoryPoint::from_processes(
+            chrono::Utc::now().timestamp_millis(),
+            &results,
+            &state.config,
+        );
+        // SQLite inserts and compaction block on disk IO
+        let history_state = state.clone();
+        let recorded = sandbox::spawn_blocking(move || {
+            history_state.history.record(point);
+            history_state.history.storage_bytes()
+        })
+        .await;
+        if let Ok(Some(bytes)) = recorded {
+            state
+                .history_db_bytes
+                .with_label_values::<&str>(&[])
+                .set(bytes as f64);
+        }
+    }
+
+    // Count unique subgroups
+    let mut used_subgroups_set: HashSet<(Arc<str>, Arc<str>)> = HashSet::new();
+    for p in &results {
+        let (group, subgroup) = classify_process_raw(p.classify_name());
+        used_subgroups_set.insert((group, subgroup));
+    }
+    let subgroups_count = used_subgroups_set.len() as u64;
+
+    let scanned = results.len() as u64;
+    let scan_duration = start.elapsed().as_secs_f64();
+    state
+        .health_stats
+        .record_scan(scanned, scan_duration, scan_duration);
+
+    state.health_stats.record_scan_success();
+    state.health_stats.record_used_subgroups(subgroups_count);
+    state.health_stats.record_cache_size(scanned);
+    state.health_stats.update_last_scan_time();
+
+    // Update buffer usage
+    let io_usage_kb = MAX_IO_BUFFER_BYTES.load(Ordering::Relaxed).div_ceil(1024);
+    let smaps_usage_kb = MAX_SMAPS_BUFFER_BYTES
+        .load(Ordering::Relaxed)
+        .div_ceil(1024);
+    let smaps_rollup_usage_kb = MAX_SMAPS_ROLLUP_BUFFER_BYTES
+        .load(Ordering::Relaxed)
+        .div_ceil(1024);
+
+    state.health_state.update_io_buffer_kb(io_usage_kb as usize);
+    state
+        .health_state
+        .update_smaps_buffer_kb(smaps_usage_kb as usize);
+    state
+        .health_state
+        .update_smaps_rollup_buffer_kb(smaps_rollup_usage_kb as usize);
+
+    let (exporter_mem_mb, exporter_cpu_pct) = read_self_resources();
+    state
+        .health_stats
+        .record_exporter_resources(exporter_mem_mb, exporter_cpu_pct);
+
+    let usage = read_self_usage();
+    state.self_rss_bytes.set(usage.rss_bytes as f64);
+    // The counter follows the kernel's CPU time, which never decreases
+    let cpu_delta = usage.cpu_seconds - state.self_cpu_seconds.get();
+    if cpu_delta > 0.0 {
+        state.self_cpu_seconds.inc_by(cpu_delta);
+    }
+    state.self_open_fds.set(usage.open_fds as f64);
+    state.self_threads.set(usage.threads as f64);
+
+    info!(
+        "Cache update completed: {} processes (subgroup filters applied at scrape), {} total scanned, {:.2}ms",
+        results.len(),
+        final_included + final_skipped,
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::generate::TestProcess;
+
+    #[test]
+    fn test_memory_scan_deferred() {
+        assert!(!memory_scan_deferred(1, 5, 42));
+
+        // Every process is read exactly once per `every` cycles
+        for pid in [1u32, 2, 3, 1000] {
+            let reads = (0..10)
+                .filter(|cycle| !memory_scan_deferred(5, *cycle, pid))
+                .count();
+            assert_eq!(reads, 2);
+        }
+
+        // Different PIDs are read in different cycles
+        assert_ne!(memory_scan_deferred(3, 0, 3), memory_scan_deferred(3, 0, 4));
+    }
+
+    #[test]
+    fn test_process_churn() {
+        let process = |pid: u32, start_time_ticks: u64| ProcMem {
+            start_time_ticks,
+            ..ProcMem::from(TestProcess {
+                pid,
+                name: "test".to_string(),
+                group: String::new(),
+                subgroup: String::new(),
+                rss: 0,
+                pss: 0,
+                uss: 0,
+                cpu_percent: 0.0,
+                cpu_time_seconds: 0.0,
+            })
+        };
+        let previous: HashMap<ProcKey, ProcMem> = [process(1, 10), process(2, 20), process(3, 30)]
+            .into_iter()
+            .map(|p| (p.key(), p))
+            .collect();
+        // 2 exited, 3 was reused by a new process, 4 started
+        let current = [process(1, 10), process(3, 35), process(4, 40)];
+
+        let (started, exited) = process_churn(&previous, &current);
+        let mut started: Vec<_> = started
+            .iter()
+            .map(|p| (p.pid, p.start_time_ticks))
+            .collect();
+        let mut exited: Vec<_> = exited.iter().map(|p| (p.pid, p.start_time_ticks)).collect();
+        started.sort_unstable();
+        exited.sort_unstable();
+        assert_eq!(started, [(3, 35), (4, 40)]);
+        assert_eq!(exited, [(2, 20), (3, 30)]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Baseline comparison for the test command.
+//!
+//! A baseline records the outcome of one full collection: processes per
+//! subgroup, memory totals and the set of exported metric families. Running
+//! `test --baseline` against a fixed test data file turns classifier and
+//! parser changes into a reviewable diff instead of a surprise in production.
+
+use ahash::AHashMap as HashMap;
+use prometheus::Registry;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock as StdRwLock;
+use std::time::Instant;
+
+use crate::aggregate::Classified;
+use crate::cache::{ProcKey, ProcMem};
+use crate::commands::generate::load_test_data_from_file;
+use crate::config::Config;
+use crate::metrics::MemoryMetrics;
+use crate::pipeline::Pipeline;
+use crate::privacy::{excluded_by_owner, resolve_users};
+use crate::process::{
+    classify_process_with_config, collect_proc_entries, parse_memory_for_process, read_proc_stat,
+    read_process_name, should_include_process, update_cpu_stat, BufferConfig, CpuEntry,
+    MaxProcessesBy, CLK_TCK,
+};
+
+/// Outcome of one collection, as stored in a baseline file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    /// Exported processes per "group/subgroup"
+    pub subgroup_counts: BTreeMap<String, usize>,
+    pub total_rss_bytes: u64,
+    pub total_pss_bytes: u64,
+    pub total_uss_bytes: u64,
+    /// Names of all non-empty metric families
+    pub metric_families: BTreeSet<String>,
+}
+
+/// Allowed deviations from a baseline.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerances {
+    /// Absolute difference allowed per subgroup count
+    pub count: usize,
+    /// Relative difference allowed for memory totals, in percent
+    pub memory_percent: f64,
+}
+
+/// Baseline options of the test command.
+#[derive(Debug, Clone)]
+pub struct BaselineOptions {
+    /// Baseline file to compare against
+    pub compare: Option<PathBuf>,
+    /// Baseline file to write
+    pub write: Option<PathBuf>,
+    pub tolerances: Tolerances,
+}
+
+/// Collects processes once (from test data if configured, else /proc),
+/// applying the include/exclude filters and `min_uss_kb`.
+///
+/// CPU percent is computed against the previous call with the same `cpu_cache`;
+/// it is zero on the first call.
+pub fn collect_processes(
+    config: &Config,
+    buffer_config: &BufferConfig,
+    cpu_cache: &StdRwLock<HashMap<ProcKey, CpuEntry>>,
+) -> Result<Vec<ProcMem>, Box<dyn std::error::Error>> {
+    let min_uss_bytes = config.min_uss_kb.unwrap_or(0) * 1024;
+    let excluded_uids = resolve_users(config.exclude_users.as_deref().unwrap_or_default())?;
+
+    let processes: Vec<ProcMem> = match &config.test_data_file {
+        Some(path) => load_test_data_from_file(path)?
+            .processes
+            .into_iter()
+            .map(ProcMem::from)
+            .collect(),
+        None => collect_proc_entries(
+            "/proc",
+            config.max_processes,
+            MaxProcessesBy::from_config(config),
+        )
+        .iter()
+        .filter(|entry| !excluded_by_owner(&entry.proc_path, &excluded_uids))
+        .filter_map(|entry| {
+            let name = read_process_name(&entry.proc_path)?;
+            let memory = parse_memory_for_process(&entry.proc_path, buffer_config).ok()?;
+            let stat = read_proc_stat(&entry.proc_path).unwrap_or_default();
+            let key = ProcKey {
+                pid: entry.pid,
+                start_time_ticks: stat.start_time,
+            };
+            let cpu = update_cpu_stat(key, stat.cpu_ticks() as f64 / *CLK_TCK, cpu_cache);
+            Some(ProcMem {
+                pid: entry.pid,
+                name: name.into(),
+                rss: memory.rss,
+                pss: memory.pss,
+                uss: memory.uss,
+                swap: memory.swap,
+                locked: memory.locked,
+                anonymous: memory.anonymous,
+                shmem: None,
+                cpu_percent: cpu.cpu_percent as f32,
+                cpu_time_seconds: cpu.cpu_time_seconds as f32,
+                start_time_ticks: stat.start_time,
+                cpu_ticks: stat.cpu_ticks(),
+                guest_ticks: stat.guest_time,
+                minor_faults: stat.minor_faults,
+                major_faults: stat.major_faults,
+                pgrp: stat.pgrp.max(0) as u32,
+                session: stat.session.max(0) as u32,
+                nice: stat.nice,
+                sched_policy: stat.policy,
+                state: stat.state,
+                io_priority: None,
+                privileges: None,
+                delays: None,
+                schedstat: None,
+                numa: None,
+                wchan: None,
+                read_bytes: None,
+                write_bytes: None,
+                read_syscalls: None,
+                write_syscalls: None,
+                memory_updated: Instant::now(),
+                extra_labels: None,
+                environ: None,
+                classify_as: None,
+                memory_limit: None,
+                threads: None,
+            })
+        })
+        .collect(),
+    };
+
+    Ok(processes
+        .into_iter()
+        .filter(|p| should_include_process(&p.name, config) && p.uss >= min_uss_bytes)
+        .collect())
+}
+
+/// Collects processes once and summarizes them like the exporter would
+/// export them.
+pub fn collect_baseline(
+    config: &Config,
+    buffer_config: &BufferConfig,
+) -> Result<Baseline, Box<dyn std::error::Error>> {
+    let processes = collect_processes(config, buffer_config, &StdRwLock::default())?;
+
+    let mut baseline = Baseline::default();
+    for p in &processes {
+        if let Some((group, subgroup)) = classify_process_with_config(p.classify_name(), config) {
+            *baseline
+                .subgroup_counts
+                .entry(format!("{}/{}", group, subgroup))
+                .or_default() += 1;
+            baseline.total_rss_bytes += p.rss;
+            baseline.total_pss_bytes += p.pss;
+            baseline.total_uss_bytes += p.uss;
+        }
+    }
+
+    let registry = Registry::new();
+    let metrics = MemoryMetrics::new(&registry, &[], config.stable_topn.unwrap_or(false))?;
+    let rates = HashMap::new();
+    Pipeline::new()
+        .with_aggregation(config, true, None)
+        .aggregate(
+            &mut Classified::new(&processes, &rates, config, "0"),
+            &metrics,
+        );
+    baseline.metric_families = registry
+        .gather()
+        .iter()
+        .filter(|family| !family.get_metric().is_empty())
+        .map(|family| family.name().to_string())
+        .collect();
+
+    Ok(baseline)
+}
+
+/// Reads a baseline file.
+pub fn read_baseline(path: &Path) -> Result<Baseline, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read baseline {}: {}", path.display(), e))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Writes a baseline file.
+pub fn write_baseline(path: &Path, baseline: &Baseline) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(path, serde_json::to_string_pretty(baseline)? + "\n")?;
+    Ok(())
+}
+
+/// Returns one line per deviation beyond the tolerances, empty if none.
+pub fn compare_baseline(expected: &Baseline, actual: &Baseline, tol: Tolerances) -> Vec<String> {
+    let mut diffs = Vec::new();
+
+    let subgroups: BTreeSet<&String> = expected
+        .subgroup_counts
+        .keys()
+        .chain(actual.subgroup_counts.keys())
+        .collect();
+    for subgroup in subgroups {
+        let before = expected.subgroup_counts.get(subgroup).copied().unwrap_or(0);
+        let after = actual.subgroup_counts.get(subgroup).copied().unwrap_or(0);
+        if before.abs_diff(after) > tol.count {
+            diffs.push(format!("subgroup {}: {} -> {}", subgroup, before, after));
+        }
+    }
+
+    let mut check_total = |name: &str, before: u64, after: u64| {
+        let allowed = before as f64 * tol.memory_percent / 100.0;
+        if before.abs_diff(after) as f64 > allowed {
+            diffs.push(format!("{}: {} -> {}", name, before, after));
+        }
+    };
+    check_total(
+        "total_rss_bytes",
+        expected.total_rss_bytes,
+        actual.total_rss_bytes,
+    );
+    check_total(
+        "total_pss_bytes",
+        expected.total_pss_bytes,
+        actual.total_pss_bytes,
+    );
+    check_total(
+        "total_uss_bytes",
+        expected.total_uss_bytes,
+        actual.total_uss_bytes,
+    );
+
+    for family in expected.metric_families.difference(&actual.metric_families) {
+        diffs.push(format!("metric family removed: {}", family));
+    }
+    for family in actual.metric_families.difference(&expected.metric_families) {
+        diffs.push(format!("metric family added: {}", family));
+    }
+
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline(counts: &[(&str, usize)], rss: u64, families: &[&str]) -> Baseline {
+        Baseline {
+            subgroup_counts: counts.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+            total_rss_bytes: rss,
+            total_pss_bytes: rss,
+            total_uss_bytes: rss,
+            metric_families: families.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_compare_baseline() {
+        let tol = Tolerances {
+            count: 1,
+            memory_percent: 5.0,
+        };
+        let expected = baseline(&[("db/postgres", 4), ("web/nginx", 2)], 1000, &["a", "b"]);
+
+        let within = baseline(&[("db/postgres", 5), ("web/nginx", 2)], 1040, &["a", "b"]);
+        assert!(compare_baseline(&expected, &within, tol).is_empty());
+
+        let beyond = baseline(&[("db/postgres", 4), ("other/other", 3)], 1100, &["a", "c"]);
+        let diffs = compare_baseline(&expected, &beyond, tol);
+        assert_eq!(
+            diffs,
+            vec![
+                "subgroup other/other: 0 -> 3",
+                "subgroup web/nginx: 2 -> 0",
+                "total_rss_bytes: 1000 -> 1100",
+                "total_pss_bytes: 1000 -> 1100",
+                "total_uss_bytes: 1000 -> 1100",
+                "metric family removed: b",
+                "metric family added: c",
+            ]
+        );
+    }
+}
@@ -5,7 +5,10 @@
 use std::path::Path;
 
 use crate::config::{validate_effective_config, Config};
-use crate::process::{collect_proc_entries, parse_memory_for_process, BufferConfig, SUBGROUPS};
+use crate::process::{
+    collect_proc_entries, parse_memory_for_process, probe_capabilities, BufferConfig,
+    MaxProcessesBy, MemoryValues, SUBGROUPS,
+};
 
 /// Validates system requirements and configuration.
 pub fn command_check(
@@ -26,13 +29,28 @@ pub fn command_check(
             println!("   ✅ /proc filesystem accessible");
 
             // Check if we can read process directories
-            let proc_entries = collect_proc_entries("/proc", Some(5));
+            let proc_entries = collect_proc_entries("/proc", Some(5), MaxProcessesBy::Pid);
             if proc_entries.is_empty() {
                 println!("   ❌ Cannot read any process entries from /proc");
                 all_ok = false;
             } else {
                 println!("   ✅ Can read {} process entries", proc_entries.len());
             }
+
+            let capabilities = probe_capabilities("/proc");
+            if capabilities.own_processes_only() {
+                println!(
+                    "   ⚠️  /proc is mounted with hidepid, processes of other users are hidden"
+                );
+                println!(
+                    "      Only the current user's processes and system metrics will be exported;"
+                );
+                println!(
+                    "      run as root or add the user to the hidepid gid= group for full coverage"
+                );
+            } else if capabilities.hidepid {
+                println!("   ✅ /proc is mounted with hidepid, but all processes are visible");
+            }
         } else {
             println!("   ❌ /proc filesystem not found");
             all_ok = false;
@@ -62,7 +80,7 @@ pub fn command_check(
         };
 
         match parse_memory_for_process(&test_path, &buffer_config) {
-            Ok((rss, pss, uss)) => {
+            Ok(MemoryValues { rss, pss, uss, .. }) => {
                 println!(
                     "   ✅ Memory parsing successful: RSS={}MB, PSS={}MB, USS={}MB",
                     rss / 1024 / 1024,
@@ -1,6 +1,7 @@
 //! Config command implementation.
 //!
-//! Generates configuration files in various formats.
+//! Generates configuration files in various formats and the JSON Schema
+//! describing them.
 
 use std::fs;
 use std::path::PathBuf;
@@ -42,6 +43,22 @@ pub fn command_config(
     Ok(())
 }
 
+/// Writes the JSON Schema of the configuration file.
+pub fn command_config_schema(output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = schemars::schema_for!(Config);
+    let content = serde_json::to_string_pretty(&schema)? + "\n";
+
+    match output {
+        Some(path) => {
+            fs::write(&path, content)?;
+            println!("✅ Configuration schema written to: {}", path.display());
+        }
+        None => print!("{}", content),
+    }
+
+    Ok(())
+}
+
 /// Adds comments to YAML configuration.
 fn add_config_comments(yaml: String) -> String {
     let comments = r#"# Herakles Process Memory Exporter Configuration
@@ -50,19 +67,37 @@ fn add_config_comments(yaml: String) -> String {
 # Server Configuration
 # --------------------
 # bind: "0.0.0.0"              # Bind IP (0.0.0.0 = all interfaces)
-# port: 9215                   # HTTP port
+# port: 9215                   # HTTP port (0 = OS-assigned, see ready_file)
+# ready_file: null             # Write the listening address here once serving
+# pid_file: null               # Lock file with the PID; refuses a second instance
 #
 # Metrics Collection
 # ------------------
 # min_uss_kb: 0                # Minimum USS in KB to include process
 # include_names: null          # Include only processes matching these names
 # exclude_names: null          # Exclude processes matching these names
+# exclude_users: null          # Exclude processes owned by these users (names or UIDs)
+# hide_monitoring_processes: false # Keep the exporter and monitoring agents out of
+#                              # per-process and Top-N series, not out of sums
+# monitoring_processes: null   # Names of monitoring agents (null = prometheus,
+#                              # grafana-agent, alloy, node_exporter, ...)
 # parallelism: null            # Parallel threads (null = auto)
+# scan_autotune:               # Adjust scan threads between scans (excludes parallelism)
+#   min_threads: 1
+#   max_threads: null          # Upper bound and starting value (null = CPUs)
 # max_processes: null          # Maximum processes to scan
+# max_processes_by: pid        # Which ones max_processes keeps: pid (lowest) | rss (largest)
 #
 # Performance Tuning
 # ------------------
 # cache_ttl: 30                # Cache metrics for N seconds
+# collection_mode: background  # "background" or "on_scrape" (scan inside /metrics,
+#                              # reusing snapshots younger than cache_ttl)
+# cache_snapshot: null         # e.g. /var/lib/herakles/cache.json, saved on shutdown
+#                              # and served at startup until the first scan completes
+# cache_snapshot_max_age_seconds: 3600 # Older snapshots are not restored
+# startup_scan_timeout_seconds: null # Serve without process metrics once the initial
+#                              # scan takes longer (null = wait for it)
 # io_buffer_kb: 256            # Buffer size for generic /proc readers
 # smaps_buffer_kb: 512         # Buffer size for smaps parsing
 # smaps_rollup_buffer_kb: 256  # Buffer size for smaps_rollup parsing
@@ -73,12 +108,15 @@ fn add_config_comments(yaml: String) -> String {
 # enable_telemetry: true       # Enable internal metrics
 # enable_default_collectors: true # Enable generic collectors
 # enable_pprof: false          # Enable /debug/pprof endpoints
+# admin_token: null            # Bearer token enabling /-/loglevel and /-/selftest (null = disabled)
+# audit_log: null              # JSON lines audit log of admin requests (null = disabled)
 #
 # Logging
 # -------
 # log_level: "info"            # off, error, warn, info, debug, trace
 # enable_file_logging: false   # Enable file logging
 # log_file: null               # Log file path (null = stderr)
+# diagnostics_dump: "log"      # SIGUSR1 state dump: "log" or "file" (written to $TMPDIR)
 #
 # Classification / Search Engine
 # ------------------------------
@@ -88,6 +126,18 @@ fn add_config_comments(yaml: String) -> String {
 # disable_others: false        # Skip 'other/unknown' processes completely
 # top_n_subgroup: 3          # Top-N processes per subgroup (non-"other" groups)
 # top_n_others: 10           # Top-N processes for "other" group
+# per_process_metrics: true    # false = only subgroup sums, Top-N and system metrics
+# stable_topn: false           # Top-N labelled by rank only, pid/name in top_info
+# top_n_hysteresis_scans: 1    # Scans inside/outside the Top-N before a process joins/leaves it
+# max_exported_processes: null # Cap per-process series across all groups by USS,
+#                              # capped processes still count in aggregates
+# min_uss_percent_of_subgroup: null # Export only processes above this share
+#                              # of their subgroup's USS (e.g. 5.0)
+# refresh_every: null          # Re-read memory only every Nth scan per group or
+#                              # group/subgroup (e.g. {other: 10, "system/kworker": 5})
+# group_labels: null           # Static labels on the series of a group or group/subgroup
+#                              # (e.g. {db: {team: dba}, "db/mysql": {team: legacy}})
+# owner_label: team            # group_labels label naming the owner on /api/v1/owners
 #
 # Metrics Enable Flags
 # --------------------
@@ -95,12 +145,166 @@ fn add_config_comments(yaml: String) -> String {
 # enable_pss: true             # Export PSS metrics
 # enable_uss: true             # Export USS metrics
 # enable_cpu: true             # Export CPU metrics
+# enable_rates: false          # Export *_per_second rates (CPU, IO, faults) between scans
+# enable_rule_hit_metrics: false # Export per-rule classification hit counts
+# enable_priority_metrics: false # Export nice, scheduling policy and I/O priority per process
+# enable_privilege_metrics: false # Count root and fully privileged processes per subgroup
+# enable_delay_metrics: false  # Export block IO and swap-in delays per process (taskstats, CAP_NET_ADMIN)
+# enable_io_metrics: false     # Export storage bytes and read/write syscalls per process (/proc/<pid>/io)
+# enable_anon_file_metrics: false # Export anonymous and file-backed RSS per process
+# enable_shmem_metrics: false  # Export resident shared memory per process (/proc/<pid>/status)
+# enable_schedstat: false      # Export run and runqueue wait time per process (/proc/<pid>/schedstat)
+# enable_numa: false           # Export resident memory per NUMA node per process (/proc/<pid>/numa_maps, expensive)
+# per_thread: false            # Export CPU of the busiest threads per process (/proc/<pid>/task)
+# top_n_threads: 5             # Threads exported per process with per_thread
+# enable_start_time_metrics: false # Export start time and age per process
+# enable_wchan_metrics: false  # Export state and wait channel of Top-N processes
+# system_collectors: null      # System collectors to run (loadavg, meminfo, cpustat, kernel, limits, zram, zfs), all if null
+# session_aggregation: off     # Sum USS per "session" or process group ("pgrp")
+# sample_timestamps: false     # Stamp process samples with the last cache update time
+# scan_id_label: false         # Label group/top/session sums with the scan they come from
+# proc_id_label: false         # Label per-process series with PID and start time (proc_id)
+# metric_schema: v1            # Metric names of /metrics (v1, v2); /metrics/v1 and /metrics/v2 are always served
+# scrape_timeout_offset_seconds: 0.5  # Safety margin below the scraper's timeout
+# max_exposition_bytes: 4194304 # Drop families above this /metrics size (unlimited by default)
+# exposition_drop_order: [process, top]  # Which families go first; aggregates and system are kept
+#
+# Scan History and Grafana JSON Datasource
+# ----------------------------------------
+# enable_history: false        # Keep scan history and serve /api/v1/query_range
+# enable_grafana: false        # Serve /grafana/search and /grafana/query
+# history_points: 120          # Scans kept in memory for range queries
+# history_db: /var/lib/herakles/history.db # Persist scans in SQLite (feature "sqlite")
+# history_retention_hours: 168 # Hours of scans kept in history_db
+# history_downsampling:        # Average older scans in history_db into intervals
+#   raw_minutes: 60            # Minutes during which every scan is kept
+#   interval_minutes: 5        # Minutes averaged into one point afterwards
 #
 # TLS/SSL Configuration
 # ---------------------
 # enable_tls: false            # Enable HTTPS (default: false)
 # tls_cert_path: null          # Path to TLS certificate (PEM format)
 # tls_key_path: null           # Path to TLS private key (PEM format)
+#
+# Textfile Output
+# ---------------
+# textfile_output: null        # .prom file for node_exporter's textfile collector
+# enable_http: true            # Serve HTTP endpoints (false = textfile only)
+#
+# Scan Windows (cache_ttl by time of day, first match wins)
+# ------------
+# scan_windows:
+#   - start: "08:00"           # Local time, inclusive
+#     end: "18:00"             # Exclusive; before start wraps past midnight
+#     cache_ttl: 30            # Refresh interval inside the window
+#     days: [mon, tue, wed, thu, fri]  # Optional, all days if unset
+#   - start: "20:00"
+#     end: "06:00"
+#     cache_ttl: 300
+#
+# Self Resource Limits
+# --------------------
+# self_limits:
+#   max_rss_mb: null           # Skip/truncate scans above this exporter RSS (MB)
+#   nice: null                 # Nice value for scan worker threads (-20..19)
+#   max_scan_cpu_percent: null # CPU duty cycle per scan thread (0-100)
+#   rlimit_as_mb: null         # RLIMIT_AS for the exporter process (MB)
+#
+# Sandbox (Linux, applied after initialization)
+# -------
+# sandbox:
+#   landlock: false            # Restrict filesystem access to /proc and configured paths
+#   seccomp: false             # Restrict system calls to an allowlist
+#   seccomp_action: errno      # On violation: "errno" (EPERM), "log" or "kill"
+#
+# Label extractors (extra labels on per-process series)
+# ----------------------------------------------------
+# label_extractors:
+#   - pattern: "^postgres: \\S+ (?P<db>\\w+)"  # Regex with named groups
+#     labels: ["db"]           # Groups exported as labels
+#     source: cmdline          # Match "cmdline" (default) or "name"
+#
+# Environment (opt-in, reads /proc/<pid>/environ; other users need CAP_SYS_PTRACE)
+# -----------
+# environ:
+#   variables: ["SERVICE_NAME", "POD_NAME"]  # Whitelist, everything else is discarded
+#   labels: true               # Export as lowercase labels (service_name, pod_name)
+#   classify_by: null          # Variable classified by the subgroup rules instead of the name
+#
+# Cgroups (cgroup v2 memory accounting per cgroup)
+# -------------------------------------------------
+# cgroups:
+#   root: /sys/fs/cgroup        # Mount point of the unified hierarchy
+#   max_depth: 2               # Levels below the root to walk
+#   stat_fields: null          # memory.stat fields (default anon, file, kernel, slab, ...)
+#   process_limits: false      # Per-process proximity to the cgroup memory.max
+#   unit_limits: false         # Per-unit usage against MemoryHigh/MemoryMax
+#
+# Short-lived processes (started and exited between two scans)
+# ------------------------------------------------------------
+# short_lived:
+#   poll_interval_ms: 500      # Interval of the /proc listing that detects new processes
+#
+# Privacy
+# -------
+# name_hash: false             # Export process names and extracted values as stable hashes
+# name_hash_salt: null         # Secret mixed into the hashes
+#
+# Tenants (served at /metrics/<tenant>)
+# -------------------------------------
+# tenants:
+#   payments:
+#     search_mode: include     # include_names or search_mode is required
+#     search_subgroups: ["postgres"]
+#     include_names: null
+#     exclude_names: null
+#     disable_others: null
+#     labels:                  # Static labels added to every tenant series
+#       team: payments
+#
+# Federation (merge downstream exporters into /metrics)
+# -----------------------------------------------------
+# federation:
+#   interval_seconds: null     # Downstream scrape interval (null = cache_ttl)
+#   timeout_seconds: 5         # Timeout per downstream scrape
+#   source_label: "source"     # Label carrying the target name
+#   targets:
+#     - name: vm1
+#       url: "http://10.0.0.11:9215/metrics"
+#       labels: null           # Extra static labels for this target
+#
+# Self-Registration (Consul or etcd v3, deregistered on shutdown)
+# ---------------------------------------------------------------
+# registration:
+#   backend: consul            # "consul" or "etcd"
+#   address: "http://127.0.0.1:8500"  # Consul agent or etcd v3 gateway
+#   service_name: "herakles-proc-mem-exporter"
+#   service_id: null           # Default "<service_name>-<host>-<port>"
+#   advertise_address: null    # Default bind address, or host name for 0.0.0.0
+#   tags: []
+#   ttl_seconds: 30            # Check/lease TTL, refreshed every third of it
+#   token: null                # Consul ACL token or etcd auth token
+#   key_prefix: "/services/herakles-proc-mem-exporter/"  # etcd only
+#
+# mDNS Announcement (requires a build with feature "mdns")
+# --------------------------------------------------------
+# mdns:
+#   instance_name: null        # Default host name
+#   advertise_address: null    # A record, default bind or outgoing interface address
+#
+# Overlap Probe (checks other exporters once at startup)
+# ------------------------------------------------------
+# overlap_probe:
+#   urls: ["http://127.0.0.1:9100/metrics"]  # e.g. node_exporter, process-exporter
+#   action: warn               # "warn" or "disable" overlapping system collectors
+#   timeout_seconds: 5
+#
+# OpenTelemetry Tracing (requires a build with feature "otlp")
+# ------------------------------------------------------------
+# otlp:
+#   endpoint: "http://localhost:4318/v1/traces"  # OTLP/HTTP traces endpoint
+#   sample_ratio: 1.0          # Fraction of traces exported (0.0-1.0)
+#   service_name: "herakles-proc-mem-exporter"
 "#;
 
     format!("{comments}\n{yaml}")
@@ -8,11 +8,14 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 use tracing::{debug, info};
 
+use herakles_proc_mem_exporter::CollectError;
+
 use crate::cache::ProcMem;
 use crate::config::Config;
-use crate::process::{classify_process_with_config, SUBGROUPS};
+use crate::process::{classify_process_with_config, CLK_TCK, SUBGROUPS};
 
 /// Test process entry for JSON serialization.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,28 +44,53 @@ impl From<TestProcess> for ProcMem {
     fn from(tp: TestProcess) -> Self {
         ProcMem {
             pid: tp.pid,
-            name: tp.name,
+            name: tp.name.into(),
             rss: tp.rss,
             pss: tp.pss,
             uss: tp.uss,
+            swap: 0,
+            locked: 0,
+            anonymous: 0,
+            shmem: None,
             cpu_percent: tp.cpu_percent as f32,
             cpu_time_seconds: tp.cpu_time_seconds as f32,
+            start_time_ticks: 0,
+            cpu_ticks: (tp.cpu_time_seconds * *CLK_TCK) as u64,
+            guest_ticks: 0,
+            minor_faults: 0,
+            major_faults: 0,
+            pgrp: 0,
+            session: 0,
+            nice: 0,
+            sched_policy: 0,
+            state: 'S',
+            io_priority: None,
+            privileges: None,
+            delays: None,
+            schedstat: None,
+            numa: None,
+            wchan: None,
+            read_bytes: None,
+            write_bytes: None,
+            read_syscalls: None,
+            write_syscalls: None,
+            memory_updated: Instant::now(),
+            extra_labels: None,
+            environ: None,
+            classify_as: None,
+            memory_limit: None,
+            threads: None,
         }
     }
 }
 
 /// Load test data from JSON file.
-pub fn load_test_data_from_file(path: &Path) -> Result<TestData, String> {
+pub fn load_test_data_from_file(path: &Path) -> Result<TestData, CollectError> {
     debug!("Loading test data from: {}", path.display());
 
-    if !path.exists() {
-        return Err(format!("Test data file not found: {}", path.display()));
-    }
-
-    let content =
-        fs::read_to_string(path).map_err(|e| format!("Failed to read test data file: {}", e))?;
+    let content = fs::read_to_string(path).map_err(|e| CollectError::io(path, e))?;
     let test_data: TestData = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse test data JSON: {}", e))?;
+        .map_err(|e| CollectError::parse(path, format!("invalid test data JSON: {}", e)))?;
 
     info!(
         "Loaded test data version {} from {}",
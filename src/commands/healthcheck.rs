@@ -0,0 +1,181 @@
+//! Healthcheck command implementation.
+//!
+//! Sends one GET to the local `/health` endpoint with the configured bind
+//! address, port and TLS settings, prints a one-line summary and exits with
+//! 0 when healthy and 1 otherwise, as expected by Docker `HEALTHCHECK` and
+//! Kubernetes exec probes (Docker reserves exit code 2).
+
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, DEFAULT_BIND_ADDR, DEFAULT_PORT};
+
+/// Host name used in the URL; resolved to the bind address so TLS
+/// certificates issued for `localhost` verify.
+const HEALTHCHECK_HOST: &str = "localhost";
+
+/// Address the healthcheck connects to: the bind address, or loopback when
+/// bound to all interfaces. With `port: 0` the address is read from the
+/// ready file.
+fn target_addr(cfg: &Config) -> Result<SocketAddr, String> {
+    let addr = match cfg.port.unwrap_or(DEFAULT_PORT) {
+        0 => {
+            let path = cfg
+                .ready_file
+                .as_ref()
+                .ok_or("port is 0 (OS-assigned) and no ready_file is configured")?;
+            let content = fs::read_to_string(path)
+                .map_err(|e| format!("ready file {}: {}", path.display(), e))?;
+            content
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid address in ready file {}", path.display()))?
+        }
+        port => {
+            let bind = cfg.bind.as_deref().unwrap_or(DEFAULT_BIND_ADDR);
+            let ip: IpAddr = bind
+                .parse()
+                .map_err(|_| format!("invalid bind address '{}'", bind))?;
+            SocketAddr::new(ip, port)
+        }
+    };
+    let ip = match addr.ip() {
+        IpAddr::V4(v4) if v4.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(v6) if v6.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    Ok(SocketAddr::new(ip, addr.port()))
+}
+
+fn client(
+    cfg: &Config,
+    addr: SocketAddr,
+    timeout: Duration,
+    insecure: bool,
+) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(timeout)
+        .resolve(HEALTHCHECK_HOST, addr)
+        .no_proxy();
+    if cfg.enable_tls.unwrap_or(false) {
+        if insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        } else if let Some(path) = &cfg.tls_cert_path {
+            // Trust the server's own certificate, e.g. a self-signed one
+            let pem = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            let cert =
+                reqwest::Certificate::from_pem(&pem).map_err(|e| format!("{}: {}", path, e))?;
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Checks the local /health endpoint; returns the process exit code.
+pub async fn command_healthcheck(cfg: &Config, timeout: u64, insecure: bool) -> i32 {
+    if !cfg.enable_http.unwrap_or(true) {
+        println!("❌ unhealthy: HTTP is disabled (enable_http: false)");
+        return 1;
+    }
+    if !cfg.enable_health.unwrap_or(true) {
+        println!("❌ unhealthy: /health is disabled (enable_health: false)");
+        return 1;
+    }
+
+    let scheme = if cfg.enable_tls.unwrap_or(false) {
+        "https"
+    } else {
+        "http"
+    };
+    let result = target_addr(cfg).and_then(|addr| {
+        let url = format!("{}://{}:{}/health", scheme, HEALTHCHECK_HOST, addr.port());
+        Ok((
+            client(cfg, addr, Duration::from_secs(timeout), insecure)?,
+            url,
+            addr,
+        ))
+    });
+    let (client, url, addr) = match result {
+        Ok(parts) => parts,
+        Err(e) => {
+            println!("❌ unhealthy: {}", e);
+            return 1;
+        }
+    };
+
+    let start = Instant::now();
+    let response = match client.get(&url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("❌ unreachable: {} ({}): {}", url, addr, error_chain(&e));
+            return 1;
+        }
+    };
+    let status = response.status();
+    let elapsed_ms = start.elapsed().as_millis();
+    // The first line of the body is the short cache status
+    let body = response.text().await.unwrap_or_default();
+    let message = body.lines().next().unwrap_or_default();
+
+    if status.is_success() {
+        println!(
+            "✅ healthy: {} {} in {}ms ({})",
+            status, message, elapsed_ms, url
+        );
+        0
+    } else {
+        println!(
+            "❌ unhealthy: {} {} in {}ms ({})",
+            status, message, elapsed_ms, url
+        );
+        1
+    }
+}
+
+/// An error with its sources, since reqwest's top-level message is generic.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(inner) = source {
+        message.push_str(": ");
+        message.push_str(&inner.to_string());
+        source = inner.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_addr() {
+        let mut cfg = Config::default();
+        assert_eq!(
+            target_addr(&cfg).unwrap(),
+            "127.0.0.1:9215".parse().unwrap()
+        );
+
+        cfg.bind = Some("::".to_string());
+        cfg.port = Some(9300);
+        assert_eq!(target_addr(&cfg).unwrap(), "[::1]:9300".parse().unwrap());
+
+        cfg.bind = Some("10.0.0.5".to_string());
+        assert_eq!(target_addr(&cfg).unwrap(), "10.0.0.5:9300".parse().unwrap());
+
+        cfg.bind = Some("localhost".to_string());
+        assert!(target_addr(&cfg).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let ready = dir.path().join("ready");
+        cfg.port = Some(0);
+        cfg.ready_file = Some(ready.clone());
+        assert!(target_addr(&cfg).is_err());
+        std::fs::write(&ready, "0.0.0.0:41234\n").unwrap();
+        assert_eq!(
+            target_addr(&cfg).unwrap(),
+            "127.0.0.1:41234".parse().unwrap()
+        );
+    }
+}
@@ -3,19 +3,26 @@
 //! This module provides implementations for all CLI subcommands:
 //! - `check`: System validation
 //! - `config`: Configuration file generation
-//! - `test`: Metrics collection testing
+//! - `test`: Metrics collection testing and baseline comparison
 //! - `subgroups`: Subgroup listing
+//! - `dump`: Scan snapshots for offline analysis
+//! - `healthcheck`: Container health check against the local /health
 //! - `generate`: Test data generation
 
+pub mod baseline;
 pub mod check;
 pub mod config;
+pub mod dump;
 pub mod generate;
+pub mod healthcheck;
 pub mod subgroups;
 pub mod test;
 
 // Re-export command functions
 pub use check::command_check;
-pub use config::command_config;
+pub use config::{command_config, command_config_schema};
+pub use dump::command_dump;
 pub use generate::command_generate_testdata;
+pub use healthcheck::command_healthcheck;
 pub use subgroups::command_subgroups;
 pub use test::command_test;
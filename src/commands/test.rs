@@ -5,10 +5,13 @@
 use std::time::Instant;
 
 use crate::cli::ConfigFormat;
+use crate::commands::baseline::{
+    collect_baseline, compare_baseline, read_baseline, write_baseline, BaselineOptions,
+};
 use crate::config::Config;
 use crate::process::{
     classify_process_raw, collect_proc_entries, parse_memory_for_process, read_process_name,
-    BufferConfig, CpuStat,
+    BufferConfig, CpuStat, MaxProcessesBy, MemoryValues,
 };
 
 /// Process memory metrics for test output.
@@ -27,6 +30,7 @@ pub fn command_test(
     iterations: usize,
     verbose: bool,
     _format: ConfigFormat,
+    baseline: &BaselineOptions,
     config: &Config,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("🧪 Herakles Process Memory Exporter - Test Mode");
@@ -38,11 +42,19 @@ pub fn command_test(
         smaps_rollup_kb: config.smaps_rollup_buffer_kb.unwrap_or(256),
     };
 
+    if baseline.compare.is_some() || baseline.write.is_some() {
+        return test_baseline(baseline, &buffer_config, config);
+    }
+
     for iteration in 1..=iterations {
         println!("\n🔄 Iteration {}/{}:", iteration, iterations);
 
         let start = Instant::now();
-        let entries = collect_proc_entries("/proc", config.max_processes);
+        let entries = collect_proc_entries(
+            "/proc",
+            config.max_processes,
+            MaxProcessesBy::from_config(config),
+        );
         println!("   📁 Found {} process entries", entries.len());
 
         let mut results = Vec::new();
@@ -51,7 +63,7 @@ pub fn command_test(
         for entry in entries.iter().take(10) {
             match read_process_name(&entry.proc_path) {
                 Some(name) => match parse_memory_for_process(&entry.proc_path, &buffer_config) {
-                    Ok((rss, pss, uss)) => {
+                    Ok(MemoryValues { rss, pss, uss, .. }) => {
                         let cpu = CpuStat {
                             cpu_percent: 0.0,
                             cpu_time_seconds: 0.0,
@@ -112,3 +124,42 @@ pub fn command_test(
     println!("\n✅ Test completed successfully");
     Ok(())
 }
+
+/// Runs one full collection and writes it as a baseline or compares it
+/// against an existing one.
+fn test_baseline(
+    options: &BaselineOptions,
+    buffer_config: &BufferConfig,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let actual = collect_baseline(config, buffer_config)?;
+    let total: usize = actual.subgroup_counts.values().sum();
+    println!(
+        "\n📊 Collected {} processes in {} subgroups, {} metric families",
+        total,
+        actual.subgroup_counts.len(),
+        actual.metric_families.len()
+    );
+
+    if let Some(path) = &options.write {
+        write_baseline(path, &actual)?;
+        println!("✅ Baseline written to: {}", path.display());
+        return Ok(());
+    }
+
+    let Some(path) = &options.compare else {
+        return Ok(());
+    };
+    let expected = read_baseline(path)?;
+    let diffs = compare_baseline(&expected, &actual, options.tolerances);
+    if diffs.is_empty() {
+        println!("✅ Matches baseline {}", path.display());
+        return Ok(());
+    }
+
+    println!("❌ Deviations from baseline {}:", path.display());
+    for diff in &diffs {
+        println!("   {}", diff);
+    }
+    Err(format!("{} deviations from baseline", diffs.len()).into())
+}
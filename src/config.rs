@@ -4,8 +4,16 @@
 //! and CLI arguments. It supports YAML, JSON, and TOML formats.
 
 use crate::cli::{Args, ConfigFormat};
+use crate::exposition_budget::DROP_CLASSES;
+use crate::pipeline::MetricSchema;
+use crate::process::MaxProcessesBy;
+use crate::system::SYSTEM_COLLECTORS;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::net::Ipv4Addr;
 use std::path::{Path, PathBuf};
 use tracing::info;
 
@@ -13,23 +21,78 @@ use tracing::info;
 pub const DEFAULT_BIND_ADDR: &str = "0.0.0.0";
 pub const DEFAULT_PORT: u16 = 9215;
 pub const DEFAULT_CACHE_TTL: u64 = 30;
+/// Label of `group_labels` naming the owner on /api/v1/owners.
+pub const DEFAULT_OWNER_LABEL: &str = "team";
+/// Monitoring agents hidden by `hide_monitoring_processes`, as process names.
+pub const DEFAULT_MONITORING_PROCESSES: &[&str] = &[
+    "prometheus",
+    "grafana-agent",
+    "alloy",
+    "node_exporter",
+    "vmagent",
+    "otelcol",
+    "otelcol-contrib",
+    "telegraf",
+    "datadog-agent",
+    "fluent-bit",
+];
+/// Threads exported per process by `per_thread` when `top_n_threads` is not set.
+pub const DEFAULT_TOP_N_THREADS: usize = 5;
 
 /// Enhanced configuration structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
     // Server configuration
     pub port: Option<u16>,
     pub bind: Option<String>,
+    /// Write the listening address to this file once the server accepts
+    /// connections (useful with `port: 0`)
+    #[serde(alias = "ready-file")]
+    pub ready_file: Option<PathBuf>,
+    /// Lock this file and write the PID into it; refuses to start if another
+    /// instance holds the lock
+    #[serde(alias = "pid-file")]
+    pub pid_file: Option<PathBuf>,
 
     // Metrics collection
     pub min_uss_kb: Option<u64>,
     pub include_names: Option<Vec<String>>,
     pub exclude_names: Option<Vec<String>>,
+    /// Exclude processes owned by these users (names or numeric UIDs)
+    #[serde(alias = "exclude-users")]
+    pub exclude_users: Option<Vec<String>>,
+    /// Leave the exporter and `monitoring_processes` out of per-process and
+    /// Top-N series; they still count in the sums
+    #[serde(alias = "hide-monitoring-processes")]
+    pub hide_monitoring_processes: Option<bool>,
+    /// Process names hidden by `hide_monitoring_processes`
+    #[serde(alias = "monitoring-processes")]
+    pub monitoring_processes: Option<Vec<String>>,
     pub parallelism: Option<usize>,
+    /// Adjust the scan thread count between scans instead of `parallelism`
+    #[serde(alias = "scan-autotune")]
+    pub scan_autotune: Option<ScanAutotuneConfig>,
     pub max_processes: Option<usize>,
+    /// Which processes max_processes keeps: "pid" (lowest, default) | "rss" (largest)
+    #[serde(alias = "max-processes-by")]
+    pub max_processes_by: Option<String>,
 
     // Performance tuning
     pub cache_ttl: Option<u64>,
+    /// "background" (default) | "on_scrape"
+    #[serde(alias = "collection-mode")]
+    pub collection_mode: Option<String>,
+    /// File the process snapshot is written to on shutdown and restored
+    /// from at startup, so /metrics answers before the first scan
+    #[serde(alias = "cache-snapshot")]
+    pub cache_snapshot: Option<PathBuf>,
+    /// Snapshots older than this are not restored (default 3600)
+    #[serde(alias = "cache-snapshot-max-age-seconds")]
+    pub cache_snapshot_max_age_seconds: Option<u64>,
+    /// Start serving without process metrics when the initial scan takes
+    /// longer than this; unset waits for it
+    #[serde(alias = "startup-scan-timeout-seconds")]
+    pub startup_scan_timeout_seconds: Option<u64>,
     pub io_buffer_kb: Option<usize>,
     pub smaps_buffer_kb: Option<usize>,
     pub smaps_rollup_buffer_kb: Option<usize>,
@@ -39,11 +102,21 @@ pub struct Config {
     pub enable_telemetry: Option<bool>,
     pub enable_default_collectors: Option<bool>,
     pub enable_pprof: Option<bool>,
+    /// Bearer token for admin endpoints (PUT /-/loglevel, GET /-/selftest);
+    /// unset disables them
+    #[serde(alias = "admin-token")]
+    pub admin_token: Option<String>,
+    /// Append-only JSON lines log of state-changing requests (unset = disabled)
+    #[serde(alias = "audit-log")]
+    pub audit_log: Option<PathBuf>,
 
     // Logging
     pub log_level: Option<String>,
     pub enable_file_logging: Option<bool>,
     pub log_file: Option<PathBuf>,
+    /// Destination of SIGUSR1 diagnostic dumps: "log" (default) | "file"
+    #[serde(alias = "diagnostics-dump")]
+    pub diagnostics_dump: Option<String>,
 
     // Classification / search engine
     /// "include" | "exclude" | None
@@ -64,6 +137,32 @@ pub struct Config {
     /// Top-N processes to export for "other" group
     #[serde(alias = "top-n-others")]
     pub top_n_others: Option<usize>,
+    /// Export pid-labelled per-process series; false exports only subgroup sums, Top-N and system metrics
+    #[serde(alias = "per-process-metrics")]
+    pub per_process_metrics: Option<bool>,
+    /// Drop pid and name from the Top-N labels and map ranks to processes in herakles_proc_mem_top_info
+    #[serde(alias = "stable-topn")]
+    pub stable_topn: Option<bool>,
+    /// Scans a process has to rank inside (or outside) the Top-N before it
+    /// joins (or leaves) it; 1 follows every scan
+    #[serde(alias = "top-n-hysteresis-scans")]
+    pub top_n_hysteresis_scans: Option<u32>,
+    /// Upper bound for per-process series across all groups (largest USS first)
+    #[serde(alias = "max-exported-processes")]
+    pub max_exported_processes: Option<usize>,
+    /// Export per-process series only for processes above this share of their subgroup's USS
+    #[serde(alias = "min-uss-percent-of-subgroup")]
+    pub min_uss_percent_of_subgroup: Option<f64>,
+    /// Re-read memory of a group or "group/subgroup" only every Nth scan
+    #[serde(alias = "refresh-every")]
+    pub refresh_every: Option<BTreeMap<String, u64>>,
+    /// Static labels added to the series of a group or "group/subgroup",
+    /// e.g. an owning team for alert routing
+    #[serde(alias = "group-labels")]
+    pub group_labels: Option<BTreeMap<String, BTreeMap<String, String>>>,
+    /// The `group_labels` label naming the owner of a subgroup on /api/v1/owners
+    #[serde(alias = "owner-label")]
+    pub owner_label: Option<String>,
 
     // Metrics enable flags
     #[serde(alias = "enable-rss")]
@@ -74,6 +173,109 @@ pub struct Config {
     pub enable_uss: Option<bool>,
     #[serde(alias = "enable-cpu")]
     pub enable_cpu: Option<bool>,
+    /// Export *_per_second rates computed between the last two scans
+    #[serde(alias = "enable-rates")]
+    pub enable_rates: Option<bool>,
+    /// Export nice value, scheduling policy and I/O priority per process
+    #[serde(alias = "enable-priority-metrics")]
+    pub enable_priority_metrics: Option<bool>,
+    /// Export the number of processes running as root or with full
+    /// capabilities per subgroup
+    #[serde(alias = "enable-privilege-metrics")]
+    pub enable_privilege_metrics: Option<bool>,
+    /// Export per-process block IO and swap-in delays from taskstats
+    /// (needs CAP_NET_ADMIN and kernel delay accounting)
+    #[serde(alias = "enable-delay-metrics")]
+    pub enable_delay_metrics: Option<bool>,
+    /// Export the storage read/write bytes and syscalls of /proc/<pid>/io
+    /// per process
+    #[serde(alias = "enable-io-metrics")]
+    pub enable_io_metrics: Option<bool>,
+    /// Export the RSS of each process split into anonymous and file-backed
+    /// memory
+    #[serde(alias = "enable-anon-file-metrics")]
+    pub enable_anon_file_metrics: Option<bool>,
+    /// Export the resident shared memory (tmpfs, System V) of each process
+    #[serde(alias = "enable-shmem-metrics")]
+    pub enable_shmem_metrics: Option<bool>,
+    /// Export run time, runqueue wait time and timeslices of each process
+    /// from /proc/<pid>/schedstat
+    #[serde(alias = "enable-schedstat")]
+    pub enable_schedstat: Option<bool>,
+    /// Export the resident memory of each process per NUMA node from
+    /// /proc/<pid>/numa_maps
+    #[serde(alias = "enable-numa")]
+    pub enable_numa: Option<bool>,
+    /// Export the CPU usage of the busiest threads of each multi-threaded
+    /// process from /proc/<pid>/task
+    #[serde(alias = "per-thread")]
+    pub per_thread: Option<bool>,
+    /// Threads exported per process with `per_thread` (default 5)
+    #[serde(alias = "top-n-threads")]
+    pub top_n_threads: Option<usize>,
+    /// Export the start time and age of each process as values
+    #[serde(alias = "enable-start-time-metrics")]
+    pub enable_start_time_metrics: Option<bool>,
+    /// Export the scheduler state and, in uninterruptible sleep, the wait
+    /// channel of Top-N processes
+    #[serde(alias = "enable-wchan-metrics")]
+    pub enable_wchan_metrics: Option<bool>,
+    /// Export per-rule classification hit counts of the last scan
+    #[serde(alias = "enable-rule-hit-metrics")]
+    pub enable_rule_hit_metrics: Option<bool>,
+    /// System collectors to run (loadavg, meminfo, cpustat, kernel, limits,
+    /// zram, zfs); all if unset
+    #[serde(alias = "system-collectors")]
+    pub system_collectors: Option<Vec<String>>,
+    /// Sum USS per session ("session"), process group ("pgrp") or not at all ("off")
+    #[serde(alias = "session-aggregation")]
+    pub session_aggregation: Option<String>,
+    /// Attach the time of the last cache update to the process samples on /metrics
+    #[serde(alias = "sample-timestamps")]
+    pub sample_timestamps: Option<bool>,
+    /// Add the scan sequence number as `scan_id` label to the aggregate families
+    #[serde(alias = "scan-id-label")]
+    pub scan_id_label: Option<bool>,
+    /// Add the stable process identity (PID and start time) as `proc_id`
+    /// label to the per-process families
+    #[serde(alias = "proc-id-label")]
+    pub proc_id_label: Option<bool>,
+    /// Metric names of /metrics and the tenant endpoints: "v1" (default) or
+    /// "v2"; /metrics/v1 and /metrics/v2 always serve the named schema
+    #[serde(alias = "metric-schema")]
+    pub metric_schema: Option<String>,
+    /// Seconds subtracted from the scraper's timeout (X-Prometheus-Scrape-Timeout-Seconds)
+    /// to get the time by which a scrape is answered
+    #[serde(alias = "scrape-timeout-offset-seconds")]
+    pub scrape_timeout_offset_seconds: Option<f64>,
+    /// Maximum size of the /metrics body; families are dropped above it
+    #[serde(alias = "max-exposition-bytes")]
+    pub max_exposition_bytes: Option<usize>,
+    /// Classes of families dropped above `max_exposition_bytes`, in order
+    /// ("process", "top"); aggregates and system metrics are never dropped
+    #[serde(alias = "exposition-drop-order")]
+    pub exposition_drop_order: Option<Vec<String>>,
+
+    /// Serve the Grafana JSON datasource endpoints under /grafana
+    #[serde(alias = "enable-grafana")]
+    pub enable_grafana: Option<bool>,
+    /// Keep the scan history and serve /api/v1/query_range (implied by
+    /// `enable_grafana` and `history_db`)
+    #[serde(alias = "enable-history")]
+    pub enable_history: Option<bool>,
+    /// Number of scans kept in the in-memory history
+    #[serde(alias = "history-points")]
+    pub history_points: Option<usize>,
+    /// SQLite file persisting the scan history across restarts (feature "sqlite")
+    #[serde(alias = "history-db")]
+    pub history_db: Option<PathBuf>,
+    /// Hours of scans kept in `history_db`
+    #[serde(alias = "history-retention-hours")]
+    pub history_retention_hours: Option<u64>,
+    /// Average scans in `history_db` older than the raw period into
+    /// fixed intervals
+    #[serde(alias = "history-downsampling")]
+    pub history_downsampling: Option<HistoryDownsamplingConfig>,
 
     /// Path to JSON test data file (uses synthetic data instead of /proc)
     #[serde(alias = "test-data-file")]
@@ -86,6 +288,356 @@ pub struct Config {
     pub tls_cert_path: Option<String>,
     #[serde(alias = "tls-key-path")]
     pub tls_key_path: Option<String>,
+
+    // node_exporter textfile collector output
+    /// .prom file rewritten atomically after every cache update
+    #[serde(alias = "textfile-output")]
+    pub textfile_output: Option<PathBuf>,
+    /// Serve the HTTP endpoints (disable to only write the textfile)
+    #[serde(alias = "enable-http")]
+    pub enable_http: Option<bool>,
+
+    // Time-of-day cache_ttl overrides for the background refresh
+    #[serde(alias = "scan-windows")]
+    pub scan_windows: Option<Vec<ScanWindow>>,
+
+    // Self resource limits
+    #[serde(alias = "self-limits")]
+    pub self_limits: Option<SelfLimitsConfig>,
+
+    // Landlock/seccomp sandbox applied after initialization
+    pub sandbox: Option<SandboxConfig>,
+
+    // Multi-tenant endpoints served at /metrics/<tenant>
+    pub tenants: Option<BTreeMap<String, TenantConfig>>,
+
+    // Extra per-process labels parsed from process names or command lines
+    #[serde(alias = "label-extractors")]
+    pub label_extractors: Option<Vec<LabelExtractorConfig>>,
+
+    // Opt-in labels and classification from /proc/<pid>/environ
+    pub environ: Option<EnvironConfig>,
+
+    // Per-cgroup memory accounting from the cgroup v2 hierarchy
+    pub cgroups: Option<CgroupConfig>,
+
+    // Accounting of processes that start and exit between scans
+    #[serde(alias = "short-lived")]
+    pub short_lived: Option<ShortLivedConfig>,
+
+    // Privacy: exported process names and extracted values as stable hashes
+    #[serde(alias = "name-hash")]
+    pub name_hash: Option<bool>,
+    /// Secret mixed into the hashes so names cannot be looked up by hashing guesses
+    #[serde(alias = "name-hash-salt")]
+    pub name_hash_salt: Option<String>,
+
+    // Federation of downstream exporters
+    pub federation: Option<FederationConfig>,
+
+    // Self-registration in Consul or etcd
+    pub registration: Option<RegistrationConfig>,
+
+    // mDNS announcement (requires the "mdns" build feature)
+    pub mdns: Option<MdnsConfig>,
+
+    // Startup check for other exporters exporting the same host data
+    #[serde(alias = "overlap-probe")]
+    pub overlap_probe: Option<OverlapProbeConfig>,
+
+    // OTLP export of tracing spans (requires the "otlp" build feature)
+    pub otlp: Option<OtlpConfig>,
+}
+
+/// Per-cgroup memory collection; present (even empty) enables it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct CgroupConfig {
+    /// Mount point of the unified hierarchy (default "/sys/fs/cgroup")
+    pub root: Option<PathBuf>,
+    /// Levels below the root to walk (default 2)
+    #[serde(alias = "max-depth")]
+    pub max_depth: Option<usize>,
+    /// memory.stat fields to export (default anon, file, kernel, kernel_stack,
+    /// slab, sock, shmem, file_mapped, file_dirty, file_writeback)
+    #[serde(alias = "stat-fields")]
+    pub stat_fields: Option<Vec<String>>,
+    /// Export how close each process' cgroup is to its memory.max
+    /// (default false)
+    #[serde(alias = "process-limits")]
+    pub process_limits: Option<bool>,
+    /// Export the usage of each systemd unit against its memory.high and
+    /// memory.max (default false)
+    #[serde(alias = "unit-limits")]
+    pub unit_limits: Option<bool>,
+}
+
+/// Downsampling of the SQLite history; present (even empty) enables it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct HistoryDownsamplingConfig {
+    /// Minutes during which every scan is kept (default 60)
+    #[serde(alias = "raw-minutes")]
+    pub raw_minutes: Option<u64>,
+    /// Minutes averaged into one point past the raw period (default 5)
+    #[serde(alias = "interval-minutes")]
+    pub interval_minutes: Option<u64>,
+}
+
+/// Scan thread auto-tuning; present (even empty) enables it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ScanAutotuneConfig {
+    /// Lower bound of the scan thread count (default 1)
+    #[serde(alias = "min-threads")]
+    pub min_threads: Option<usize>,
+    /// Upper bound and starting value of the scan thread count (default: CPUs)
+    #[serde(alias = "max-threads")]
+    pub max_threads: Option<usize>,
+}
+
+/// Short-lived process accounting; present (even empty) enables it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ShortLivedConfig {
+    /// Interval of the /proc listing that detects new processes (default 500)
+    #[serde(alias = "poll-interval-ms")]
+    pub poll_interval_ms: Option<u64>,
+}
+
+/// OTLP/HTTP export of the exporter's tracing spans.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct OtlpConfig {
+    /// Collector traces endpoint, e.g. "http://localhost:4318/v1/traces"
+    pub endpoint: String,
+    /// Fraction of root spans exported (0.0-1.0, default 1.0)
+    #[serde(alias = "sample-ratio")]
+    pub sample_ratio: Option<f64>,
+    /// service.name resource attribute (default "herakles-proc-mem-exporter")
+    #[serde(alias = "service-name")]
+    pub service_name: Option<String>,
+}
+
+/// Sandboxing applied after initialization (Linux only).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SandboxConfig {
+    /// Restrict filesystem access to /proc and configured paths
+    pub landlock: Option<bool>,
+    /// Restrict system calls to an allowlist
+    pub seccomp: Option<bool>,
+    /// Action on a disallowed syscall: "errno" (default) | "log" | "kill"
+    #[serde(alias = "seccomp-action")]
+    pub seccomp_action: Option<String>,
+}
+
+/// Whitelisted variables read from /proc/<pid>/environ.
+///
+/// Nothing else of the environment is kept; unreadable environments (other
+/// users' processes without CAP_SYS_PTRACE) count as all variables unset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct EnvironConfig {
+    /// Variables to read, e.g. ["SERVICE_NAME", "POD_NAME"]
+    pub variables: Vec<String>,
+    /// Export the variables as lowercase labels of per-process series (default true)
+    pub labels: Option<bool>,
+    /// Variable whose value is classified by the subgroup rules instead of the name
+    #[serde(alias = "classify-by")]
+    pub classify_by: Option<String>,
+}
+
+/// cache_ttl override for a time-of-day window.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ScanWindow {
+    /// Window start "HH:MM" (local time, inclusive)
+    pub start: String,
+    /// Window end "HH:MM" (exclusive, may be before start to wrap midnight)
+    pub end: String,
+    /// Refresh interval in seconds while the window is active
+    #[serde(alias = "cache-ttl")]
+    pub cache_ttl: u64,
+    /// Weekdays the window starts on ("mon".."sun"); all days if unset
+    pub days: Option<Vec<String>>,
+}
+
+/// Downstream exporters merged into this exporter's /metrics output.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct FederationConfig {
+    /// Scrape interval for downstream targets (defaults to cache_ttl)
+    #[serde(alias = "interval-seconds")]
+    pub interval_seconds: Option<u64>,
+    /// Timeout per downstream scrape
+    #[serde(alias = "timeout-seconds")]
+    pub timeout_seconds: Option<u64>,
+    /// Label carrying the target name on federated series (default "source")
+    #[serde(alias = "source-label")]
+    pub source_label: Option<String>,
+    /// Downstream exporters to scrape
+    pub targets: Vec<FederationTarget>,
+}
+
+/// Registration of this exporter in a service catalog.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RegistrationConfig {
+    /// "consul" or "etcd"
+    pub backend: String,
+    /// Base URL of the Consul agent or etcd v3 gateway, e.g. "http://127.0.0.1:8500"
+    pub address: String,
+    /// Service name (default "herakles-proc-mem-exporter")
+    #[serde(alias = "service-name")]
+    pub service_name: Option<String>,
+    /// Service instance ID (default "<service_name>-<host>-<port>")
+    #[serde(alias = "service-id")]
+    pub service_id: Option<String>,
+    /// Address announced to the catalog (default the bind address, or the
+    /// host name when bound to all interfaces)
+    #[serde(alias = "advertise-address")]
+    pub advertise_address: Option<String>,
+    /// Service tags
+    pub tags: Option<Vec<String>>,
+    /// Consul check TTL / etcd lease TTL, refreshed at a third of it (default 30)
+    #[serde(alias = "ttl-seconds")]
+    pub ttl_seconds: Option<u64>,
+    /// Consul ACL token or etcd auth token
+    pub token: Option<String>,
+    /// etcd key prefix (default "/services/herakles-proc-mem-exporter/")
+    #[serde(alias = "key-prefix")]
+    pub key_prefix: Option<String>,
+}
+
+/// mDNS/DNS-SD announcement of the metrics endpoint; present (even empty)
+/// enables it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct MdnsConfig {
+    /// Instance name announced under _prometheus-http._tcp (default the host name)
+    #[serde(alias = "instance-name")]
+    pub instance_name: Option<String>,
+    /// IPv4 address in the A record (default the bind address, or the
+    /// address of the interface multicast leaves through)
+    #[serde(alias = "advertise-address")]
+    pub advertise_address: Option<Ipv4Addr>,
+}
+
+/// Startup probe of other exporters on the host for overlapping families.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct OverlapProbeConfig {
+    /// /metrics URLs of the other exporters, e.g. "http://127.0.0.1:9100/metrics"
+    pub urls: Vec<String>,
+    /// "warn" (default) logs overlaps, "disable" also turns off the
+    /// overlapping system collectors
+    pub action: Option<String>,
+    /// Timeout per probe request (default 5)
+    #[serde(alias = "timeout-seconds")]
+    pub timeout_seconds: Option<u64>,
+}
+
+/// A single downstream exporter.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FederationTarget {
+    /// Name used as value of the source label
+    pub name: String,
+    /// Full URL of the downstream /metrics endpoint
+    pub url: String,
+    /// Additional static labels for this target's series
+    pub labels: Option<BTreeMap<String, String>>,
+}
+
+/// Label names used by the exporter's own metric families.
+const RESERVED_LABELS: &[&str] = &[
+    "pid",
+    "name",
+    "group",
+    "subgroup",
+    "rank",
+    "uptime_in_seconds",
+    "proc_id",
+    "cpu",
+];
+
+/// A tenant view on the shared process cache.
+///
+/// Filter fields that are set replace the global ones for this tenant only;
+/// processes are always limited to what the global filters already collected.
+/// Validation requires `include_names` or `search_mode`, so a tenant never
+/// sees every process by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TenantConfig {
+    /// Include only processes matching these names
+    #[serde(alias = "include-names")]
+    pub include_names: Option<Vec<String>>,
+    /// Exclude processes matching these names
+    #[serde(alias = "exclude-names")]
+    pub exclude_names: Option<Vec<String>>,
+    /// "include" | "exclude" | None
+    #[serde(alias = "search-mode")]
+    pub search_mode: Option<String>,
+    /// List of group names
+    #[serde(alias = "search-groups", alias = "groups")]
+    pub search_groups: Option<Vec<String>>,
+    /// List of subgroup names
+    #[serde(alias = "search-subgroups", alias = "subgroups")]
+    pub search_subgroups: Option<Vec<String>>,
+    /// If true, completely ignore "other"/"unknown" processes
+    #[serde(alias = "disable-others")]
+    pub disable_others: Option<bool>,
+    /// Static labels added to every series of this tenant
+    pub labels: Option<BTreeMap<String, String>>,
+}
+
+/// Regex whose named capture groups become labels of per-process series.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct LabelExtractorConfig {
+    /// Regex with named groups, e.g. "^postgres: \\S+ (?P<db>\\w+)"
+    pub pattern: String,
+    /// Named groups exported as labels
+    pub labels: Vec<String>,
+    /// Text the pattern is matched against: "cmdline" (default) | "name"
+    pub source: Option<String>,
+}
+
+impl TenantConfig {
+    /// Builds the effective config for this tenant on top of the global one.
+    pub fn apply_to(&self, base: &Config) -> Config {
+        let mut cfg = base.clone();
+        if self.include_names.is_some() {
+            cfg.include_names = self.include_names.clone();
+        }
+        if self.exclude_names.is_some() {
+            cfg.exclude_names = self.exclude_names.clone();
+        }
+        if self.search_mode.is_some() {
+            cfg.search_mode = self.search_mode.clone();
+            cfg.search_groups = self.search_groups.clone();
+            cfg.search_subgroups = self.search_subgroups.clone();
+        }
+        if self.disable_others.is_some() {
+            cfg.disable_others = self.disable_others;
+        }
+        cfg.tenants = None;
+        cfg.federation = None;
+        cfg
+    }
+}
+
+/// Returns true if `name` is a valid Prometheus label name.
+fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    !name.starts_with("__") && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Resource limits the exporter enforces on itself.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SelfLimitsConfig {
+    /// Abort or truncate a scan once the exporter's own RSS exceeds this (MB)
+    #[serde(alias = "max-rss-mb")]
+    pub max_rss_mb: Option<u64>,
+    /// Nice value applied to the scan worker threads (-20..19)
+    pub nice: Option<i32>,
+    /// Maximum CPU duty cycle per scan thread in percent (0-100)
+    #[serde(alias = "max-scan-cpu-percent")]
+    pub max_scan_cpu_percent: Option<f64>,
+    /// Optional RLIMIT_AS for the whole exporter process (MB)
+    #[serde(alias = "rlimit-as-mb")]
+    pub rlimit_as_mb: Option<u64>,
 }
 
 impl Default for Config {
@@ -93,12 +645,21 @@ impl Default for Config {
         Self {
             bind: Some(DEFAULT_BIND_ADDR.to_string()),
             port: Some(DEFAULT_PORT),
+            ready_file: None,
+            pid_file: None,
             min_uss_kb: Some(0),
             include_names: None,
             exclude_names: None,
+            exclude_users: None,
             parallelism: None,
+            scan_autotune: None,
             max_processes: None,
+            max_processes_by: None,
             cache_ttl: Some(DEFAULT_CACHE_TTL),
+            collection_mode: Some("background".into()),
+            cache_snapshot: None,
+            cache_snapshot_max_age_seconds: None,
+            startup_scan_timeout_seconds: None,
             io_buffer_kb: Some(256),
             smaps_buffer_kb: Some(512),
             smaps_rollup_buffer_kb: Some(256),
@@ -106,23 +667,82 @@ impl Default for Config {
             enable_telemetry: Some(true),
             enable_default_collectors: Some(true),
             enable_pprof: Some(false),
+            admin_token: None,
+            audit_log: None,
             log_level: Some("info".into()),
             enable_file_logging: Some(false),
             log_file: None,
+            diagnostics_dump: Some("log".into()),
             search_mode: None,
             search_groups: None,
             search_subgroups: None,
             disable_others: Some(false),
             top_n_subgroup: Some(3),
             top_n_others: Some(10),
+            per_process_metrics: Some(true),
+            stable_topn: Some(false),
+            top_n_hysteresis_scans: None,
+            max_exported_processes: None,
+            min_uss_percent_of_subgroup: None,
+            refresh_every: None,
+            group_labels: None,
+            owner_label: Some(DEFAULT_OWNER_LABEL.into()),
+            hide_monitoring_processes: Some(false),
+            monitoring_processes: None,
             enable_rss: Some(true),
             enable_pss: Some(true),
             enable_uss: Some(true),
             enable_cpu: Some(true),
+            enable_rates: Some(false),
+            enable_priority_metrics: Some(false),
+            enable_privilege_metrics: Some(false),
+            enable_delay_metrics: Some(false),
+            enable_io_metrics: Some(false),
+            enable_anon_file_metrics: Some(false),
+            enable_shmem_metrics: Some(false),
+            enable_schedstat: Some(false),
+            enable_numa: Some(false),
+            per_thread: Some(false),
+            top_n_threads: None,
+            enable_start_time_metrics: Some(false),
+            enable_wchan_metrics: Some(false),
+            enable_rule_hit_metrics: Some(false),
+            system_collectors: None,
+            session_aggregation: Some("off".into()),
+            sample_timestamps: Some(false),
+            scan_id_label: Some(false),
+            proc_id_label: Some(false),
+            metric_schema: Some("v1".into()),
+            scrape_timeout_offset_seconds: Some(0.5),
+            max_exposition_bytes: None,
+            exposition_drop_order: None,
+            enable_grafana: Some(false),
+            enable_history: Some(false),
+            history_points: Some(120),
+            history_db: None,
+            history_retention_hours: Some(168),
+            history_downsampling: None,
             test_data_file: None,
             enable_tls: Some(false),
             tls_cert_path: None,
             tls_key_path: None,
+            textfile_output: None,
+            enable_http: Some(true),
+            scan_windows: None,
+            self_limits: None,
+            otlp: None,
+            sandbox: None,
+            tenants: None,
+            label_extractors: None,
+            environ: None,
+            cgroups: None,
+            short_lived: None,
+            name_hash: Some(false),
+            name_hash_salt: None,
+            federation: None,
+            registration: None,
+            mdns: None,
+            overlap_probe: None,
         }
     }
 }
@@ -164,6 +784,226 @@ pub fn validate_effective_config(cfg: &Config) -> Result<(), Box<dyn std::error:
         }
     }
 
+    // Collection mode validation
+    if let Some(mode) = cfg.collection_mode.as_deref() {
+        if mode != "background" && mode != "on_scrape" {
+            return Err(format!(
+                "Invalid collection_mode '{}', expected 'background' or 'on_scrape'",
+                mode
+            )
+            .into());
+        }
+    }
+
+    if cfg.cache_snapshot_max_age_seconds == Some(0) {
+        return Err("cache_snapshot_max_age_seconds must be > 0".into());
+    }
+    if cfg.startup_scan_timeout_seconds == Some(0) {
+        return Err("startup_scan_timeout_seconds must be > 0".into());
+    }
+
+    if let Some(percent) = cfg.min_uss_percent_of_subgroup {
+        if !(0.0..100.0).contains(&percent) {
+            return Err(format!(
+                "min_uss_percent_of_subgroup must be between 0 and 100 (exclusive), got {}",
+                percent
+            )
+            .into());
+        }
+    }
+
+    if cfg.history_points == Some(0) {
+        return Err("history_points must be > 0".into());
+    }
+    if cfg.history_retention_hours == Some(0) {
+        return Err("history_retention_hours must be > 0".into());
+    }
+    if let Some(downsampling) = &cfg.history_downsampling {
+        if downsampling.interval_minutes == Some(0) {
+            return Err("history_downsampling.interval_minutes must be > 0".into());
+        }
+    }
+
+    for name in cfg.system_collectors.iter().flatten() {
+        if !SYSTEM_COLLECTORS.iter().any(|(known, _)| known == name) {
+            let known: Vec<&str> = SYSTEM_COLLECTORS.iter().map(|(n, _)| *n).collect();
+            return Err(format!(
+                "Unknown system collector '{}', expected one of: {}",
+                name,
+                known.join(", ")
+            )
+            .into());
+        }
+    }
+
+    if let Some(by) = cfg.session_aggregation.as_deref() {
+        if !matches!(by, "off" | "session" | "pgrp") {
+            return Err(format!(
+                "Invalid session_aggregation '{}', expected 'off', 'session' or 'pgrp'",
+                by
+            )
+            .into());
+        }
+    }
+
+    if let Some(schema) = cfg.metric_schema.as_deref() {
+        if MetricSchema::parse(schema).is_none() {
+            return Err(
+                format!("Invalid metric_schema '{}', expected 'v1' or 'v2'", schema).into(),
+            );
+        }
+    }
+
+    if cfg.top_n_threads == Some(0) {
+        return Err("top_n_threads must be at least 1".into());
+    }
+    if cfg.top_n_hysteresis_scans == Some(0) {
+        return Err("top_n_hysteresis_scans must be at least 1".into());
+    }
+    if cfg.max_exposition_bytes == Some(0) {
+        return Err("max_exposition_bytes must be > 0".into());
+    }
+    if let Some(order) = cfg.exposition_drop_order.as_deref() {
+        for (i, class) in order.iter().enumerate() {
+            if !DROP_CLASSES.contains(&class.as_str()) {
+                return Err(format!(
+                    "Unknown exposition_drop_order class '{}', expected one of: {}",
+                    class,
+                    DROP_CLASSES.join(", ")
+                )
+                .into());
+            }
+            if order[..i].contains(class) {
+                return Err(format!("exposition_drop_order: duplicate class '{}'", class).into());
+            }
+        }
+    }
+
+    if let Some(dest) = cfg.diagnostics_dump.as_deref() {
+        if dest != "log" && dest != "file" {
+            return Err(format!(
+                "Invalid diagnostics_dump '{}', expected 'log' or 'file'",
+                dest
+            )
+            .into());
+        }
+    }
+
+    for (key, every) in cfg.refresh_every.iter().flatten() {
+        if key.is_empty() || key.split('/').count() > 2 {
+            return Err(format!(
+                "refresh_every: invalid key '{}', expected 'group' or 'group/subgroup'",
+                key
+            )
+            .into());
+        }
+        if *every == 0 {
+            return Err(format!("refresh_every.{} must be greater than 0", key).into());
+        }
+    }
+
+    for (key, labels) in cfg.group_labels.iter().flatten() {
+        if key.is_empty() || key.split('/').count() > 2 {
+            return Err(format!(
+                "group_labels: invalid key '{}', expected 'group' or 'group/subgroup'",
+                key
+            )
+            .into());
+        }
+        for label in labels.keys() {
+            if !is_valid_label_name(label)
+                || RESERVED_LABELS.contains(&label.as_str())
+                || label == "scan_id"
+            {
+                return Err(format!(
+                    "Invalid group_labels label '{}' for '{}' (invalid or reserved name)",
+                    label, key
+                )
+                .into());
+            }
+        }
+    }
+
+    if let Some(label) = cfg.owner_label.as_deref() {
+        if !is_valid_label_name(label) {
+            return Err(format!("Invalid owner_label '{}'", label).into());
+        }
+    }
+
+    if let Some(names) = cfg.monitoring_processes.as_deref() {
+        if names.iter().any(|name| name.trim().is_empty()) {
+            return Err("monitoring_processes must not contain empty names".into());
+        }
+    }
+
+    // Scan window validation
+    for (i, window) in cfg.scan_windows.iter().flatten().enumerate() {
+        for (field, value) in [("start", &window.start), ("end", &window.end)] {
+            if crate::schedule::parse_time_of_day(value).is_none() {
+                return Err(format!(
+                    "scan_windows[{}]: invalid {} '{}', expected HH:MM",
+                    i, field, value
+                )
+                .into());
+            }
+        }
+        if window.start == window.end {
+            return Err(format!("scan_windows[{}]: start and end must differ", i).into());
+        }
+        if window.cache_ttl == 0 {
+            return Err(format!("scan_windows[{}]: cache_ttl must be greater than 0", i).into());
+        }
+        for day in window.days.iter().flatten() {
+            if crate::schedule::parse_weekday(day).is_none() {
+                return Err(format!("scan_windows[{}]: invalid day '{}'", i, day).into());
+            }
+        }
+    }
+
+    if let Some(action) = cfg
+        .sandbox
+        .as_ref()
+        .and_then(|s| s.seccomp_action.as_deref())
+    {
+        if !matches!(action, "errno" | "log" | "kill") {
+            return Err(format!(
+                "Invalid sandbox.seccomp_action '{}', expected 'errno', 'log' or 'kill'",
+                action
+            )
+            .into());
+        }
+    }
+
+    // Textfile output validation
+    if let Some(path) = &cfg.textfile_output {
+        if path.extension().and_then(|e| e.to_str()) != Some("prom") {
+            return Err(format!(
+                "textfile_output '{}' must end in .prom to be picked up by node_exporter",
+                path.display()
+            )
+            .into());
+        }
+        if cfg.collection_mode.as_deref() == Some("on_scrape") {
+            return Err("textfile_output requires collection_mode 'background', \
+                nothing triggers scans in on_scrape mode"
+                .into());
+        }
+    }
+    if !cfg.enable_http.unwrap_or(true) && cfg.textfile_output.is_none() {
+        return Err("enable_http is false but no textfile_output is configured".into());
+    }
+    if !cfg.enable_http.unwrap_or(true) && cfg.ready_file.is_some() {
+        return Err("ready_file requires enable_http, there is no address to report".into());
+    }
+
+    if cfg
+        .admin_token
+        .as_deref()
+        .is_some_and(|t| t.trim().is_empty())
+    {
+        return Err("admin_token must not be empty when set".into());
+    }
+
     // TLS validation
     if cfg.enable_tls.unwrap_or(false) {
         let cert_path = cfg.tls_cert_path.as_deref();
@@ -225,34 +1065,465 @@ pub fn validate_effective_config(cfg: &Config) -> Result<(), Box<dyn std::error:
         }
     }
 
+    // Self limits validation
+    if let Some(limits) = &cfg.self_limits {
+        if let Some(nice) = limits.nice {
+            if !(-20..=19).contains(&nice) {
+                return Err(format!(
+                    "Invalid self_limits.nice {}, expected a value between -20 and 19",
+                    nice
+                )
+                .into());
+            }
+        }
+        if let Some(pct) = limits.max_scan_cpu_percent {
+            if !(pct > 0.0 && pct <= 100.0) {
+                return Err(format!(
+                    "Invalid self_limits.max_scan_cpu_percent {}, expected a value in (0, 100]",
+                    pct
+                )
+                .into());
+            }
+        }
+        if limits.max_rss_mb == Some(0) || limits.rlimit_as_mb == Some(0) {
+            return Err("self_limits.max_rss_mb and self_limits.rlimit_as_mb must be > 0".into());
+        }
+    }
+
+    // OTLP validation
+    if let Some(otlp) = &cfg.otlp {
+        if !(otlp.endpoint.starts_with("http://") || otlp.endpoint.starts_with("https://")) {
+            return Err(format!(
+                "Invalid otlp.endpoint '{}', expected an http:// or https:// URL",
+                otlp.endpoint
+            )
+            .into());
+        }
+        if let Some(ratio) = otlp.sample_ratio {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(format!(
+                    "Invalid otlp.sample_ratio {}, expected a value between 0.0 and 1.0",
+                    ratio
+                )
+                .into());
+            }
+        }
+    }
+
+    // Tenant validation
+    for (name, tenant) in cfg.tenants.iter().flatten() {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Invalid tenant name '{}', only letters, digits, '-' and '_' are allowed",
+                name
+            )
+            .into());
+        }
+        if MetricSchema::parse(name).is_some() {
+            return Err(format!(
+                "Invalid tenant name '{}', /metrics/{} serves the metric schema of that name",
+                name, name
+            )
+            .into());
+        }
+        // Without its own filter a tenant would see every collected process
+        let has_names = tenant.include_names.as_ref().is_some_and(|v| !v.is_empty());
+        if !has_names && tenant.search_mode.is_none() {
+            return Err(format!(
+                "Tenant '{}' needs include_names or search_mode to select its processes",
+                name
+            )
+            .into());
+        }
+        if let Some(mode) = tenant.search_mode.as_deref() {
+            if mode != "include" && mode != "exclude" {
+                return Err(format!(
+                    "Invalid search_mode '{}' for tenant '{}', expected 'include' or 'exclude'",
+                    mode, name
+                )
+                .into());
+            }
+            let has_groups = tenant.search_groups.as_ref().is_some_and(|v| !v.is_empty());
+            let has_subgroups = tenant
+                .search_subgroups
+                .as_ref()
+                .is_some_and(|v| !v.is_empty());
+            if !(has_groups || has_subgroups) {
+                return Err(format!(
+                    "Tenant '{}' sets search_mode but no search_groups or search_subgroups",
+                    name
+                )
+                .into());
+            }
+        }
+        for label in tenant.labels.iter().flat_map(|l| l.keys()) {
+            if !is_valid_label_name(label) || RESERVED_LABELS.contains(&label.as_str()) {
+                return Err(format!(
+                    "Invalid static label '{}' for tenant '{}' (invalid or reserved name)",
+                    label, name
+                )
+                .into());
+            }
+        }
+    }
+
+    // Cgroup validation
+    if let Some(cgroups) = &cfg.cgroups {
+        if cgroups.max_depth.is_some_and(|d| d > 8) {
+            return Err("cgroups.max_depth must be at most 8".into());
+        }
+        if cgroups.stat_fields.as_ref().is_some_and(|f| f.is_empty()) {
+            return Err("cgroups.stat_fields must not be empty when set".into());
+        }
+    }
+
+    if let Some(by) = &cfg.max_processes_by {
+        if MaxProcessesBy::parse(by).is_none() {
+            return Err(
+                format!("Invalid max_processes_by '{}', expected 'pid' or 'rss'", by).into(),
+            );
+        }
+    }
+
+    // Scan auto-tuning validation
+    if let Some(autotune) = &cfg.scan_autotune {
+        if cfg.parallelism.is_some() {
+            return Err("scan_autotune and parallelism are mutually exclusive".into());
+        }
+        if autotune.min_threads == Some(0) {
+            return Err("scan_autotune.min_threads must be at least 1".into());
+        }
+        if let (Some(min), Some(max)) = (autotune.min_threads, autotune.max_threads) {
+            if min > max {
+                return Err(format!(
+                    "scan_autotune.min_threads ({}) must not exceed max_threads ({})",
+                    min, max
+                )
+                .into());
+            }
+        }
+    }
+
+    // Short-lived process validation
+    if let Some(short_lived) = &cfg.short_lived {
+        if short_lived
+            .poll_interval_ms
+            .is_some_and(|ms| !(100..=10_000).contains(&ms))
+        {
+            return Err("short_lived.poll_interval_ms must be between 100 and 10000".into());
+        }
+    }
+
+    // Privacy validation
+    if let Some(users) = &cfg.exclude_users {
+        crate::privacy::resolve_users(users)?;
+    }
+    if cfg.name_hash_salt.is_some() && !cfg.name_hash.unwrap_or(false) {
+        return Err("name_hash_salt is set but name_hash is disabled".into());
+    }
+
+    // Label extractor validation
+    let mut extracted_labels: HashSet<&str> = HashSet::new();
+    for extractor in cfg.label_extractors.iter().flatten() {
+        let regex = regex::Regex::new(&extractor.pattern).map_err(|e| {
+            format!(
+                "Invalid label_extractors pattern '{}': {}",
+                extractor.pattern, e
+            )
+        })?;
+        if let Some(source) = extractor.source.as_deref() {
+            if source != "cmdline" && source != "name" {
+                return Err(format!(
+                    "Invalid label_extractors source '{}', expected 'cmdline' or 'name'",
+                    source
+                )
+                .into());
+            }
+        }
+        if extractor.labels.is_empty() {
+            return Err(format!(
+                "label_extractors pattern '{}' has no labels",
+                extractor.pattern
+            )
+            .into());
+        }
+        for label in &extractor.labels {
+            if !is_valid_label_name(label) || RESERVED_LABELS.contains(&label.as_str()) {
+                return Err(format!(
+                    "Invalid extracted label '{}' (invalid or reserved name)",
+                    label
+                )
+                .into());
+            }
+            if !regex.capture_names().flatten().any(|n| n == label) {
+                return Err(format!(
+                    "label_extractors pattern '{}' has no capture group named '{}'",
+                    extractor.pattern, label
+                )
+                .into());
+            }
+            extracted_labels.insert(label);
+        }
+    }
+    let mut environ_labels: Vec<String> = Vec::new();
+    if let Some(environ) = &cfg.environ {
+        if environ.variables.is_empty() {
+            return Err("environ is configured but has no variables".into());
+        }
+        for variable in &environ.variables {
+            let valid = variable
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && variable
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(format!("Invalid environ variable name '{}'", variable).into());
+            }
+            let label = variable.to_ascii_lowercase();
+            if environ.labels.unwrap_or(true)
+                && (RESERVED_LABELS.contains(&label.as_str())
+                    || extracted_labels.contains(label.as_str()))
+            {
+                return Err(format!(
+                    "environ variable '{}' collides with the label '{}'",
+                    variable, label
+                )
+                .into());
+            }
+            environ_labels.push(label);
+        }
+        if let Some(variable) = &environ.classify_by {
+            if !environ.variables.contains(variable) {
+                return Err(format!(
+                    "environ.classify_by '{}' is not one of environ.variables",
+                    variable
+                )
+                .into());
+            }
+        }
+        if !environ.labels.unwrap_or(true) {
+            environ_labels.clear();
+        }
+    }
+    extracted_labels.extend(environ_labels.iter().map(String::as_str));
+    for (key, labels) in cfg.group_labels.iter().flatten() {
+        if let Some(label) = labels
+            .keys()
+            .find(|l| extracted_labels.contains(l.as_str()))
+        {
+            return Err(format!(
+                "group_labels label '{}' for '{}' is also an extracted label",
+                label, key
+            )
+            .into());
+        }
+    }
+    for (name, tenant) in cfg.tenants.iter().flatten() {
+        for label in tenant.labels.iter().flat_map(|l| l.keys()) {
+            if extracted_labels.contains(label.as_str()) {
+                return Err(format!(
+                    "Static label '{}' of tenant '{}' is also an extracted label",
+                    label, name
+                )
+                .into());
+            }
+            if cfg
+                .group_labels
+                .iter()
+                .flat_map(|g| g.values())
+                .any(|labels| labels.contains_key(label))
+            {
+                return Err(format!(
+                    "Static label '{}' of tenant '{}' is also a group_labels label",
+                    label, name
+                )
+                .into());
+            }
+        }
+    }
+
+    // Registration validation
+    if let Some(reg) = &cfg.registration {
+        if !matches!(reg.backend.as_str(), "consul" | "etcd") {
+            return Err(format!(
+                "Invalid registration.backend '{}', expected consul or etcd",
+                reg.backend
+            )
+            .into());
+        }
+        if !(reg.address.starts_with("http://") || reg.address.starts_with("https://")) {
+            return Err(format!(
+                "Invalid registration.address '{}', expected http:// or https://",
+                reg.address
+            )
+            .into());
+        }
+        if reg
+            .ttl_seconds
+            .is_some_and(|ttl| !(5..=3600).contains(&ttl))
+        {
+            return Err("registration.ttl_seconds must be between 5 and 3600".into());
+        }
+        if !cfg.enable_http.unwrap_or(true) {
+            return Err("registration requires enable_http".into());
+        }
+    }
+
+    if cfg
+        .scrape_timeout_offset_seconds
+        .is_some_and(|offset| !(0.0..=60.0).contains(&offset))
+    {
+        return Err("scrape_timeout_offset_seconds must be between 0 and 60".into());
+    }
+
+    // mDNS validation
+    if let Some(mdns) = &cfg.mdns {
+        if mdns
+            .instance_name
+            .as_ref()
+            .is_some_and(|name| name.is_empty() || name.len() > 63)
+        {
+            return Err("mdns.instance_name must be 1 to 63 bytes long".into());
+        }
+        if !cfg.enable_http.unwrap_or(true) {
+            return Err("mdns requires enable_http".into());
+        }
+    }
+
+    if let Some(probe) = &cfg.overlap_probe {
+        if probe.urls.is_empty() {
+            return Err("overlap_probe is configured but has no urls".into());
+        }
+        if let Some(url) = probe
+            .urls
+            .iter()
+            .find(|u| !(u.starts_with("http://") || u.starts_with("https://")))
+        {
+            return Err(format!(
+                "Invalid overlap_probe URL '{}', expected http:// or https://",
+                url
+            )
+            .into());
+        }
+        if let Some(action) = probe.action.as_deref() {
+            if action != "warn" && action != "disable" {
+                return Err(format!(
+                    "Invalid overlap_probe.action '{}', expected 'warn' or 'disable'",
+                    action
+                )
+                .into());
+            }
+        }
+        if probe.timeout_seconds == Some(0) {
+            return Err("overlap_probe.timeout_seconds must be > 0".into());
+        }
+    }
+
+    // Federation validation
+    if let Some(fed) = &cfg.federation {
+        if fed.targets.is_empty() {
+            return Err("federation is configured but has no targets".into());
+        }
+        if fed.interval_seconds == Some(0) || fed.timeout_seconds == Some(0) {
+            return Err(
+                "federation.interval_seconds and federation.timeout_seconds must be > 0".into(),
+            );
+        }
+        if let Some(label) = fed.source_label.as_deref() {
+            if !is_valid_label_name(label) || RESERVED_LABELS.contains(&label) {
+                return Err(format!(
+                    "Invalid federation.source_label '{}' (invalid or reserved name)",
+                    label
+                )
+                .into());
+            }
+        }
+        let mut seen = std::collections::HashSet::new();
+        for target in &fed.targets {
+            if !seen.insert(target.name.as_str()) {
+                return Err(format!("Duplicate federation target name '{}'", target.name).into());
+            }
+            if !(target.url.starts_with("http://") || target.url.starts_with("https://")) {
+                return Err(format!(
+                    "Invalid URL '{}' for federation target '{}', expected http:// or https://",
+                    target.url, target.name
+                )
+                .into());
+            }
+            for label in target.labels.iter().flat_map(|l| l.keys()) {
+                if !is_valid_label_name(label) || RESERVED_LABELS.contains(&label.as_str()) {
+                    return Err(format!(
+                        "Invalid static label '{}' for federation target '{}'",
+                        label, target.name
+                    )
+                    .into());
+                }
+            }
+        }
+    }
+
     Ok(())
 }
 
 /// Resolves configuration from CLI args, config file, and defaults.
 /// This enforces precedence: CLI (if provided) > config file > default.
 pub fn resolve_config(args: &Args) -> Result<Config, Box<dyn std::error::Error>> {
+    resolve_config_with_origins(args).map(|(config, _)| config)
+}
+
+/// Like [`resolve_config`], also returning where each top-level key came from.
+pub fn resolve_config_with_origins(
+    args: &Args,
+) -> Result<(Config, ConfigOrigins), Box<dyn std::error::Error>> {
+    let mut origins = ConfigOrigins::default();
     let mut config = if args.no_config {
         Config::default()
     } else {
-        load_config(args.config.as_deref().and_then(|p| p.to_str()))?
+        let loaded = load_config(args.config.as_deref().and_then(|p| p.to_str()))?;
+        if let Some(path) = &loaded.path {
+            for key in &loaded.keys {
+                origins.set(key, ConfigSource::File(path.clone()));
+            }
+        }
+        let unknown = loaded.unknown_keys;
+        if !unknown.is_empty() {
+            let keys: Vec<String> = unknown.iter().map(|k| k.to_string()).collect();
+            if args.strict_config || args.check_config {
+                return Err(format!("config file contains {}", keys.join(", ")).into());
+            }
+            for key in keys {
+                eprintln!("⚠️  Ignoring {} in config file", key);
+            }
+        }
+        loaded.config
     };
 
     // Override with CLI args
     if let Some(bind_ip) = args.bind {
+        origins.set("bind", ConfigSource::Cli);
         config.bind = Some(bind_ip.to_string());
     }
 
     // Only override port if the user supplied it on the CLI.
     if let Some(cli_port) = args.port {
+        origins.set("port", ConfigSource::Cli);
         config.port = Some(cli_port);
     }
 
     if args.min_uss_kb.is_some() {
+        origins.set("min_uss_kb", ConfigSource::Cli);
         config.min_uss_kb = args.min_uss_kb;
     }
 
     // Parse comma-separated include/exclude names
     if let Some(include_str) = &args.include_names {
+        origins.set("include_names", ConfigSource::Cli);
         config.include_names = Some(
             include_str
                 .split(',')
@@ -262,6 +1533,7 @@ pub fn resolve_config(args: &Args) -> Result<Config, Box<dyn std::error::Error>>
     }
 
     if let Some(exclude_str) = &args.exclude_names {
+        origins.set("exclude_names", ConfigSource::Cli);
         config.exclude_names = Some(
             exclude_str
                 .split(',')
@@ -272,61 +1544,155 @@ pub fn resolve_config(args: &Args) -> Result<Config, Box<dyn std::error::Error>>
 
     // Performance settings
     if let Some(io_buffer_kb) = args.io_buffer_kb {
+        origins.set("io_buffer_kb", ConfigSource::Cli);
         config.io_buffer_kb = Some(io_buffer_kb);
     }
     if let Some(smaps_buffer_kb) = args.smaps_buffer_kb {
+        origins.set("smaps_buffer_kb", ConfigSource::Cli);
         config.smaps_buffer_kb = Some(smaps_buffer_kb);
     }
     if let Some(smaps_rollup_buffer_kb) = args.smaps_rollup_buffer_kb {
+        origins.set("smaps_rollup_buffer_kb", ConfigSource::Cli);
         config.smaps_rollup_buffer_kb = Some(smaps_rollup_buffer_kb);
     }
     if let Some(cache_ttl) = args.cache_ttl {
+        origins.set("cache_ttl", ConfigSource::Cli);
         config.cache_ttl = Some(cache_ttl);
     }
 
     // Top-N overrides: CLI wins if provided
     if let Some(n) = args.top_n_subgroup {
+        origins.set("top_n_subgroup", ConfigSource::Cli);
         config.top_n_subgroup = Some(n);
     }
     if let Some(n) = args.top_n_others {
+        origins.set("top_n_others", ConfigSource::Cli);
         config.top_n_others = Some(n);
     }
+    if args.aggregates_only {
+        origins.set("per_process_metrics", ConfigSource::Cli);
+        config.per_process_metrics = Some(false);
+    }
+    if let Some(n) = args.max_exported_processes {
+        origins.set("max_exported_processes", ConfigSource::Cli);
+        config.max_exported_processes = Some(n);
+    }
+    if let Some(percent) = args.min_uss_percent_of_subgroup {
+        origins.set("min_uss_percent_of_subgroup", ConfigSource::Cli);
+        config.min_uss_percent_of_subgroup = Some(percent);
+    }
 
     // Feature flags
     if args.disable_health {
+        origins.set("enable_health", ConfigSource::Cli);
         config.enable_health = Some(false);
     }
     if args.disable_telemetry {
+        origins.set("enable_telemetry", ConfigSource::Cli);
         config.enable_telemetry = Some(false);
     }
     if args.disable_default_collectors {
+        origins.set("enable_default_collectors", ConfigSource::Cli);
         config.enable_default_collectors = Some(false);
     }
     if args.debug {
+        origins.set("enable_pprof", ConfigSource::Cli);
         config.enable_pprof = Some(true);
     }
 
     // Test data file: CLI wins if provided
     if let Some(test_file) = &args.test_data_file {
+        origins.set("test_data_file", ConfigSource::Cli);
         config.test_data_file = Some(test_file.clone());
     }
 
     // TLS configuration: CLI wins if provided
     if args.enable_tls {
+        origins.set("enable_tls", ConfigSource::Cli);
         config.enable_tls = Some(true);
     }
     if let Some(cert_path) = &args.tls_cert {
+        origins.set("tls_cert_path", ConfigSource::Cli);
         config.tls_cert_path = Some(cert_path.to_string_lossy().to_string());
     }
     if let Some(key_path) = &args.tls_key {
+        origins.set("tls_key_path", ConfigSource::Cli);
         config.tls_key_path = Some(key_path.to_string_lossy().to_string());
     }
 
-    Ok(config)
+    // Textfile output: CLI wins if provided
+    if let Some(path) = &args.textfile_output {
+        origins.set("textfile_output", ConfigSource::Cli);
+        config.textfile_output = Some(path.clone());
+    }
+    if let Some(path) = &args.ready_file {
+        origins.set("ready_file", ConfigSource::Cli);
+        config.ready_file = Some(path.clone());
+    }
+    if let Some(path) = &args.pid_file {
+        origins.set("pid_file", ConfigSource::Cli);
+        config.pid_file = Some(path.clone());
+    }
+    if args.disable_http {
+        origins.set("enable_http", ConfigSource::Cli);
+        config.enable_http = Some(false);
+    }
+
+    Ok((config, origins))
 }
 
-/// Enhanced configuration loading with multiple format support
-pub fn load_config(path: Option<&str>) -> Result<Config, Box<dyn std::error::Error>> {
+/// Where an effective config value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    File(PathBuf),
+    Cli,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Cli => write!(f, "cli"),
+        }
+    }
+}
+
+/// Source of every top-level config key; keys not recorded are defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOrigins(BTreeMap<String, ConfigSource>);
+
+impl ConfigOrigins {
+    pub fn set(&mut self, key: &str, source: ConfigSource) {
+        self.0.insert(key.to_string(), source);
+    }
+
+    pub fn get(&self, key: &str) -> ConfigSource {
+        self.0.get(key).cloned().unwrap_or(ConfigSource::Default)
+    }
+}
+
+/// A key in the config file that does not correspond to any setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// Dotted path of the key, e.g. "sandbox.seccomp_actoin"
+    pub path: String,
+    /// 1-based line of the key in the file, if it could be located
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "unknown key '{}' at line {}", self.path, line),
+            None => write!(f, "unknown key '{}'", self.path),
+        }
+    }
+}
+
+/// Returns the config file to load: the given path or the first existing default.
+fn config_file_path(path: Option<&str>) -> Option<PathBuf> {
     let path = if let Some(p) = path {
         PathBuf::from(p)
     } else {
@@ -348,41 +1714,167 @@ pub fn load_config(path: Option<&str>) -> Result<Config, Box<dyn std::error::Err
     };
 
     if !path.exists() || path.to_string_lossy().is_empty() {
-        return Ok(Config::default());
+        return None;
     }
+    Some(path)
+}
+
+/// Result of loading the config file.
+pub struct LoadedConfig {
+    pub config: Config,
+    /// Keys that do not match any setting
+    pub unknown_keys: Vec<UnknownKey>,
+    /// File the config was loaded from, None if defaults were used
+    pub path: Option<PathBuf>,
+    /// Top-level settings present in the file
+    pub keys: Vec<String>,
+}
+
+/// Enhanced configuration loading with multiple format support.
+///
+/// Keys that do not match any setting are returned alongside the config
+/// instead of being silently ignored.
+pub fn load_config(path: Option<&str>) -> Result<LoadedConfig, Box<dyn std::error::Error>> {
+    let Some(path) = config_file_path(path) else {
+        return Ok(LoadedConfig {
+            config: Config::default(),
+            unknown_keys: Vec::new(),
+            path: None,
+            keys: Vec::new(),
+        });
+    };
 
     let content = fs::read_to_string(&path)?;
+    let format = match path.extension().and_then(|s| s.to_str()) {
+        Some("json") => ConfigFormat::Json,
+        Some("toml") => ConfigFormat::Toml,
+        // Default to YAML
+        _ => ConfigFormat::Yaml,
+    };
 
-    match path.extension().and_then(|s| s.to_str()) {
-        Some("json") => {
-            let config: Config = serde_json::from_str(&content)?;
-            info!("Loaded JSON configuration from: {}", path.display());
-            Ok(config)
-        }
-        Some("toml") => {
-            let config: Config = toml::from_str(&content)?;
-            info!("Loaded TOML configuration from: {}", path.display());
-            Ok(config)
+    let label = match format {
+        ConfigFormat::Json => "JSON",
+        ConfigFormat::Toml => "TOML",
+        ConfigFormat::Yaml => "YAML",
+    };
+
+    let keys = top_level_keys(&content, format.clone())?;
+    let (config, unknown_keys) = parse_config(&content, format)?;
+    info!("Loaded {} configuration from: {}", label, path.display());
+    Ok(LoadedConfig {
+        config,
+        unknown_keys: unknown_keys.clone(),
+        path: Some(path),
+        keys: keys
+            .into_iter()
+            .filter(|k| !unknown_keys.iter().any(|u| &u.path == k))
+            .map(|k| k.replace('-', "_"))
+            .collect(),
+    })
+}
+
+/// Parses config file content, collecting keys unknown to `Config`.
+pub fn parse_config(
+    content: &str,
+    format: ConfigFormat,
+) -> Result<(Config, Vec<UnknownKey>), Box<dyn std::error::Error>> {
+    let mut unknown = Vec::new();
+    let mut record = |path: serde_ignored::Path| {
+        // Option values show up as "?" segments
+        let path = path.to_string().replace(".?", "");
+        let key = path.rsplit('.').next().unwrap_or(&path);
+        unknown.push(UnknownKey {
+            line: key_line(content, key),
+            path,
+        });
+    };
+
+    let config: Config = match format {
+        ConfigFormat::Json => serde_ignored::deserialize(
+            &mut serde_json::Deserializer::from_str(content),
+            &mut record,
+        )?,
+        ConfigFormat::Toml => {
+            serde_ignored::deserialize(&mut toml::Deserializer::new(content), &mut record)?
         }
-        _ => {
-            // Default to YAML
-            let config: Config = serde_yaml::from_str(&content)?;
-            info!("Loaded YAML configuration from: {}", path.display());
-            Ok(config)
+        ConfigFormat::Yaml => {
+            serde_ignored::deserialize(serde_yaml::Deserializer::from_str(content), &mut record)?
         }
-    }
+    };
+
+    Ok((config, unknown))
 }
 
-/// Shows configuration in requested format
+/// Returns the top-level keys of a config file as written.
+fn top_level_keys(
+    content: &str,
+    format: ConfigFormat,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let value: serde_json::Value = match format {
+        ConfigFormat::Json => serde_json::from_str(content)?,
+        ConfigFormat::Toml => toml::from_str(content)?,
+        ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+    };
+    Ok(value
+        .as_object()
+        .map(|map| map.keys().cloned().collect())
+        .unwrap_or_default())
+}
+
+/// Finds the first line that defines `key` in YAML, JSON or TOML syntax.
+fn key_line(content: &str, key: &str) -> Option<usize> {
+    let defines_key = |line: &str| {
+        line.match_indices(key).any(|(i, _)| {
+            let before = line[..i].trim_end_matches('"').trim_end();
+            let after = line[i + key.len()..].trim_start_matches('"').trim_start();
+            (before.is_empty() || before.ends_with(['{', ',', '-']))
+                && (after.starts_with(':') || after.starts_with('='))
+        })
+    };
+    content.lines().position(defines_key).map(|idx| idx + 1)
+}
+
+/// Shows configuration in requested format, annotated with the source of
+/// every top-level value.
+///
+/// YAML and TOML get a trailing comment per key; JSON stays machine-readable
+/// on stdout and the sources are listed on stderr instead.
 pub fn show_config(
     config: &Config,
+    origins: &ConfigOrigins,
     format: ConfigFormat,
     user_config: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let output = match format {
-        ConfigFormat::Json => serde_json::to_string_pretty(config)?,
-        ConfigFormat::Toml => toml::to_string_pretty(config)?,
-        ConfigFormat::Yaml => serde_yaml::to_string(config)?,
+        ConfigFormat::Json => {
+            for (key, _) in serde_json::to_value(config)?
+                .as_object()
+                .into_iter()
+                .flatten()
+            {
+                eprintln!("{:<28} {}", key, origins.get(key));
+            }
+            serde_json::to_string_pretty(config)?
+        }
+        ConfigFormat::Toml => {
+            // Plain keys are top-level only until the first table header
+            let mut in_tables = false;
+            annotate_sources(&toml::to_string_pretty(config)?, origins, |line| {
+                in_tables |= line.starts_with('[');
+                if in_tables && !line.starts_with('[') {
+                    return None;
+                }
+                let line = line.trim_start_matches('[');
+                let end = line.find([' ', '=', '.', ']'])?;
+                Some(&line[..end])
+            })
+        }
+        ConfigFormat::Yaml => annotate_sources(&serde_yaml::to_string(config)?, origins, |line| {
+            if line.starts_with([' ', '-', '#']) {
+                return None;
+            }
+            line.split_once(':').map(|(key, _)| key)
+        }),
     };
 
     if user_config {
@@ -391,3 +1883,124 @@ pub fn show_config(
     println!("{output}");
     Ok(())
 }
+
+/// Appends `# <source>` to the first line of every top-level key, where
+/// `top_level_key` extracts the key from lines that start one.
+fn annotate_sources(
+    rendered: &str,
+    origins: &ConfigOrigins,
+    mut top_level_key: impl FnMut(&str) -> Option<&str>,
+) -> String {
+    let mut seen = std::collections::BTreeSet::new();
+    let mut out = String::new();
+    for line in rendered.lines() {
+        out.push_str(line);
+        if let Some(key) = top_level_key(line).filter(|k| !k.is_empty()) {
+            if seen.insert(key.to_string()) {
+                out.push_str(&format!("  # {}", origins.get(key)));
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_reports_unknown_keys() {
+        let yaml = "port: 9300\ncollection-mode: on_scrape\nmin_us_kb: 10\nsandbox:\n  seccomp_actoin: log\n";
+        let (config, unknown) = parse_config(yaml, ConfigFormat::Yaml).unwrap();
+
+        assert_eq!(config.port, Some(9300));
+        assert_eq!(config.collection_mode.as_deref(), Some("on_scrape"));
+        assert_eq!(
+            unknown,
+            vec![
+                UnknownKey {
+                    path: "min_us_kb".into(),
+                    line: Some(3),
+                },
+                UnknownKey {
+                    path: "sandbox.seccomp_actoin".into(),
+                    line: Some(5),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_line_formats() {
+        assert_eq!(
+            key_line("{\"port\": 1, \"min_us_kb\": 2}", "min_us_kb"),
+            Some(1)
+        );
+        assert_eq!(key_line("port = 1\nmin_us_kb = 2\n", "min_us_kb"), Some(2));
+        assert_eq!(key_line("x: min_us_kb\n", "min_us_kb"), None);
+    }
+
+    #[test]
+    fn test_resolve_config_origins() {
+        use clap::Parser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        fs::write(
+            &path,
+            "port: 9300\ncache_ttl: 5\ncollection-mode: background\n",
+        )
+        .unwrap();
+
+        let args = Args::parse_from([
+            "herakles-proc-mem-exporter",
+            "-c",
+            path.to_str().unwrap(),
+            "--port",
+            "9400",
+        ]);
+        let (config, origins) = resolve_config_with_origins(&args).unwrap();
+
+        assert_eq!(config.port, Some(9400));
+        assert_eq!(origins.get("port"), ConfigSource::Cli);
+        assert_eq!(origins.get("cache_ttl"), ConfigSource::File(path.clone()));
+        assert_eq!(origins.get("collection_mode"), ConfigSource::File(path));
+        assert_eq!(origins.get("bind"), ConfigSource::Default);
+    }
+
+    #[test]
+    fn test_tenant_without_filter_rejected() {
+        let tenant = |tenant: TenantConfig| Config {
+            tenants: Some(BTreeMap::from([("team-a".to_string(), tenant)])),
+            ..Default::default()
+        };
+
+        let unfiltered = tenant(TenantConfig {
+            exclude_names: Some(vec!["sshd".to_string()]),
+            disable_others: Some(true),
+            ..Default::default()
+        });
+        let err = validate_effective_config(&unfiltered).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Tenant 'team-a' needs include_names"));
+        assert!(validate_effective_config(&tenant(TenantConfig {
+            include_names: Some(Vec::new()),
+            ..Default::default()
+        }))
+        .is_err());
+
+        assert!(validate_effective_config(&tenant(TenantConfig {
+            include_names: Some(vec!["postgres".to_string()]),
+            ..Default::default()
+        }))
+        .is_ok());
+        assert!(validate_effective_config(&tenant(TenantConfig {
+            search_mode: Some("include".to_string()),
+            search_subgroups: Some(vec!["postgres".to_string()]),
+            ..Default::default()
+        }))
+        .is_ok());
+    }
+}
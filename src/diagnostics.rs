@@ -0,0 +1,160 @@
+//! Diagnostic state dumps.
+//!
+//! On SIGUSR1 the exporter writes a full snapshot of its internal state
+//! (effective config, cache summary, health statistics, CPU cache size and
+//! buffer high-water marks) to the log or to a file in the temp directory,
+//! so state can be collected from a misbehaving exporter without HTTP access.
+
+use chrono::Local;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use tracing::{error, info};
+
+use crate::process::{
+    CAPABILITIES, MAX_IO_BUFFER_BYTES, MAX_SMAPS_BUFFER_BYTES, MAX_SMAPS_ROLLUP_BUFFER_BYTES,
+};
+use crate::state::SharedState;
+
+/// Renders the diagnostic snapshot as plain text.
+pub async fn render_dump(state: &SharedState) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "HERAKLES PROC MEM EXPORTER - DIAGNOSTIC DUMP").ok();
+    writeln!(out, "============================================").ok();
+    writeln!(
+        out,
+        "time:                       {}",
+        Local::now().to_rfc3339()
+    )
+    .ok();
+    writeln!(out, "pid:                        {}", std::process::id()).ok();
+    writeln!(
+        out,
+        "version:                    {}",
+        env!("CARGO_PKG_VERSION")
+    )
+    .ok();
+    writeln!(
+        out,
+        "active_log_level:           {}",
+        state.log_control.current()
+    )
+    .ok();
+    writeln!(out).ok();
+
+    writeln!(out, "CACHE").ok();
+    writeln!(out, "-----").ok();
+    {
+        let cache = state.cache.read().await;
+        writeln!(out, "processes:                  {}", cache.processes.len()).ok();
+        writeln!(
+            out,
+            "previous_processes:         {}",
+            cache.previous_processes.len()
+        )
+        .ok();
+        writeln!(
+            out,
+            "last_updated:               {}",
+            cache
+                .last_updated
+                .map(|t| format!("{:.1}s ago", t.elapsed().as_secs_f64()))
+                .unwrap_or_else(|| "never".to_string())
+        )
+        .ok();
+        writeln!(
+            out,
+            "update_duration_seconds:    {:.3}",
+            cache.update_duration_seconds
+        )
+        .ok();
+        writeln!(out, "update_success:             {}", cache.update_success).ok();
+        writeln!(out, "is_updating:                {}", cache.is_updating).ok();
+        writeln!(out, "restored:                   {}", cache.restored).ok();
+        writeln!(out, "initializing:               {}", cache.initializing).ok();
+    }
+    let cpu_cache_entries = state.cpu_cache.read().map(|c| c.len()).unwrap_or(0);
+    writeln!(out, "cpu_cache_entries:          {}", cpu_cache_entries).ok();
+    writeln!(out).ok();
+
+    writeln!(out, "BUFFER HIGH-WATER MARKS").ok();
+    writeln!(out, "-----------------------").ok();
+    writeln!(
+        out,
+        "io_buffer:                  {} bytes (capacity {} KB)",
+        MAX_IO_BUFFER_BYTES.load(Ordering::Relaxed),
+        state.buffer_config.io_kb
+    )
+    .ok();
+    writeln!(
+        out,
+        "smaps_buffer:               {} bytes (capacity {} KB)",
+        MAX_SMAPS_BUFFER_BYTES.load(Ordering::Relaxed),
+        state.buffer_config.smaps_kb
+    )
+    .ok();
+    writeln!(
+        out,
+        "smaps_rollup_buffer:        {} bytes (capacity {} KB)",
+        MAX_SMAPS_ROLLUP_BUFFER_BYTES.load(Ordering::Relaxed),
+        state.buffer_config.smaps_rollup_kb
+    )
+    .ok();
+    writeln!(out).ok();
+
+    writeln!(out, "CAPABILITIES").ok();
+    writeln!(out, "------------").ok();
+    for (feature, available) in CAPABILITIES.snapshot().features() {
+        writeln!(out, "{:<28}{}", format!("{}:", feature), available).ok();
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "HEALTH STATISTICS").ok();
+    writeln!(out, "-----------------").ok();
+    out.push_str(&state.health_stats.render_table());
+    writeln!(out).ok();
+
+    writeln!(out, "EFFECTIVE CONFIGURATION").ok();
+    writeln!(out, "-----------------------").ok();
+    let mut config = state.config.as_ref().clone();
+    if config.admin_token.is_some() {
+        config.admin_token = Some("<redacted>".into());
+    }
+    match serde_yaml::to_string(&config) {
+        Ok(yaml) => out.push_str(&yaml),
+        Err(e) => {
+            writeln!(out, "<failed to serialize config: {}>", e).ok();
+        }
+    }
+
+    out
+}
+
+/// Writes a diagnostic dump to the configured destination.
+pub async fn write_dump(state: &SharedState) {
+    let dump = render_dump(state).await;
+
+    if state.config.diagnostics_dump.as_deref() == Some("file") {
+        let path = dump_file_path();
+        match std::fs::write(&path, &dump) {
+            Ok(()) => info!("Diagnostic dump written to {}", path.display()),
+            Err(e) => error!(
+                "Failed to write diagnostic dump to {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    } else {
+        info!("Diagnostic dump requested via SIGUSR1:\n{}", dump);
+    }
+}
+
+/// Returns a unique dump file path in the temp directory.
+fn dump_file_path() -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "herakles-proc-mem-exporter-{}-{}.dump",
+        std::process::id(),
+        Local::now().format("%Y%m%dT%H%M%S")
+    ))
+}
@@ -0,0 +1,143 @@
+//! Error types for process and system metric collection.
+//!
+//! Every failure carries the /proc (or test data) path it came from, and
+//! per-process failures can be wrapped with the pid, so log lines and error
+//! counters can tell a vanished process from a permission problem or a
+//! malformed file.
+
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Error raised while collecting process or system metrics.
+#[derive(Debug, Error)]
+pub enum CollectError {
+    /// Opening or reading a file failed.
+    #[error("failed to read {}: {source}", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A file was read but its content could not be parsed.
+    #[error("invalid content in {}: {reason}", .path.display())]
+    Parse { path: PathBuf, reason: String },
+
+    /// Collection for a single process failed.
+    #[error("pid {pid}: {source}")]
+    Process {
+        pid: u32,
+        #[source]
+        source: Box<CollectError>,
+    },
+
+    /// A scan was skipped or aborted before reading any process.
+    #[error("scan aborted: {0}")]
+    ScanAborted(String),
+}
+
+impl CollectError {
+    /// Creates an I/O error for `path`.
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// Creates a parse error for `path`.
+    pub fn parse(path: impl Into<PathBuf>, reason: impl Into<String>) -> Self {
+        Self::Parse {
+            path: path.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Wraps the error with the pid of the process being collected.
+    pub fn for_pid(self, pid: u32) -> Self {
+        Self::Process {
+            pid,
+            source: Box::new(self),
+        }
+    }
+
+    /// Kind of the underlying I/O error, if any.
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            Self::Io { source, .. } => Some(source.kind()),
+            Self::Process { source, .. } => source.io_kind(),
+            _ => None,
+        }
+    }
+
+    /// True if the process exited or the file does not exist.
+    pub fn is_not_found(&self) -> bool {
+        self.io_kind() == Some(io::ErrorKind::NotFound)
+    }
+
+    /// True if access to the file was denied.
+    pub fn is_permission_denied(&self) -> bool {
+        self.io_kind() == Some(io::ErrorKind::PermissionDenied)
+    }
+
+    /// Short, stable category name, suitable as a metric label value.
+    pub fn category(&self) -> &'static str {
+        match self {
+            // Kernel threads and zombies have no address space, reads fail with ESRCH
+            Self::Io { source, .. } if source.raw_os_error() == Some(libc::ESRCH) => "not_found",
+            Self::Io { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => "not_found",
+                io::ErrorKind::PermissionDenied => "permission_denied",
+                _ => "io",
+            },
+            Self::Parse { .. } => "parse",
+            Self::Process { source, .. } => source.category(),
+            Self::ScanAborted(_) => "scan_aborted",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_includes_context() {
+        let err = CollectError::io(
+            "/proc/42/smaps_rollup",
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        )
+        .for_pid(42);
+
+        let msg = err.to_string();
+        assert!(msg.starts_with("pid 42: failed to read /proc/42/smaps_rollup"));
+        assert!(err.is_permission_denied());
+        assert!(!err.is_not_found());
+    }
+
+    #[test]
+    fn test_category() {
+        let not_found = CollectError::io("/proc/1/stat", io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(not_found.category(), "not_found");
+        let kernel_thread = CollectError::io(
+            "/proc/2/smaps_rollup",
+            io::Error::from_raw_os_error(libc::ESRCH),
+        );
+        assert_eq!(kernel_thread.category(), "not_found");
+        assert_eq!(
+            CollectError::parse("/proc/1/stat", "truncated").category(),
+            "parse"
+        );
+        assert_eq!(
+            CollectError::io("/proc/1/io", io::Error::other("boom"))
+                .for_pid(1)
+                .category(),
+            "io"
+        );
+        assert_eq!(
+            CollectError::ScanAborted("rss".into()).category(),
+            "scan_aborted"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Size budget of the /metrics exposition.
+//!
+//! Some scrape proxies cap response bodies and fail the whole scrape above
+//! the cap. With `max_exposition_bytes` set, encoded families are dropped in
+//! the order of `exposition_drop_order` until the exposition fits: by default
+//! per-process families ("process") before Top-N families ("top"), largest
+//! first within a class. Aggregates, system and exporter families are never
+//! dropped, so they alone may still exceed the budget.
+
+use prometheus::core::Collector;
+use prometheus::proto::MetricFamily;
+use prometheus::{GaugeVec, Opts};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, warn};
+
+/// Classes of families that may be dropped, in the default drop order.
+pub const DROP_CLASSES: &[&str] = &["process", "top"];
+
+/// Whether a dropped exposition has been logged at warn level.
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Drop class of `family`: "top" for Top-N families (`rank` label),
+/// "process" for per-process families (`pid` label), None for the rest.
+pub fn family_class(family: &MetricFamily) -> Option<&'static str> {
+    let labels = family.get_metric().first()?.get_label();
+    if labels.iter().any(|l| l.name() == "rank") {
+        Some("top")
+    } else if labels.iter().any(|l| l.name() == "pid") {
+        Some("process")
+    } else {
+        None
+    }
+}
+
+/// Drops families from `families` and their text from `encoded` until the
+/// encoded size is at most `max_bytes`, class by class in `order`. Returns
+/// the number of families dropped per class of `order`.
+pub fn apply(
+    families: &mut Vec<MetricFamily>,
+    encoded: &mut Vec<(String, Vec<u8>)>,
+    max_bytes: usize,
+    order: &[String],
+) -> Vec<(String, usize)> {
+    let mut total: usize = encoded.iter().map(|(_, text)| text.len()).sum();
+    let mut dropped: Vec<(String, usize)> = order.iter().map(|c| (c.clone(), 0)).collect();
+    if total <= max_bytes {
+        return dropped;
+    }
+    let before = total;
+
+    for (class, count) in &mut dropped {
+        let mut candidates: Vec<(usize, String)> = families
+            .iter()
+            .filter(|f| family_class(f) == Some(class.as_str()))
+            .filter_map(|f| {
+                let (name, text) = encoded.iter().find(|(name, _)| name == f.name())?;
+                Some((text.len(), name.clone()))
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        for (bytes, name) in candidates {
+            if total <= max_bytes {
+                break;
+            }
+            encoded.retain(|(n, _)| *n != name);
+            families.retain(|f| f.name() != name);
+            total -= bytes;
+            *count += 1;
+        }
+    }
+
+    let summary: Vec<String> = dropped
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(class, count)| format!("{} {}", count, class))
+        .collect();
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        warn!(
+            "Exposition of {} bytes exceeds max_exposition_bytes {}, dropped {} families to {} bytes; further truncations are logged at debug level",
+            before,
+            max_bytes,
+            summary.join(", "),
+            total
+        );
+    } else {
+        debug!(
+            "Exposition of {} bytes truncated to {} bytes, dropped {} families",
+            before,
+            total,
+            summary.join(", ")
+        );
+    }
+    dropped
+}
+
+/// `herakles_exporter_exposition_dropped_families` for the `dropped` counts.
+pub fn dropped_families(dropped: &[(String, usize)]) -> Vec<MetricFamily> {
+    let Ok(gauge) = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_exposition_dropped_families",
+            "Families left out of this exposition to stay within max_exposition_bytes, by class",
+        ),
+        &["class"],
+    ) else {
+        return Vec::new();
+    };
+    for (class, count) in dropped {
+        gauge.with_label_values(&[class]).set(*count as f64);
+    }
+    gauge.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn family(name: &str, labels: &[&str]) -> MetricFamily {
+        let vec = GaugeVec::new(Opts::new(name, "help"), labels).unwrap();
+        vec.with_label_values(&vec!["1"; labels.len()]).set(1.0);
+        vec.collect().remove(0)
+    }
+
+    #[test]
+    fn test_apply_budget() {
+        let mut families = vec![
+            family("herakles_proc_mem_rss_bytes", &["pid", "name"]),
+            family("herakles_proc_mem_uss_bytes", &["pid", "name"]),
+            family("herakles_proc_mem_top_rss_bytes", &["group", "rank"]),
+            family("herakles_proc_mem_group_rss_bytes_sum", &["group"]),
+        ];
+        assert_eq!(family_class(&families[0]), Some("process"));
+        assert_eq!(family_class(&families[2]), Some("top"));
+        assert_eq!(family_class(&families[3]), None);
+        let sizes = [300, 200, 100, 50];
+        let mut encoded: Vec<(String, Vec<u8>)> = families
+            .iter()
+            .zip(sizes)
+            .map(|(f, size)| (f.name().to_string(), vec![0; size]))
+            .collect();
+        let order: Vec<String> = DROP_CLASSES.iter().map(|c| c.to_string()).collect();
+
+        // Fits: nothing dropped
+        let dropped = apply(&mut families, &mut encoded, 650, &order);
+        assert_eq!(
+            dropped,
+            [("process".to_string(), 0), ("top".to_string(), 0)]
+        );
+
+        // The largest per-process family is enough
+        let mut f = families.clone();
+        let mut e = encoded.clone();
+        let dropped = apply(&mut f, &mut e, 400, &order);
+        assert_eq!(
+            dropped,
+            [("process".to_string(), 1), ("top".to_string(), 0)]
+        );
+        assert!(e
+            .iter()
+            .all(|(name, _)| name != "herakles_proc_mem_rss_bytes"));
+        assert_eq!(f.len(), 3);
+
+        // Aggregates are kept even above the budget
+        let dropped = apply(&mut families, &mut encoded, 10, &order);
+        assert_eq!(
+            dropped,
+            [("process".to_string(), 2), ("top".to_string(), 1)]
+        );
+        assert_eq!(encoded.len(), 1);
+        assert_eq!(families[0].name(), "herakles_proc_mem_group_rss_bytes_sum");
+
+        let family = dropped_families(&dropped);
+        assert_eq!(family[0].get_metric().len(), 2);
+    }
+}
@@ -0,0 +1,238 @@
+//! Statistics of the last /metrics exposition.
+//!
+//! Every render of the full exposition records the series and bytes of each
+//! family, the time taken by each pipeline stage and the label values
+//! carried by the most series. `/debug/exposition` shows them, so
+//! cardinality can be tuned without downloading and grepping /metrics.
+
+use ahash::AHashMap as HashMap;
+use chrono::{DateTime, Utc};
+use prometheus::proto::MetricFamily;
+use std::fmt::Write as FmtWrite;
+use std::time::Duration;
+
+/// Number of labels and label values listed as top cardinality contributors.
+pub const TOP_CONTRIBUTORS: usize = 10;
+
+/// Series and encoded size of one family.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FamilyStats {
+    pub name: String,
+    pub series: usize,
+    pub bytes: usize,
+}
+
+/// A label value and the number of series carrying it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contributor {
+    pub label: String,
+    pub value: String,
+    pub series: usize,
+}
+
+/// Statistics of one exposition.
+#[derive(Debug, Clone)]
+pub struct ExpositionStats {
+    pub rendered_at: DateTime<Utc>,
+    /// Families by encoded size, largest first
+    pub families: Vec<FamilyStats>,
+    /// Pipeline stages in order with their build time
+    pub stages: Vec<(&'static str, Duration)>,
+    /// Labels with the most distinct values and their series
+    pub labels: Vec<(String, usize, usize)>,
+    /// Label values carried by the most series
+    pub contributors: Vec<Contributor>,
+}
+
+impl ExpositionStats {
+    /// Builds the statistics of `families`, encoded into `encoded` (family
+    /// name and text). Families that failed to encode count with 0 bytes.
+    pub fn new(
+        families: &[MetricFamily],
+        encoded: &[(String, Vec<u8>)],
+        stages: Vec<(&'static str, Duration)>,
+    ) -> Self {
+        let bytes: HashMap<&str, usize> = encoded
+            .iter()
+            .map(|(name, text)| (name.as_str(), text.len()))
+            .collect();
+        let mut family_stats: Vec<FamilyStats> = families
+            .iter()
+            .map(|f| FamilyStats {
+                name: f.name().to_string(),
+                series: f.get_metric().len(),
+                bytes: bytes.get(f.name()).copied().unwrap_or(0),
+            })
+            .collect();
+        family_stats.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+
+        let mut counts: HashMap<(&str, &str), usize> = HashMap::new();
+        for metric in families.iter().flat_map(|f| f.get_metric()) {
+            for label in metric.get_label() {
+                *counts.entry((label.name(), label.value())).or_default() += 1;
+            }
+        }
+        // A label with a single value (uptime, scan_id) adds no cardinality
+        let mut labels: HashMap<&str, (usize, usize)> = HashMap::new();
+        for ((label, _), series) in &counts {
+            let entry = labels.entry(label).or_default();
+            entry.0 += 1;
+            entry.1 += series;
+        }
+        let mut label_stats: Vec<(String, usize, usize)> = labels
+            .into_iter()
+            .filter(|(_, (values, _))| *values > 1)
+            .map(|(label, (values, series))| (label.to_string(), values, series))
+            .collect();
+        label_stats.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        label_stats.truncate(TOP_CONTRIBUTORS);
+
+        let mut contributors: Vec<Contributor> = counts
+            .into_iter()
+            .filter(|((label, _), _)| label_stats.iter().any(|(name, ..)| name == label))
+            .map(|((label, value), series)| Contributor {
+                label: label.to_string(),
+                value: value.to_string(),
+                series,
+            })
+            .collect();
+        contributors.sort_by(|a, b| {
+            b.series
+                .cmp(&a.series)
+                .then_with(|| (&a.label, &a.value).cmp(&(&b.label, &b.value)))
+        });
+        contributors.truncate(TOP_CONTRIBUTORS);
+
+        Self {
+            rendered_at: Utc::now(),
+            families: family_stats,
+            stages,
+            labels: label_stats,
+            contributors,
+        }
+    }
+
+    /// Plain-text report for /debug/exposition.
+    pub fn render(&self) -> String {
+        let total_series: usize = self.families.iter().map(|f| f.series).sum();
+        let total_bytes: usize = self.families.iter().map(|f| f.bytes).sum();
+        let mut out = String::new();
+
+        writeln!(out, "Last exposition: {}", self.rendered_at.to_rfc3339()).ok();
+        writeln!(
+            out,
+            "Families: {} | Series: {} | Bytes: {}",
+            self.families.len(),
+            total_series,
+            total_bytes
+        )
+        .ok();
+        writeln!(out).ok();
+
+        writeln!(out, "STAGES").ok();
+        writeln!(out, "------").ok();
+        for (name, elapsed) in &self.stages {
+            writeln!(
+                out,
+                "  {:<26} {:>10.3}ms",
+                name,
+                elapsed.as_secs_f64() * 1000.0
+            )
+            .ok();
+        }
+        writeln!(out).ok();
+
+        writeln!(out, "TOP CARDINALITY CONTRIBUTORS").ok();
+        writeln!(out, "----------------------------").ok();
+        for (label, values, series) in &self.labels {
+            writeln!(
+                out,
+                "  {:<40} {:>8} values {:>8} series",
+                label, values, series
+            )
+            .ok();
+        }
+        writeln!(out).ok();
+        for c in &self.contributors {
+            writeln!(
+                out,
+                "  {:<40} {:>8} series",
+                format!("{}=\"{}\"", c.label, c.value),
+                c.series
+            )
+            .ok();
+        }
+        writeln!(out).ok();
+
+        writeln!(out, "FAMILIES (by bytes)").ok();
+        writeln!(out, "-------------------").ok();
+        writeln!(out, "  {:<60} {:>8} {:>10}", "family", "series", "bytes").ok();
+        for f in &self.families {
+            writeln!(out, "  {:<60} {:>8} {:>10}", f.name, f.series, f.bytes).ok();
+        }
+        writeln!(out).ok();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::core::Collector;
+    use prometheus::{GaugeVec, Opts};
+
+    #[test]
+    fn test_exposition_stats() {
+        let rss = GaugeVec::new(
+            Opts::new("herakles_proc_mem_rss_bytes", "RSS"),
+            &["group", "pid"],
+        )
+        .unwrap();
+        rss.with_label_values(&["db", "1"]).set(1.0);
+        rss.with_label_values(&["db", "2"]).set(1.0);
+        rss.with_label_values(&["web", "3"]).set(1.0);
+        let sum = GaugeVec::new(
+            Opts::new("herakles_proc_mem_group_rss_bytes_sum", "Sum"),
+            &["group"],
+        )
+        .unwrap();
+        sum.with_label_values(&["db"]).set(2.0);
+        let families: Vec<MetricFamily> = rss.collect().into_iter().chain(sum.collect()).collect();
+        let encoded = vec![
+            (
+                "herakles_proc_mem_group_rss_bytes_sum".to_string(),
+                vec![0; 100],
+            ),
+            ("herakles_proc_mem_rss_bytes".to_string(), vec![0; 300]),
+        ];
+
+        let stats = ExpositionStats::new(
+            &families,
+            &encoded,
+            vec![("aggregate", Duration::from_millis(2))],
+        );
+        assert_eq!(
+            stats.families[0],
+            FamilyStats {
+                name: "herakles_proc_mem_rss_bytes".to_string(),
+                series: 3,
+                bytes: 300,
+            }
+        );
+        assert_eq!(
+            stats.labels,
+            [("pid".to_string(), 3, 3), ("group".to_string(), 2, 4)]
+        );
+        assert_eq!(
+            stats.contributors[0],
+            Contributor {
+                label: "group".to_string(),
+                value: "db".to_string(),
+                series: 3,
+            }
+        );
+        let report = stats.render();
+        assert!(report.contains("Families: 2 | Series: 4 | Bytes: 400"));
+        assert!(report.contains("aggregate"));
+    }
+}
@@ -0,0 +1,425 @@
+//! Federation of downstream exporters.
+//!
+//! In federation mode the exporter periodically scrapes other
+//! herakles-proc-mem-exporter instances (containers, VMs on the same host),
+//! relabels their series with the target name and merges them into its own
+//! `/metrics` output, so a hypervisor needs a single scrape target.
+//!
+//! Merging happens on the text exposition format: downstream samples are
+//! appended to the local family of the same name, or emitted as a new family.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tracing::{debug, warn};
+
+use crate::config::{FederationConfig, FederationTarget};
+use crate::state::SharedState;
+
+/// Default label carrying the downstream target name.
+pub const DEFAULT_SOURCE_LABEL: &str = "source";
+
+/// Default timeout for a downstream scrape.
+pub const DEFAULT_TIMEOUT_SECONDS: u64 = 5;
+
+/// A metric family from a text exposition.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Family {
+    pub name: String,
+    pub help: Option<String>,
+    pub kind: Option<String>,
+    /// Sample lines, exactly as they are rendered.
+    pub samples: Vec<String>,
+}
+
+/// Last successful scrape result per downstream target.
+#[derive(Default)]
+pub struct FederationCache {
+    targets: RwLock<BTreeMap<String, Vec<Family>>>,
+}
+
+impl FederationCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn store(&self, target: &str, families: Option<Vec<Family>>) {
+        if let Ok(mut targets) = self.targets.write() {
+            match families {
+                Some(f) => {
+                    targets.insert(target.to_string(), f);
+                }
+                None => {
+                    targets.remove(target);
+                }
+            }
+        }
+    }
+
+    /// Merges all cached downstream families into a local exposition.
+    pub fn merge_into(&self, local: &str) -> String {
+        let Ok(targets) = self.targets.read() else {
+            return local.to_string();
+        };
+        let mut families = parse_exposition(local);
+        for remote in targets.values() {
+            merge_families(&mut families, remote);
+        }
+        render_exposition(&families)
+    }
+}
+
+/// Parses a Prometheus text exposition into families.
+///
+/// Samples are attached to the family announced by the preceding HELP/TYPE
+/// lines when their name matches it (including `_sum`/`_count`/`_bucket`
+/// suffixes); otherwise they start an untyped family of their own.
+pub fn parse_exposition(text: &str) -> Vec<Family> {
+    let mut families: Vec<Family> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("# HELP ") {
+            let (name, help) = rest.split_once(' ').unwrap_or((rest, ""));
+            current_family(&mut families, name).help = Some(help.to_string());
+        } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+            let (name, kind) = rest.split_once(' ').unwrap_or((rest, "untyped"));
+            current_family(&mut families, name).kind = Some(kind.to_string());
+        } else if line.starts_with('#') {
+            continue;
+        } else {
+            let name = sample_name(line);
+            let belongs = families
+                .last()
+                .is_some_and(|f| name == f.name || name.starts_with(&format!("{}_", f.name)));
+            if !belongs {
+                families.push(Family {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+            }
+            if let Some(family) = families.last_mut() {
+                family.samples.push(line.to_string());
+            }
+        }
+    }
+
+    families
+}
+
+/// Returns the last family if it has `name`, otherwise starts a new one.
+fn current_family<'a>(families: &'a mut Vec<Family>, name: &str) -> &'a mut Family {
+    if families.last().is_none_or(|f| f.name != name) {
+        families.push(Family {
+            name: name.to_string(),
+            ..Default::default()
+        });
+    }
+    families.last_mut().expect("family was just pushed")
+}
+
+/// Extracts the metric name of a sample line.
+fn sample_name(line: &str) -> &str {
+    let end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Adds labels to a sample line, keeping labels the sample already has.
+pub fn relabel_sample(line: &str, labels: &[(String, String)]) -> String {
+    let name = sample_name(line);
+    let rest = &line[name.len()..];
+
+    let (existing, value) = match rest.strip_prefix('{') {
+        Some(inner) => match inner.rfind('}') {
+            Some(end) => (&inner[..end], &inner[end + 1..]),
+            None => return line.to_string(),
+        },
+        None => ("", rest),
+    };
+
+    let mut out = String::with_capacity(line.len() + 32);
+    out.push_str(name);
+    out.push('{');
+    out.push_str(existing);
+    let mut first = existing.is_empty();
+    let existing_names = label_names(existing);
+    for (key, val) in labels {
+        if existing_names.contains(&key.as_str()) {
+            continue;
+        }
+        if !first {
+            out.push(',');
+        }
+        first = false;
+        let _ = write!(out, "{}=\"{}\"", key, escape_label_value(val));
+    }
+    out.push('}');
+    out.push_str(value);
+    out
+}
+
+/// Names of the labels in a rendered label set, e.g. `a="1",b="x,y"`.
+///
+/// Quoted values are skipped, so label names inside values do not count.
+fn label_names(labels: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = labels;
+    while let Some(eq) = rest.find('=') {
+        names.push(rest[..eq].trim_start_matches([',', ' ']).trim_end());
+        let Some(value) = rest[eq + 1..].trim_start().strip_prefix('"') else {
+            break;
+        };
+        let mut escaped = false;
+        let end = value.find(|c| {
+            let quote = !escaped && c == '"';
+            escaped = !escaped && c == '\\';
+            quote
+        });
+        match end {
+            Some(end) => rest = &value[end + 1..],
+            None => break,
+        }
+    }
+    names
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Appends `remote` families to `local`, joining families of the same name.
+///
+/// Families whose type differs from the local one are dropped, since the
+/// merged output would be rejected by Prometheus.
+pub fn merge_families(local: &mut Vec<Family>, remote: &[Family]) {
+    for family in remote {
+        match local.iter_mut().find(|f| f.name == family.name) {
+            Some(existing) => {
+                let local_kind = existing.kind.as_deref().unwrap_or("untyped");
+                let remote_kind = family.kind.as_deref().unwrap_or("untyped");
+                if local_kind != remote_kind {
+                    debug!(
+                        "Dropping federated family {}: type {} conflicts with local type {}",
+                        family.name, remote_kind, local_kind
+                    );
+                    continue;
+                }
+                existing.samples.extend(family.samples.iter().cloned());
+            }
+            None => local.push(family.clone()),
+        }
+    }
+}
+
+/// Renders families back into the text exposition format.
+pub fn render_exposition(families: &[Family]) -> String {
+    let mut out = String::new();
+    for family in families {
+        if let Some(help) = &family.help {
+            let _ = writeln!(out, "# HELP {} {}", family.name, help);
+        }
+        if let Some(kind) = &family.kind {
+            let _ = writeln!(out, "# TYPE {} {}", family.name, kind);
+        }
+        for sample in &family.samples {
+            out.push_str(sample);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Scrapes a single downstream target and relabels its series.
+async fn scrape_target(
+    client: reqwest::Client,
+    target: FederationTarget,
+    source_label: String,
+) -> (String, Result<Vec<Family>, String>, Duration) {
+    let start = Instant::now();
+    let result = async {
+        let response = client
+            .get(&target.url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+        let body = response.text().await.map_err(|e| e.to_string())?;
+
+        let mut labels = vec![(source_label, target.name.clone())];
+        labels.extend(target.labels.clone().unwrap_or_default());
+
+        let mut families = parse_exposition(&body);
+        for family in &mut families {
+            for sample in &mut family.samples {
+                *sample = relabel_sample(sample, &labels);
+            }
+        }
+        Ok(families)
+    }
+    .await;
+
+    (target.name, result, start.elapsed())
+}
+
+/// Scrapes all configured downstream targets concurrently.
+pub async fn refresh_federation(state: &SharedState, cfg: &FederationConfig) {
+    let timeout = Duration::from_secs(cfg.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS));
+    let client = match reqwest::Client::builder().timeout(timeout).build() {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to build federation HTTP client: {}", e);
+            return;
+        }
+    };
+    let source_label = cfg
+        .source_label
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SOURCE_LABEL.to_string());
+
+    let mut tasks = JoinSet::new();
+    for target in &cfg.targets {
+        tasks.spawn(scrape_target(
+            client.clone(),
+            target.clone(),
+            source_label.clone(),
+        ));
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((name, result, elapsed)) = joined else {
+            continue;
+        };
+
+        state
+            .federation_scrape_duration
+            .with_label_values(&[&name])
+            .set(elapsed.as_secs_f64());
+
+        match result {
+            Ok(families) => {
+                debug!(
+                    "Federated {} families from {} in {:.3}s",
+                    families.len(),
+                    name,
+                    elapsed.as_secs_f64()
+                );
+                state.federation_up.with_label_values(&[&name]).set(1.0);
+                state.federation_cache.store(&name, Some(families));
+            }
+            Err(e) => {
+                warn!("Federation scrape of {} failed: {}", name, e);
+                state.federation_up.with_label_values(&[&name]).set(0.0);
+                // Stale series of a failed target are dropped rather than repeated
+                state.federation_cache.store(&name, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: &str = "# HELP herakles_proc_mem_rss_bytes RSS\n\
+        # TYPE herakles_proc_mem_rss_bytes gauge\n\
+        herakles_proc_mem_rss_bytes{pid=\"1\",name=\"init\"} 100\n\
+        # HELP herakles_system_load1 Load\n\
+        # TYPE herakles_system_load1 gauge\n\
+        herakles_system_load1 0.5\n";
+
+    #[test]
+    fn test_parse_exposition() {
+        let families = parse_exposition(LOCAL);
+        assert_eq!(families.len(), 2);
+        assert_eq!(families[0].name, "herakles_proc_mem_rss_bytes");
+        assert_eq!(families[0].kind.as_deref(), Some("gauge"));
+        assert_eq!(families[0].help.as_deref(), Some("RSS"));
+        assert_eq!(families[0].samples.len(), 1);
+        assert_eq!(families[1].samples, vec!["herakles_system_load1 0.5"]);
+    }
+
+    #[test]
+    fn test_parse_exposition_untyped_and_suffixes() {
+        let text =
+            "# TYPE req histogram\nreq_bucket{le=\"1\"} 1\nreq_sum 2\nreq_count 1\nother 3\n";
+        let families = parse_exposition(text);
+        assert_eq!(families.len(), 2);
+        assert_eq!(families[0].samples.len(), 3);
+        assert_eq!(families[1].name, "other");
+        assert_eq!(families[1].kind, None);
+    }
+
+    #[test]
+    fn test_relabel_sample() {
+        let labels = vec![("source".to_string(), "vm1".to_string())];
+        assert_eq!(
+            relabel_sample("up 1", &labels),
+            "up{source=\"vm1\"} 1".to_string()
+        );
+        assert_eq!(
+            relabel_sample("m{pid=\"1\"} 2 1700000000", &labels),
+            "m{pid=\"1\",source=\"vm1\"} 2 1700000000".to_string()
+        );
+        // Labels set by a nested federation are kept
+        assert_eq!(
+            relabel_sample("m{source=\"inner\"} 2", &labels),
+            "m{source=\"inner\"} 2".to_string()
+        );
+        // A label that merely ends with the key is not a match
+        assert_eq!(
+            relabel_sample("m{xsource=\"a\"} 2", &labels),
+            "m{xsource=\"a\",source=\"vm1\"} 2".to_string()
+        );
+        // Nor is the key inside another label's value
+        assert_eq!(
+            relabel_sample("m{cmd=\"a,source=\",arg=\"\\\"x\"} 2", &labels),
+            "m{cmd=\"a,source=\",arg=\"\\\"x\",source=\"vm1\"} 2".to_string()
+        );
+        assert_eq!(
+            relabel_sample("m{pid=\"1\", source=\"inner\"} 2", &labels),
+            "m{pid=\"1\", source=\"inner\"} 2".to_string()
+        );
+    }
+
+    #[test]
+    fn test_merge_families() {
+        let mut local = parse_exposition(LOCAL);
+        let remote = parse_exposition(
+            "# TYPE herakles_proc_mem_rss_bytes gauge\n\
+             herakles_proc_mem_rss_bytes{pid=\"7\",source=\"vm1\"} 70\n\
+             # TYPE herakles_system_load1 counter\n\
+             herakles_system_load1{source=\"vm1\"} 9\n\
+             # TYPE only_remote gauge\n\
+             only_remote{source=\"vm1\"} 1\n",
+        );
+        merge_families(&mut local, &remote);
+
+        assert_eq!(local.len(), 3);
+        assert_eq!(local[0].samples.len(), 2);
+        // Conflicting type is dropped
+        assert_eq!(local[1].samples.len(), 1);
+        assert_eq!(local[2].name, "only_remote");
+
+        let rendered = render_exposition(&local);
+        assert_eq!(
+            rendered
+                .matches("# TYPE herakles_proc_mem_rss_bytes")
+                .count(),
+            1
+        );
+        assert!(rendered.contains("herakles_proc_mem_rss_bytes{pid=\"7\",source=\"vm1\"} 70\n"));
+    }
+}
@@ -5,11 +5,26 @@
 
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
 use std::fmt::Write as FmtWrite;
+use std::path::Path;
 use tracing::{debug, instrument};
 
-use crate::config::{DEFAULT_BIND_ADDR, DEFAULT_CACHE_TTL, DEFAULT_PORT};
+use crate::cgroup::{DEFAULT_CGROUP_ROOT, DEFAULT_MAX_DEPTH, DEFAULT_STAT_FIELDS};
+use crate::config::{
+    DEFAULT_BIND_ADDR, DEFAULT_CACHE_TTL, DEFAULT_MONITORING_PROCESSES, DEFAULT_OWNER_LABEL,
+    DEFAULT_PORT, DEFAULT_TOP_N_THREADS,
+};
+use crate::exposition_budget::DROP_CLASSES;
 use crate::handlers::health::FOOTER_TEXT;
+use crate::handlers::metrics::DEFAULT_SCRAPE_TIMEOUT_OFFSET;
+use crate::history::{
+    history_enabled, DEFAULT_DOWNSAMPLING_INTERVAL_MINUTES, DEFAULT_DOWNSAMPLING_RAW_MINUTES,
+    DEFAULT_HISTORY_RETENTION_HOURS,
+};
+use crate::overlap;
+use crate::registration::{DEFAULT_ETCD_PREFIX, DEFAULT_SERVICE_NAME, DEFAULT_TTL_SECONDS};
+use crate::short_lived::DEFAULT_POLL_INTERVAL_MS;
 use crate::state::SharedState;
+use crate::warm_start;
 
 /// Handler for the /config endpoint.
 #[instrument(skip(state))]
@@ -41,12 +56,63 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
         cfg.port.unwrap_or(DEFAULT_PORT)
     )
     .ok();
+    if let Some(path) = &cfg.ready_file {
+        writeln!(out, "ready_file:                 {}", path.display()).ok();
+    }
+    if let Some(path) = &cfg.pid_file {
+        writeln!(out, "pid_file:                   {}", path.display()).ok();
+    }
     writeln!(
         out,
         "cache_ttl:                  {} seconds",
         cfg.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL)
     )
     .ok();
+    writeln!(
+        out,
+        "collection_mode:            {}",
+        cfg.collection_mode.as_deref().unwrap_or("background")
+    )
+    .ok();
+    writeln!(
+        out,
+        "cache_snapshot:             {}",
+        cfg.cache_snapshot
+            .as_ref()
+            .map_or_else(|| "off".to_string(), |p| p.display().to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "startup_scan_timeout:       {}",
+        cfg.startup_scan_timeout_seconds
+            .map_or_else(|| "off".to_string(), |s| format!("{} seconds", s))
+    )
+    .ok();
+    if cfg.cache_snapshot.is_some() {
+        writeln!(
+            out,
+            "cache_snapshot_max_age:     {} seconds",
+            cfg.cache_snapshot_max_age_seconds
+                .unwrap_or(warm_start::DEFAULT_MAX_AGE_SECONDS)
+        )
+        .ok();
+    }
+    for window in cfg.scan_windows.iter().flatten() {
+        writeln!(
+            out,
+            "scan_window:                {}-{} {}s{}",
+            window.start,
+            window.end,
+            window.cache_ttl,
+            window
+                .days
+                .as_ref()
+                .map(|d| format!(" ({})", d.join(",")))
+                .unwrap_or_default()
+        )
+        .ok();
+    }
     writeln!(out).ok();
 
     writeln!(out, "TLS/SSL CONFIGURATION").ok();
@@ -97,6 +163,30 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
             .unwrap_or_else(|| "none".to_string())
     )
     .ok();
+    writeln!(
+        out,
+        "exclude_users:              {}",
+        cfg.exclude_users
+            .as_ref()
+            .map(|v| v.join(", "))
+            .unwrap_or_else(|| "none".to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "hide_monitoring_processes:  {}",
+        cfg.hide_monitoring_processes.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "monitoring_processes:       {}",
+        cfg.monitoring_processes
+            .as_ref()
+            .map(|v| v.join(", "))
+            .unwrap_or_else(|| DEFAULT_MONITORING_PROCESSES.join(", "))
+    )
+    .ok();
     writeln!(
         out,
         "parallelism:                {}",
@@ -105,6 +195,23 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
             .unwrap_or_else(|| "auto".to_string())
     )
     .ok();
+    match &cfg.scan_autotune {
+        Some(autotune) => {
+            writeln!(
+                out,
+                "scan_autotune:              {}-{} threads",
+                autotune.min_threads.unwrap_or(1),
+                autotune
+                    .max_threads
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "auto".to_string())
+            )
+            .ok();
+        }
+        None => {
+            writeln!(out, "scan_autotune:              disabled").ok();
+        }
+    }
     writeln!(
         out,
         "max_processes:              {}",
@@ -113,6 +220,12 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
             .unwrap_or_else(|| "unlimited".to_string())
     )
     .ok();
+    writeln!(
+        out,
+        "max_processes_by:           {}",
+        cfg.max_processes_by.as_deref().unwrap_or("pid")
+    )
+    .ok();
     writeln!(
         out,
         "top_n_subgroup:             {}",
@@ -125,6 +238,78 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
         cfg.top_n_others.unwrap_or(10)
     )
     .ok();
+    writeln!(
+        out,
+        "per_process_metrics:        {}",
+        cfg.per_process_metrics.unwrap_or(true)
+    )
+    .ok();
+    writeln!(
+        out,
+        "stable_topn:                {}",
+        cfg.stable_topn.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "top_n_hysteresis_scans:     {}",
+        cfg.top_n_hysteresis_scans.unwrap_or(1)
+    )
+    .ok();
+    writeln!(
+        out,
+        "max_exported_processes:     {}",
+        cfg.max_exported_processes
+            .map_or("unlimited".to_string(), |n| n.to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "min_uss_percent_of_subgroup: {}",
+        cfg.min_uss_percent_of_subgroup
+            .map_or("off".to_string(), |p| format!("{}%", p))
+    )
+    .ok();
+    writeln!(
+        out,
+        "refresh_every:              {}",
+        cfg.refresh_every
+            .as_ref()
+            .filter(|r| !r.is_empty())
+            .map(|r| {
+                r.iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_else(|| "none".to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "group_labels:               {}",
+        cfg.group_labels
+            .as_ref()
+            .filter(|g| !g.is_empty())
+            .map(|g| {
+                g.iter()
+                    .map(|(key, labels)| {
+                        let labels: Vec<String> =
+                            labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                        format!("{} {{{}}}", key, labels.join(", "))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_else(|| "none".to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "owner_label:                {}",
+        cfg.owner_label.as_deref().unwrap_or(DEFAULT_OWNER_LABEL)
+    )
+    .ok();
     writeln!(out).ok();
 
     writeln!(out, "PERFORMANCE TUNING").ok();
@@ -175,6 +360,25 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
         cfg.enable_pprof.unwrap_or(false)
     )
     .ok();
+    writeln!(
+        out,
+        "admin_token:                {}",
+        if cfg.admin_token.is_some() {
+            "set"
+        } else {
+            "none"
+        }
+    )
+    .ok();
+    writeln!(
+        out,
+        "audit_log:                  {}",
+        cfg.audit_log
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "disabled".to_string())
+    )
+    .ok();
     writeln!(out).ok();
 
     writeln!(out, "METRICS FLAGS").ok();
@@ -203,6 +407,196 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
         cfg.enable_cpu.unwrap_or(true)
     )
     .ok();
+    writeln!(
+        out,
+        "enable_rates:               {}",
+        cfg.enable_rates.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_rule_hit_metrics:    {}",
+        cfg.enable_rule_hit_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_priority_metrics:    {}",
+        cfg.enable_priority_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_privilege_metrics:   {}",
+        cfg.enable_privilege_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_delay_metrics:       {}",
+        cfg.enable_delay_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_io_metrics:          {}",
+        cfg.enable_io_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_anon_file_metrics:   {}",
+        cfg.enable_anon_file_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_shmem_metrics:       {}",
+        cfg.enable_shmem_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_schedstat:           {}",
+        cfg.enable_schedstat.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_numa:                {}",
+        cfg.enable_numa.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "per_thread:                 {}",
+        cfg.per_thread.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "top_n_threads:              {}",
+        cfg.top_n_threads.unwrap_or(DEFAULT_TOP_N_THREADS)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_start_time_metrics:  {}",
+        cfg.enable_start_time_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_wchan_metrics:       {}",
+        cfg.enable_wchan_metrics.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "system_collectors:          {}",
+        cfg.system_collectors
+            .as_ref()
+            .map_or_else(|| "all".to_string(), |c| c.join(","))
+    )
+    .ok();
+    writeln!(
+        out,
+        "session_aggregation:        {}",
+        cfg.session_aggregation.as_deref().unwrap_or("off")
+    )
+    .ok();
+    writeln!(
+        out,
+        "sample_timestamps:          {}",
+        cfg.sample_timestamps.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "scan_id_label:              {}",
+        cfg.scan_id_label.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "proc_id_label:              {}",
+        cfg.proc_id_label.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "metric_schema:              {}",
+        cfg.metric_schema.as_deref().unwrap_or("v1")
+    )
+    .ok();
+    writeln!(
+        out,
+        "scrape_timeout_offset:      {}s",
+        cfg.scrape_timeout_offset_seconds
+            .unwrap_or(DEFAULT_SCRAPE_TIMEOUT_OFFSET)
+    )
+    .ok();
+    writeln!(
+        out,
+        "max_exposition_bytes:       {}",
+        cfg.max_exposition_bytes
+            .map_or("unlimited".to_string(), |b| b.to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "exposition_drop_order:      {}",
+        cfg.exposition_drop_order
+            .as_deref()
+            .map_or(DROP_CLASSES.join(", "), |order| order.join(", "))
+    )
+    .ok();
+    writeln!(out, "enable_history:             {}", history_enabled(cfg)).ok();
+    writeln!(
+        out,
+        "enable_grafana:             {}",
+        cfg.enable_grafana.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "history_points:             {}",
+        cfg.history_points.unwrap_or(120)
+    )
+    .ok();
+    writeln!(
+        out,
+        "history_db:                 {}",
+        cfg.history_db
+            .as_ref()
+            .map_or_else(|| "off".to_string(), |p| p.display().to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "history_retention_hours:    {}",
+        cfg.history_retention_hours
+            .unwrap_or(DEFAULT_HISTORY_RETENTION_HOURS)
+    )
+    .ok();
+    match &cfg.history_downsampling {
+        Some(downsampling) => {
+            writeln!(
+                out,
+                "history_downsampling:       raw {}m, then {}m intervals",
+                downsampling
+                    .raw_minutes
+                    .unwrap_or(DEFAULT_DOWNSAMPLING_RAW_MINUTES),
+                downsampling
+                    .interval_minutes
+                    .unwrap_or(DEFAULT_DOWNSAMPLING_INTERVAL_MINUTES)
+            )
+            .ok();
+        }
+        None => {
+            writeln!(out, "history_downsampling:       disabled").ok();
+        }
+    }
     writeln!(out).ok();
 
     writeln!(out, "CLASSIFICATION").ok();
@@ -247,6 +641,12 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
         cfg.log_level.as_deref().unwrap_or("info")
     )
     .ok();
+    writeln!(
+        out,
+        "active_log_level:           {}",
+        state.log_control.current()
+    )
+    .ok();
     writeln!(
         out,
         "enable_file_logging:        {}",
@@ -262,6 +662,441 @@ pub async fn config_handler(State(state): State<SharedState>) -> impl IntoRespon
             .unwrap_or_else(|| "none".to_string())
     )
     .ok();
+    writeln!(
+        out,
+        "diagnostics_dump:           {}",
+        cfg.diagnostics_dump.as_deref().unwrap_or("log")
+    )
+    .ok();
+    writeln!(out).ok();
+
+    writeln!(out, "SELF LIMITS").ok();
+    writeln!(out, "-----------").ok();
+    let limits = cfg.self_limits.clone().unwrap_or_default();
+    writeln!(
+        out,
+        "max_rss_mb:                 {}",
+        limits
+            .max_rss_mb
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "unlimited".to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "nice:                       {}",
+        limits
+            .nice
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "unchanged".to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "max_scan_cpu_percent:       {}",
+        limits
+            .max_scan_cpu_percent
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "unlimited".to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "rlimit_as_mb:               {}",
+        limits
+            .rlimit_as_mb
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "none".to_string())
+    )
+    .ok();
+    writeln!(out).ok();
+
+    writeln!(out, "SANDBOX").ok();
+    writeln!(out, "-------").ok();
+    let sandbox = cfg.sandbox.clone().unwrap_or_default();
+    writeln!(
+        out,
+        "landlock:                   {}",
+        sandbox.landlock.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "seccomp:                    {}",
+        sandbox.seccomp.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "seccomp_action:             {}",
+        sandbox.seccomp_action.as_deref().unwrap_or("errno")
+    )
+    .ok();
+    writeln!(out).ok();
+
+    writeln!(out, "LABEL EXTRACTORS").ok();
+    writeln!(out, "----------------").ok();
+    match &cfg.label_extractors {
+        Some(extractors) if !extractors.is_empty() => {
+            for extractor in extractors {
+                writeln!(
+                    out,
+                    "{:<28}{} labels={}",
+                    format!("{}:", extractor.source.as_deref().unwrap_or("cmdline")),
+                    extractor.pattern,
+                    extractor.labels.join(",")
+                )
+                .ok();
+            }
+        }
+        _ => {
+            writeln!(out, "label_extractors:           none").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "ENVIRON").ok();
+    writeln!(out, "-------").ok();
+    match &cfg.environ {
+        Some(environ) => {
+            writeln!(
+                out,
+                "variables:                  {}",
+                environ.variables.join(",")
+            )
+            .ok();
+            writeln!(
+                out,
+                "labels:                     {}",
+                environ.labels.unwrap_or(true)
+            )
+            .ok();
+            writeln!(
+                out,
+                "classify_by:                {}",
+                environ.classify_by.as_deref().unwrap_or("none")
+            )
+            .ok();
+        }
+        None => {
+            writeln!(out, "environ:                    disabled").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "CGROUPS").ok();
+    writeln!(out, "-------").ok();
+    match &cfg.cgroups {
+        Some(cgroups) => {
+            writeln!(
+                out,
+                "root:                       {}",
+                cgroups
+                    .root
+                    .as_deref()
+                    .unwrap_or(Path::new(DEFAULT_CGROUP_ROOT))
+                    .display()
+            )
+            .ok();
+            writeln!(
+                out,
+                "max_depth:                  {}",
+                cgroups.max_depth.unwrap_or(DEFAULT_MAX_DEPTH)
+            )
+            .ok();
+            writeln!(
+                out,
+                "stat_fields:                {}",
+                cgroups
+                    .stat_fields
+                    .as_ref()
+                    .map_or_else(|| DEFAULT_STAT_FIELDS.join(","), |f| f.join(","))
+            )
+            .ok();
+            writeln!(
+                out,
+                "process_limits:             {}",
+                cgroups.process_limits.unwrap_or(false)
+            )
+            .ok();
+            writeln!(
+                out,
+                "unit_limits:                {}",
+                cgroups.unit_limits.unwrap_or(false)
+            )
+            .ok();
+        }
+        None => {
+            writeln!(out, "cgroups:                    disabled").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "SHORT-LIVED PROCESSES").ok();
+    writeln!(out, "---------------------").ok();
+    match &cfg.short_lived {
+        Some(short_lived) => {
+            writeln!(
+                out,
+                "poll_interval_ms:           {}",
+                short_lived
+                    .poll_interval_ms
+                    .unwrap_or(DEFAULT_POLL_INTERVAL_MS)
+            )
+            .ok();
+        }
+        None => {
+            writeln!(out, "short_lived:                disabled").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "PRIVACY").ok();
+    writeln!(out, "-------").ok();
+    writeln!(
+        out,
+        "name_hash:                  {}",
+        cfg.name_hash.unwrap_or(false)
+    )
+    .ok();
+    writeln!(
+        out,
+        "name_hash_salt:             {}",
+        if cfg.name_hash_salt.is_some() {
+            "set"
+        } else {
+            "none"
+        }
+    )
+    .ok();
+    writeln!(out).ok();
+
+    writeln!(out, "TENANTS").ok();
+    writeln!(out, "-------").ok();
+    match &cfg.tenants {
+        Some(tenants) if !tenants.is_empty() => {
+            for (name, tenant) in tenants {
+                let labels = tenant
+                    .labels
+                    .as_ref()
+                    .map(|l| {
+                        l.iter()
+                            .map(|(k, v)| format!("{}={}", k, v))
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    })
+                    .unwrap_or_default();
+                writeln!(
+                    out,
+                    "{:<28}/metrics/{} search_mode={} labels={{{}}}",
+                    format!("{}:", name),
+                    name,
+                    tenant.search_mode.as_deref().unwrap_or("inherit"),
+                    labels
+                )
+                .ok();
+            }
+        }
+        _ => {
+            writeln!(out, "tenants:                    none").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "FEDERATION").ok();
+    writeln!(out, "----------").ok();
+    match &cfg.federation {
+        Some(fed) => {
+            writeln!(
+                out,
+                "interval_seconds:           {}",
+                fed.interval_seconds
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "cache_ttl".to_string())
+            )
+            .ok();
+            writeln!(
+                out,
+                "timeout_seconds:            {}",
+                fed.timeout_seconds
+                    .unwrap_or(crate::federation::DEFAULT_TIMEOUT_SECONDS)
+            )
+            .ok();
+            writeln!(
+                out,
+                "source_label:               {}",
+                fed.source_label
+                    .as_deref()
+                    .unwrap_or(crate::federation::DEFAULT_SOURCE_LABEL)
+            )
+            .ok();
+            for target in &fed.targets {
+                writeln!(out, "{:<28}{}", format!("{}:", target.name), target.url).ok();
+            }
+        }
+        None => {
+            writeln!(out, "federation:                 disabled").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "REGISTRATION").ok();
+    writeln!(out, "------------").ok();
+    match &cfg.registration {
+        Some(reg) => {
+            writeln!(out, "backend:                    {}", reg.backend).ok();
+            writeln!(out, "address:                    {}", reg.address).ok();
+            writeln!(
+                out,
+                "service_name:               {}",
+                reg.service_name.as_deref().unwrap_or(DEFAULT_SERVICE_NAME)
+            )
+            .ok();
+            writeln!(
+                out,
+                "service_id:                 {}",
+                reg.service_id
+                    .as_deref()
+                    .unwrap_or("<service_name>-<host>-<port>")
+            )
+            .ok();
+            writeln!(
+                out,
+                "advertise_address:          {}",
+                reg.advertise_address.as_deref().unwrap_or("bind address")
+            )
+            .ok();
+            writeln!(
+                out,
+                "tags:                       {}",
+                reg.tags.as_deref().unwrap_or_default().join(",")
+            )
+            .ok();
+            writeln!(
+                out,
+                "ttl_seconds:                {}",
+                reg.ttl_seconds.unwrap_or(DEFAULT_TTL_SECONDS)
+            )
+            .ok();
+            writeln!(
+                out,
+                "token:                      {}",
+                if reg.token.is_some() { "set" } else { "none" }
+            )
+            .ok();
+            if reg.backend == "etcd" {
+                writeln!(
+                    out,
+                    "key_prefix:                 {}",
+                    reg.key_prefix.as_deref().unwrap_or(DEFAULT_ETCD_PREFIX)
+                )
+                .ok();
+            }
+        }
+        None => {
+            writeln!(out, "registration:               disabled").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "MDNS ANNOUNCEMENT").ok();
+    writeln!(out, "-----------------").ok();
+    match &cfg.mdns {
+        Some(mdns) => {
+            writeln!(
+                out,
+                "instance_name:              {}",
+                mdns.instance_name.as_deref().unwrap_or("host name")
+            )
+            .ok();
+            writeln!(
+                out,
+                "advertise_address:          {}",
+                mdns.advertise_address
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_else(|| "bind address".to_string())
+            )
+            .ok();
+            writeln!(
+                out,
+                "compiled_in:                {}",
+                cfg!(feature = "mdns")
+            )
+            .ok();
+        }
+        None => {
+            writeln!(out, "mdns:                       disabled").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "OVERLAP PROBE").ok();
+    writeln!(out, "-------------").ok();
+    match &cfg.overlap_probe {
+        Some(probe) => {
+            writeln!(out, "urls:                       {}", probe.urls.join(",")).ok();
+            writeln!(
+                out,
+                "action:                     {}",
+                probe.action.as_deref().unwrap_or("warn")
+            )
+            .ok();
+            writeln!(
+                out,
+                "timeout_seconds:            {}",
+                probe
+                    .timeout_seconds
+                    .unwrap_or(overlap::DEFAULT_TIMEOUT_SECONDS)
+            )
+            .ok();
+        }
+        None => {
+            writeln!(out, "overlap_probe:              disabled").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "OTLP TRACING").ok();
+    writeln!(out, "------------").ok();
+    match &cfg.otlp {
+        Some(otlp) => {
+            writeln!(out, "endpoint:                   {}", otlp.endpoint).ok();
+            writeln!(
+                out,
+                "sample_ratio:               {}",
+                otlp.sample_ratio.unwrap_or(1.0)
+            )
+            .ok();
+            writeln!(
+                out,
+                "compiled_in:                {}",
+                cfg!(feature = "otlp")
+            )
+            .ok();
+        }
+        None => {
+            writeln!(out, "otlp:                       disabled").ok();
+        }
+    }
+    writeln!(out).ok();
+
+    writeln!(out, "TEXTFILE OUTPUT").ok();
+    writeln!(out, "---------------").ok();
+    writeln!(
+        out,
+        "textfile_output:            {}",
+        cfg.textfile_output
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "none".to_string())
+    )
+    .ok();
+    writeln!(
+        out,
+        "enable_http:                {}",
+        cfg.enable_http.unwrap_or(true)
+    )
+    .ok();
     writeln!(out).ok();
 
     writeln!(out, "TEST DATA").ok();
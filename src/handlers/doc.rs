@@ -28,10 +28,18 @@ DESCRIPTION: Prometheus exporter for per-process RSS/PSS/USS and CPU metrics
 HTTP ENDPOINTS
 --------------
 GET /metrics     - Prometheus metrics endpoint
+GET /metrics/<tenant> - Metrics limited to a configured tenant
 GET /health      - Health check with internal statistics (plain text)
 GET /config      - Current configuration (plain text)
-GET /subgroups   - Loaded subgroups overview (plain text)
+GET /subgroups   - Loaded subgroups with rule hits of the last scan (plain text)
+GET /api/v1/unclassified - Top unclassified process names since startup (JSON)
+POST /grafana/search, /grafana/query - Grafana JSON datasource (enable_grafana)
+GET /api/v1/query_range - Subgroup series from the scan history (enable_history)
 GET /doc         - This documentation (plain text)
+GET /export.csv  - Current processes as CSV (?group=&subgroup=&name=&sort=&order=&limit=)
+PUT /-/loglevel  - Change log level at runtime (requires admin_token)
+GET /-/selftest  - Run a mini scan and check parsers (requires admin_token)
+GET /debug/exposition - Series, bytes and stage timings of the last /metrics exposition
 
 AVAILABLE METRICS
 -----------------
@@ -43,6 +51,7 @@ herakles_proc_mem_cpu_time_seconds       - Total CPU time per process
 
 herakles_proc_mem_group_*_sum            - Aggregated metrics per subgroup
 herakles_proc_mem_top_*                  - Top-N metrics per subgroup
+herakles_proc_mem_session_uss_bytes_sum  - USS per session or process group
 
 CONFIGURATION
 -------------
@@ -59,6 +68,8 @@ Key configuration options:
 - min_uss_kb: Minimum USS threshold (default: 0)
 - top_n_subgroup: Top-N processes per subgroup (default: 3)
 - top_n_others: Top-N processes for "other" group (default: 10)
+- max_exported_processes: Cap on per-process series by USS (default: unlimited)
+- min_uss_percent_of_subgroup: Export only heavy hitters of a subgroup (default: off)
 
 TLS/SSL Configuration:
 - enable_tls: Enable HTTPS (default: false)
@@ -72,6 +83,9 @@ herakles-proc-mem-exporter check --all        - Validate system requirements
 herakles-proc-mem-exporter config -o config.yaml - Generate config file
 herakles-proc-mem-exporter test               - Test metrics collection
 herakles-proc-mem-exporter subgroups          - List available subgroups
+herakles-proc-mem-exporter dump -o snap.parquet - Write scans for offline analysis
+herakles-proc-mem-exporter --once             - Collect once, print metrics and exit
+herakles-proc-mem-exporter --textfile-output <file.prom> - Also write node_exporter textfile
 herakles-proc-mem-exporter --help             - Show all CLI options
 
 EXAMPLE USAGE
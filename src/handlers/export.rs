@@ -0,0 +1,214 @@
+//! CSV export endpoint handler.
+//!
+//! This module provides the `/export.csv` endpoint that returns the current
+//! cache snapshot as CSV for spreadsheets, one row per classified process.
+//! Query parameters filter and sort the rows:
+//! - `group`, `subgroup`: exact match
+//! - `name`: substring of the process name
+//! - `sort`: column to sort by (default `uss`), `order`: `asc` | `desc` (default `desc`)
+//! - `limit`: maximum number of rows
+
+use axum::{
+    extract::{Query, State},
+    http::{header, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::fmt::Write as FmtWrite;
+use std::sync::Arc;
+use tracing::{debug, instrument};
+
+use crate::cache::ProcMem;
+use crate::privacy::public_name;
+use crate::process::classify_process_with_config;
+use crate::state::SharedState;
+
+/// CSV header line, also the valid values of `sort`.
+const COLUMNS: &[&str] = &[
+    "pid",
+    "name",
+    "group",
+    "subgroup",
+    "rss_bytes",
+    "pss_bytes",
+    "uss_bytes",
+    "cpu_percent",
+    "cpu_time_seconds",
+];
+
+/// Query parameters of /export.csv.
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    group: Option<String>,
+    subgroup: Option<String>,
+    name: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    limit: Option<usize>,
+}
+
+/// A classified process as exported.
+struct Row<'a> {
+    process: &'a ProcMem,
+    group: Arc<str>,
+    subgroup: Arc<str>,
+}
+
+/// Handler for GET /export.csv.
+#[instrument(skip(state))]
+pub async fn export_csv_handler(
+    State(state): State<SharedState>,
+    Query(query): Query<ExportQuery>,
+    method: Method,
+) -> Response {
+    debug!("Processing /export.csv request");
+    state.health_stats.record_http_request();
+
+    let sort = query.sort.as_deref().unwrap_or("uss");
+    let Some(compare) = comparator(sort) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "invalid sort '{}', expected one of: {}\n",
+                sort,
+                COLUMNS.join(", ")
+            ),
+        )
+            .into_response();
+    };
+    let descending = match query.order.as_deref().unwrap_or("desc") {
+        "desc" => true,
+        "asc" => false,
+        other => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("invalid order '{}', expected 'asc' or 'desc'\n", other),
+            )
+                .into_response()
+        }
+    };
+    // Parameters are checked, HEAD needs no rows
+    if method == Method::HEAD {
+        return StatusCode::OK.into_response();
+    }
+
+    let cache = state.cache.read().await;
+    let mut rows: Vec<Row> = cache
+        .processes
+        .values()
+        .filter(|p| {
+            query
+                .name
+                .as_ref()
+                .is_none_or(|n| public_name(&p.name, &state.config).contains(n.as_str()))
+        })
+        .filter_map(|p| {
+            let (group, subgroup) = classify_process_with_config(p.classify_name(), &state.config)?;
+            Some(Row {
+                process: p,
+                group,
+                subgroup,
+            })
+        })
+        .filter(|r| query.group.as_deref().is_none_or(|g| g == r.group.as_ref()))
+        .filter(|r| {
+            query
+                .subgroup
+                .as_deref()
+                .is_none_or(|s| s == r.subgroup.as_ref())
+        })
+        .collect();
+
+    rows.sort_by(|a, b| {
+        let ord = compare(a, b).then_with(|| a.process.pid.cmp(&b.process.pid));
+        if descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+    rows.truncate(query.limit.unwrap_or(usize::MAX));
+
+    let mut out = String::with_capacity(64 * (rows.len() + 1));
+    out.push_str(&COLUMNS.join(","));
+    out.push('\n');
+    for row in &rows {
+        let p = row.process;
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{:.2},{:.2}",
+            p.pid,
+            csv_field(&public_name(&p.name, &state.config)),
+            csv_field(&row.group),
+            csv_field(&row.subgroup),
+            p.rss,
+            p.pss,
+            p.uss,
+            p.cpu_percent,
+            p.cpu_time_seconds
+        )
+        .ok();
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"processes.csv\"",
+            ),
+        ],
+        out,
+    )
+        .into_response()
+}
+
+/// Ordering of two rows by the column `sort`, None for unknown columns.
+fn comparator(sort: &str) -> Option<fn(&Row, &Row) -> Ordering> {
+    Some(match sort {
+        "pid" => |a, b| a.process.pid.cmp(&b.process.pid),
+        "name" => |a, b| a.process.name.cmp(&b.process.name),
+        "group" => |a, b| a.group.cmp(&b.group),
+        "subgroup" => |a, b| a.subgroup.cmp(&b.subgroup),
+        "rss" | "rss_bytes" => |a, b| a.process.rss.cmp(&b.process.rss),
+        "pss" | "pss_bytes" => |a, b| a.process.pss.cmp(&b.process.pss),
+        "uss" | "uss_bytes" => |a, b| a.process.uss.cmp(&b.process.uss),
+        "cpu" | "cpu_percent" => |a, b| a.process.cpu_percent.total_cmp(&b.process.cpu_percent),
+        "cpu_time_seconds" => |a, b| {
+            a.process
+                .cpu_time_seconds
+                .total_cmp(&b.process.cpu_time_seconds)
+        },
+        _ => return None,
+    })
+}
+
+/// Quotes a field if it contains a separator, quote or line break (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("postgres"), "postgres");
+        assert_eq!(csv_field("tmux: a,b"), "\"tmux: a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_comparator_accepts_columns() {
+        for column in COLUMNS {
+            assert!(comparator(column).is_some(), "{}", column);
+        }
+        assert!(comparator("vsz").is_none());
+    }
+}
@@ -0,0 +1,44 @@
+//! Exposition statistics endpoint handler.
+//!
+//! This module provides the `/debug/exposition` endpoint that reports the
+//! series and bytes per family, the pipeline stage timings and the top
+//! cardinality contributors of the last /metrics exposition.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use std::fmt::Write as FmtWrite;
+use tracing::{debug, instrument};
+
+use crate::handlers::health::FOOTER_TEXT;
+use crate::state::SharedState;
+
+/// Handler for the /debug/exposition endpoint.
+#[instrument(skip(state))]
+pub async fn exposition_handler(State(state): State<SharedState>) -> impl IntoResponse {
+    debug!("Processing /debug/exposition request");
+    state.health_stats.record_http_request();
+
+    let mut out = String::new();
+    writeln!(out, "HERAKLES PROC MEM EXPORTER - EXPOSITION").ok();
+    writeln!(out, "=======================================").ok();
+    writeln!(out).ok();
+
+    let stats = state
+        .exposition_stats
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    match stats {
+        Some(stats) => out.push_str(&stats.render()),
+        None => {
+            writeln!(out, "No exposition rendered yet, scrape /metrics first.").ok();
+            writeln!(out).ok();
+        }
+    }
+    writeln!(out, "{FOOTER_TEXT}").ok();
+
+    (
+        StatusCode::OK,
+        [("Content-Type", "text/plain; charset=utf-8")],
+        out,
+    )
+}
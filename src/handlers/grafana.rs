@@ -0,0 +1,235 @@
+//! Grafana JSON datasource endpoints.
+//!
+//! This module implements the contract of the Grafana "simple JSON" (and
+//! Infinity in JSON mode) datasource on top of the in-memory scan history:
+//! - `GET /grafana`: connection test
+//! - `POST /grafana/search`: available targets
+//! - `POST /grafana/query`: time series per subgroup and a table of the
+//!   current top processes
+//!
+//! Time series targets are `<field>:<group>/<subgroup>` with field one of
+//! `uss`, `rss`, `pss`, `cpu_percent` or `processes`.
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, instrument};
+
+use crate::history::{HistoryPoint, SubgroupTotals};
+use crate::privacy::public_name;
+use crate::process::classify_process_with_config;
+use crate::state::SharedState;
+
+/// Target returning the current top processes as a table.
+const TOP_PROCESSES_TARGET: &str = "top_processes";
+/// Rows of the top processes table.
+const TOP_PROCESSES_ROWS: usize = 20;
+
+/// Body of /grafana/search.
+#[derive(Debug, Default, Deserialize)]
+struct SearchRequest {
+    #[serde(default)]
+    target: String,
+}
+
+/// Body of /grafana/query.
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    range: Option<QueryRange>,
+    targets: Vec<QueryTarget>,
+    #[serde(rename = "maxDataPoints")]
+    max_data_points: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryRange {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryTarget {
+    target: String,
+}
+
+/// Handler for GET /grafana (datasource connection test).
+#[instrument(skip(state))]
+pub async fn grafana_test_handler(State(state): State<SharedState>) -> &'static str {
+    state.health_stats.record_http_request();
+    "OK\n"
+}
+
+/// Handler for POST /grafana/search.
+#[instrument(skip(state, body))]
+pub async fn grafana_search_handler(
+    State(state): State<SharedState>,
+    body: Bytes,
+) -> Json<Vec<String>> {
+    debug!("Processing /grafana/search request");
+    state.health_stats.record_http_request();
+
+    // Grafana may send an empty body on the first search
+    let request: SearchRequest = serde_json::from_slice(&body).unwrap_or_default();
+
+    let mut targets = vec![TOP_PROCESSES_TARGET.to_string()];
+    for subgroup in state.history.subgroups() {
+        for field in SubgroupTotals::FIELDS {
+            targets.push(format!("{}:{}", field, subgroup));
+        }
+    }
+    targets.retain(|t| t.contains(&request.target));
+    Json(targets)
+}
+
+/// Handler for POST /grafana/query.
+#[instrument(skip(state, request))]
+pub async fn grafana_query_handler(
+    State(state): State<SharedState>,
+    Json(request): Json<QueryRequest>,
+) -> Response {
+    debug!("Processing /grafana/query request");
+    state.health_stats.record_http_request();
+
+    let (from_ms, to_ms) = match &request.range {
+        Some(range) => match (parse_time(&range.from), parse_time(&range.to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return (StatusCode::BAD_REQUEST, "invalid range\n").into_response(),
+        },
+        None => (i64::MIN, i64::MAX),
+    };
+    let points = state.history.range(from_ms, to_ms);
+    let max_points = request.max_data_points.unwrap_or(usize::MAX);
+
+    let mut results = Vec::with_capacity(request.targets.len());
+    for target in &request.targets {
+        if target.target == TOP_PROCESSES_TARGET {
+            results.push(top_processes_table(&state).await);
+            continue;
+        }
+        match time_series(&target.target, &points, max_points) {
+            Some(series) => results.push(series),
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("unknown target '{}'\n", target.target),
+                )
+                    .into_response()
+            }
+        }
+    }
+    Json(results).into_response()
+}
+
+/// Parses an RFC 3339 timestamp to Unix milliseconds.
+fn parse_time(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| t.timestamp_millis())
+}
+
+/// Builds a "timeserie" response for `<field>:<group>/<subgroup>`.
+///
+/// Returns None for malformed targets or unknown fields. Scans without the
+/// subgroup are left out; at most `max_points` evenly spaced points are returned.
+fn time_series(target: &str, points: &[HistoryPoint], max_points: usize) -> Option<Value> {
+    let (field, subgroup) = target.split_once(':')?;
+    SubgroupTotals::default().field(field)?;
+
+    let step = points.len().div_ceil(max_points.max(1)).max(1);
+    let datapoints: Vec<Value> = points
+        .iter()
+        .step_by(step)
+        .filter_map(|p| {
+            let value = p.subgroups.get(subgroup)?.field(field)?;
+            Some(json!([value, p.timestamp_ms]))
+        })
+        .collect();
+
+    Some(json!({ "target": target, "datapoints": datapoints }))
+}
+
+/// Builds a "table" response of the largest processes by USS in the current snapshot.
+async fn top_processes_table(state: &SharedState) -> Value {
+    let cache = state.cache.read().await;
+    let mut processes: Vec<_> = cache
+        .processes
+        .values()
+        .filter_map(|p| {
+            classify_process_with_config(p.classify_name(), &state.config).map(|(g, s)| (p, g, s))
+        })
+        .collect();
+    processes.sort_by_key(|(p, _, _)| std::cmp::Reverse(p.uss));
+
+    let rows: Vec<Value> = processes
+        .iter()
+        .take(TOP_PROCESSES_ROWS)
+        .map(|(p, group, subgroup)| {
+            json!([
+                p.pid,
+                public_name(&p.name, &state.config),
+                group.as_ref(),
+                subgroup.as_ref(),
+                p.uss,
+                p.pss,
+                p.rss,
+                p.cpu_percent
+            ])
+        })
+        .collect();
+
+    json!({
+        "type": "table",
+        "columns": [
+            { "text": "pid", "type": "number" },
+            { "text": "name", "type": "string" },
+            { "text": "group", "type": "string" },
+            { "text": "subgroup", "type": "string" },
+            { "text": "uss_bytes", "type": "number" },
+            { "text": "pss_bytes", "type": "number" },
+            { "text": "rss_bytes", "type": "number" },
+            { "text": "cpu_percent", "type": "number" },
+        ],
+        "rows": rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_time_series() {
+        let points: Vec<HistoryPoint> = (0..10)
+            .map(|i| HistoryPoint {
+                timestamp_ms: i * 1000,
+                subgroups: BTreeMap::from([(
+                    "db/postgres".to_string(),
+                    SubgroupTotals {
+                        uss: i as u64,
+                        ..Default::default()
+                    },
+                )]),
+                top_processes: Vec::new(),
+            })
+            .collect();
+
+        let series = time_series("uss:db/postgres", &points, 5).unwrap();
+        assert_eq!(series["datapoints"].as_array().unwrap().len(), 5);
+        assert_eq!(series["datapoints"][1], json!([2.0, 2000]));
+
+        let missing = time_series("uss:web/nginx", &points, 100).unwrap();
+        assert!(missing["datapoints"].as_array().unwrap().is_empty());
+
+        assert!(time_series("vsz:db/postgres", &points, 100).is_none());
+        assert!(time_series("db/postgres", &points, 100).is_none());
+        assert_eq!(parse_time("1970-01-01T00:00:01.500Z"), Some(1500));
+    }
+}
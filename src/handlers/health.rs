@@ -8,6 +8,8 @@ use herakles_proc_mem_exporter::HealthResponse;
 use std::fmt::Write as FmtWrite;
 use tracing::{debug, instrument};
 
+use crate::collector::is_on_scrape;
+use crate::schedule::current_cache_ttl;
 use crate::state::SharedState;
 
 /// Footer text for human-readable HTTP endpoints.
@@ -31,7 +33,11 @@ pub async fn health_handler(State(state): State<SharedState>) -> impl IntoRespon
     };
 
     // Short status message for human-readable heading
-    let message = if cache.is_updating {
+    let message = if cache.restored {
+        "Serving restored cache snapshot, first scan running"
+    } else if cache.initializing {
+        "Initializing, first scan running"
+    } else if cache.is_updating {
         "OK - Cache updating"
     } else if cache.update_success {
         "OK"
@@ -41,6 +47,10 @@ pub async fn health_handler(State(state): State<SharedState>) -> impl IntoRespon
 
     // Render plain-text table from HealthStats
     let table = state.health_stats.render_table();
+    let scrape_notes = state.scrape_intervals.render_notes(
+        current_cache_ttl(&state.config) as f64,
+        is_on_scrape(&state),
+    );
 
     // Get buffer health and render it
     let buffer_health = state.health_state.get_health();
@@ -50,7 +60,7 @@ pub async fn health_handler(State(state): State<SharedState>) -> impl IntoRespon
     (
         status,
         [("Content-Type", "text/plain; charset=utf-8")],
-        format!("{message}\n\n{table}\n{buffer_section}\n{FOOTER_TEXT}"),
+        format!("{message}\n\n{table}\n{scrape_notes}{buffer_section}\n{FOOTER_TEXT}"),
     )
 }
 
@@ -0,0 +1,141 @@
+//! Runtime log level endpoint handler.
+//!
+//! This module provides the `PUT /-/loglevel` endpoint that switches the
+//! active tracing level. It is only routed when `admin_token` is configured
+//! and requires `Authorization: Bearer <admin_token>`. Every request,
+//! including rejected ones, is written to the audit log.
+
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use tracing::{info, instrument, level_filters::LevelFilter, warn};
+
+use crate::audit::{AuditContext, AuditOutcome};
+use crate::state::SharedState;
+
+/// Action name of log level changes in the audit log.
+const AUDIT_ACTION: &str = "set_log_level";
+/// Audit principal of requests authenticated with `admin_token`.
+const PRINCIPAL: &str = "admin_token";
+
+/// Handler for PUT /-/loglevel. The body is a level name (off, error, warn,
+/// info, debug, trace) or "toggle" to switch between debug and the startup level.
+#[instrument(skip(state, audit, headers, body))]
+pub async fn loglevel_handler(
+    State(state): State<SharedState>,
+    audit: AuditContext,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    state.health_stats.record_http_request();
+
+    if !admin_authorized(&headers, state.config.admin_token.as_deref()) {
+        warn!("Rejected unauthorized log level change");
+        state.audit.record(
+            &audit,
+            "anonymous",
+            AUDIT_ACTION,
+            "",
+            AuditOutcome::Denied,
+            StatusCode::UNAUTHORIZED.as_u16(),
+        );
+        return (StatusCode::UNAUTHORIZED, "unauthorized\n".to_string());
+    }
+
+    let previous = state.log_control.current();
+    let requested = body.trim();
+    let result = if requested.eq_ignore_ascii_case("toggle") {
+        state.log_control.toggle_debug()
+    } else {
+        match requested.parse::<LevelFilter>() {
+            Ok(level) => state.log_control.set_level(level).map(|_| level),
+            Err(_) => {
+                state.audit.record(
+                    &audit,
+                    PRINCIPAL,
+                    AUDIT_ACTION,
+                    &format!("invalid level '{}'", requested),
+                    AuditOutcome::Invalid,
+                    StatusCode::BAD_REQUEST.as_u16(),
+                );
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "invalid log level '{}', expected off, error, warn, info, debug, trace or toggle\n",
+                        requested
+                    ),
+                );
+            }
+        }
+    };
+
+    match result {
+        Ok(level) => {
+            info!(
+                "Log level changed via /-/loglevel: {} -> {}",
+                previous, level
+            );
+            let change = format!("{} -> {}", previous, level);
+            state.audit.record(
+                &audit,
+                PRINCIPAL,
+                AUDIT_ACTION,
+                &change,
+                AuditOutcome::Success,
+                StatusCode::OK.as_u16(),
+            );
+            (StatusCode::OK, format!("log level: {}\n", change))
+        }
+        Err(e) => {
+            state.audit.record(
+                &audit,
+                PRINCIPAL,
+                AUDIT_ACTION,
+                &e.to_string(),
+                AuditOutcome::Error,
+                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            );
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to change log level: {}\n", e),
+            )
+        }
+    }
+}
+
+/// True if `headers` carry `Authorization: Bearer <admin_token>`; always
+/// false without a configured token.
+pub fn admin_authorized(headers: &HeaderMap, admin_token: Option<&str>) -> bool {
+    let expected = admin_token.unwrap_or_default();
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    !expected.is_empty() && token_matches(provided, expected)
+}
+
+/// Compares tokens without short-circuiting on the first differing byte.
+fn token_matches(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cre", "s3cret"));
+        assert!(!token_matches("s3creT", "s3cret"));
+        assert!(!token_matches("", "s3cret"));
+    }
+}
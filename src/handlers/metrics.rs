@@ -1,419 +1,850 @@
 //! Metrics endpoint handler for Prometheus scraping.
 //!
 //! This module provides the `/metrics` endpoint handler that formats and returns
-//! process metrics in Prometheus text format.
+//! process metrics in Prometheus text format, and the per-tenant
+//! `/metrics/{tenant}` views on the same cache.
 
 use ahash::AHashMap as HashMap;
-use axum::{extract::State, http::StatusCode, response::IntoResponse};
-use prometheus::{Encoder, TextEncoder};
-use std::sync::Arc;
-use std::time::Instant;
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, Method, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+use prometheus::core::Collector;
+use prometheus::proto::MetricFamily;
+use prometheus::{Encoder, Gauge, GaugeVec, Opts, Registry, TextEncoder};
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLockReadGuard;
 use tracing::{debug, error, instrument, warn};
 
-use crate::cache::ProcMem;
-use crate::process::classify_process_with_config;
+use crate::aggregate::Classified;
+use crate::cache::{MetricsCache, ProcKey, ProcMem, ProcRates};
+use crate::collector::{ensure_fresh_cache, is_on_scrape, record_collect_error};
+use crate::config::Config;
+use crate::exposition_budget;
+use crate::exposition_stats::ExpositionStats;
+use crate::metrics::{process_label_names, MemoryMetrics};
+use crate::pipeline::{MetricSchema, Pipeline};
+use crate::process::CLK_TCK;
+use crate::schedule::current_cache_ttl;
 use crate::state::SharedState;
 use crate::system;
 
 /// Buffer capacity for metrics encoding.
 const BUFFER_CAP: usize = 512 * 1024;
 
+/// Header carrying the scraper's timeout.
+const SCRAPE_TIMEOUT_HEADER: &str = "x-prometheus-scrape-timeout-seconds";
+
+/// Default safety margin between the answer and the scraper's timeout.
+pub const DEFAULT_SCRAPE_TIMEOUT_OFFSET: f64 = 0.5;
+
+/// Partial collection component of the process and cgroup families.
+const PROCESSES_COMPONENT: &str = "processes";
+/// Partial collection component of families that failed to encode.
+const ENCODING_COMPONENT: &str = "encoding";
+
 /// Error type for metrics endpoint failures.
 #[derive(Debug)]
 pub enum MetricsError {
     EncodingFailed,
+    UnknownTenant,
 }
 
 impl IntoResponse for MetricsError {
     fn into_response(self) -> axum::response::Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to encode metrics",
-        )
-            .into_response()
+        match self {
+            MetricsError::EncodingFailed => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to encode metrics",
+            )
+                .into_response(),
+            MetricsError::UnknownTenant => {
+                (StatusCode::NOT_FOUND, "Unknown tenant").into_response()
+            }
+        }
     }
 }
 
 /// Handler for the /metrics endpoint.
-#[instrument(skip(state))]
-pub async fn metrics_handler(State(state): State<SharedState>) -> Result<String, MetricsError> {
+#[instrument(skip(state, headers))]
+pub async fn metrics_handler(
+    State(state): State<SharedState>,
+    method: Method,
+    headers: HeaderMap,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> Result<String, MetricsError> {
+    let schema = MetricSchema::from_config(&state.config);
+    serve_metrics(&state, &method, &headers, connect_info, "/metrics", schema).await
+}
+
+/// Handler for /metrics/v1, the full exposition in metric schema v1.
+#[instrument(skip(state, headers))]
+pub async fn metrics_v1_handler(
+    State(state): State<SharedState>,
+    method: Method,
+    headers: HeaderMap,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> Result<String, MetricsError> {
+    serve_metrics(
+        &state,
+        &method,
+        &headers,
+        connect_info,
+        "/metrics/v1",
+        MetricSchema::V1,
+    )
+    .await
+}
+
+/// Handler for /metrics/v2, the full exposition in metric schema v2.
+#[instrument(skip(state, headers))]
+pub async fn metrics_v2_handler(
+    State(state): State<SharedState>,
+    method: Method,
+    headers: HeaderMap,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> Result<String, MetricsError> {
+    serve_metrics(
+        &state,
+        &method,
+        &headers,
+        connect_info,
+        "/metrics/v2",
+        MetricSchema::V2,
+    )
+    .await
+}
+
+async fn serve_metrics(
+    state: &SharedState,
+    method: &Method,
+    headers: &HeaderMap,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    path: &str,
+    schema: MetricSchema,
+) -> Result<String, MetricsError> {
+    debug!("Processing {} request", path);
+    // HEAD probes of load balancers need no exposition, nor are they scrapes
+    if *method == Method::HEAD {
+        return Ok(String::new());
+    }
+    let deadline = scrape_deadline(headers, Instant::now(), &state.config);
+    state
+        .scrape_intervals
+        .record(connect_info.map(|c| c.0 .0.ip()), path);
+    render_metrics(
+        state,
+        schema,
+        state.config.sample_timestamps.unwrap_or(false),
+        deadline,
+    )
+    .await
+}
+
+/// Renders the full exposition served at /metrics from the current cache,
+/// with the family names of `schema`.
+///
+/// With `sample_timestamps` the process samples carry the time of the last
+/// cache update (the textfile collector rejects timestamps, so it never does).
+/// With a `deadline` the answer is given by then: an unfinished scan leaves
+/// the previous snapshot to serve, and per-process series are left out if
+/// the last full exposition took longer than the time remaining.
+pub async fn render_metrics(
+    state: &SharedState,
+    schema: MetricSchema,
+    sample_timestamps: bool,
+    deadline: Option<Instant>,
+) -> Result<String, MetricsError> {
     let start = Instant::now();
-    debug!("Processing /metrics request");
 
-    // Wait for cache to be available (not currently updating)
-    loop {
-        let cache_guard = state.cache.read().await;
-        if !cache_guard.is_updating {
-            let processes_vec: Vec<ProcMem> = cache_guard.processes.values().cloned().collect();
-            let meta = (
-                cache_guard.update_duration_seconds,
-                cache_guard.update_success,
-                cache_guard.is_updating,
+    refresh_on_scrape(state, deadline).await;
+
+    let cache_guard = settled_cache(state, deadline).await;
+    let render_start = Instant::now();
+    let processes_vec: Vec<ProcMem> = cache_guard.processes.values().cloned().collect();
+    let rates = snapshot_rates(&cache_guard, &state.config);
+    let meta = (
+        cache_guard.update_duration_seconds,
+        cache_guard.update_success,
+        cache_guard.is_updating,
+    );
+    let updated_unix_ms = cache_guard.last_updated_unix_ms;
+    let scan_id = cache_guard.scan_cycle;
+    let cgroups = cache_guard.cgroups.clone();
+
+    drop(cache_guard);
+    // Stage timings for /debug/exposition
+    let mut stages = vec![("snapshot", start.elapsed())];
+
+    // Update cache metadata metrics
+    state.cache_update_duration.set(meta.0);
+    state
+        .cache_update_success
+        .set(if meta.1 { 1.0 } else { 0.0 });
+    state.cache_updating.set(if meta.2 { 1.0 } else { 0.0 });
+
+    let truncated =
+        state.config.per_process_metrics.unwrap_or(true) && !full_render_fits(state, deadline);
+    let pipeline = Pipeline::for_metrics(
+        &state.config,
+        schema,
+        updated_unix_ms.filter(|_| sample_timestamps),
+        scan_id,
+        !truncated,
+        &state.topn_hysteresis,
+    );
+
+    // Families derived from the cache are built into a staging
+    // registry, so concurrent scrapes never see them half populated
+    let staging = Registry::new();
+    register_truncated(&staging, truncated)?;
+
+    // Get uptime for this scrape cycle (constant for all metrics)
+    let uptime_seconds = state.health_stats.get_uptime_seconds().to_string();
+
+    // Components that failed are left out; the rest is still served
+    let collectors = state.config.system_collectors.as_deref();
+    let mut failed = Vec::new();
+    let exported_count = match MemoryMetrics::new(
+        &staging,
+        &process_label_names(&state.config, state.label_extractors.label_names()),
+        state.config.stable_topn.unwrap_or(false),
+    ) {
+        Ok(metrics) => {
+            let stage_start = Instant::now();
+            let mut snapshot =
+                Classified::new(&processes_vec, &rates, &state.config, &uptime_seconds);
+            stages.push(("classify", stage_start.elapsed()));
+            stages.extend(pipeline.aggregate(&mut snapshot, &metrics));
+            let stage_start = Instant::now();
+            let unit_limits = state
+                .config
+                .cgroups
+                .as_ref()
+                .and_then(|cfg| cfg.unit_limits)
+                .unwrap_or(false);
+            metrics.set_cgroup_metrics(&cgroups, unit_limits);
+            stages.push(("cgroups", stage_start.elapsed()));
+            let stage_start = Instant::now();
+            failed.extend(collect_system_metrics(state, &metrics));
+            stages.push(("system", stage_start.elapsed()));
+            snapshot.exported_count
+        }
+        Err(e) => {
+            error!(
+                "Failed to create metrics, serving exporter metrics only: {}",
+                e
             );
+            failed.push(PROCESSES_COMPONENT);
+            failed.extend(
+                system::SYSTEM_COLLECTORS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .filter(|name| system::collector_enabled(collectors, name)),
+            );
+            0
+        }
+    };
+
+    state.processes_total.set(exported_count as f64);
+    state.scan_id.set(scan_id as f64);
+    state.scrape_duration.set(start.elapsed().as_secs_f64());
+
+    state.scrape_intervals.export(
+        &state.scrape_interval,
+        &state.scrape_interval_mismatch,
+        current_cache_ttl(&state.config) as f64,
+        is_on_scrape(state),
+    );
+
+    // Encode metrics in Prometheus text format
+    let stage_start = Instant::now();
+    let mut families = staging.gather();
+    families.extend(state.registry.gather());
+    stages.push(("gather", stage_start.elapsed()));
+    stages.extend(pipeline.rewrite(&mut families));
+
+    // Calculate label cardinality
+    let mut label_count: u64 = 0;
+    for family in &families {
+        for metric in family.get_metric() {
+            label_count += metric.get_label().len() as u64;
+        }
+    }
+    state.health_stats.record_label_cardinality(label_count);
 
-            drop(cache_guard);
-
-            // Update cache metadata metrics
-            state.cache_update_duration.set(meta.0);
+    let stage_start = Instant::now();
+    let (mut encoded, encoding_failed) = encode_families(&families);
+    if encoding_failed {
+        failed.push(ENCODING_COMPONENT);
+    }
+    let components = [PROCESSES_COMPONENT]
+        .into_iter()
+        .chain(
+            system::SYSTEM_COLLECTORS
+                .iter()
+                .map(|(name, _)| *name)
+                .filter(|name| system::collector_enabled(collectors, name)),
+        )
+        .chain([ENCODING_COMPONENT]);
+    let partial = partial_collection_families(components, &failed);
+    encoded.extend(encode_families(&partial).0);
+    families.extend(partial);
+    stages.push(("encode", stage_start.elapsed()));
+    if let Some(max_bytes) = state.config.max_exposition_bytes {
+        let stage_start = Instant::now();
+        let order: Vec<String> = match state.config.exposition_drop_order.as_deref() {
+            Some(order) => order.to_vec(),
+            None => exposition_budget::DROP_CLASSES
+                .iter()
+                .map(|c| c.to_string())
+                .collect(),
+        };
+        let dropped = exposition_budget::apply(&mut families, &mut encoded, max_bytes, &order);
+        if dropped.iter().any(|(_, count)| *count > 0) {
             state
-                .cache_update_success
-                .set(if meta.1 { 1.0 } else { 0.0 });
-            state.cache_updating.set(if meta.2 { 1.0 } else { 0.0 });
-
-            // Reset metrics before populating with fresh data
-            state.metrics.reset();
-
-            // Get uptime for this scrape cycle (constant for all metrics)
-            let uptime_seconds = state.health_stats.get_uptime_seconds().to_string();
-
-            let cfg = &state.config;
-            let enable_rss = cfg.enable_rss.unwrap_or(true);
-            let enable_pss = cfg.enable_pss.unwrap_or(true);
-            let enable_uss = cfg.enable_uss.unwrap_or(true);
-            let enable_cpu = cfg.enable_cpu.unwrap_or(true);
-
-            // Aggregation map
-            let mut groups: HashMap<(Arc<str>, Arc<str>), Vec<&ProcMem>> = HashMap::new();
-            let mut exported_count = 0usize;
-
-            // Enforce an overall limit for processes classified as "other".
-            let mut other_exported = 0usize;
-            let other_limit = state.config.top_n_others.unwrap_or(10);
-
-            // Populate per-process metrics + prepare aggregation
-            for p in &processes_vec {
-                if let Some((group, subgroup)) =
-                    classify_process_with_config(&p.name, &state.config)
-                {
-                    // If this is the "other" group, enforce the configured per-group limit.
-                    if group.as_ref().eq_ignore_ascii_case("other") {
-                        if other_exported >= other_limit {
-                            continue;
-                        }
-                        other_exported += 1;
-                    }
-
-                    exported_count += 1;
-                    let pid_str = p.pid.to_string();
-
-                    state.metrics.set_for_process(
-                        &pid_str,
-                        &p.name,
-                        group.as_ref(),
-                        subgroup.as_ref(),
-                        p.rss,
-                        p.pss,
-                        p.uss,
-                        p.cpu_percent as f64,
-                        p.cpu_time_seconds as f64,
-                        &state.config,
-                        &uptime_seconds,
-                    );
-
-                    groups.entry((group, subgroup)).or_default().push(p);
-                }
-            }
-
-            state.processes_total.set(exported_count as f64);
-            state.scrape_duration.set(start.elapsed().as_secs_f64());
-
-            // Aggregated sums and Top-N metrics per subgroup
-            for ((group, subgroup), mut list) in groups {
-                let mut rss_sum: u64 = 0;
-                let mut pss_sum: u64 = 0;
-                let mut uss_sum: u64 = 0;
-                let mut cpu_percent_sum: f64 = 0.0;
-                let mut cpu_time_sum: f64 = 0.0;
-
-                for p in &list {
-                    rss_sum += p.rss;
-                    pss_sum += p.pss;
-                    uss_sum += p.uss;
-                    cpu_percent_sum += p.cpu_percent as f64;
-                    cpu_time_sum += p.cpu_time_seconds as f64;
-                }
+                .scrapes_truncated
+                .with_label_values(&["size_budget"])
+                .inc();
+        }
+        let budget = exposition_budget::dropped_families(&dropped);
+        encoded.extend(encode_families(&budget).0);
+        families.extend(budget);
+        stages.push(("size_budget", stage_start.elapsed()));
+    }
+    *state
+        .exposition_stats
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) =
+        Some(ExpositionStats::new(&families, &encoded, stages));
+    encoded.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut buffer = Vec::with_capacity(BUFFER_CAP);
+    for (_, text) in encoded {
+        buffer.extend(text);
+    }
 
-                let group_ref: &str = group.as_ref();
-                let subgroup_ref: &str = subgroup.as_ref();
+    if truncated {
+        record_truncated(state);
+    } else {
+        record_full_render(state, render_start.elapsed());
+    }
 
-                // Set aggregation metrics (respect enable_* flags)
-                if enable_rss {
-                    state
-                        .metrics
-                        .agg_rss_sum
-                        .with_label_values(&[group_ref, subgroup_ref, &uptime_seconds])
-                        .set(rss_sum as f64);
-                }
-                if enable_pss {
-                    state
-                        .metrics
-                        .agg_pss_sum
-                        .with_label_values(&[group_ref, subgroup_ref, &uptime_seconds])
-                        .set(pss_sum as f64);
-                }
-                if enable_uss {
-                    state
-                        .metrics
-                        .agg_uss_sum
-                        .with_label_values(&[group_ref, subgroup_ref, &uptime_seconds])
-                        .set(uss_sum as f64);
-                }
-                if enable_cpu {
-                    state
-                        .metrics
-                        .agg_cpu_percent_sum
-                        .with_label_values(&[group_ref, subgroup_ref, &uptime_seconds])
-                        .set(cpu_percent_sum);
-                    state
-                        .metrics
-                        .agg_cpu_time_sum
-                        .with_label_values(&[group_ref, subgroup_ref, &uptime_seconds])
-                        .set(cpu_time_sum);
-                }
+    // Record metrics request statistics
+    let request_duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+    state.health_stats.record_metrics_endpoint_call();
+    state
+        .health_stats
+        .record_request_duration(request_duration_ms);
+    state.health_stats.record_http_request();
+    state.health_stats.record_cache_hit();
+
+    debug!(
+        "Metrics request completed: {} processes (exported {}), {} bytes, {:.3}ms",
+        processes_vec.len(),
+        exported_count,
+        buffer.len(),
+        request_duration_ms
+    );
+
+    let body = String::from_utf8(buffer).map_err(|_| MetricsError::EncodingFailed)?;
+    if state.config.federation.is_some() {
+        return Ok(state.federation_cache.merge_into(&body));
+    }
+    Ok(body)
+}
 
-                // Sort by USS for Top-N selection
-                list.sort_by_key(|p| std::cmp::Reverse(p.uss));
-
-                let is_other_group = group_ref.eq_ignore_ascii_case("other")
-                    || group_ref.eq_ignore_ascii_case("others")
-                    || subgroup_ref.eq_ignore_ascii_case("other")
-                    || subgroup_ref.eq_ignore_ascii_case("others");
-
-                let top_subgroup = state.config.top_n_subgroup.unwrap_or(3);
-                let top_others = state.config.top_n_others.unwrap_or(10);
-                let limit = if is_other_group {
-                    std::cmp::max(1, top_others)
-                } else {
-                    std::cmp::max(1, top_subgroup)
-                };
-
-                let rss_total = rss_sum as f64;
-                let pss_total = pss_sum as f64;
-                let uss_total = uss_sum as f64;
-                let cpu_total = cpu_time_sum;
-
-                for (rank, p) in list.iter().take(limit).enumerate() {
-                    let pid_s = p.pid.to_string();
-                    let rank_s = (rank + 1).to_string();
-                    let name_s = p.name.as_str();
-
-                    // Absolute Top-N values
-                    if enable_rss {
-                        state
-                            .metrics
-                            .top_rss
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(p.rss as f64);
-                    }
-                    if enable_pss {
-                        state
-                            .metrics
-                            .top_pss
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(p.pss as f64);
-                    }
-                    if enable_uss {
-                        state
-                            .metrics
-                            .top_uss
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(p.uss as f64);
-                    }
-                    if enable_cpu {
-                        state
-                            .metrics
-                            .top_cpu_percent
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(p.cpu_percent as f64);
-                        state
-                            .metrics
-                            .top_cpu_time
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(p.cpu_time_seconds as f64);
-                    }
-
-                    // Percentage-of-subgroup values
-                    if enable_cpu && cpu_total > 0.0 {
-                        let pct = (p.cpu_time_seconds as f64 / cpu_total) * 100.0;
-                        state
-                            .metrics
-                            .top_cpu_percent_of_subgroup
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(pct);
-                    }
-
-                    if enable_rss && rss_total > 0.0 {
-                        let pct = (p.rss as f64 / rss_total) * 100.0;
-                        state
-                            .metrics
-                            .top_rss_percent_of_subgroup
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(pct);
-                    }
-
-                    if enable_pss && pss_total > 0.0 {
-                        let pct = (p.pss as f64 / pss_total) * 100.0;
-                        state
-                            .metrics
-                            .top_pss_percent_of_subgroup
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(pct);
-                    }
-
-                    if enable_uss && uss_total > 0.0 {
-                        let pct = (p.uss as f64 / uss_total) * 100.0;
-                        state
-                            .metrics
-                            .top_uss_percent_of_subgroup
-                            .with_label_values(&[
-                                group_ref,
-                                subgroup_ref,
-                                &rank_s,
-                                &pid_s,
-                                name_s,
-                                &uptime_seconds,
-                            ])
-                            .set(pct);
-                    }
+/// Encodes `families` one at a time, so a family that fails to encode is
+/// left out instead of failing the whole scrape. Returns the text of each
+/// encoded family with its name, and whether any family was left out.
+fn encode_families(families: &[MetricFamily]) -> (Vec<(String, Vec<u8>)>, bool) {
+    let encoder = TextEncoder::new();
+    let mut failed = false;
+    let encoded = families
+        .iter()
+        .filter_map(|family| {
+            let mut text = Vec::new();
+            match encoder.encode(std::slice::from_ref(family), &mut text) {
+                Ok(()) => Some((family.name().to_string(), text)),
+                Err(e) => {
+                    error!("Failed to encode metric family {}: {}", family.name(), e);
+                    failed = true;
+                    None
                 }
             }
+        })
+        .collect();
+    (encoded, failed)
+}
 
-            // Update system-wide metrics
-            match system::read_load_average() {
-                Ok(load_avg) => {
-                    // Set load metrics
-                    state.metrics.set_system_load_metrics(
-                        load_avg.one_min,
-                        load_avg.five_min,
-                        load_avg.fifteen_min,
-                    );
-                }
-                Err(e) => {
-                    warn!("Failed to read load average: {}", e);
+/// Per-scrape family telling for each component whether it failed (1) and
+/// its families are missing or incomplete, or succeeded (0).
+fn partial_collection_families<'a>(
+    components: impl Iterator<Item = &'a str>,
+    failed: &[&str],
+) -> Vec<MetricFamily> {
+    let Ok(gauge) = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_partial_collection",
+            "Whether a collection component failed in this scrape and its families are missing or incomplete (1) or not (0)",
+        ),
+        &["component"],
+    ) else {
+        return Vec::new();
+    };
+    for component in components {
+        gauge
+            .with_label_values(&[component])
+            .set(if failed.contains(&component) {
+                1.0
+            } else {
+                0.0
+            });
+    }
+    gauge.collect()
+}
+
+/// Sets the host-wide families of the enabled system collectors.
+///
+/// Returns the collectors that failed; their families are left out or
+/// incomplete, the rest of the exposition is unaffected.
+fn collect_system_metrics(state: &SharedState, metrics: &MemoryMetrics) -> Vec<&'static str> {
+    let collectors = state.config.system_collectors.as_deref();
+    let enabled = |name: &str| system::collector_enabled(collectors, name);
+    let mut failed = Vec::new();
+    let mut tasks = None;
+    // The task count for the PID and thread ratios comes from /proc/loadavg
+    if enabled("loadavg") || enabled("limits") {
+        match system::read_load_average() {
+            Ok(load_avg) => {
+                // Set load metrics
+                metrics.set_system_load_metrics(
+                    load_avg.one_min,
+                    load_avg.five_min,
+                    load_avg.fifteen_min,
+                );
+                tasks = load_avg.tasks;
+            }
+            Err(e) => {
+                warn!("Failed to read load average: {}", e);
+                record_collect_error(state, "loadavg", &e);
+                if enabled("loadavg") {
+                    failed.push("loadavg");
                 }
             }
+        }
+    }
 
-            // Set new extended memory metrics
-            match system::read_extended_memory_info() {
-                Ok(mem_info) => {
-                    state.metrics.set_system_memory_metrics(
-                        mem_info.total_bytes,
-                        mem_info.available_bytes,
-                    );
-                }
-                Err(e) => {
-                    warn!("Failed to read extended memory info: {}", e);
-                }
+    // File handle, PID and thread table saturation
+    if enabled("limits") {
+        match system::read_kernel_limits() {
+            Ok(limits) => metrics.set_system_limit_metrics(&limits, tasks),
+            Err(e) => {
+                warn!("Failed to read kernel limits: {}", e);
+                record_collect_error(state, "kernel", &e);
+                failed.push("limits");
             }
+        }
+    }
 
-            // Set CPU usage ratio metrics
-            match state.system_cpu_cache.calculate_usage_ratios() {
-                Ok(cpu_ratios) => {
-                    state.metrics.set_system_cpu_usage_ratios(&cpu_ratios);
-                }
-                Err(e) => {
-                    warn!("Failed to calculate CPU usage ratios: {}", e);
-                }
+    // Set new extended memory metrics
+    if enabled("meminfo") {
+        match system::read_extended_memory_info() {
+            Ok(mem_info) => {
+                metrics.set_system_memory_metrics(mem_info.total_bytes, mem_info.available_bytes);
             }
+            Err(e) => {
+                warn!("Failed to read extended memory info: {}", e);
+                record_collect_error(state, "meminfo", &e);
+                failed.push("meminfo");
+            }
+        }
+        // Ballooned memory lowers MemAvailable without any process using it
+        if let Some(bytes) = system::read_balloon_bytes() {
+            metrics.set_system_balloon_bytes(bytes);
+        }
+    }
 
-            // Encode metrics in Prometheus text format
-            let families = state.registry.gather();
+    if enabled("kernel") {
+        let boot_time = system::read_boot_time()
+            .map_err(|e| {
+                warn!("Failed to read boot time: {}", e);
+                record_collect_error(state, "system_stat", &e);
+            })
+            .ok();
+        let kernel = system::read_kernel_info()
+            .map_err(|e| {
+                warn!("Failed to read kernel info: {}", e);
+                record_collect_error(state, "kernel", &e);
+            })
+            .ok();
+        if boot_time.is_none() || kernel.is_none() {
+            failed.push("kernel");
+        }
+        metrics.set_system_info(boot_time, kernel.as_ref());
+    }
 
-            // Calculate label cardinality
-            let mut label_count: u64 = 0;
-            for family in &families {
-                for metric in family.get_metric() {
-                    label_count += metric.get_label().len() as u64;
-                }
+    // zram and zswap; absent on hosts without compressed swap
+    if enabled("zram") {
+        match system::read_zram_devices() {
+            Ok(devices) => metrics.set_system_swap_metrics(&devices, system::read_zswap()),
+            Err(e) => {
+                warn!("Failed to read zram devices: {}", e);
+                record_collect_error(state, "zram", &e);
+                failed.push("zram");
+                metrics.set_system_swap_metrics(&[], system::read_zswap());
             }
-            state.health_stats.record_label_cardinality(label_count);
+        }
+    }
 
-            let mut buffer = Vec::with_capacity(BUFFER_CAP);
-            let encoder = TextEncoder::new();
+    // ZFS ARC; absent on hosts without ZFS
+    if enabled("zfs") {
+        match system::read_zfs_arc() {
+            Ok(Some(arc)) => metrics.set_system_zfs_metrics(&arc),
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Failed to read ZFS ARC statistics: {}", e);
+                record_collect_error(state, "zfs", &e);
+                failed.push("zfs");
+            }
+        }
+    }
 
-            if encoder.encode(&families, &mut buffer).is_err() {
-                error!("Failed to encode Prometheus metrics");
-                return Err(MetricsError::EncodingFailed);
+    // Set CPU usage ratio metrics
+    if enabled("cpustat") {
+        match state.system_cpu_cache.calculate_usage_ratios() {
+            Ok(cpu_ratios) => {
+                metrics.set_system_cpu_ratios(&cpu_ratios);
+            }
+            Err(e) => {
+                warn!("Failed to calculate CPU usage ratios: {}", e);
+                record_collect_error(state, "system_stat", &e);
+                failed.push("cpustat");
             }
+        }
+    }
 
-            // Record metrics request statistics
-            let request_duration_ms = start.elapsed().as_secs_f64() * 1000.0;
-            state.health_stats.record_metrics_endpoint_call();
-            state
-                .health_stats
-                .record_request_duration(request_duration_ms);
-            state.health_stats.record_http_request();
-            state.health_stats.record_cache_hit();
-
-            debug!(
-                "Metrics request completed: {} processes (exported {}), {} bytes, {:.3}ms",
-                processes_vec.len(),
-                exported_count,
-                buffer.len(),
-                request_duration_ms
-            );
+    failed
+}
 
-            return String::from_utf8(buffer).map_err(|_| MetricsError::EncodingFailed);
+/// Time by which a scrape has to be answered, from the timeout the scraper
+/// sends (Prometheus sets `X-Prometheus-Scrape-Timeout-Seconds`).
+///
+/// `scrape_timeout_offset_seconds` is kept free for the transfer; timeouts
+/// shorter than the offset get half of their time.
+fn scrape_deadline(headers: &HeaderMap, start: Instant, cfg: &Config) -> Option<Instant> {
+    let timeout: f64 = headers
+        .get(SCRAPE_TIMEOUT_HEADER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|t: &f64| t.is_finite() && *t > 0.0)?;
+    let offset = cfg
+        .scrape_timeout_offset_seconds
+        .unwrap_or(DEFAULT_SCRAPE_TIMEOUT_OFFSET);
+    let budget = if timeout > offset {
+        timeout - offset
+    } else {
+        timeout / 2.0
+    };
+    Some(start + Duration::from_secs_f64(budget))
+}
+
+/// Refreshes the cache in `on_scrape` mode. When the deadline passes first,
+/// the scan carries on in the background for the next scrape.
+async fn refresh_on_scrape(state: &SharedState, deadline: Option<Instant>) {
+    if !is_on_scrape(state) {
+        return;
+    }
+    // The scan replacing a restored snapshot or completing the initial one
+    // already runs in the background
+    {
+        let cache = state.cache.read().await;
+        if cache.restored || cache.initializing {
+            return;
         }
+    }
+    let Some(deadline) = deadline else {
+        ensure_fresh_cache(state).await;
+        return;
+    };
+    let scan_state = state.clone();
+    let scan = tokio::spawn(async move { ensure_fresh_cache(&scan_state).await });
+    if tokio::time::timeout_at(deadline.into(), scan)
+        .await
+        .is_err()
+    {
+        debug!("On-scrape scan still running at the scrape deadline");
+    }
+}
 
+/// Read access to the cache once no update is in progress. When the
+/// deadline passes first, the previous snapshot (still complete while a scan
+/// runs) is returned instead, as is the cache before the first scan after
+/// a restored snapshot or an exceeded `startup_scan_timeout_seconds`.
+async fn settled_cache(
+    state: &SharedState,
+    deadline: Option<Instant>,
+) -> RwLockReadGuard<'_, MetricsCache> {
+    loop {
+        let cache_guard = state.cache.read().await;
+        // A restored or empty snapshot is served rather than waiting for the
+        // first scan
+        if !cache_guard.is_updating || cache_guard.restored || cache_guard.initializing {
+            return cache_guard;
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            debug!("Cache update still running at the scrape deadline, serving previous snapshot");
+            state
+                .scrapes_truncated
+                .with_label_values(&["previous_snapshot"])
+                .inc();
+            return cache_guard;
+        }
         drop(cache_guard);
         // Wait for notification that cache update is complete
-        state.cache_ready.notified().await;
+        match deadline {
+            Some(d) => {
+                let _ = tokio::time::timeout_at(d.into(), state.cache_ready.notified()).await;
+            }
+            None => state.cache_ready.notified().await,
+        }
+    }
+}
+
+/// Whether the full exposition, judged by the last one, is done before the
+/// deadline.
+fn full_render_fits(state: &SharedState, deadline: Option<Instant>) -> bool {
+    let estimate = Duration::from_micros(state.full_render_micros.load(Ordering::Relaxed));
+    deadline.is_none_or(|d| Instant::now() + estimate < d)
+}
+
+/// Keeps the render time of a full exposition as the next estimate.
+fn record_full_render(state: &SharedState, elapsed: Duration) {
+    state
+        .full_render_micros
+        .store(elapsed.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Counts an exposition without per-process series. Each one lowers the
+/// estimate by a quarter, so a single slow render does not truncate all
+/// later scrapes.
+fn record_truncated(state: &SharedState) {
+    debug!("Left out per-process series to meet the scrape deadline");
+    state
+        .scrapes_truncated
+        .with_label_values(&["aggregates_only"])
+        .inc();
+    let _ = state
+        .full_render_micros
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |m| Some(m - m / 4));
+}
+
+/// Registers the per-scrape gauge telling whether per-process series are missing.
+fn register_truncated(registry: &Registry, truncated: bool) -> Result<(), MetricsError> {
+    let gauge = Gauge::new(
+        "herakles_exporter_scrape_truncated",
+        "Whether this exposition leaves out per-process series to meet the scrape timeout (1) or not (0)",
+    )
+    .and_then(|g| registry.register(Box::new(g.clone())).map(|_| g))
+    .map_err(|e| {
+        error!("Failed to create truncation metric: {}", e);
+        MetricsError::EncodingFailed
+    })?;
+    gauge.set(if truncated { 1.0 } else { 0.0 });
+    Ok(())
+}
+
+/// Handler for the /metrics/{tenant} endpoint.
+///
+/// Renders the tenant's view of the shared cache into a private registry, so
+/// tenants never see processes outside their filters and scrapes of different
+/// tenants do not interfere. Only process families are exported; host-wide
+/// system metrics stay on the main endpoint.
+#[instrument(skip(state, headers))]
+pub async fn tenant_metrics_handler(
+    State(state): State<SharedState>,
+    Path(tenant): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> Result<String, MetricsError> {
+    let start = Instant::now();
+    let deadline = scrape_deadline(&headers, start, &state.config);
+    debug!("Processing /metrics/{} request", tenant);
+
+    let Some(tenant_cfg) = state.config.tenants.as_ref().and_then(|t| t.get(&tenant)) else {
+        debug!("Rejecting metrics request for unknown tenant {}", tenant);
+        return Err(MetricsError::UnknownTenant);
+    };
+    if method == Method::HEAD {
+        return Ok(String::new());
+    }
+    let cfg = tenant_cfg.apply_to(&state.config);
+    state.scrape_intervals.record(
+        connect_info.map(|c| c.0 .0.ip()),
+        &format!("/metrics/{}", tenant),
+    );
+
+    refresh_on_scrape(&state, deadline).await;
+
+    let ProcessSnapshot {
+        processes: mut processes_vec,
+        rates,
+        updated_unix_ms,
+        scan_id,
+    } = snapshot_processes(&state, deadline).await;
+    let truncated = cfg.per_process_metrics.unwrap_or(true) && !full_render_fits(&state, deadline);
+    let pipeline = Pipeline::for_tenant(
+        &state.config,
+        &cfg,
+        updated_unix_ms.filter(|_| state.config.sample_timestamps.unwrap_or(false)),
+        scan_id,
+        !truncated,
+    );
+    pipeline.filter(&mut processes_vec);
+
+    let const_labels: std::collections::HashMap<String, String> = tenant_cfg
+        .labels
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let registry = Registry::new_custom(None, Some(const_labels)).map_err(|e| {
+        error!("Failed to create registry for tenant {}: {}", tenant, e);
+        MetricsError::EncodingFailed
+    })?;
+    let metrics = MemoryMetrics::new(
+        &registry,
+        &process_label_names(&state.config, state.label_extractors.label_names()),
+        cfg.stable_topn.unwrap_or(false),
+    )
+    .map_err(|e| {
+        error!("Failed to create metrics for tenant {}: {}", tenant, e);
+        MetricsError::EncodingFailed
+    })?;
+    register_truncated(&registry, truncated)?;
+    if truncated {
+        record_truncated(&state);
+    }
+
+    let uptime_seconds = state.health_stats.get_uptime_seconds().to_string();
+    let mut snapshot = Classified::new(&processes_vec, &rates, &cfg, &uptime_seconds);
+    pipeline.aggregate(&mut snapshot, &metrics);
+    let exported_count = snapshot.exported_count;
+
+    let mut families = registry.gather();
+    pipeline.rewrite(&mut families);
+
+    let (encoded, _) = encode_families(&families);
+    let mut buffer = Vec::with_capacity(BUFFER_CAP);
+    for (_, text) in encoded {
+        buffer.extend(text);
+    }
+
+    let request_duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+    state.health_stats.record_metrics_endpoint_call();
+    state
+        .health_stats
+        .record_request_duration(request_duration_ms);
+    state.health_stats.record_http_request();
+    state.health_stats.record_cache_hit();
+
+    debug!(
+        "Tenant {} metrics request completed: exported {} processes, {} bytes, {:.3}ms",
+        tenant,
+        exported_count,
+        buffer.len(),
+        request_duration_ms
+    );
+
+    String::from_utf8(buffer).map_err(|_| MetricsError::EncodingFailed)
+}
+
+/// Computes per-process rates from the cache if rates are enabled.
+fn snapshot_rates(cache: &MetricsCache, cfg: &Config) -> HashMap<ProcKey, ProcRates> {
+    if cfg.enable_rates.unwrap_or(false) {
+        cache.rates(*CLK_TCK)
+    } else {
+        HashMap::new()
+    }
+}
+
+/// Copy of the cached processes with their rates and scan metadata.
+struct ProcessSnapshot {
+    processes: Vec<ProcMem>,
+    rates: HashMap<ProcKey, ProcRates>,
+    updated_unix_ms: Option<i64>,
+    scan_id: u64,
+}
+
+/// Returns a copy of the cache once no update is in progress (see
+/// [`settled_cache`]).
+async fn snapshot_processes(state: &SharedState, deadline: Option<Instant>) -> ProcessSnapshot {
+    let cache_guard = settled_cache(state, deadline).await;
+    ProcessSnapshot {
+        processes: cache_guard.processes.values().cloned().collect(),
+        rates: snapshot_rates(&cache_guard, &state.config),
+        updated_unix_ms: cache_guard.last_updated_unix_ms,
+        scan_id: cache_guard.scan_cycle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrape_deadline() {
+        let cfg = Config::default();
+        let start = Instant::now();
+        let mut headers = HeaderMap::new();
+        assert_eq!(scrape_deadline(&headers, start, &cfg), None);
+
+        headers.insert(SCRAPE_TIMEOUT_HEADER, "10".parse().unwrap());
+        assert_eq!(
+            scrape_deadline(&headers, start, &cfg),
+            Some(start + Duration::from_millis(9500))
+        );
+        headers.insert(SCRAPE_TIMEOUT_HEADER, "0.4".parse().unwrap());
+        assert_eq!(
+            scrape_deadline(&headers, start, &cfg),
+            Some(start + Duration::from_millis(200))
+        );
+        for invalid in ["0", "-1", "soon", "NaN"] {
+            headers.insert(SCRAPE_TIMEOUT_HEADER, invalid.parse().unwrap());
+            assert_eq!(scrape_deadline(&headers, start, &cfg), None);
+        }
+    }
+
+    #[test]
+    fn test_encode_families_skips_failures() {
+        let good = Gauge::new("good", "Encodes").unwrap();
+        good.set(1.0);
+        // A family without samples is rejected by the encoder
+        let mut empty = MetricFamily::new();
+        empty.set_name("empty".to_string());
+        let families: Vec<MetricFamily> = good.collect().into_iter().chain([empty]).collect();
+
+        let (encoded, failed) = encode_families(&families);
+        assert!(failed);
+        assert_eq!(encoded.len(), 1);
+        assert_eq!(encoded[0].0, "good");
+        assert!(String::from_utf8_lossy(&encoded[0].1).contains("good 1"));
+
+        let partial = partial_collection_families(
+            ["processes", "loadavg", "encoding"].into_iter(),
+            &["loadavg", "encoding"],
+        );
+        let (encoded, failed) = encode_families(&partial);
+        assert!(!failed);
+        let text = String::from_utf8_lossy(&encoded[0].1).to_string();
+        assert!(text.contains("herakles_exporter_partial_collection{component=\"processes\"} 0"));
+        assert!(text.contains("herakles_exporter_partial_collection{component=\"loadavg\"} 1"));
     }
 }
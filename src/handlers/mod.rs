@@ -2,20 +2,47 @@
 //!
 //! This module provides handlers for all HTTP endpoints:
 //! - `/metrics`: Prometheus metrics endpoint
+//! - `/metrics/{tenant}`: Per-tenant metrics endpoints
 //! - `/health`: Health check endpoint
 //! - `/config`: Configuration display endpoint
 //! - `/subgroups`: Subgroups display endpoint
 //! - `/doc`: Documentation endpoint
+//! - `/export.csv`: Current snapshot as CSV
+//! - `/api/v1/unclassified`: Most common unclassified process names (JSON)
+//! - `/api/v1/owners`: Subgroups and resource sums per owner (JSON)
+//! - `/grafana/*`: Grafana JSON datasource (`enable_grafana`)
+//! - `/api/v1/query_range`: Range queries over the scan history
+//! - `/-/loglevel`: Runtime log level changes (requires `admin_token`)
+//! - `/-/selftest`: Mini scan checking parsers and classification (requires `admin_token`)
+//! - `/debug/exposition`: Statistics of the last /metrics exposition
 
 pub mod config;
 pub mod doc;
+pub mod export;
+pub mod exposition;
+pub mod grafana;
 pub mod health;
+pub mod loglevel;
 pub mod metrics;
+pub mod owners;
+pub mod query_range;
+pub mod selftest;
 pub mod subgroups;
+pub mod unclassified;
 
 // Re-export handlers
 pub use config::config_handler;
 pub use doc::doc_handler;
+pub use export::export_csv_handler;
+pub use exposition::exposition_handler;
+pub use grafana::{grafana_query_handler, grafana_search_handler, grafana_test_handler};
 pub use health::health_handler;
-pub use metrics::metrics_handler;
+pub use loglevel::loglevel_handler;
+pub use metrics::{
+    metrics_handler, metrics_v1_handler, metrics_v2_handler, render_metrics, tenant_metrics_handler,
+};
+pub use owners::owners_handler;
+pub use query_range::query_range_handler;
+pub use selftest::selftest_handler;
 pub use subgroups::subgroups_handler;
+pub use unclassified::unclassified_handler;
@@ -0,0 +1,195 @@
+//! Ownership endpoint handler.
+//!
+//! This module provides the `/api/v1/owners` endpoint that maps subgroups to
+//! their owners, the `owner_label` of `group_labels`, and sums the current
+//! memory and CPU of each owner's processes, as JSON. Subgroups without an
+//! owner are listed under a null owner.
+
+use ahash::AHashMap as HashMap;
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{debug, instrument};
+
+use crate::cache::ProcMem;
+use crate::config::{Config, DEFAULT_OWNER_LABEL};
+use crate::pipeline::GroupLabels;
+use crate::process::{classify_process_with_config, SUBGROUPS};
+use crate::state::SharedState;
+
+/// Current sums of a subgroup.
+#[derive(Debug, Default, Serialize)]
+pub struct SubgroupUsage {
+    pub group: String,
+    pub subgroup: String,
+    /// Labels of the subgroup from `group_labels`
+    pub labels: BTreeMap<String, String>,
+    pub processes: u64,
+    pub rss_bytes: u64,
+    pub pss_bytes: u64,
+    pub uss_bytes: u64,
+    pub cpu_percent: f64,
+}
+
+/// Current sums of an owner and its subgroups, largest USS first.
+#[derive(Debug, Default, Serialize)]
+pub struct OwnerUsage {
+    /// Value of the owner label, None for subgroups without one
+    pub owner: Option<String>,
+    pub processes: u64,
+    pub rss_bytes: u64,
+    pub pss_bytes: u64,
+    pub uss_bytes: u64,
+    pub cpu_percent: f64,
+    pub subgroups: Vec<SubgroupUsage>,
+}
+
+/// Body of /api/v1/owners.
+#[derive(Debug, Serialize)]
+pub struct OwnersReport {
+    pub owner_label: String,
+    /// Owners by USS, largest first
+    pub owners: Vec<OwnerUsage>,
+}
+
+/// Handler for GET /api/v1/owners.
+#[instrument(skip(state))]
+pub async fn owners_handler(State(state): State<SharedState>) -> impl IntoResponse {
+    debug!("Processing /api/v1/owners request");
+    state.health_stats.record_http_request();
+
+    let cache = state.cache.read().await;
+    Json(owners_report(cache.processes.values(), &state.config))
+}
+
+/// Sums `processes` per subgroup and owner.
+fn owners_report<'a>(processes: impl Iterator<Item = &'a ProcMem>, cfg: &Config) -> OwnersReport {
+    let owner_label = cfg.owner_label.as_deref().unwrap_or(DEFAULT_OWNER_LABEL);
+    let no_labels = BTreeMap::new();
+    let group_labels = GroupLabels {
+        labels: cfg.group_labels.as_ref().unwrap_or(&no_labels),
+    };
+
+    let mut subgroups: HashMap<(Arc<str>, Arc<str>), SubgroupUsage> = HashMap::new();
+    for p in processes {
+        let Some(key) = classify_process_with_config(p.classify_name(), cfg) else {
+            continue;
+        };
+        let usage = subgroups.entry(key).or_default();
+        usage.processes += 1;
+        usage.rss_bytes += p.rss;
+        usage.pss_bytes += p.pss;
+        usage.uss_bytes += p.uss;
+        usage.cpu_percent += p.cpu_percent as f64;
+    }
+    // Owned subgroups of the table are listed even without processes
+    for (group, subgroup) in SUBGROUPS.values() {
+        if group_labels
+            .for_series(group, Some(subgroup))
+            .contains_key(owner_label)
+        {
+            subgroups
+                .entry((group.clone(), subgroup.clone()))
+                .or_default();
+        }
+    }
+
+    let mut owners: HashMap<Option<String>, OwnerUsage> = HashMap::new();
+    for ((group, subgroup), mut usage) in subgroups {
+        let labels = group_labels.for_series(&group, Some(&subgroup));
+        let owner = labels.get(owner_label).map(|o| o.to_string());
+        usage.group = group.to_string();
+        usage.subgroup = subgroup.to_string();
+        usage.labels = labels
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let entry = owners.entry(owner.clone()).or_insert_with(|| OwnerUsage {
+            owner,
+            ..Default::default()
+        });
+        entry.processes += usage.processes;
+        entry.rss_bytes += usage.rss_bytes;
+        entry.pss_bytes += usage.pss_bytes;
+        entry.uss_bytes += usage.uss_bytes;
+        entry.cpu_percent += usage.cpu_percent;
+        entry.subgroups.push(usage);
+    }
+
+    let mut owners: Vec<OwnerUsage> = owners.into_values().collect();
+    for owner in &mut owners {
+        owner.subgroups.sort_by(|a, b| {
+            b.uss_bytes
+                .cmp(&a.uss_bytes)
+                .then_with(|| (&a.group, &a.subgroup).cmp(&(&b.group, &b.subgroup)))
+        });
+    }
+    owners.sort_by(|a, b| {
+        b.uss_bytes
+            .cmp(&a.uss_bytes)
+            .then_with(|| a.owner.cmp(&b.owner))
+    });
+
+    OwnersReport {
+        owner_label: owner_label.to_string(),
+        owners,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::generate::TestProcess;
+
+    #[test]
+    fn test_owners_report() {
+        let process = |pid: u32, name: &str, uss: u64| {
+            ProcMem::from(TestProcess {
+                pid,
+                name: name.to_string(),
+                group: String::new(),
+                subgroup: String::new(),
+                rss: uss,
+                pss: uss,
+                uss,
+                cpu_percent: 1.0,
+                cpu_time_seconds: 0.0,
+            })
+        };
+        let processes = [
+            process(1, "postgres", 300),
+            process(2, "postgres", 200),
+            process(3, "herakles-unknown-test", 1000),
+        ];
+        let (group, subgroup) = classify_process_with_config("postgres", &Config::default())
+            .expect("postgres is a built-in subgroup");
+        let cfg = Config {
+            group_labels: Some(BTreeMap::from([(
+                group.to_string(),
+                BTreeMap::from([("team".to_string(), "dba".to_string())]),
+            )])),
+            ..Default::default()
+        };
+
+        let report = owners_report(processes.iter(), &cfg);
+        assert_eq!(report.owner_label, "team");
+        let dba = report
+            .owners
+            .iter()
+            .find(|o| o.owner.as_deref() == Some("dba"))
+            .unwrap();
+        assert_eq!((dba.processes, dba.uss_bytes), (2, 500));
+        let postgres = dba
+            .subgroups
+            .iter()
+            .find(|s| s.subgroup == *subgroup)
+            .unwrap();
+        assert_eq!(postgres.labels["team"], "dba");
+        assert_eq!(postgres.cpu_percent, 2.0);
+        // The unowned process is the largest
+        assert_eq!(report.owners[0].owner, None);
+        assert_eq!(report.owners[0].uss_bytes, 1000);
+    }
+}
@@ -0,0 +1,262 @@
+//! Range query endpoint handler.
+//!
+//! This module provides `/api/v1/query_range` over the scan history (memory or
+//! SQLite), for on-box triage without a Prometheus server. Query parameters:
+//! - `metric`: `uss`, `rss`, `pss`, `cpu_percent` or `processes` (required)
+//! - `subgroup`: `group/subgroup`, a group or a subgroup name; all subgroups if omitted
+//! - `start`, `end`: Unix seconds or RFC 3339 (default: the last hour)
+//! - `step`: seconds or a duration like `5m` (default: `cache_ttl`, at most 250 points)
+//!
+//! The response has the shape of a Prometheus matrix result. Each point is
+//! the average of the scans in `[t, t + step)`; steps without scans are left out.
+
+use axum::{
+    extract::{Query, State},
+    http::{Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, instrument};
+
+use crate::config::DEFAULT_CACHE_TTL;
+use crate::history::{HistoryPoint, SubgroupTotals};
+use crate::state::SharedState;
+
+/// Range used when `start` is omitted.
+const DEFAULT_RANGE_SECONDS: f64 = 3600.0;
+/// Points per series when `step` is omitted.
+const DEFAULT_MAX_POINTS: f64 = 250.0;
+/// Points per series beyond which a query is rejected (as in Prometheus).
+const MAX_POINTS: f64 = 11_000.0;
+
+/// Query parameters of /api/v1/query_range.
+#[derive(Debug, Default, Deserialize)]
+pub struct QueryRangeParams {
+    metric: Option<String>,
+    subgroup: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    step: Option<String>,
+}
+
+/// Handler for GET /api/v1/query_range.
+#[instrument(skip(state))]
+pub async fn query_range_handler(
+    State(state): State<SharedState>,
+    Query(params): Query<QueryRangeParams>,
+    method: Method,
+) -> Response {
+    debug!("Processing /api/v1/query_range request");
+    state.health_stats.record_http_request();
+
+    let metric = match params.metric.as_deref() {
+        Some(m) if SubgroupTotals::FIELDS.contains(&m) => m,
+        Some(m) => {
+            return bad_data(format!(
+                "unknown metric '{}', expected one of: {}",
+                m,
+                SubgroupTotals::FIELDS.join(", ")
+            ))
+        }
+        None => return bad_data("missing parameter 'metric'".to_string()),
+    };
+
+    let now = chrono::Utc::now().timestamp_millis() as f64 / 1000.0;
+    let end = match params.end.as_deref().map(parse_time).unwrap_or(Some(now)) {
+        Some(end) => end,
+        None => return bad_data("invalid parameter 'end'".to_string()),
+    };
+    let start = match params
+        .start
+        .as_deref()
+        .map(parse_time)
+        .unwrap_or(Some(end - DEFAULT_RANGE_SECONDS))
+    {
+        Some(start) if start <= end => start,
+        Some(_) => return bad_data("'end' must not be before 'start'".to_string()),
+        None => return bad_data("invalid parameter 'start'".to_string()),
+    };
+    let step = match params.step.as_deref() {
+        Some(s) => match parse_duration(s) {
+            Some(step) => step,
+            None => return bad_data("invalid parameter 'step'".to_string()),
+        },
+        None => {
+            let ttl = state.config.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL) as f64;
+            ttl.max((end - start) / DEFAULT_MAX_POINTS).max(1.0)
+        }
+    };
+    if (end - start) / step > MAX_POINTS {
+        return bad_data(format!(
+            "exceeded maximum resolution of {} points per series; increase 'step'",
+            MAX_POINTS
+        ));
+    }
+    // Parameters are checked, HEAD needs no series
+    if method == Method::HEAD {
+        return StatusCode::OK.into_response();
+    }
+
+    let points = state
+        .history
+        .range((start * 1000.0) as i64, (end * 1000.0) as i64);
+    let subgroups = state.history.subgroups();
+    let result: Vec<Value> = subgroups
+        .iter()
+        .filter(|key| {
+            params
+                .subgroup
+                .as_deref()
+                .is_none_or(|s| subgroup_matches(key, s))
+        })
+        .filter_map(|key| {
+            let values = downsample(&points, key, metric, start, step);
+            if values.is_empty() {
+                return None;
+            }
+            let (group, subgroup) = key.split_once('/').unwrap_or((key, key));
+            Some(json!({
+                "metric": { "__name__": metric, "group": group, "subgroup": subgroup },
+                "values": values,
+            }))
+        })
+        .collect();
+
+    Json(json!({
+        "status": "success",
+        "data": { "resultType": "matrix", "result": result },
+    }))
+    .into_response()
+}
+
+/// Prometheus-style error response for invalid parameters.
+fn bad_data(error: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "status": "error", "errorType": "bad_data", "error": error })),
+    )
+        .into_response()
+}
+
+/// Parses Unix seconds (possibly fractional) or an RFC 3339 timestamp.
+fn parse_time(value: &str) -> Option<f64> {
+    if let Ok(seconds) = value.parse::<f64>() {
+        return seconds.is_finite().then_some(seconds);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| t.timestamp_millis() as f64 / 1000.0)
+}
+
+/// Parses seconds or a duration with unit `s`, `m`, `h` or `d`; must be positive.
+fn parse_duration(value: &str) -> Option<f64> {
+    let (number, unit) = match value.char_indices().last()? {
+        (i, 's') => (&value[..i], 1.0),
+        (i, 'm') => (&value[..i], 60.0),
+        (i, 'h') => (&value[..i], 3600.0),
+        (i, 'd') => (&value[..i], 86400.0),
+        _ => (value, 1.0),
+    };
+    let seconds = number.parse::<f64>().ok()? * unit;
+    (seconds.is_finite() && seconds > 0.0).then_some(seconds)
+}
+
+/// Whether the history key `group/subgroup` is selected by the `subgroup` parameter.
+fn subgroup_matches(key: &str, selector: &str) -> bool {
+    key == selector
+        || key
+            .split_once('/')
+            .is_some_and(|(group, subgroup)| group == selector || subgroup == selector)
+}
+
+/// Averages `metric` of the subgroup `key` over the scans in each step.
+///
+/// `points` must be ordered by time. Returns `[unix_seconds, "value"]` pairs.
+fn downsample(
+    points: &[HistoryPoint],
+    key: &str,
+    metric: &str,
+    start: f64,
+    step: f64,
+) -> Vec<Value> {
+    let mut values = Vec::new();
+    let mut bucket: Option<(i64, f64, u32)> = None;
+
+    for point in points {
+        let Some(value) = point.subgroups.get(key).and_then(|t| t.field(metric)) else {
+            continue;
+        };
+        let index = ((point.timestamp_ms as f64 / 1000.0 - start) / step).floor() as i64;
+        match &mut bucket {
+            Some((i, sum, count)) if *i == index => {
+                *sum += value;
+                *count += 1;
+            }
+            _ => {
+                if let Some(done) = bucket.replace((index, value, 1)) {
+                    values.push(bucket_value(done, start, step));
+                }
+            }
+        }
+    }
+    if let Some(done) = bucket {
+        values.push(bucket_value(done, start, step));
+    }
+    values
+}
+
+fn bucket_value((index, sum, count): (i64, f64, u32), start: f64, step: f64) -> Value {
+    json!([
+        start + index as f64 * step,
+        (sum / count as f64).to_string()
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_downsample() {
+        // One scan per second with uss = second
+        let points: Vec<HistoryPoint> = (0..10)
+            .map(|i| HistoryPoint {
+                timestamp_ms: i * 1000,
+                subgroups: BTreeMap::from([(
+                    "db/postgres".to_string(),
+                    SubgroupTotals {
+                        uss: i as u64,
+                        ..Default::default()
+                    },
+                )]),
+                top_processes: Vec::new(),
+            })
+            .collect();
+
+        let values = downsample(&points, "db/postgres", "uss", 0.0, 5.0);
+        assert_eq!(values, vec![json!([0.0, "2"]), json!([5.0, "7"])]);
+        assert!(downsample(&points, "web/nginx", "uss", 0.0, 5.0).is_empty());
+    }
+
+    #[test]
+    fn test_parse_parameters() {
+        assert_eq!(parse_time("1700000000.5"), Some(1_700_000_000.5));
+        assert_eq!(parse_time("1970-01-01T00:01:00Z"), Some(60.0));
+        assert_eq!(parse_time("yesterday"), None);
+
+        assert_eq!(parse_duration("30"), Some(30.0));
+        assert_eq!(parse_duration("5m"), Some(300.0));
+        assert_eq!(parse_duration("1.5h"), Some(5400.0));
+        assert_eq!(parse_duration("0s"), None);
+        assert_eq!(parse_duration("m"), None);
+
+        assert!(subgroup_matches("db/postgres", "db"));
+        assert!(subgroup_matches("db/postgres", "postgres"));
+        assert!(subgroup_matches("db/postgres", "db/postgres"));
+        assert!(!subgroup_matches("db/postgres", "post"));
+    }
+}
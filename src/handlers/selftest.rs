@@ -0,0 +1,361 @@
+//! Self-test endpoint handler.
+//!
+//! This module provides the `GET /-/selftest` endpoint that runs a small scan
+//! synchronously: it reads a sample of processes with the same parsers and
+//! buffer sizes as the collector, classifies them and reads the system files,
+//! and answers with the result of each check as JSON. After a kernel upgrade
+//! it tells whether collection still works without waiting for the next full
+//! scan to fail. Like `/-/loglevel` it is only routed when `admin_token` is
+//! configured and requires `Authorization: Bearer <admin_token>`.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use tracing::{info, instrument, warn};
+
+use herakles_proc_mem_exporter::CollectError;
+
+use crate::config::Config;
+use crate::handlers::loglevel::admin_authorized;
+use crate::process::{
+    classify_process_with_config, collect_proc_entries, parse_memory_for_process, read_proc_io,
+    read_proc_stat, read_process_cmdline, read_process_name, BufferConfig, MaxProcessesBy,
+    SUBGROUPS,
+};
+use crate::sandbox;
+use crate::state::SharedState;
+use crate::system::{
+    read_cpu_stats, read_extended_memory_info, read_kernel_info, read_load_average,
+};
+
+/// Processes read besides the exporter itself, lowest PIDs first.
+const SAMPLE_PROCESSES: usize = 8;
+/// /proc entries listed to find the sample among kernel threads.
+const LISTED_PROCESSES: usize = 256;
+
+/// Result of one check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    /// Nothing to check, e.g. no readable process besides the exporter
+    Skip,
+    /// Works, but not as configured
+    Warn,
+    Fail,
+}
+
+/// One check of the self-test.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Body of /-/selftest.
+#[derive(Debug, Serialize)]
+pub struct SelftestReport {
+    /// Worst status of all checks
+    pub status: CheckStatus,
+    pub kernel: String,
+    pub duration_seconds: f64,
+    /// PIDs of the sampled processes, the exporter's first
+    pub sample: Vec<u32>,
+    pub checks: Vec<Check>,
+}
+
+/// Handler for GET /-/selftest. Answers 503 if any check failed.
+#[instrument(skip(state, headers))]
+pub async fn selftest_handler(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    state.health_stats.record_http_request();
+
+    if !admin_authorized(&headers, state.config.admin_token.as_deref()) {
+        warn!("Rejected unauthorized self-test");
+        return (StatusCode::UNAUTHORIZED, "unauthorized\n").into_response();
+    }
+
+    let config = state.config.clone();
+    let buffers = state.buffer_config;
+    let report = match sandbox::spawn_blocking(move || run_selftest(&config, &buffers)).await {
+        Ok(report) => report,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("self-test failed to run: {}\n", e),
+            )
+                .into_response()
+        }
+    };
+
+    info!(
+        "Self-test finished in {:.3}s: {:?}",
+        report.duration_seconds, report.status
+    );
+    let status = if report.status == CheckStatus::Fail {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(report)).into_response()
+}
+
+/// Counts of one per-process parser over the sample.
+#[derive(Default)]
+struct ParserTally {
+    parsed: usize,
+    /// Permission denied or exited, expected for foreign processes
+    unreadable: usize,
+    errors: Vec<String>,
+}
+
+impl ParserTally {
+    /// Counts a read of process `pid`; errors of the exporter itself are
+    /// always failures, it can read its own files.
+    fn record<T>(&mut self, pid: u32, result: &Result<T, CollectError>) {
+        match result {
+            Ok(_) => self.parsed += 1,
+            Err(e)
+                if pid != std::process::id() && (e.is_permission_denied() || e.is_not_found()) =>
+            {
+                self.unreadable += 1
+            }
+            Err(e) => self.errors.push(format!("pid {}: {}", pid, e)),
+        }
+    }
+
+    fn check(self, name: &'static str) -> Check {
+        let mut detail = format!("{} parsed", self.parsed);
+        if self.unreadable > 0 {
+            detail.push_str(&format!(", {} not readable", self.unreadable));
+        }
+        let status = if !self.errors.is_empty() {
+            detail.push_str(&format!("; {}", self.errors.join("; ")));
+            CheckStatus::Fail
+        } else if self.parsed == 0 {
+            CheckStatus::Skip
+        } else {
+            CheckStatus::Pass
+        };
+        Check {
+            name,
+            status,
+            detail,
+        }
+    }
+}
+
+/// Checks a system file reader.
+fn system_check<T>(name: &'static str, result: Result<T, CollectError>) -> Check {
+    match result {
+        Ok(_) => Check {
+            name,
+            status: CheckStatus::Pass,
+            detail: "parsed".to_string(),
+        },
+        Err(e) => Check {
+            name,
+            status: CheckStatus::Fail,
+            detail: e.to_string(),
+        },
+    }
+}
+
+/// Largest file of a sample against its configured buffer.
+fn buffer_check(name: &'static str, largest: usize, buffer_kb: usize) -> Check {
+    let status = if largest > buffer_kb.saturating_mul(1024) {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+    Check {
+        name,
+        status,
+        detail: format!(
+            "largest file {} KiB, buffer {} KiB",
+            largest.div_ceil(1024),
+            buffer_kb
+        ),
+    }
+}
+
+/// Reads a sample of processes and the system files, and checks the results.
+pub fn run_selftest(cfg: &Config, buffers: &BufferConfig) -> SelftestReport {
+    let start = Instant::now();
+    let own_pid = std::process::id();
+    let mut checks = Vec::new();
+
+    let entries = collect_proc_entries("/proc", Some(LISTED_PROCESSES), MaxProcessesBy::Pid);
+    let mut sample = vec![own_pid];
+    // Kernel threads have no memory maps to parse and no command line
+    sample.extend(
+        entries
+            .iter()
+            .filter(|e| e.pid != own_pid && read_process_cmdline(&e.proc_path).is_some())
+            .map(|e| e.pid)
+            .take(SAMPLE_PROCESSES),
+    );
+    checks.push(Check {
+        name: "proc_listing",
+        status: if entries.is_empty() {
+            CheckStatus::Fail
+        } else {
+            CheckStatus::Pass
+        },
+        detail: format!("{} processes listed", entries.len()),
+    });
+
+    let mut stat = ParserTally::default();
+    let mut memory = ParserTally::default();
+    let mut io = ParserTally::default();
+    let mut inconsistent = Vec::new();
+    let (mut largest_smaps, mut largest_io) = (0, 0);
+    let mut names = Vec::new();
+    for &pid in &sample {
+        let proc_path = Path::new("/proc").join(pid.to_string());
+        stat.record(pid, &read_proc_stat(&proc_path));
+
+        let values = parse_memory_for_process(&proc_path, buffers);
+        if let Ok(v) = &values {
+            if v.uss > v.pss || v.pss > v.rss {
+                inconsistent.push(format!(
+                    "pid {}: RSS {}, PSS {}, USS {} out of order",
+                    pid, v.rss, v.pss, v.uss
+                ));
+            }
+        }
+        memory.record(pid, &values);
+        io.record(pid, &read_proc_io(&proc_path, buffers.io_kb));
+
+        let smaps = if proc_path.join("smaps_rollup").exists() {
+            "smaps_rollup"
+        } else {
+            "smaps"
+        };
+        if let Ok(content) = fs::read(proc_path.join(smaps)) {
+            largest_smaps = largest_smaps.max(content.len());
+        }
+        if let Ok(content) = fs::read(proc_path.join("io")) {
+            largest_io = largest_io.max(content.len());
+        }
+        if let Some(name) = read_process_name(&proc_path) {
+            names.push(name);
+        }
+    }
+    checks.push(stat.check("stat"));
+    checks.push(memory.check("memory"));
+    checks.push(io.check("io"));
+    checks.push(Check {
+        name: "memory_consistency",
+        status: if inconsistent.is_empty() {
+            CheckStatus::Pass
+        } else {
+            CheckStatus::Fail
+        },
+        detail: if inconsistent.is_empty() {
+            "USS <= PSS <= RSS".to_string()
+        } else {
+            inconsistent.join("; ")
+        },
+    });
+
+    let smaps_kb = if Path::new("/proc/self/smaps_rollup").exists() {
+        buffers.smaps_rollup_kb
+    } else {
+        buffers.smaps_kb
+    };
+    checks.push(buffer_check("smaps_buffer", largest_smaps, smaps_kb));
+    checks.push(buffer_check("io_buffer", largest_io, buffers.io_kb));
+
+    // A built-in rule must match, or the subgroup table failed to load
+    let builtin = SUBGROUPS
+        .keys()
+        .min()
+        .map(|name| (name, classify_process_with_config(name, &Config::default())));
+    let classified = names
+        .iter()
+        .filter(|name| classify_process_with_config(name, cfg).is_some())
+        .count();
+    checks.push(match builtin {
+        Some((name, Some((group, subgroup)))) => Check {
+            name: "classification",
+            status: CheckStatus::Pass,
+            detail: format!(
+                "{} subgroups loaded, {} -> {}/{}; {} of {} sampled processes classified",
+                SUBGROUPS.len(),
+                name,
+                group,
+                subgroup,
+                classified,
+                names.len()
+            ),
+        },
+        Some((name, None)) => Check {
+            name: "classification",
+            status: CheckStatus::Fail,
+            detail: format!("built-in rule {} does not classify", name),
+        },
+        None => Check {
+            name: "classification",
+            status: CheckStatus::Fail,
+            detail: "no subgroups loaded".to_string(),
+        },
+    });
+
+    checks.push(system_check("meminfo", read_extended_memory_info()));
+    checks.push(system_check("loadavg", read_load_average()));
+    checks.push(system_check("system_stat", read_cpu_stats()));
+
+    SelftestReport {
+        status: checks
+            .iter()
+            .map(|c| c.status)
+            .filter(|&s| s != CheckStatus::Skip)
+            .max()
+            .unwrap_or(CheckStatus::Pass),
+        kernel: read_kernel_info()
+            .map(|k| k.release)
+            .unwrap_or_else(|_| "unknown".to_string()),
+        duration_seconds: start.elapsed().as_secs_f64(),
+        sample,
+        checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parser_tally() {
+        let own = std::process::id();
+        let denied = || CollectError::io("/proc/1/io", std::io::ErrorKind::PermissionDenied.into());
+
+        let mut tally = ParserTally::default();
+        tally.record(own, &Ok(()));
+        tally.record(1, &Err::<(), _>(denied()));
+        let check = tally.check("io");
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert_eq!(check.detail, "1 parsed, 1 not readable");
+
+        // The exporter can always read its own files
+        let mut tally = ParserTally::default();
+        tally.record(own, &Err::<(), _>(denied()));
+        assert_eq!(tally.check("io").status, CheckStatus::Fail);
+
+        let mut tally = ParserTally::default();
+        tally.record(
+            1,
+            &Err::<(), _>(CollectError::parse("/proc/1/stat", "invalid")),
+        );
+        assert_eq!(tally.check("stat").status, CheckStatus::Fail);
+        assert_eq!(ParserTally::default().check("io").status, CheckStatus::Skip);
+    }
+}
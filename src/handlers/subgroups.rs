@@ -1,7 +1,9 @@
 //! Subgroups endpoint handler.
 //!
 //! This module provides the `/subgroups` endpoint handler that displays
-//! the loaded process subgroups configuration.
+//! the loaded process subgroups configuration together with how many
+//! processes each pattern matched in the last scan and the most common
+//! unclassified process names.
 
 use ahash::AHashMap as HashMap;
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
@@ -9,6 +11,7 @@ use std::fmt::Write as FmtWrite;
 use tracing::{debug, instrument};
 
 use crate::handlers::health::FOOTER_TEXT;
+use crate::privacy::public_name;
 use crate::process::SUBGROUPS;
 use crate::state::SharedState;
 
@@ -20,6 +23,8 @@ pub async fn subgroups_handler(State(state): State<SharedState>) -> impl IntoRes
     // Track HTTP request
     state.health_stats.record_http_request();
 
+    let rule_stats = state.cache.read().await.rule_stats.clone();
+
     // Collect unique (group, subgroup) pairs with their associated process name matches
     let mut subgroup_data: HashMap<(String, String), Vec<String>> = HashMap::new();
 
@@ -52,11 +57,14 @@ pub async fn subgroups_handler(State(state): State<SharedState>) -> impl IntoRes
     writeln!(out).ok();
     writeln!(
         out,
-        "Total patterns: {} | Unique subgroups: {}",
+        "Total patterns: {} | Unique subgroups: {} | Patterns with hits: {} | Unclassified processes: {}",
         SUBGROUPS.len(),
-        unique_subgroups_count
+        unique_subgroups_count,
+        rule_stats.hits.len(),
+        rule_stats.unclassified_total
     )
     .ok();
+    writeln!(out, "Hit counts (in parentheses) refer to the last scan.").ok();
     writeln!(out).ok();
 
     // Group entries by group name for better readability
@@ -73,8 +81,40 @@ pub async fn subgroups_handler(State(state): State<SharedState>) -> impl IntoRes
         }
 
         matches.sort();
-        let matches_str = matches.join(", ");
-        writeln!(out, "  {:<20} -> {}", subgroup, matches_str).ok();
+        let subgroup_hits: usize = matches
+            .iter()
+            .filter_map(|m| rule_stats.hits.get(m.as_str()))
+            .sum();
+        let matches_str = matches
+            .iter()
+            .map(|m| match rule_stats.hits.get(m.as_str()) {
+                Some(hits) => format!("{} ({})", m, hits),
+                None => m.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            out,
+            "  {:<20} {:>5} -> {}",
+            subgroup, subgroup_hits, matches_str
+        )
+        .ok();
+    }
+
+    writeln!(out).ok();
+    writeln!(out, "MOST COMMON UNCLASSIFIED").ok();
+    writeln!(out, "{}", "-".repeat(40)).ok();
+    if rule_stats.unclassified.is_empty() {
+        writeln!(out, "  none").ok();
+    }
+    for (name, count) in &rule_stats.unclassified {
+        writeln!(
+            out,
+            "  {:<26} {:>5}",
+            public_name(name, &state.config),
+            count
+        )
+        .ok();
     }
 
     writeln!(out).ok();
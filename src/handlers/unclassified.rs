@@ -0,0 +1,45 @@
+//! Unclassified process names endpoint handler.
+//!
+//! This module provides the `/api/v1/unclassified` endpoint that returns the
+//! most common process names not matched by any subgroup rule since startup,
+//! as JSON. Requests are limited to one per second.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use tracing::{debug, instrument};
+
+use crate::privacy::public_name;
+use crate::state::SharedState;
+use crate::unclassified::DEFAULT_LIMIT;
+
+/// Query parameters of /api/v1/unclassified.
+#[derive(Debug, Deserialize)]
+pub struct UnclassifiedQuery {
+    /// Number of entries to return (capped at 100)
+    limit: Option<usize>,
+}
+
+/// Handler for GET /api/v1/unclassified.
+#[instrument(skip(state))]
+pub async fn unclassified_handler(
+    State(state): State<SharedState>,
+    Query(query): Query<UnclassifiedQuery>,
+) -> Response {
+    debug!("Processing /api/v1/unclassified request");
+    state.health_stats.record_http_request();
+
+    if !state.unclassified.try_acquire_request() {
+        return (StatusCode::TOO_MANY_REQUESTS, "rate limited\n").into_response();
+    }
+
+    let mut report = state.unclassified.top(query.limit.unwrap_or(DEFAULT_LIMIT));
+    for entry in &mut report.entries {
+        entry.name = public_name(&entry.name, &state.config).into_owned();
+    }
+    Json(report).into_response()
+}
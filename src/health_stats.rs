@@ -133,6 +133,7 @@ pub struct HealthStats {
     // Exporter resources
     pub exporter_memory_mb: Stat,
     pub exporter_cpu_percent: Stat,
+    pub self_limit_events: AtomicU64,
 
     // Timing
     pub start_time: Instant,
@@ -158,6 +159,7 @@ impl Default for HealthStats {
             metrics_endpoint_calls: AtomicU64::new(0),
             exporter_memory_mb: Stat::default(),
             exporter_cpu_percent: Stat::default(),
+            self_limit_events: AtomicU64::new(0),
             start_time: Instant::now(),
             last_scan_time: StdRwLock::new(None),
         }
@@ -229,6 +231,10 @@ impl HealthStats {
         self.exporter_cpu_percent.add_sample(cpu_percent);
     }
 
+    pub fn record_self_limit_event(&self) {
+        self.self_limit_events.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn update_last_scan_time(&self) {
         if let Ok(mut guard) = self.last_scan_time.write() {
             *guard = Some(Instant::now());
@@ -309,6 +315,7 @@ impl HealthStats {
         let cache_hit_ratio = self.get_cache_hit_ratio();
         let http_requests_last_minute = self.http_request_timestamps.count_last_minute();
         let metrics_calls = self.metrics_endpoint_calls.load(Ordering::Relaxed);
+        let self_limit_events = self.self_limit_events.load(Ordering::Relaxed);
         let uptime_hours = self.get_uptime_hours();
         let last_scan = self.get_last_scan_time_str();
 
@@ -524,6 +531,19 @@ impl HealthStats {
         )
         .ok();
 
+        writeln!(
+            out,
+            "{:left$} | {:^col$} | {:^col$} | {:^col$} | {:^col$}",
+            "self_limit_events",
+            format!("{}", self_limit_events),
+            "N/A",
+            "N/A",
+            "N/A",
+            left = left_col,
+            col = col_w
+        )
+        .ok();
+
         // Summary line
         writeln!(out).ok();
         writeln!(
@@ -0,0 +1,317 @@
+//! In-memory history of recent scans.
+//!
+//! After every cache update the per-subgroup totals are appended to a ring
+//! buffer of fixed size, so endpoints like the Grafana datasource can answer
+//! range queries without a Prometheus server. Each scan keeps its subgroup
+//! totals and its largest processes by USS.
+//!
+//! With the `sqlite` build feature and `history_db`, scans are also appended
+//! to a SQLite file with time-based retention, and range queries are answered
+//! from it, so the history survives restarts. `history_downsampling` keeps
+//! the file small on long-running hosts by averaging older scans.
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Mutex;
+#[cfg(feature = "sqlite")]
+use tracing::{info, warn};
+
+use crate::cache::ProcMem;
+use crate::config::Config;
+use crate::privacy::public_name;
+use crate::process::classify_process_with_config;
+
+/// Number of scans kept when `history_points` is not configured.
+pub const DEFAULT_HISTORY_POINTS: usize = 120;
+/// Retention of the SQLite history when `history_retention_hours` is not configured.
+pub const DEFAULT_HISTORY_RETENTION_HOURS: u64 = 168;
+/// Minutes of raw scans kept by `history_downsampling` when not configured.
+pub const DEFAULT_DOWNSAMPLING_RAW_MINUTES: u64 = 60;
+/// Downsampling interval when `history_downsampling.interval_minutes` is not configured.
+pub const DEFAULT_DOWNSAMPLING_INTERVAL_MINUTES: u64 = 5;
+/// Largest processes by USS kept per scan.
+pub const TOP_PROCESSES_PER_POINT: usize = 10;
+
+/// Whether scans are recorded, i.e. any consumer of the history is configured.
+pub fn history_enabled(config: &Config) -> bool {
+    config.enable_history.unwrap_or(false)
+        || config.enable_grafana.unwrap_or(false)
+        || config.history_db.is_some()
+}
+
+/// Sums over all processes of one subgroup in one scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct SubgroupTotals {
+    pub processes: u64,
+    pub rss: u64,
+    pub pss: u64,
+    pub uss: u64,
+    pub cpu_percent: f64,
+}
+
+impl SubgroupTotals {
+    /// Value of a field by name, as used in datasource targets.
+    pub fn field(&self, name: &str) -> Option<f64> {
+        match name {
+            "processes" => Some(self.processes as f64),
+            "rss" => Some(self.rss as f64),
+            "pss" => Some(self.pss as f64),
+            "uss" => Some(self.uss as f64),
+            "cpu_percent" => Some(self.cpu_percent),
+            _ => None,
+        }
+    }
+
+    /// Field names accepted by [`SubgroupTotals::field`].
+    pub const FIELDS: &'static [&'static str] = &["uss", "rss", "pss", "cpu_percent", "processes"];
+}
+
+/// One of the largest processes of a scan.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopProcess {
+    pub pid: u32,
+    /// Stable process identity (PID and start time), tells a reused PID
+    /// apart from the process that had it before
+    pub proc_id: String,
+    pub name: String,
+    /// "group/subgroup"
+    pub subgroup: String,
+    pub rss: u64,
+    pub pss: u64,
+    pub uss: u64,
+    pub cpu_percent: f64,
+}
+
+/// Subgroup totals and largest processes of one scan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryPoint {
+    /// Unix time of the scan in milliseconds
+    pub timestamp_ms: i64,
+    /// Totals by "group/subgroup"
+    pub subgroups: BTreeMap<String, SubgroupTotals>,
+    /// Up to [`TOP_PROCESSES_PER_POINT`] processes, largest USS first
+    pub top_processes: Vec<TopProcess>,
+}
+
+impl HistoryPoint {
+    /// Classifies `processes` with `config` and sums them per subgroup.
+    pub fn from_processes(timestamp_ms: i64, processes: &[ProcMem], config: &Config) -> Self {
+        let mut subgroups: BTreeMap<String, SubgroupTotals> = BTreeMap::new();
+        let mut top_processes = Vec::new();
+        for p in processes {
+            if let Some((group, subgroup)) = classify_process_with_config(p.classify_name(), config)
+            {
+                let key = format!("{}/{}", group, subgroup);
+                let totals = subgroups.entry(key.clone()).or_default();
+                totals.processes += 1;
+                totals.rss += p.rss;
+                totals.pss += p.pss;
+                totals.uss += p.uss;
+                totals.cpu_percent += p.cpu_percent as f64;
+                top_processes.push(TopProcess {
+                    pid: p.pid,
+                    proc_id: p.key().to_string(),
+                    name: public_name(&p.name, config).into_owned(),
+                    subgroup: key,
+                    rss: p.rss,
+                    pss: p.pss,
+                    uss: p.uss,
+                    cpu_percent: p.cpu_percent as f64,
+                });
+            }
+        }
+        top_processes.sort_by(|a, b| b.uss.cmp(&a.uss).then(a.pid.cmp(&b.pid)));
+        top_processes.truncate(TOP_PROCESSES_PER_POINT);
+        Self {
+            timestamp_ms,
+            subgroups,
+            top_processes,
+        }
+    }
+}
+
+/// Ring buffer of the most recent scans, optionally backed by a SQLite file.
+pub struct SnapshotHistory {
+    points: Mutex<VecDeque<HistoryPoint>>,
+    capacity: usize,
+    #[cfg(feature = "sqlite")]
+    store: Option<sqlite::SqliteStore>,
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            points: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            #[cfg(feature = "sqlite")]
+            store: None,
+        }
+    }
+
+    /// Creates the history configured by `history_points` and `history_db`.
+    ///
+    /// A SQLite file that cannot be opened is logged and the history stays
+    /// in memory; the exporter does not refuse to start over it.
+    pub fn from_config(config: &Config) -> Self {
+        #[allow(unused_mut)]
+        let mut history = Self::new(config.history_points.unwrap_or(DEFAULT_HISTORY_POINTS));
+
+        #[cfg(feature = "sqlite")]
+        if let Some(path) = &config.history_db {
+            let retention_hours = config
+                .history_retention_hours
+                .unwrap_or(DEFAULT_HISTORY_RETENTION_HOURS);
+            let downsampling = config
+                .history_downsampling
+                .as_ref()
+                .map(|d| sqlite::Downsampling {
+                    raw_ms: d.raw_minutes.unwrap_or(DEFAULT_DOWNSAMPLING_RAW_MINUTES) as i64
+                        * 60_000,
+                    interval_ms: d
+                        .interval_minutes
+                        .unwrap_or(DEFAULT_DOWNSAMPLING_INTERVAL_MINUTES)
+                        .max(1) as i64
+                        * 60_000,
+                });
+            match sqlite::SqliteStore::open(path, retention_hours, downsampling) {
+                Ok(store) => {
+                    info!(
+                        "Persisting scan history to {} ({}h retention{})",
+                        path.display(),
+                        retention_hours,
+                        downsampling.map_or(String::new(), |d| format!(
+                            ", {}m intervals after {}m",
+                            d.interval_ms / 60_000,
+                            d.raw_ms / 60_000
+                        ))
+                    );
+                    history.store = Some(store);
+                }
+                Err(e) => warn!(
+                    "History database {} disabled, keeping history in memory: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        #[cfg(not(feature = "sqlite"))]
+        if config.history_db.is_some() {
+            tracing::warn!(
+                "history_db is configured but this build lacks the \"sqlite\" feature; \
+                 keeping history in memory"
+            );
+        }
+
+        history
+    }
+
+    /// Appends a scan, dropping the oldest one once the buffer is full.
+    pub fn record(&self, point: HistoryPoint) {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            if let Err(e) = store.insert(&point) {
+                warn!("Failed to write scan to history database: {}", e);
+            }
+        }
+
+        let mut points = self.points.lock().unwrap_or_else(|e| e.into_inner());
+        while points.len() >= self.capacity.max(1) {
+            points.pop_front();
+        }
+        points.push_back(point);
+    }
+
+    /// Scans with `from_ms <= timestamp <= to_ms`, oldest first.
+    pub fn range(&self, from_ms: i64, to_ms: i64) -> Vec<HistoryPoint> {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            match store.range(from_ms, to_ms) {
+                Ok(points) => return points,
+                Err(e) => warn!("Failed to read history database: {}", e),
+            }
+        }
+
+        let points = self.points.lock().unwrap_or_else(|e| e.into_inner());
+        points
+            .iter()
+            .filter(|p| (from_ms..=to_ms).contains(&p.timestamp_ms))
+            .cloned()
+            .collect()
+    }
+
+    /// Size of the history database in bytes, None without one.
+    pub fn storage_bytes(&self) -> Option<u64> {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            match store.size_bytes() {
+                Ok(bytes) => return Some(bytes),
+                Err(e) => warn!("Failed to read history database size: {}", e),
+            }
+        }
+        None
+    }
+
+    /// All "group/subgroup" keys present in the buffer.
+    pub fn subgroups(&self) -> BTreeSet<String> {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            match store.subgroups() {
+                Ok(subgroups) => return subgroups,
+                Err(e) => warn!("Failed to read history database: {}", e),
+            }
+        }
+
+        let points = self.points.lock().unwrap_or_else(|e| e.into_inner());
+        points
+            .iter()
+            .flat_map(|p| p.subgroups.keys().cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(timestamp_ms: i64, uss: u64) -> HistoryPoint {
+        let totals = SubgroupTotals {
+            processes: 1,
+            uss,
+            ..Default::default()
+        };
+        HistoryPoint {
+            timestamp_ms,
+            subgroups: BTreeMap::from([("db/postgres".to_string(), totals)]),
+            top_processes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let history = SnapshotHistory::new(3);
+        for i in 0..5 {
+            history.record(point(i * 1000, i as u64));
+        }
+
+        let points = history.range(i64::MIN, i64::MAX);
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].timestamp_ms, 2000);
+        assert_eq!(history.range(2500, 3000).len(), 1);
+        assert_eq!(
+            history.subgroups().into_iter().collect::<Vec<_>>(),
+            vec!["db/postgres"]
+        );
+    }
+
+    #[test]
+    fn test_totals_field() {
+        let totals = point(0, 42).subgroups["db/postgres"];
+        assert_eq!(totals.field("uss"), Some(42.0));
+        assert_eq!(totals.field("processes"), Some(1.0));
+        assert_eq!(totals.field("vsz"), None);
+    }
+}
@@ -0,0 +1,463 @@
+//! SQLite persistence of the scan history.
+//!
+//! Every scan is appended in one transaction: one row per subgroup in
+//! `subgroup_totals` and one row per top process in `top_processes`, both keyed
+//! by the scan's Unix time in milliseconds. Rows older than the retention are
+//! deleted on insert. The schema is plain so the file can also be queried with
+//! the `sqlite3` shell.
+//!
+//! Every few minutes the store is compacted: with downsampling, the scans of
+//! each complete interval past the raw period are replaced by one point at
+//! the start of the interval, then freed pages are returned to the file
+//! system and the write-ahead log is truncated.
+
+use rusqlite::{params, Connection, Transaction};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use super::{HistoryPoint, SubgroupTotals, TopProcess, TOP_PROCESSES_PER_POINT};
+
+/// Time between compactions.
+const COMPACTION_INTERVAL_MS: i64 = 300_000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS subgroup_totals (
+    timestamp_ms INTEGER NOT NULL,
+    subgroup     TEXT    NOT NULL,
+    processes    INTEGER NOT NULL,
+    rss          INTEGER NOT NULL,
+    pss          INTEGER NOT NULL,
+    uss          INTEGER NOT NULL,
+    cpu_percent  REAL    NOT NULL,
+    PRIMARY KEY (timestamp_ms, subgroup)
+) WITHOUT ROWID;
+
+CREATE TABLE IF NOT EXISTS top_processes (
+    timestamp_ms INTEGER NOT NULL,
+    rank         INTEGER NOT NULL,
+    pid          INTEGER NOT NULL,
+    name         TEXT    NOT NULL,
+    subgroup     TEXT    NOT NULL,
+    rss          INTEGER NOT NULL,
+    pss          INTEGER NOT NULL,
+    uss          INTEGER NOT NULL,
+    cpu_percent  REAL    NOT NULL,
+    proc_id      TEXT    NOT NULL DEFAULT '',
+    PRIMARY KEY (timestamp_ms, rank)
+) WITHOUT ROWID;
+";
+
+/// Periods of the downsampled history, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Downsampling {
+    /// Age up to which every scan is kept
+    pub raw_ms: i64,
+    /// Width of the intervals older scans are averaged over
+    pub interval_ms: i64,
+}
+
+/// Scan history in a SQLite file.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    /// None for in-memory databases
+    path: Option<PathBuf>,
+    retention_ms: i64,
+    downsampling: Option<Downsampling>,
+    last_compaction_ms: AtomicI64,
+}
+
+impl SqliteStore {
+    /// Opens or creates the database at `path`.
+    pub fn open(
+        path: &Path,
+        retention_hours: u64,
+        downsampling: Option<Downsampling>,
+    ) -> rusqlite::Result<Self> {
+        let mut store =
+            Self::with_connection(Connection::open(path)?, retention_hours, downsampling)?;
+        store.path = Some(path.to_path_buf());
+        Ok(store)
+    }
+
+    fn with_connection(
+        conn: Connection,
+        retention_hours: u64,
+        downsampling: Option<Downsampling>,
+    ) -> rusqlite::Result<Self> {
+        // Freed pages are only returned to the file system with auto_vacuum;
+        // files created without it are converted once
+        let auto_vacuum: i64 = conn.pragma_query_value(None, "auto_vacuum", |row| row.get(0))?;
+        if auto_vacuum != 2 {
+            conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+            conn.execute_batch("VACUUM")?;
+        }
+        // WAL keeps readers (HTTP handlers) from blocking the collector's insert
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+        // Files written before top processes had a proc_id
+        let has_proc_id: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('top_processes') WHERE name = 'proc_id'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_proc_id {
+            conn.execute_batch(
+                "ALTER TABLE top_processes ADD COLUMN proc_id TEXT NOT NULL DEFAULT ''",
+            )?;
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+            path: None,
+            retention_ms: (retention_hours as i64).saturating_mul(3_600_000),
+            downsampling,
+            last_compaction_ms: AtomicI64::new(i64::MIN),
+        })
+    }
+
+    /// Appends a scan, deletes scans older than the retention and compacts
+    /// the store when due.
+    pub fn insert(&self, point: &HistoryPoint) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let compact = point.timestamp_ms.saturating_sub(COMPACTION_INTERVAL_MS)
+            >= self.last_compaction_ms.load(Ordering::Relaxed);
+        let tx = conn.transaction()?;
+        insert_point(&tx, point)?;
+        let cutoff = point.timestamp_ms.saturating_sub(self.retention_ms);
+        tx.execute(
+            "DELETE FROM subgroup_totals WHERE timestamp_ms < ?1",
+            [cutoff],
+        )?;
+        tx.execute(
+            "DELETE FROM top_processes WHERE timestamp_ms < ?1",
+            [cutoff],
+        )?;
+        if compact {
+            if let Some(downsampling) = self.downsampling {
+                downsample(&tx, point.timestamp_ms, downsampling)?;
+            }
+        }
+        tx.commit()?;
+
+        if compact {
+            self.last_compaction_ms
+                .store(point.timestamp_ms, Ordering::Relaxed);
+            // Both pragmas do their work while their rows are stepped through
+            let mut vacuum = conn.prepare_cached("PRAGMA incremental_vacuum")?;
+            let mut rows = vacuum.query([])?;
+            while rows.next()?.is_some() {}
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        }
+        Ok(())
+    }
+
+    /// Size of the database file and its write-ahead log in bytes.
+    pub fn size_bytes(&self) -> rusqlite::Result<u64> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let pages: i64 = conn.pragma_query_value(None, "page_count", |row| row.get(0))?;
+        let page_size: i64 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+        let wal = self.path.as_ref().map_or(0, |path| {
+            let mut wal = path.clone().into_os_string();
+            wal.push("-wal");
+            std::fs::metadata(wal).map_or(0, |m| m.len())
+        });
+        Ok((pages * page_size) as u64 + wal)
+    }
+
+    /// Scans with `from_ms <= timestamp <= to_ms`, oldest first.
+    pub fn range(&self, from_ms: i64, to_ms: i64) -> rusqlite::Result<Vec<HistoryPoint>> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut points: BTreeMap<i64, HistoryPoint> = BTreeMap::new();
+        let mut totals = conn.prepare_cached(
+            "SELECT timestamp_ms, subgroup, processes, rss, pss, uss, cpu_percent
+             FROM subgroup_totals WHERE timestamp_ms BETWEEN ?1 AND ?2",
+        )?;
+        let mut rows = totals.query([from_ms, to_ms])?;
+        while let Some(row) = rows.next()? {
+            let totals = SubgroupTotals {
+                processes: row.get::<_, i64>(2)? as u64,
+                rss: row.get::<_, i64>(3)? as u64,
+                pss: row.get::<_, i64>(4)? as u64,
+                uss: row.get::<_, i64>(5)? as u64,
+                cpu_percent: row.get(6)?,
+            };
+            point_at(&mut points, row.get(0)?)
+                .subgroups
+                .insert(row.get(1)?, totals);
+        }
+
+        let mut top = conn.prepare_cached(
+            "SELECT timestamp_ms, pid, name, subgroup, rss, pss, uss, cpu_percent, proc_id
+             FROM top_processes WHERE timestamp_ms BETWEEN ?1 AND ?2
+             ORDER BY timestamp_ms, rank",
+        )?;
+        let mut rows = top.query([from_ms, to_ms])?;
+        while let Some(row) = rows.next()? {
+            let process = TopProcess {
+                pid: row.get(1)?,
+                proc_id: row.get(8)?,
+                name: row.get(2)?,
+                subgroup: row.get(3)?,
+                rss: row.get::<_, i64>(4)? as u64,
+                pss: row.get::<_, i64>(5)? as u64,
+                uss: row.get::<_, i64>(6)? as u64,
+                cpu_percent: row.get(7)?,
+            };
+            point_at(&mut points, row.get(0)?)
+                .top_processes
+                .push(process);
+        }
+
+        Ok(points.into_values().collect())
+    }
+
+    /// All "group/subgroup" keys within the retention.
+    pub fn subgroups(&self) -> rusqlite::Result<BTreeSet<String>> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn.prepare_cached("SELECT DISTINCT subgroup FROM subgroup_totals")?;
+        let subgroups = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(subgroups)
+    }
+}
+
+/// Writes the rows of one point.
+fn insert_point(tx: &Transaction, point: &HistoryPoint) -> rusqlite::Result<()> {
+    let mut insert_totals = tx.prepare_cached(
+        "INSERT OR REPLACE INTO subgroup_totals VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    for (subgroup, t) in &point.subgroups {
+        insert_totals.execute(params![
+            point.timestamp_ms,
+            subgroup,
+            t.processes as i64,
+            t.rss as i64,
+            t.pss as i64,
+            t.uss as i64,
+            t.cpu_percent
+        ])?;
+    }
+
+    let mut insert_top = tx.prepare_cached(
+        "INSERT OR REPLACE INTO top_processes VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )?;
+    for (rank, p) in point.top_processes.iter().enumerate() {
+        insert_top.execute(params![
+            point.timestamp_ms,
+            rank as i64,
+            p.pid,
+            p.name,
+            p.subgroup,
+            p.rss as i64,
+            p.pss as i64,
+            p.uss as i64,
+            p.cpu_percent,
+            p.proc_id
+        ])?;
+    }
+    Ok(())
+}
+
+/// Replaces the scans of every complete interval older than the raw period
+/// by one point at the start of the interval. Subgroup totals are averaged
+/// over the scans of the interval; a subgroup missing from a scan counts as
+/// zero there. The top processes are the largest processes of the interval
+/// by their peak USS.
+fn downsample(tx: &Transaction, now_ms: i64, downsampling: Downsampling) -> rusqlite::Result<()> {
+    let Downsampling {
+        raw_ms,
+        interval_ms,
+    } = downsampling;
+    let cutoff = now_ms.saturating_sub(raw_ms);
+    let cutoff = cutoff - cutoff.rem_euclid(interval_ms);
+
+    // Intervals still holding scans after their start, with their scan count
+    let buckets: Vec<(i64, i64)> = tx
+        .prepare_cached(
+            "SELECT timestamp_ms - timestamp_ms % ?1 AS bucket, COUNT(DISTINCT timestamp_ms)
+             FROM subgroup_totals WHERE timestamp_ms >= 0 AND timestamp_ms < ?2
+             GROUP BY bucket HAVING MAX(timestamp_ms) > bucket",
+        )?
+        .query_map([interval_ms, cutoff], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    for (start, scans) in buckets {
+        let end = start + interval_ms;
+        let mut point = HistoryPoint {
+            timestamp_ms: start,
+            subgroups: BTreeMap::new(),
+            top_processes: Vec::new(),
+        };
+        let average = |sum: i64| (sum as f64 / scans as f64).round() as u64;
+        let mut totals = tx.prepare_cached(
+            "SELECT subgroup, SUM(processes), SUM(rss), SUM(pss), SUM(uss), SUM(cpu_percent)
+             FROM subgroup_totals WHERE timestamp_ms >= ?1 AND timestamp_ms < ?2
+             GROUP BY subgroup",
+        )?;
+        let mut rows = totals.query([start, end])?;
+        while let Some(row) = rows.next()? {
+            let totals = SubgroupTotals {
+                processes: average(row.get(1)?),
+                rss: average(row.get(2)?),
+                pss: average(row.get(3)?),
+                uss: average(row.get(4)?),
+                cpu_percent: row.get::<_, f64>(5)? / scans as f64,
+            };
+            point.subgroups.insert(row.get(0)?, totals);
+        }
+
+        let mut top = tx.prepare_cached(
+            "SELECT pid, name, subgroup, rss, pss, uss, cpu_percent, proc_id
+             FROM top_processes WHERE timestamp_ms >= ?1 AND timestamp_ms < ?2
+             ORDER BY uss DESC, timestamp_ms",
+        )?;
+        let mut rows = top.query([start, end])?;
+        let mut seen = HashSet::new();
+        while let Some(row) = rows.next()? {
+            if point.top_processes.len() >= TOP_PROCESSES_PER_POINT {
+                break;
+            }
+            let process = TopProcess {
+                pid: row.get(0)?,
+                proc_id: row.get(7)?,
+                name: row.get(1)?,
+                subgroup: row.get(2)?,
+                rss: row.get::<_, i64>(3)? as u64,
+                pss: row.get::<_, i64>(4)? as u64,
+                uss: row.get::<_, i64>(5)? as u64,
+                cpu_percent: row.get(6)?,
+            };
+            if seen.insert((process.pid, process.proc_id.clone())) {
+                point.top_processes.push(process);
+            }
+        }
+
+        tx.execute(
+            "DELETE FROM subgroup_totals WHERE timestamp_ms >= ?1 AND timestamp_ms < ?2",
+            [start, end],
+        )?;
+        tx.execute(
+            "DELETE FROM top_processes WHERE timestamp_ms >= ?1 AND timestamp_ms < ?2",
+            [start, end],
+        )?;
+        insert_point(tx, &point)?;
+    }
+    Ok(())
+}
+
+/// The point of a scan while assembling query results.
+fn point_at(points: &mut BTreeMap<i64, HistoryPoint>, timestamp_ms: i64) -> &mut HistoryPoint {
+    points.entry(timestamp_ms).or_insert_with(|| HistoryPoint {
+        timestamp_ms,
+        subgroups: BTreeMap::new(),
+        top_processes: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(timestamp_ms: i64, uss: u64) -> HistoryPoint {
+        HistoryPoint {
+            timestamp_ms,
+            subgroups: BTreeMap::from([(
+                "db/postgres".to_string(),
+                SubgroupTotals {
+                    processes: 1,
+                    uss,
+                    ..Default::default()
+                },
+            )]),
+            top_processes: vec![TopProcess {
+                pid: 42,
+                proc_id: "42.1f4".to_string(),
+                name: "postgres".to_string(),
+                subgroup: "db/postgres".to_string(),
+                rss: uss,
+                pss: uss,
+                uss,
+                cpu_percent: 0.5,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_store_roundtrip_and_retention() {
+        let store =
+            SqliteStore::with_connection(Connection::open_in_memory().unwrap(), 1, None).unwrap();
+        store.insert(&point(0, 1)).unwrap();
+        store.insert(&point(1_800_000, 2)).unwrap();
+
+        let points = store.range(i64::MIN, i64::MAX).unwrap();
+        assert_eq!(points, vec![point(0, 1), point(1_800_000, 2)]);
+        assert_eq!(store.subgroups().unwrap().len(), 1);
+
+        // One hour retention: the scan at 0 expires with a scan after 3_600_000
+        store.insert(&point(3_700_000, 3)).unwrap();
+        let points = store.range(i64::MIN, i64::MAX).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].timestamp_ms, 1_800_000);
+        assert_eq!(store.range(3_000_000, 4_000_000).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_store_downsampling() {
+        let dir = tempfile::tempdir().unwrap();
+        let downsampling = Downsampling {
+            raw_ms: 600_000,
+            interval_ms: 300_000,
+        };
+        let store =
+            SqliteStore::open(&dir.path().join("history.db"), 1, Some(downsampling)).unwrap();
+        for (i, uss) in [1, 2, 6].into_iter().enumerate() {
+            store.insert(&point(i as i64 * 60_000, uss)).unwrap();
+        }
+        // Within the raw period nothing is averaged
+        assert_eq!(store.range(i64::MIN, i64::MAX).unwrap().len(), 3);
+
+        // The first interval is past the raw period now
+        store.insert(&point(1_000_000, 9)).unwrap();
+        let points = store.range(i64::MIN, i64::MAX).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].timestamp_ms, 0);
+        assert_eq!(points[0].subgroups["db/postgres"].uss, 3);
+        assert_eq!(points[0].subgroups["db/postgres"].processes, 1);
+        // The process appears once, with its peak
+        assert_eq!(points[0].top_processes.len(), 1);
+        assert_eq!(points[0].top_processes[0].uss, 6);
+        assert_eq!(points[1], point(1_000_000, 9));
+        assert!(store.size_bytes().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_store_adds_proc_id_column() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE top_processes (
+                timestamp_ms INTEGER NOT NULL,
+                rank         INTEGER NOT NULL,
+                pid          INTEGER NOT NULL,
+                name         TEXT    NOT NULL,
+                subgroup     TEXT    NOT NULL,
+                rss          INTEGER NOT NULL,
+                pss          INTEGER NOT NULL,
+                uss          INTEGER NOT NULL,
+                cpu_percent  REAL    NOT NULL,
+                PRIMARY KEY (timestamp_ms, rank)
+            ) WITHOUT ROWID;
+            INSERT INTO top_processes VALUES (0, 0, 42, 'postgres', 'db/postgres', 1, 1, 1, 0.5);",
+        )
+        .unwrap();
+
+        let store = SqliteStore::with_connection(conn, 1, None).unwrap();
+        let points = store.range(i64::MIN, i64::MAX).unwrap();
+        assert_eq!(points[0].top_processes[0].proc_id, "");
+        store.insert(&point(1_000, 1)).unwrap();
+        let points = store.range(1_000, 1_000).unwrap();
+        assert_eq!(points, vec![point(1_000, 1)]);
+    }
+}
@@ -10,6 +10,7 @@
 //! - **Configurable Thresholds**: Set warning and critical thresholds
 //! - **Flexible Status Logic**: Support for both "larger is better" and "smaller is better" buffers
 //! - **Thread-Safe Updates**: Atomic operations for efficient cross-thread updates
+//! - **Typed Collection Errors**: [`CollectError`] with pid/path context for embedding the collector
 //!
 //! # Usage
 //!
@@ -40,9 +41,11 @@
 //!
 //! - `health-actix`: Enables actix-web integration example (see examples/health_server.rs)
 
+pub mod error;
 pub mod health;
 pub mod health_config;
 
 // Re-export main types for convenience
+pub use error::CollectError;
 pub use health::{BufferHealth, HealthResponse, HealthState};
 pub use health_config::{AppConfig, BufferHealthConfig};
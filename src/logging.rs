@@ -0,0 +1,212 @@
+//! Tracing setup and runtime log level control.
+//!
+//! The level filter is installed behind a reload layer so it can be switched
+//! between the startup level and debug without restarting the exporter
+//! (SIGUSR2 or an authenticated `PUT /-/loglevel`). Restarting to get debug
+//! output would throw away the state that triggered the problem.
+//!
+//! With the `otlp` build feature and an `otlp` config section, the same spans
+//! (cache update and its phases, HTTP handlers) are also exported to an
+//! OpenTelemetry collector.
+
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+use tracing::warn;
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, prelude::*, reload, EnvFilter, Registry};
+
+use crate::config::OtlpConfig;
+
+/// Default `service.name` of exported spans.
+#[cfg(feature = "otlp")]
+const DEFAULT_SERVICE_NAME: &str = "herakles-proc-mem-exporter";
+
+/// Handle for changing the active log level at runtime.
+pub struct LogControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    base_level: LevelFilter,
+    current: Mutex<LevelFilter>,
+    #[cfg(feature = "otlp")]
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl LogControl {
+    /// Returns the currently active level.
+    pub fn current(&self) -> LevelFilter {
+        *self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replaces the active level filter.
+    pub fn set_level(&self, level: LevelFilter) -> Result<(), reload::Error> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        self.handle.reload(filter_for(level))?;
+        *current = level;
+        Ok(())
+    }
+
+    /// Switches between debug and the startup level, returning the new level.
+    pub fn toggle_debug(&self) -> Result<LevelFilter, reload::Error> {
+        let next = toggled_level(self.current(), self.base_level);
+        self.set_level(next)?;
+        Ok(next)
+    }
+
+    /// Flushes spans still queued for OTLP export, called before exit.
+    pub fn shutdown(&self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = &self.tracer_provider {
+            if let Err(e) = provider.shutdown() {
+                warn!("Failed to flush OTLP spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Level selected by a toggle: debug, or back to the startup level once debug
+/// is active (info if the exporter was already started in debug or trace).
+fn toggled_level(current: LevelFilter, base: LevelFilter) -> LevelFilter {
+    if current >= LevelFilter::DEBUG {
+        if base >= LevelFilter::DEBUG {
+            LevelFilter::INFO
+        } else {
+            base
+        }
+    } else {
+        LevelFilter::DEBUG
+    }
+}
+
+fn filter_for(level: LevelFilter) -> EnvFilter {
+    EnvFilter::default().add_directive(level.into())
+}
+
+/// Builds the OTLP/HTTP tracer provider for `cfg`.
+#[cfg(feature = "otlp")]
+fn otlp_tracer_provider(
+    cfg: &OtlpConfig,
+) -> Result<opentelemetry_sdk::trace::SdkTracerProvider, Box<dyn std::error::Error>> {
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+    use opentelemetry_sdk::Resource;
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&cfg.endpoint)
+        .build()?;
+    // Child spans follow their root, so a sampled cache update is exported whole
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+        cfg.sample_ratio.unwrap_or(1.0),
+    )));
+    let resource = Resource::builder()
+        .with_service_name(
+            cfg.service_name
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string()),
+        )
+        .build();
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(sampler)
+        .with_resource(resource)
+        .build())
+}
+
+/// Installs the global tracing subscriber with a reloadable level filter.
+///
+/// Logs go to stdout unless `to_stderr` is set, which keeps stdout clean when
+/// it carries the metrics exposition (`--once`).
+pub fn init(level: LevelFilter, to_stderr: bool, otlp: Option<&OtlpConfig>) -> LogControl {
+    let (filter, handle) = reload::Layer::new(filter_for(level));
+
+    let writer = if to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_target(true)
+        .with_thread_ids(false)
+        .with_file(true)
+        .with_line_number(true);
+
+    #[cfg(feature = "otlp")]
+    {
+        use opentelemetry::trace::TracerProvider;
+
+        let (tracer_provider, otlp_error) = match otlp.map(otlp_tracer_provider) {
+            Some(Ok(provider)) => (Some(provider), None),
+            Some(Err(e)) => (None, Some(e)),
+            None => (None, None),
+        };
+        let otel_layer = tracer_provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer("herakles-proc-mem-exporter"))
+        });
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(fmt_layer)
+            .with(otel_layer)
+            .init();
+
+        if let Some(e) = otlp_error {
+            warn!("OTLP trace export disabled: {}", e);
+        }
+
+        LogControl {
+            handle,
+            base_level: level,
+            current: Mutex::new(level),
+            tracer_provider,
+        }
+    }
+
+    #[cfg(not(feature = "otlp"))]
+    {
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(fmt_layer)
+            .init();
+
+        if otlp.is_some() {
+            warn!("otlp is configured, but this build has no OTLP support (feature \"otlp\")");
+        }
+
+        LogControl {
+            handle,
+            base_level: level,
+            current: Mutex::new(level),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggled_level() {
+        assert_eq!(
+            toggled_level(LevelFilter::INFO, LevelFilter::INFO),
+            LevelFilter::DEBUG
+        );
+        assert_eq!(
+            toggled_level(LevelFilter::DEBUG, LevelFilter::INFO),
+            LevelFilter::INFO
+        );
+        assert_eq!(
+            toggled_level(LevelFilter::DEBUG, LevelFilter::WARN),
+            LevelFilter::WARN
+        );
+        assert_eq!(
+            toggled_level(LevelFilter::TRACE, LevelFilter::TRACE),
+            LevelFilter::INFO
+        );
+        assert_eq!(
+            toggled_level(LevelFilter::INFO, LevelFilter::TRACE),
+            LevelFilter::DEBUG
+        );
+    }
+}
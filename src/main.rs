@@ -3,83 +3,202 @@
 //! Professional memory metrics exporter with tracing logging.
 //! This is the main entry point that initializes the server and handles subcommands.
 
+// Tests build configs by assigning fields to Config::default()
+#![cfg_attr(test, allow(clippy::field_reassign_with_default))]
+
+mod aggregate;
+mod audit;
+mod autotune;
 mod cache;
+mod cache_control;
+mod cgroup;
 mod cli;
+mod collector;
 mod commands;
 mod config;
+mod diagnostics;
+mod exposition_budget;
+mod exposition_stats;
+mod federation;
 mod handlers;
 mod health_stats;
+mod history;
+mod logging;
+#[cfg(feature = "mdns")]
+mod mdns;
 mod metrics;
+mod overlap;
+mod pidfile;
+mod pipeline;
+mod privacy;
 mod process;
+mod registration;
+mod request_id;
+mod sandbox;
+mod schedule;
+mod scrape_intervals;
+mod self_limits;
+mod short_lived;
 mod state;
 mod system;
-
-use ahash::AHashMap as HashMap;
-use axum::{routing::get, Router};
+mod textfile;
+mod topn_hysteresis;
+mod unclassified;
+mod warm_start;
+
+use axum::{
+    middleware,
+    routing::{get, post, put},
+    Router,
+};
 use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
 use herakles_proc_mem_exporter::{AppConfig as HealthAppConfig, BufferHealthConfig, HealthState};
-use prometheus::{Gauge, Registry};
-use rayon::prelude::*;
-use std::collections::HashSet;
-use std::fs;
+use once_cell::sync::Lazy;
+use prometheus::{Counter, CounterVec, Gauge, GaugeVec, IntCounterVec, Opts, Registry};
+use std::io::Write;
 use std::net::SocketAddr;
-use std::sync::atomic::Ordering;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, RwLock as StdRwLock};
-use std::time::Instant;
 use tokio::{
     net::TcpListener,
     signal,
-    sync::{Notify, RwLock},
-    time::{interval, Duration},
+    sync::{Mutex, Notify, RwLock},
+    time::{interval, sleep, Duration},
 };
-use tracing::{debug, error, info, instrument, warn, Level};
+use tracing::{debug, error, info, level_filters::LevelFilter, warn};
 
-use cache::{MetricsCache, ProcMem};
-use cli::{Args, Commands, LogLevel};
+use audit::AuditLog;
+use autotune::ScanTuner;
+use cli::{Args, Commands, ConfigAction, LogLevel};
+use collector::{is_on_scrape, log_own_processes_only, update_cache};
+use commands::baseline::{BaselineOptions, Tolerances};
 use commands::{
-    command_check, command_config, command_generate_testdata, command_subgroups, command_test,
+    command_check, command_config, command_config_schema, command_dump, command_generate_testdata,
+    command_healthcheck, command_subgroups, command_test,
 };
 use config::{
-    resolve_config, show_config, validate_effective_config, Config, DEFAULT_BIND_ADDR,
-    DEFAULT_CACHE_TTL, DEFAULT_PORT,
+    resolve_config, resolve_config_with_origins, show_config, validate_effective_config, Config,
+    DEFAULT_BIND_ADDR, DEFAULT_CACHE_TTL, DEFAULT_PORT,
+};
+use federation::{refresh_federation, FederationCache};
+use handlers::{
+    config_handler, doc_handler, export_csv_handler, exposition_handler, grafana_query_handler,
+    grafana_search_handler, grafana_test_handler, health_handler, loglevel_handler,
+    metrics_handler, metrics_v1_handler, metrics_v2_handler, owners_handler, query_range_handler,
+    render_metrics, selftest_handler, subgroups_handler, tenant_metrics_handler,
+    unclassified_handler,
 };
-use handlers::{config_handler, doc_handler, health_handler, metrics_handler, subgroups_handler};
 use health_stats::HealthStats;
-use metrics::MemoryMetrics;
+use history::{history_enabled, SnapshotHistory};
+use logging::LogControl;
+use metrics::{process_label_names, MemoryMetrics};
+use pidfile::PidFile;
+use pipeline::MetricSchema;
 use process::{
-    classify_process_raw, collect_proc_entries, get_cpu_stat_for_pid, parse_memory_for_process,
-    read_process_name, should_include_process, BufferConfig, CLK_TCK, MAX_IO_BUFFER_BYTES,
-    MAX_SMAPS_BUFFER_BYTES, MAX_SMAPS_ROLLUP_BUFFER_BYTES,
+    delayacct_enabled, probe_capabilities, should_include_process, BufferConfig, LabelExtractors,
+    TaskstatsPool, CAPABILITIES, SUBGROUPS,
 };
+use registration::{Registration, Service};
+use sandbox::apply_sandbox;
+use schedule::current_cache_ttl;
+use scrape_intervals::ScrapeIntervals;
+use self_limits::{apply_process_limits, renice_current_thread};
+use short_lived::{ShortLivedTracker, DEFAULT_POLL_INTERVAL_MS};
 use state::{AppState, SharedState};
 use system::CpuStatsCache;
-
-// Re-export load_test_data_from_file for use in update_cache
-use commands::generate::load_test_data_from_file;
+use topn_hysteresis::TopNHysteresis;
+use unclassified::UnclassifiedTracker;
 
 /// Initializes tracing logging subsystem with configured log level.
-fn setup_logging(_config: &Config, args: &Args) {
+fn setup_logging(config: &Config, args: &Args) -> LogControl {
     let log_level = match args.log_level {
-        LogLevel::Off => Level::ERROR,
-        LogLevel::Error => Level::ERROR,
-        LogLevel::Warn => Level::WARN,
-        LogLevel::Info => Level::INFO,
-        LogLevel::Debug => Level::DEBUG,
-        LogLevel::Trace => Level::TRACE,
+        LogLevel::Off => LevelFilter::ERROR,
+        LogLevel::Error => LevelFilter::ERROR,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Trace => LevelFilter::TRACE,
     };
 
-    let subscriber = tracing_subscriber::fmt()
-        .with_max_level(log_level)
-        .with_target(true)
-        .with_thread_ids(false)
-        .with_file(true)
-        .with_line_number(true)
-        .finish();
-
-    tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
+    let log_control = logging::init(log_level, args.once, config.otlp.as_ref());
 
     info!("Logging initialized with level: {:?}", args.log_level);
+    log_control
+}
+
+/// Renders a single exposition after the initial scan and writes it to stdout
+/// or `output` (`--once`).
+async fn write_once(
+    state: &SharedState,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(fed) = &state.config.federation {
+        refresh_federation(state, fed).await;
+    }
+
+    let schema = MetricSchema::from_config(&state.config);
+    let body = render_metrics(
+        state,
+        schema,
+        state.config.sample_timestamps.unwrap_or(false),
+        None,
+    )
+    .await
+    .map_err(|_| "Failed to encode metrics")?;
+
+    match output {
+        Some(path) => {
+            textfile::write_atomic(path, body.as_bytes())?;
+            info!("Metrics written to {}", path.display());
+        }
+        None => std::io::stdout().write_all(body.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// Logs the listening address and writes it to the ready file, if configured.
+///
+/// With `port: 0` this is the only place the OS-assigned port shows up.
+fn announce_listening(cfg: &Config, scheme: &str, addr: SocketAddr) -> std::io::Result<()> {
+    info!(
+        "herakles-proc-mem-exporter listening on {}://{}",
+        scheme, addr
+    );
+    if let Some(path) = &cfg.ready_file {
+        textfile::write_atomic(path, format!("{}\n", addr).as_bytes()).map_err(|e| {
+            error!("Failed to write ready file {}: {}", path.display(), e);
+            e
+        })?;
+        info!("Listening address written to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Registers the exporter in Consul or etcd, if configured.
+fn start_registration(cfg: &Config, tls: bool, addr: SocketAddr) -> Option<Registration> {
+    let reg = cfg.registration.as_ref()?;
+    Registration::start(reg, Service::new(reg, addr, tls))
+        .map_err(|e| error!("{}", e))
+        .ok()
+}
+
+/// Announces the exporter via mDNS, if configured and compiled in.
+#[cfg(feature = "mdns")]
+fn start_mdns(cfg: &Config, tls: bool, addr: SocketAddr) -> Option<mdns::Responder> {
+    let mdns_cfg = cfg.mdns.as_ref()?;
+    mdns::Responder::start(mdns::Announcement::new(mdns_cfg, addr, tls))
+        .map_err(|e| error!("{}", e))
+        .ok()
+}
+
+#[cfg(not(feature = "mdns"))]
+fn start_mdns(cfg: &Config, _tls: bool, _addr: SocketAddr) -> Option<()> {
+    if cfg.mdns.is_some() {
+        tracing::warn!("mdns is configured, but this build has no mDNS support (feature \"mdns\")");
+    }
+    None
 }
 
 /// Resolve effective buffer sizes (CLI > config > defaults).
@@ -101,266 +220,44 @@ fn resolve_buffer_config(cfg: &Config, args: &Args) -> BufferConfig {
     }
 }
 
-/// Reads the exporter's own memory and CPU usage from /proc/self.
-fn read_self_resources() -> (f64, f64) {
-    let memory_mb = read_self_memory_mb().unwrap_or(0.0);
-    let cpu_percent = read_self_cpu_percent().unwrap_or(0.0);
-    (memory_mb, cpu_percent)
-}
-
-/// Reads the exporter's RSS memory usage from /proc/self/status.
-fn read_self_memory_mb() -> Option<f64> {
-    let content = fs::read_to_string("/proc/self/status").ok()?;
-    for line in content.lines() {
-        if let Some(value) = line.strip_prefix("VmRSS:") {
-            let kb: u64 = value.split_whitespace().next()?.parse().ok()?;
-            return Some(kb as f64 / 1024.0);
-        }
-    }
-    None
-}
-
-/// Reads the exporter's CPU usage from /proc/self/stat.
-fn read_self_cpu_percent() -> Option<f64> {
-    let content = fs::read_to_string("/proc/self/stat").ok()?;
-    let parts: Vec<&str> = content.split_whitespace().collect();
-    if parts.len() <= 14 {
+/// Opens the taskstats sockets of `enable_delay_metrics`. Delay metrics are
+/// left out, with a warning, if the first cannot be opened.
+fn open_taskstats(cfg: &Config) -> Option<TaskstatsPool> {
+    if !cfg.enable_delay_metrics.unwrap_or(false) {
         return None;
     }
-
-    let utime: f64 = parts[13].parse().ok()?;
-    let stime: f64 = parts[14].parse().ok()?;
-    let total_ticks = utime + stime;
-
-    let uptime_content = fs::read_to_string("/proc/uptime").ok()?;
-    let uptime_seconds: f64 = uptime_content.split_whitespace().next()?.parse().ok()?;
-
-    if uptime_seconds > 0.0 {
-        let cpu_time_seconds = total_ticks / *CLK_TCK;
-        Some((cpu_time_seconds / uptime_seconds) * 100.0)
-    } else {
-        None
+    if delayacct_enabled() == Some(false) {
+        warn!("kernel.task_delayacct is 0, IO and swap-in delays will stay at zero");
+    }
+    match TaskstatsPool::open() {
+        Ok(pool) => Some(pool),
+        Err(e) => {
+            warn!(
+                "enable_delay_metrics is set, but taskstats is unavailable (needs CAP_NET_ADMIN): {}",
+                e
+            );
+            None
+        }
     }
 }
 
-/// Cache update function.
-#[instrument(skip(state))]
-async fn update_cache(state: &SharedState) -> Result<(), Box<dyn std::error::Error>> {
-    let start = Instant::now();
-    info!("Starting cache update");
-
-    // Mark cache as updating
-    {
-        let mut cache = state.cache.write().await;
-        cache.is_updating = true;
-        cache.update_success = false;
-        state.cache_updating.set(1.0);
-        debug!("Cache marked as updating (old snapshot still available)");
-    }
-
-    let min_uss_bytes = state.config.min_uss_kb.unwrap_or(0) * 1024;
-
-    use std::sync::atomic::AtomicUsize;
-    let included_count = AtomicUsize::new(0);
-    let skipped_count = AtomicUsize::new(0);
-
-    let results: Vec<ProcMem> = if let Some(test_file) = &state.config.test_data_file {
-        info!("Using test data from file: {}", test_file.display());
-
-        let test_data = match load_test_data_from_file(test_file) {
-            Ok(data) => data,
-            Err(err_msg) => {
-                error!("Failed to load test data: {}", err_msg);
-                state.health_stats.record_scan_failure();
-                {
-                    let mut cache = state.cache.write().await;
-                    cache.is_updating = false;
-                    state.cache_updating.set(0.0);
-                }
-                return Err(err_msg.into());
+/// Runs the initial cache update in the background.
+///
+/// The scan reads /proc synchronously; on a blocking thread it cannot stall
+/// the runtime, which on single-CPU hosts has only one worker to serve
+/// scrapes and fire timers.
+fn spawn_initial_update(state: &SharedState) -> tokio::task::JoinHandle<()> {
+    let state = state.clone();
+    let handle = tokio::runtime::Handle::current();
+    sandbox::spawn_blocking(move || {
+        handle.block_on(async {
+            let _scan = state.scan_lock.lock().await;
+            match update_cache(&state).await {
+                Ok(()) => info!("Initial cache update completed successfully"),
+                Err(e) => error!("Initial cache update failed: {}", e),
             }
-        };
-
-        info!("Loaded {} test processes", test_data.processes.len());
-
-        test_data
-            .processes
-            .into_iter()
-            .filter_map(|tp| {
-                if !should_include_process(&tp.name, &state.config) {
-                    debug!("Skipping process {}: filtered by name config", tp.name);
-                    skipped_count.fetch_add(1, Ordering::Relaxed);
-                    return None;
-                }
-
-                if tp.uss < min_uss_bytes {
-                    debug!(
-                        "Skipping process {}: USS {} bytes below threshold {} bytes",
-                        tp.name, tp.uss, min_uss_bytes
-                    );
-                    skipped_count.fetch_add(1, Ordering::Relaxed);
-                    return None;
-                }
-
-                debug!(
-                    "Including test process {}: {} (RSS: {} MB, PSS: {} MB, USS: {} MB, CPU: {:.6}%)",
-                    tp.pid,
-                    tp.name,
-                    tp.rss / 1024 / 1024,
-                    tp.pss / 1024 / 1024,
-                    tp.uss / 1024 / 1024,
-                    tp.cpu_percent
-                );
-
-                included_count.fetch_add(1, Ordering::Relaxed);
-                Some(ProcMem::from(tp))
-            })
-            .collect()
-    } else {
-        let entries = collect_proc_entries("/proc", state.config.max_processes);
-        debug!("Collected {} process entries from /proc", entries.len());
-
-        entries
-            .par_iter()
-            .filter_map(|entry| {
-                let name = match read_process_name(&entry.proc_path) {
-                    Some(name) => name,
-                    None => {
-                        debug!("Skipping process {}: could not read name", entry.pid);
-                        skipped_count.fetch_add(1, Ordering::Relaxed);
-                        return None;
-                    }
-                };
-
-                if !should_include_process(&name, &state.config) {
-                    debug!("Skipping process {}: filtered by name config", name);
-                    skipped_count.fetch_add(1, Ordering::Relaxed);
-                    return None;
-                }
-
-                let cpu = get_cpu_stat_for_pid(entry.pid, &entry.proc_path, &state.cpu_cache);
-
-                match parse_memory_for_process(&entry.proc_path, &state.buffer_config) {
-                    Ok((rss, pss, uss)) => {
-                        if uss < min_uss_bytes {
-                            debug!(
-                                "Skipping process {}: USS {} bytes below threshold {} bytes",
-                                name, uss, min_uss_bytes
-                            );
-                            skipped_count.fetch_add(1, Ordering::Relaxed);
-                            return None;
-                        }
-
-                        debug!(
-                            "Including process {}: {} (RSS: {} MB, PSS: {} MB, USS: {} MB, CPU: {:.6}%)",
-                            entry.pid,
-                            name,
-                            rss / 1024 / 1024,
-                            pss / 1024 / 1024,
-                            uss / 1024 / 1024,
-                            cpu.cpu_percent
-                        );
-
-                        included_count.fetch_add(1, Ordering::Relaxed);
-                        Some(ProcMem {
-                            pid: entry.pid,
-                            name,
-                            rss,
-                            pss,
-                            uss,
-                            cpu_percent: cpu.cpu_percent as f32,
-                            cpu_time_seconds: cpu.cpu_time_seconds as f32,
-                        })
-                    }
-                    Err(e) => {
-                        debug!("Skipping process {}: failed to parse memory: {}", name, e);
-                        skipped_count.fetch_add(1, Ordering::Relaxed);
-                        None
-                    }
-                }
-            })
-            .collect()
-    };
-
-    let final_included = included_count.load(Ordering::Relaxed);
-    let final_skipped = skipped_count.load(Ordering::Relaxed);
-
-    debug!(
-        "Process filtering completed: {} included, {} skipped",
-        final_included, final_skipped
-    );
-
-    if results.is_empty() {
-        warn!("No processes matched filters after sorting");
-    }
-
-    // Update cache with new data
-    {
-        let mut cache = state.cache.write().await;
-        cache.processes.clear();
-        for p in &results {
-            cache.processes.insert(p.pid, p.clone());
-        }
-
-        cache.update_duration_seconds = start.elapsed().as_secs_f64();
-        cache.update_success = true;
-        cache.last_updated = Some(start);
-        cache.is_updating = false;
-
-        state.cache_updating.set(0.0);
-    }
-
-    state.cache_ready.notify_waiters();
-
-    // Count unique subgroups
-    let mut used_subgroups_set: HashSet<(Arc<str>, Arc<str>)> = HashSet::new();
-    for p in &results {
-        let (group, subgroup) = classify_process_raw(&p.name);
-        used_subgroups_set.insert((group, subgroup));
-    }
-    let subgroups_count = used_subgroups_set.len() as u64;
-
-    let scanned = results.len() as u64;
-    let scan_duration = start.elapsed().as_secs_f64();
-    state
-        .health_stats
-        .record_scan(scanned, scan_duration, scan_duration);
-
-    state.health_stats.record_scan_success();
-    state.health_stats.record_used_subgroups(subgroups_count);
-    state.health_stats.record_cache_size(scanned);
-    state.health_stats.update_last_scan_time();
-
-    // Update buffer usage
-    let io_usage_kb = MAX_IO_BUFFER_BYTES.load(Ordering::Relaxed).div_ceil(1024);
-    let smaps_usage_kb = MAX_SMAPS_BUFFER_BYTES
-        .load(Ordering::Relaxed)
-        .div_ceil(1024);
-    let smaps_rollup_usage_kb = MAX_SMAPS_ROLLUP_BUFFER_BYTES
-        .load(Ordering::Relaxed)
-        .div_ceil(1024);
-
-    state.health_state.update_io_buffer_kb(io_usage_kb as usize);
-    state
-        .health_state
-        .update_smaps_buffer_kb(smaps_usage_kb as usize);
-    state
-        .health_state
-        .update_smaps_rollup_buffer_kb(smaps_rollup_usage_kb as usize);
-
-    let (exporter_mem_mb, exporter_cpu_pct) = read_self_resources();
-    state
-        .health_stats
-        .record_exporter_resources(exporter_mem_mb, exporter_cpu_pct);
-
-    info!(
-        "Cache update completed: {} processes (subgroup filters applied at scrape), {} total scanned, {:.2}ms",
-        results.len(),
-        final_included + final_skipped,
-        start.elapsed().as_secs_f64() * 1000.0
-    );
-
-    Ok(())
+        })
+    })
 }
 
 /// Main application entry point.
@@ -368,9 +265,20 @@ async fn update_cache(state: &SharedState) -> Result<(), Box<dyn std::error::Err
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
+    // Both ring (reqwest) and aws-lc-rs (axum-server) are linked, so rustls
+    // cannot pick a process-wide provider by itself
+    let _ = tokio_rustls::rustls::crypto::aws_lc_rs::default_provider().install_default();
+
     // Early config resolution for show/check modes
     if args.show_config || args.show_user_config || args.check_config {
-        let config = resolve_config(&args)?;
+        let (config, origins) = match resolve_config_with_origins(&args) {
+            Ok(resolved) => resolved,
+            Err(e) if args.check_config => {
+                eprintln!("❌ Configuration invalid: {}", e);
+                std::process::exit(1);
+            }
+            Err(e) => return Err(e),
+        };
 
         if args.check_config {
             if let Err(e) = validate_effective_config(&config) {
@@ -382,11 +290,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
 
         if args.show_config {
-            return show_config(&config, args.config_format, false);
+            return show_config(&config, &origins, args.config_format, false);
         }
 
         if args.show_user_config {
-            return show_config(&config, args.config_format, true);
+            return show_config(&config, &origins, args.config_format, true);
         }
     }
 
@@ -401,6 +309,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return match command {
             Commands::Check { memory, proc, all } => command_check(*memory, *proc, *all, &config),
             Commands::Config {
+                action: Some(ConfigAction::Schema { output }),
+                ..
+            } => command_config_schema(output.clone()),
+            Commands::Config {
+                action: None,
                 output,
                 format,
                 commented,
@@ -409,8 +322,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 iterations,
                 verbose,
                 format,
-            } => command_test(*iterations, *verbose, format.clone(), &config),
+                baseline,
+                write_baseline,
+                count_tolerance,
+                memory_tolerance,
+            } => {
+                let baseline = BaselineOptions {
+                    compare: baseline.clone(),
+                    write: write_baseline.clone(),
+                    tolerances: Tolerances {
+                        count: *count_tolerance,
+                        memory_percent: *memory_tolerance,
+                    },
+                };
+                command_test(*iterations, *verbose, format.clone(), &baseline, &config)
+            }
             Commands::Subgroups { verbose, group } => command_subgroups(*verbose, group.clone()),
+            Commands::Dump {
+                output,
+                format,
+                scans,
+                interval,
+            } => command_dump(output, format.clone(), *scans, *interval, &config),
+            Commands::Healthcheck { timeout, insecure } => {
+                std::process::exit(command_healthcheck(&config, *timeout, *insecure).await)
+            }
             Commands::GenerateTestdata {
                 output,
                 min_per_subgroup,
@@ -422,29 +358,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Load configuration for main server mode
-    let config = resolve_config(&args)?;
+    let mut config = resolve_config(&args)?;
 
     if let Err(e) = validate_effective_config(&config) {
         eprintln!("❌ Configuration invalid: {}", e);
         std::process::exit(1);
     }
 
-    setup_logging(&config, &args);
+    let log_control = setup_logging(&config, &args);
 
     info!("Starting herakles-proc-mem-exporter");
 
+    // May turn off system collectors, so before anything reads them
+    if let Some(probe) = config.overlap_probe.clone() {
+        overlap::run(&mut config, &probe).await;
+    }
+
+    // Refuse to start next to a running instance before binding the port
+    let _pid_file = match (&config.pid_file, args.once) {
+        (Some(path), false) => match PidFile::acquire(path) {
+            Ok(pid_file) => Some(pid_file),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+
     let bind_ip_str = config.bind.as_deref().unwrap_or(DEFAULT_BIND_ADDR);
     let port = config.port.unwrap_or(DEFAULT_PORT);
 
-    // Configure parallel processing
-    if let Some(threads) = config.parallelism {
-        if threads > 0 {
-            rayon::ThreadPoolBuilder::new()
-                .num_threads(threads)
-                .build_global()
-                .unwrap_or_else(|e| error!("Failed to set rayon thread pool: {}", e));
-            debug!("Rayon thread pool configured with {} threads", threads);
+    // Apply process-wide self limits before any scan work happens
+    if let Some(limits) = &config.self_limits {
+        apply_process_limits(limits);
+    }
+
+    // Configure parallel processing (thread count and scan thread niceness)
+    let threads = config.parallelism.unwrap_or(0);
+    let scan_nice = config.self_limits.as_ref().and_then(|l| l.nice);
+    if threads > 0 || scan_nice.is_some() {
+        let mut builder = rayon::ThreadPoolBuilder::new().num_threads(threads);
+        if let Some(nice) = scan_nice {
+            builder = builder.start_handler(move |_| renice_current_thread(nice));
         }
+        builder
+            .build_global()
+            .unwrap_or_else(|e| error!("Failed to set rayon thread pool: {}", e));
+        debug!(
+            "Rayon thread pool configured with {} threads (nice: {:?})",
+            threads, scan_nice
+        );
     }
 
     let buffer_config = resolve_buffer_config(&config, &args);
@@ -453,7 +417,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let registry = Registry::new();
     debug!("Prometheus registry initialized");
 
-    let metrics = MemoryMetrics::new(&registry)?;
+    let label_extractors = LabelExtractors::from_config(&config);
+    let audit = AuditLog::from_path(config.audit_log.as_deref())
+        .map_err(|e| format!("Failed to open audit_log: {}", e))?;
+    if audit.is_enabled() {
+        info!("Audit log of admin requests enabled");
+    }
+    let excluded_uids =
+        privacy::resolve_users(config.exclude_users.as_deref().unwrap_or_default())?;
+    let taskstats = open_taskstats(&config);
+    // Scrapes build the cache families into their own registry; this fails
+    // early on extracted label names Prometheus would reject
+    MemoryMetrics::new(
+        &Registry::new(),
+        &process_label_names(&config, label_extractors.label_names()),
+        config.stable_topn.unwrap_or(false),
+    )?;
     let scrape_duration = Gauge::new(
         "herakles_proc_mem_scrape_duration_seconds",
         "Time spent serving /metrics request (reading from cache)",
@@ -462,6 +441,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "herakles_proc_mem_processes_total",
         "Number of processes currently exported by herakles-proc-mem-exporter",
     )?;
+    let scan_id = Gauge::new(
+        "herakles_proc_mem_scan_id",
+        "Sequence number of the scan the exported process metrics come from",
+    )?;
     let cache_update_duration = Gauge::new(
         "herakles_proc_mem_cache_update_duration_seconds",
         "Time spent updating the process metrics cache in background",
@@ -477,10 +460,236 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     registry.register(Box::new(scrape_duration.clone()))?;
     registry.register(Box::new(processes_total.clone()))?;
+    registry.register(Box::new(scan_id.clone()))?;
     registry.register(Box::new(cache_update_duration.clone()))?;
     registry.register(Box::new(cache_update_success.clone()))?;
     registry.register(Box::new(cache_updating.clone()))?;
 
+    let cache_restored = Gauge::new(
+        "herakles_proc_mem_cache_restored",
+        "Whether the exported process metrics were restored from the cache snapshot (1) or scanned (0)",
+    )?;
+    registry.register(Box::new(cache_restored.clone()))?;
+
+    let self_limit_events = IntCounterVec::new(
+        Opts::new(
+            "herakles_exporter_self_limit_events_total",
+            "Number of scans aborted or truncated because the exporter hit its own resource limits",
+        ),
+        &["action"],
+    )?;
+    registry.register(Box::new(self_limit_events.clone()))?;
+
+    let self_rss_bytes = Gauge::new(
+        "herakles_exporter_self_rss_bytes",
+        "Resident memory of the exporter process",
+    )?;
+    let self_cpu_seconds = Counter::new(
+        "herakles_exporter_self_cpu_seconds_total",
+        "User and system CPU time used by the exporter process",
+    )?;
+    let self_open_fds = Gauge::new(
+        "herakles_exporter_self_open_fds",
+        "Open file descriptors of the exporter process",
+    )?;
+    let self_threads = Gauge::new(
+        "herakles_exporter_self_threads",
+        "Threads of the exporter process",
+    )?;
+    registry.register(Box::new(self_rss_bytes.clone()))?;
+    registry.register(Box::new(self_cpu_seconds.clone()))?;
+    registry.register(Box::new(self_open_fds.clone()))?;
+    registry.register(Box::new(self_threads.clone()))?;
+
+    let scan_threads = Gauge::new(
+        "herakles_exporter_scan_threads",
+        "Threads used by the last /proc scan",
+    )?;
+    registry.register(Box::new(scan_threads.clone()))?;
+
+    let initializing = Gauge::new(
+        "herakles_exporter_initializing",
+        "Whether the initial /proc scan exceeded startup_scan_timeout_seconds and is still running (1) or completed (0)",
+    )?;
+    registry.register(Box::new(initializing.clone()))?;
+
+    let processes_capped = Gauge::new(
+        "herakles_exporter_processes_capped",
+        "Processes left out of the last /proc scan by max_processes",
+    )?;
+    registry.register(Box::new(processes_capped.clone()))?;
+
+    let zombie_processes = Gauge::new(
+        "herakles_proc_mem_zombie_processes",
+        "Zombie processes in the last /proc scan, including processes excluded by filters",
+    )?;
+    registry.register(Box::new(zombie_processes.clone()))?;
+
+    let cpu_steal_ratio = Gauge::new(
+        "herakles_proc_mem_cpu_steal_ratio",
+        "Share of CPU time stolen by the hypervisor between the last two scans, the interval of herakles_proc_mem_cpu_percent",
+    )?;
+    registry.register(Box::new(cpu_steal_ratio.clone()))?;
+
+    let collect_errors = IntCounterVec::new(
+        Opts::new(
+            "herakles_exporter_collect_errors_total",
+            "Number of failed /proc reads during collection by error kind and source file",
+        ),
+        &["kind", "source"],
+    )?;
+    registry.register(Box::new(collect_errors.clone()))?;
+
+    let process_starts = IntCounterVec::new(
+        Opts::new(
+            "herakles_proc_mem_process_starts_total",
+            "Number of processes that appeared between consecutive scans per subgroup",
+        ),
+        &["group", "subgroup"],
+    )?;
+    registry.register(Box::new(process_starts.clone()))?;
+
+    let process_exits = IntCounterVec::new(
+        Opts::new(
+            "herakles_proc_mem_process_exits_total",
+            "Number of processes that disappeared between consecutive scans per subgroup",
+        ),
+        &["group", "subgroup"],
+    )?;
+    registry.register(Box::new(process_exits.clone()))?;
+
+    let short_lived_processes = IntCounterVec::new(
+        Opts::new(
+            "herakles_proc_mem_short_lived_processes_total",
+            "Number of processes that started and exited between two scans",
+        ),
+        &["group", "subgroup"],
+    )?;
+    registry.register(Box::new(short_lived_processes.clone()))?;
+
+    let short_lived_cpu_seconds = CounterVec::new(
+        Opts::new(
+            "herakles_proc_mem_short_lived_cpu_seconds_total",
+            "CPU seconds used by processes that started and exited between two scans",
+        ),
+        &["group", "subgroup"],
+    )?;
+    registry.register(Box::new(short_lived_cpu_seconds.clone()))?;
+
+    let short_lived_peak_rss = GaugeVec::new(
+        Opts::new(
+            "herakles_proc_mem_short_lived_peak_rss_bytes",
+            "Largest peak RSS of the processes that started and exited before the last scan",
+        ),
+        &["group", "subgroup"],
+    )?;
+    registry.register(Box::new(short_lived_peak_rss.clone()))?;
+
+    let rule_hits = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_classification_rule_hits",
+            "Number of processes matched by a subgroup classification rule in the last scan",
+        ),
+        &["group", "subgroup", "rule"],
+    )?;
+    registry.register(Box::new(rule_hits.clone()))?;
+
+    let capability = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_capability",
+            "Whether an optional /proc feature is available to the exporter (1) or not (0)",
+        ),
+        &["feature"],
+    )?;
+    registry.register(Box::new(capability.clone()))?;
+
+    let federation_up = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_federation_up",
+            "Whether the last scrape of a federated downstream exporter succeeded (1) or not (0)",
+        ),
+        &["target"],
+    )?;
+    let federation_scrape_duration = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_federation_scrape_duration_seconds",
+            "Duration of the last scrape of a federated downstream exporter",
+        ),
+        &["target"],
+    )?;
+    let sandbox_active = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_sandbox_active",
+            "Whether a sandbox mechanism is active (1) or not (0)",
+        ),
+        &["mechanism"],
+    )?;
+    registry.register(Box::new(sandbox_active.clone()))?;
+
+    let data_model = system::read_data_model();
+    let data_model_info = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_data_model_info",
+            "Data model of the exporter build and word size of the kernel (always 1)",
+        ),
+        &["model", "machine", "kernel_bits"],
+    )?;
+    data_model_info
+        .with_label_values(&[
+            data_model.model,
+            &data_model.machine,
+            &data_model.kernel_bits.to_string(),
+        ])
+        .set(1.0);
+    registry.register(Box::new(data_model_info))?;
+    if data_model.model == "ILP32" && data_model.kernel_bits == 64 {
+        info!(
+            "32-bit exporter on a 64-bit kernel ({}), consider a native build",
+            data_model.machine
+        );
+    }
+
+    registry.register(Box::new(federation_up.clone()))?;
+    registry.register(Box::new(federation_scrape_duration.clone()))?;
+
+    let scrape_interval = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_scrape_interval_seconds",
+            "Smoothed interval between scrapes of a client",
+        ),
+        &["client", "path"],
+    )?;
+    let scrape_interval_mismatch = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_scrape_interval_mismatch",
+            "Whether a client scrapes much more or less often than the cache refreshes (1) or not (0)",
+        ),
+        &["client", "path", "reason"],
+    )?;
+    registry.register(Box::new(scrape_interval.clone()))?;
+    registry.register(Box::new(scrape_interval_mismatch.clone()))?;
+
+    let scrapes_truncated = IntCounterVec::new(
+        Opts::new(
+            "herakles_exporter_scrapes_truncated_total",
+            "Scrapes answered with a previous snapshot or without per-process series to meet the scrape timeout, or without families to meet max_exposition_bytes",
+        ),
+        &["reason"],
+    )?;
+    for reason in ["previous_snapshot", "aggregates_only", "size_budget"] {
+        scrapes_truncated.with_label_values(&[reason]);
+    }
+    registry.register(Box::new(scrapes_truncated.clone()))?;
+
+    let history_db_bytes = GaugeVec::new(
+        Opts::new(
+            "herakles_exporter_history_db_bytes",
+            "Size of the SQLite scan history including its write-ahead log",
+        ),
+        &[],
+    )?;
+    registry.register(Box::new(history_db_bytes.clone()))?;
+
     debug!("All metrics registered successfully");
 
     let health_stats = Arc::new(HealthStats::new());
@@ -507,54 +716,271 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
     let health_state = Arc::new(HealthState::new(health_config));
 
+    let capabilities = probe_capabilities("/proc");
+    let previous = CAPABILITIES.store(capabilities);
+    info!("Probed /proc capabilities: {:?}", capabilities);
+    log_own_processes_only(previous, capabilities);
+
+    // A one-shot run never serves a restored snapshot
+    let restored_cache = config
+        .cache_snapshot
+        .as_deref()
+        .filter(|_| !args.once)
+        .and_then(|path| warm_start::load(&config, path));
+    let restored = restored_cache.is_some();
+    let restored_cpu = restored_cache
+        .as_ref()
+        .map(warm_start::cpu_entries)
+        .unwrap_or_default();
+
     let state = Arc::new(AppState {
         registry,
-        metrics,
         scrape_duration,
         processes_total,
+        scan_id,
         cache_update_duration,
         cache_update_success,
         cache_updating,
-        cache: Arc::new(RwLock::new(MetricsCache::default())),
+        cache_restored,
+        self_limit_events,
+        self_rss_bytes,
+        self_cpu_seconds,
+        self_open_fds,
+        self_threads,
+        scan_threads,
+        initializing,
+        processes_capped,
+        zombie_processes,
+        cpu_steal_ratio,
+        exposition_stats: StdRwLock::new(None),
+        scan_tuner: config
+            .scan_autotune
+            .as_ref()
+            .map(|autotune| ScanTuner::new(autotune, scan_nice)),
+        collect_errors,
+        process_starts,
+        process_exits,
+        short_lived_processes,
+        short_lived_cpu_seconds,
+        short_lived_peak_rss,
+        short_lived: ShortLivedTracker::new(),
+        rule_hits,
+        capability,
+        federation_up,
+        federation_scrape_duration,
+        scrape_interval,
+        scrape_interval_mismatch,
+        scrape_intervals: ScrapeIntervals::new(),
+        scrapes_truncated,
+        history_db_bytes,
+        full_render_micros: AtomicU64::new(0),
+        federation_cache: FederationCache::new(),
+        cache: Arc::new(RwLock::new(restored_cache.unwrap_or_default())),
         config: Arc::new(config.clone()),
         buffer_config,
-        cpu_cache: StdRwLock::new(HashMap::new()),
+        cpu_cache: StdRwLock::new(restored_cpu),
+        thread_cpu_cache: StdRwLock::default(),
         health_stats: health_stats.clone(),
         health_state,
         cache_ready: Arc::new(Notify::new()),
+        scan_lock: Mutex::new(()),
+        log_control,
         system_cpu_cache: CpuStatsCache::new(),
+        scan_cpu_cache: CpuStatsCache::new(),
+        topn_hysteresis: TopNHysteresis::new(config.top_n_hysteresis_scans.unwrap_or(1)),
+        unclassified: UnclassifiedTracker::new(),
+        history: SnapshotHistory::from_config(&config),
+        label_extractors,
+        excluded_uids,
+        taskstats,
+        audit,
     });
 
-    // Perform initial cache population
-    info!("Performing initial cache update");
-    if let Err(e) = update_cache(&state).await {
-        error!("Initial cache update failed: {}", e);
+    // Perform initial cache population, in the background when a restored
+    // snapshot can be served meanwhile or once it exceeds the startup timeout
+    let startup_timeout = state
+        .config
+        .startup_scan_timeout_seconds
+        .filter(|_| !args.once)
+        .map(Duration::from_secs);
+    if restored {
+        info!("Serving restored cache snapshot, initial cache update runs in the background");
+        state.cache_restored.set(1.0);
+        spawn_initial_update(&state);
+    } else if let Some(timeout) = startup_timeout {
+        info!(
+            "Performing initial cache update (startup timeout {}s)",
+            timeout.as_secs()
+        );
+        // Set before the scan starts, which clears them on completion; no
+        // scrape is served before the timeout anyway
+        state.cache.write().await.initializing = true;
+        state.initializing.set(1.0);
+        let initial = spawn_initial_update(&state);
+        if tokio::time::timeout(timeout, initial).await.is_err() {
+            warn!(
+                "Initial cache update still running after {}s, serving without process metrics until it completes",
+                timeout.as_secs()
+            );
+        }
     } else {
-        info!("Initial cache update completed successfully");
+        info!("Performing initial cache update");
+        if let Err(e) = update_cache(&state).await {
+            error!("Initial cache update failed: {}", e);
+        } else {
+            info!("Initial cache update completed successfully");
+        }
     }
 
-    // Start background cache refresh task
-    let bg_state = state.clone();
+    if let Some(path) = &state.config.textfile_output {
+        // Written by the background task once the initial scan completed
+        if !args.once && !state.cache.read().await.initializing {
+            info!("Writing textfile output to {}", path.display());
+            textfile::write_textfile(&state, path).await;
+        }
+    }
+
+    if args.once {
+        let result = write_once(&state, args.output.as_deref()).await;
+        state.log_control.shutdown();
+        return result;
+    }
+
+    // Sandbox the exporter now that startup files are loaded
+    let sandbox_cfg = state.config.sandbox.clone().unwrap_or_default();
+    Lazy::force(&SUBGROUPS);
+    let sandbox_status = apply_sandbox(&state.config, &sandbox_cfg, args.config.as_deref()).await;
+    for (mechanism, active) in sandbox_status.mechanisms() {
+        sandbox_active
+            .with_label_values(&[mechanism])
+            .set(if active { 1.0 } else { 0.0 });
+    }
+
+    // Start background cache refresh task (not needed when scrapes drive the scans)
     let ttl = Duration::from_secs(state.config.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL));
 
-    let background_task = tokio::spawn(async move {
-        let mut int = interval(ttl);
-        debug!(
-            "Background cache update task started with {}s interval",
+    let background_task = if is_on_scrape(&state) {
+        info!(
+            "Collection mode on_scrape: scanning on demand, snapshots reused for up to {}s",
             ttl.as_secs()
         );
+        None
+    } else {
+        let bg_state = state.clone();
+        if let Some(windows) = &state.config.scan_windows {
+            info!(
+                "{} scan windows configured, current refresh interval {}s",
+                windows.len(),
+                current_cache_ttl(&state.config)
+            );
+        }
+        Some(tokio::spawn(async move {
+            debug!(
+                "Background cache update task started with {}s base interval",
+                ttl.as_secs()
+            );
+
+            loop {
+                // Re-evaluated every cycle so scan windows take effect
+                let next = current_cache_ttl(&bg_state.config);
+                sleep(Duration::from_secs(next)).await;
+                debug!("Starting scheduled cache update ({}s interval)", next);
+                // Waits for an initial scan still running in the background
+                let scan = bg_state.scan_lock.lock().await;
+                let updated = match update_cache(&bg_state).await {
+                    Ok(()) => {
+                        debug!("Scheduled cache update completed");
+                        true
+                    }
+                    Err(e) => {
+                        error!("Scheduled cache update failed: {}", e);
+                        false
+                    }
+                };
+                drop(scan);
+                if let (true, Some(path)) = (updated, &bg_state.config.textfile_output) {
+                    textfile::write_textfile(&bg_state, path).await;
+                }
+            }
+        }))
+    };
+
+    // Follow processes born between scans
+    let short_lived_task = state.config.short_lived.as_ref().map(|short_lived| {
+        let poll_state = state.clone();
+        let poll_interval = Duration::from_millis(
+            short_lived
+                .poll_interval_ms
+                .unwrap_or(DEFAULT_POLL_INTERVAL_MS),
+        );
+        info!(
+            "Short-lived process accounting enabled, polling /proc every {}ms",
+            poll_interval.as_millis()
+        );
 
-        loop {
-            int.tick().await;
-            debug!("Starting scheduled cache update");
-            if let Err(e) = update_cache(&bg_state).await {
-                error!("Scheduled cache update failed: {}", e);
-            } else {
-                debug!("Scheduled cache update completed");
+        tokio::spawn(async move {
+            let mut int = interval(poll_interval);
+            loop {
+                int.tick().await;
+                let poll_state = poll_state.clone();
+                let _ = sandbox::spawn_blocking(move || {
+                    poll_state
+                        .short_lived
+                        .poll(|name| should_include_process(name, &poll_state.config))
+                })
+                .await;
             }
-        }
+        })
     });
 
+    // Start federation task scraping downstream exporters
+    let federation_task = state.config.federation.clone().map(|fed| {
+        let fed_state = state.clone();
+        let fed_interval = Duration::from_secs(fed.interval_seconds.unwrap_or(ttl.as_secs()));
+        info!(
+            "Federation enabled: {} downstream targets every {}s",
+            fed.targets.len(),
+            fed_interval.as_secs()
+        );
+
+        tokio::spawn(async move {
+            let mut int = interval(fed_interval);
+            loop {
+                int.tick().await;
+                refresh_federation(&fed_state, &fed).await;
+            }
+        })
+    });
+
+    // Dump diagnostic state on SIGUSR1
+    #[cfg(unix)]
+    let diagnostics_task = {
+        let diag_state = state.clone();
+        let mut usr1 = signal::unix::signal(signal::unix::SignalKind::user_defined1())
+            .expect("Failed to install SIGUSR1 handler");
+        tokio::spawn(async move {
+            while usr1.recv().await.is_some() {
+                diagnostics::write_dump(&diag_state).await;
+            }
+        })
+    };
+
+    // Toggle debug logging on SIGUSR2
+    #[cfg(unix)]
+    let loglevel_task = {
+        let log_state = state.clone();
+        let mut usr2 = signal::unix::signal(signal::unix::SignalKind::user_defined2())
+            .expect("Failed to install SIGUSR2 handler");
+        tokio::spawn(async move {
+            while usr2.recv().await.is_some() {
+                match log_state.log_control.toggle_debug() {
+                    Ok(level) => info!("Log level switched to {} via SIGUSR2", level),
+                    Err(e) => error!("Failed to switch log level: {}", e),
+                }
+            }
+        })
+    };
+
     // Setup graceful shutdown signal handlers
     let shutdown_signal = async {
         let ctrl_c = async {
@@ -587,27 +1013,78 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Configure HTTP server routes
     let addr: SocketAddr = format!("{}:{}", bind_ip_str, port).parse()?;
 
-    let mut app = Router::new().route("/metrics", get(metrics_handler));
+    let mut app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/metrics/v1", get(metrics_v1_handler))
+        .route("/metrics/v2", get(metrics_v2_handler));
+
+    if let Some(tenants) = &config.tenants {
+        app = app.route("/metrics/{tenant}", get(tenant_metrics_handler));
+        for name in tenants.keys() {
+            info!("Tenant endpoint enabled at /metrics/{}", name);
+        }
+    }
 
     if config.enable_health.unwrap_or(true) {
         app = app.route("/health", get(health_handler));
     }
 
-    app = app
+    // Bodies rendered from the cache or config; HEAD skips rendering them
+    let read_routes = Router::new()
         .route("/config", get(config_handler))
         .route("/subgroups", get(subgroups_handler))
-        .route("/doc", get(doc_handler));
+        .route("/api/v1/unclassified", get(unclassified_handler))
+        .route("/api/v1/owners", get(owners_handler))
+        .route("/doc", get(doc_handler))
+        .route("/debug/exposition", get(exposition_handler))
+        .route_layer(middleware::from_fn(cache_control::answer_head));
+    app = app
+        .merge(read_routes)
+        .route("/export.csv", get(export_csv_handler));
+
+    if config.enable_grafana.unwrap_or(false) {
+        app = app
+            .route("/grafana", get(grafana_test_handler))
+            .route("/grafana/", get(grafana_test_handler))
+            .route("/grafana/search", post(grafana_search_handler))
+            .route("/grafana/query", post(grafana_query_handler));
+        info!("Grafana JSON datasource enabled at /grafana");
+    }
+
+    if history_enabled(&config) {
+        app = app.route("/api/v1/query_range", get(query_range_handler));
+        info!("Range queries over the scan history enabled at /api/v1/query_range");
+    }
+
+    if config.admin_token.is_some() {
+        app = app
+            .route("/-/loglevel", put(loglevel_handler))
+            .route("/-/selftest", get(selftest_handler));
+        info!("Admin endpoints enabled at /-/loglevel and /-/selftest");
+    }
 
     if config.enable_pprof.unwrap_or(false) {
         debug!("Debug endpoints enabled at /debug/pprof");
     }
 
-    let app = app.with_state(state.clone());
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            cache_control::set_cache_control,
+        ))
+        .layer(middleware::from_fn(request_id::track_request))
+        .with_state(state.clone());
 
     // Check if TLS is enabled
     let enable_tls = config.enable_tls.unwrap_or(false);
-
-    if enable_tls {
+    let mut registration = None;
+    let mut mdns = None;
+
+    if !config.enable_http.unwrap_or(true) {
+        // Textfile-only mode - no listener, run until shutdown
+        info!("HTTP disabled, only writing textfile output");
+        shutdown_signal.await;
+    } else if enable_tls {
         // TLS is enabled - use axum_server with rustls
         // These paths are guaranteed to exist since validate_effective_config() was called earlier
         let cert_path = config
@@ -629,12 +1106,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 e
             })?;
 
-        info!(
-            "herakles-proc-mem-exporter listening on https://{}:{}",
-            bind_ip_str, port
-        );
+        // Bound here rather than by axum_server so an OS-assigned port is known
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        announce_listening(&config, "https", listener.local_addr()?)?;
+        registration = start_registration(&config, true, listener.local_addr()?);
+        mdns = start_mdns(&config, true, listener.local_addr()?);
 
-        let server = axum_server::bind_rustls(addr, tls_config).serve(app.into_make_service());
+        let server = axum_server::from_tcp_rustls(listener, tls_config)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
 
         tokio::select! {
             result = server => {
@@ -650,12 +1130,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         // TLS is disabled - use standard TCP listener
         let listener = TcpListener::bind(addr).await?;
-        info!(
-            "herakles-proc-mem-exporter listening on http://{}:{}",
-            bind_ip_str, port
-        );
+        announce_listening(&config, "http", listener.local_addr()?)?;
+        registration = start_registration(&config, false, listener.local_addr()?);
+        mdns = start_mdns(&config, false, listener.local_addr()?);
 
-        let server = axum::serve(listener, app);
+        let server = axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
         tokio::select! {
             result = server => {
@@ -670,9 +1152,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    background_task.abort();
-    let _ = background_task.await;
+    // Deregister first, so the catalog stops sending scrapes here
+    if let Some(registration) = registration {
+        registration.stop().await;
+    }
+    #[cfg(feature = "mdns")]
+    if let Some(mdns) = mdns {
+        mdns.stop().await;
+    }
+    #[cfg(not(feature = "mdns"))]
+    let _ = mdns;
+
+    if let Some(task) = background_task {
+        task.abort();
+        let _ = task.await;
+    }
+    #[cfg(unix)]
+    {
+        diagnostics_task.abort();
+        loglevel_task.abort();
+    }
+
+    if let Some(task) = federation_task {
+        task.abort();
+        let _ = task.await;
+    }
+
+    if let Some(task) = short_lived_task {
+        task.abort();
+        let _ = task.await;
+    }
+
+    // After the scan tasks stopped; an aborted scan leaves the last snapshot
+    if let Some(path) = &config.cache_snapshot {
+        warm_start::save(&state, path).await;
+    }
+
+    // A stale ready file would point harnesses at a dead address
+    if let Some(path) = &config.ready_file {
+        let _ = std::fs::remove_file(path);
+    }
 
+    state.log_control.shutdown();
     info!("herakles-proc-mem-exporter stopped gracefully");
     Ok(())
 }
@@ -0,0 +1,439 @@
+//! mDNS/DNS-SD announcement of the metrics endpoint (feature "mdns").
+//!
+//! With `mdns` configured, the exporter answers multicast DNS queries for
+//! `_prometheus-http._tcp.local` on the local link, so discovery-capable
+//! collectors in labs and home labs find instances without a catalog. This
+//! is a minimal responder: it announces a PTR, SRV, TXT and A record at
+//! startup, answers queries for them and sends a goodbye on shutdown. It does
+//! no conflict probing, so instance names must be unique on the link.
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::config::MdnsConfig;
+use crate::registration::host_name;
+
+/// DNS-SD service type announced by Prometheus exporters.
+pub const SERVICE_TYPE: &str = "_prometheus-http._tcp.local";
+/// Name queried by browsers that enumerate all service types.
+const SERVICES_META: &str = "_services._dns-sd._udp.local";
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+/// Record TTL in seconds (RFC 6762 recommends 120 for host records).
+const RECORD_TTL: u32 = 120;
+/// Unsolicited announcements at startup, one second apart.
+const ANNOUNCEMENTS: usize = 2;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set on records this host owns exclusively.
+const CACHE_FLUSH: u16 = 0x8000;
+
+/// What is announced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announcement {
+    /// Instance label, e.g. "node1"
+    pub instance: String,
+    /// Host label without ".local"
+    pub host: String,
+    pub ip: Ipv4Addr,
+    pub port: u16,
+    pub tls: bool,
+}
+
+impl Announcement {
+    /// Describes this exporter listening on `addr`.
+    pub fn new(cfg: &MdnsConfig, addr: SocketAddr, tls: bool) -> Self {
+        // The first label only, "node1.example.com" is announced as node1.local
+        let host = host_name()
+            .split('.')
+            .next()
+            .unwrap_or("localhost")
+            .to_string();
+        let ip = match (cfg.advertise_address, addr.ip()) {
+            (Some(ip), _) => ip,
+            (None, IpAddr::V4(ip)) if !ip.is_unspecified() => ip,
+            _ => outgoing_ipv4().unwrap_or(Ipv4Addr::LOCALHOST),
+        };
+        Self {
+            instance: cfg.instance_name.clone().unwrap_or_else(|| host.clone()),
+            host,
+            ip,
+            port: addr.port(),
+            tls,
+        }
+    }
+
+    fn instance_name(&self) -> String {
+        format!("{}.{}", self.instance, SERVICE_TYPE)
+    }
+
+    fn host_name(&self) -> String {
+        format!("{}.local", self.host)
+    }
+
+    /// The PTR, SRV, TXT and A records of this instance.
+    fn records(&self, ttl: u32) -> Vec<Record> {
+        let instance = name_labels(&self.instance, SERVICE_TYPE);
+        let host = name_labels(&self.host, "local");
+        let mut srv = Vec::new();
+        srv.extend_from_slice(&0u16.to_be_bytes()); // priority
+        srv.extend_from_slice(&0u16.to_be_bytes()); // weight
+        srv.extend_from_slice(&self.port.to_be_bytes());
+        encode_name(&mut srv, &host);
+        let scheme = if self.tls { "https" } else { "http" };
+        let mut txt = Vec::new();
+        for entry in ["path=/metrics".to_string(), format!("scheme={}", scheme)] {
+            txt.push(entry.len() as u8);
+            txt.extend_from_slice(entry.as_bytes());
+        }
+        let mut ptr = Vec::new();
+        encode_name(&mut ptr, &instance);
+
+        vec![
+            Record::new(split_name(SERVICE_TYPE), TYPE_PTR, false, ttl, ptr),
+            Record::new(instance.clone(), TYPE_SRV, true, ttl, srv),
+            Record::new(instance, TYPE_TXT, true, ttl, txt),
+            Record::new(host, TYPE_A, true, ttl, self.ip.octets().to_vec()),
+        ]
+    }
+
+    /// The response to a query packet, None if it asks for nothing of ours.
+    fn answer(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let questions = parse_questions(packet)?;
+        let mut meta = false;
+        let mut ours = false;
+        for (name, qtype) in &questions {
+            let matches = |own: &str, types: &[u16]| {
+                name.eq_ignore_ascii_case(own) && (*qtype == TYPE_ANY || types.contains(qtype))
+            };
+            meta |= matches(SERVICES_META, &[TYPE_PTR]);
+            ours |= matches(SERVICE_TYPE, &[TYPE_PTR])
+                || matches(&self.instance_name(), &[TYPE_SRV, TYPE_TXT])
+                || matches(&self.host_name(), &[TYPE_A]);
+        }
+        let mut records = Vec::new();
+        if meta {
+            let mut data = Vec::new();
+            encode_name(&mut data, &split_name(SERVICE_TYPE));
+            records.push(Record::new(
+                split_name(SERVICES_META),
+                TYPE_PTR,
+                false,
+                RECORD_TTL,
+                data,
+            ));
+        }
+        if ours {
+            records.extend(self.records(RECORD_TTL));
+        }
+        (!records.is_empty()).then(|| encode_response(&records))
+    }
+}
+
+/// A resource record with pre-encoded data.
+#[derive(Debug, Clone, PartialEq)]
+struct Record {
+    name: Vec<String>,
+    rtype: u16,
+    unique: bool,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+impl Record {
+    fn new(name: Vec<String>, rtype: u16, unique: bool, ttl: u32, data: Vec<u8>) -> Self {
+        Self {
+            name,
+            rtype,
+            unique,
+            ttl,
+            data,
+        }
+    }
+}
+
+fn split_name(name: &str) -> Vec<String> {
+    name.split('.').map(str::to_string).collect()
+}
+
+/// `first` as a single label (instance names may contain dots) before `rest`.
+fn name_labels(first: &str, rest: &str) -> Vec<String> {
+    let mut labels = vec![first.to_string()];
+    labels.extend(split_name(rest));
+    labels
+}
+
+/// Encodes a name without compression; labels are cut at 63 bytes.
+fn encode_name(out: &mut Vec<u8>, labels: &[String]) {
+    for label in labels {
+        let bytes = &label.as_bytes()[..label.len().min(63)];
+        out.push(bytes.len() as u8);
+        out.extend_from_slice(bytes);
+    }
+    out.push(0);
+}
+
+/// An authoritative response carrying `records` as answers.
+fn encode_response(records: &[Record]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(512);
+    out.extend_from_slice(&0u16.to_be_bytes()); // ID, always 0 in mDNS
+    out.extend_from_slice(&0x8400u16.to_be_bytes()); // QR + AA
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&(records.len() as u16).to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    for record in records {
+        encode_name(&mut out, &record.name);
+        out.extend_from_slice(&record.rtype.to_be_bytes());
+        let class = if record.unique {
+            CLASS_IN | CACHE_FLUSH
+        } else {
+            CLASS_IN
+        };
+        out.extend_from_slice(&class.to_be_bytes());
+        out.extend_from_slice(&record.ttl.to_be_bytes());
+        out.extend_from_slice(&(record.data.len() as u16).to_be_bytes());
+        out.extend_from_slice(&record.data);
+    }
+    out
+}
+
+/// Name and type of each question of a query; None for responses and
+/// malformed packets.
+fn parse_questions(packet: &[u8]) -> Option<Vec<(String, u16)>> {
+    let header = packet.get(..12)?;
+    if header[2] & 0x80 != 0 {
+        return None;
+    }
+    let count = u16::from_be_bytes([header[4], header[5]]);
+    let mut pos = 12;
+    let mut questions = Vec::new();
+    for _ in 0..count {
+        let (name, next) = read_name(packet, pos)?;
+        let qtype = u16::from_be_bytes([*packet.get(next)?, *packet.get(next + 1)?]);
+        packet.get(next + 3)?;
+        questions.push((name, qtype));
+        pos = next + 4;
+    }
+    Some(questions)
+}
+
+/// Reads a possibly compressed name at `pos`; returns it and the offset
+/// behind it.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bounds pointer loops
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => {
+                let name = labels.join(".");
+                return Some((name, end.unwrap_or(pos + 1)));
+            }
+            l if l & 0xc0 == 0xc0 => {
+                let target = ((l & 0x3f) << 8) | *packet.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            l if l <= 63 => {
+                let label = packet.get(pos + 1..pos + 1 + l)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + l;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Address of the interface multicast traffic leaves through.
+fn outgoing_ipv4() -> Option<Ipv4Addr> {
+    let socket = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((MDNS_GROUP, MDNS_PORT)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+        _ => None,
+    }
+}
+
+/// Joins the mDNS group on port 5353, shared with other responders.
+fn bind_socket() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// A running responder; sends a goodbye when stopped by [`Responder::stop`].
+pub struct Responder {
+    socket: std::sync::Arc<UdpSocket>,
+    announcement: Announcement,
+    task: JoinHandle<()>,
+}
+
+impl Responder {
+    /// Announces `announcement` and answers queries in the background.
+    pub fn start(announcement: Announcement) -> Result<Self, String> {
+        let socket = bind_socket()
+            .map(std::sync::Arc::new)
+            .map_err(|e| format!("Failed to join the mDNS group: {}", e))?;
+        info!(
+            "Announcing {} via mDNS ({}:{})",
+            announcement.instance_name(),
+            announcement.ip,
+            announcement.port
+        );
+
+        let task_socket = socket.clone();
+        let task_announcement = announcement.clone();
+        let task = tokio::spawn(async move {
+            let socket = task_socket;
+            let announcement = task_announcement;
+            let target = SocketAddr::from((MDNS_GROUP, MDNS_PORT));
+            let announce = encode_response(&announcement.records(RECORD_TTL));
+            for i in 0..ANNOUNCEMENTS {
+                if i > 0 {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                if let Err(e) = socket.send_to(&announce, target).await {
+                    warn!("mDNS announcement failed: {}", e);
+                }
+            }
+
+            let mut buf = [0u8; 9000];
+            loop {
+                let (len, from) = match socket.recv_from(&mut buf).await {
+                    Ok(received) => received,
+                    Err(e) => {
+                        warn!("mDNS receive failed: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                if let Some(response) = announcement.answer(&buf[..len]) {
+                    debug!("Answering mDNS query from {}", from);
+                    if let Err(e) = socket.send_to(&response, target).await {
+                        warn!("mDNS response failed: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            socket,
+            announcement,
+            task,
+        })
+    }
+
+    /// Stops answering and tells caches to drop the records.
+    pub async fn stop(self) {
+        self.task.abort();
+        let _ = self.task.await;
+        let goodbye = encode_response(&self.announcement.records(0));
+        let target = SocketAddr::from((MDNS_GROUP, MDNS_PORT));
+        match self.socket.send_to(&goodbye, target).await {
+            Ok(_) => info!(
+                "Withdrew mDNS announcement of {}",
+                self.announcement.instance_name()
+            ),
+            Err(e) => warn!("Failed to send mDNS goodbye: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn announcement() -> Announcement {
+        Announcement {
+            instance: "node1".to_string(),
+            host: "node1".to_string(),
+            ip: Ipv4Addr::new(192, 168, 1, 20),
+            port: 9215,
+            tls: false,
+        }
+    }
+
+    /// A query for `first`, optionally followed by a label compressed against it.
+    fn query(first: &str, qtype: u16, second: Option<(&str, u16)>) -> Vec<u8> {
+        let mut packet = vec![0, 0, 0, 0, 0, 1 + second.is_some() as u8, 0, 0, 0, 0, 0, 0];
+        encode_name(&mut packet, &split_name(first));
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        if let Some((label, qtype)) = second {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+            packet.extend_from_slice(&[0xc0, 12]);
+            packet.extend_from_slice(&qtype.to_be_bytes());
+            packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn test_parse_questions() {
+        let packet = query(SERVICE_TYPE, TYPE_PTR, Some(("node1", TYPE_SRV)));
+        assert_eq!(
+            parse_questions(&packet).unwrap(),
+            [
+                (SERVICE_TYPE.to_string(), TYPE_PTR),
+                (format!("node1.{}", SERVICE_TYPE), TYPE_SRV),
+            ]
+        );
+
+        // Responses, truncated packets and pointer loops
+        let mut response = packet.clone();
+        response[2] = 0x84;
+        assert!(parse_questions(&response).is_none());
+        assert!(parse_questions(&packet[..20]).is_none());
+        let mut looped = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0xc0, 12];
+        looped.extend_from_slice(&[0, 1, 0, 1]);
+        assert!(parse_questions(&looped).is_none());
+    }
+
+    #[test]
+    fn test_answer() {
+        let announcement = announcement();
+        assert!(announcement
+            .answer(&query("_http._tcp.local", TYPE_PTR, None))
+            .is_none());
+
+        let response = announcement
+            .answer(&query("_Prometheus-HTTP._tcp.local", TYPE_PTR, None))
+            .unwrap();
+        assert_eq!(&response[2..8], &[0x84, 0, 0, 0, 0, 4]);
+        let (name, pos) = read_name(&response, 12).unwrap();
+        assert_eq!(name, SERVICE_TYPE);
+        let (target, _) = read_name(&response, pos + 10).unwrap();
+        assert_eq!(target, format!("node1.{}", SERVICE_TYPE));
+
+        let records = announcement.records(RECORD_TTL);
+        assert_eq!(records[1].data[4..6], 9215u16.to_be_bytes());
+        assert_eq!(&records[2].data[..14], b"\x0dpath=/metrics");
+        assert_eq!(records[3].data, [192, 168, 1, 20]);
+        assert!(response.ends_with(&[192, 168, 1, 20]));
+
+        let response = announcement
+            .answer(&query(SERVICES_META, TYPE_PTR, None))
+            .unwrap();
+        assert_eq!(&response[6..8], &[0, 1]);
+    }
+}
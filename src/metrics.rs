@@ -2,246 +2,723 @@
 //!
 //! This module defines all the Prometheus metrics used to export process
 //! memory and CPU usage information.
+//!
+//! Labelled families are typed: each family takes one label struct whose
+//! fields are its label names, so a call site cannot pass the wrong number or
+//! order of label values.
 
+use crate::cache::{ProcMem, ProcRates};
+use crate::cgroup::{systemd_unit, CgroupMemory};
 use crate::config::Config;
+use crate::process::CLK_TCK;
+use crate::system::{
+    CpuRatios, KernelInfo, KernelLimits, ZfsArcInfo, ZramDevice, ZswapInfo, BOOT_TIME,
+};
 use prometheus::{Gauge, GaugeVec, Opts, Registry};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Defines a label struct and a gauge family labelled by it.
+///
+/// Label names and values are both derived from the field list, in order.
+macro_rules! gauge_family {
+    ($(#[$doc:meta])* $labels:ident => $family:ident { $($field:ident),+ $(,)? }) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $labels<'a> {
+            $(pub $field: &'a str,)+
+        }
+
+        impl $labels<'_> {
+            pub const NAMES: &'static [&'static str] = &[$(stringify!($field)),+];
+        }
+
+        #[doc = concat!("Gauge family labelled by [`", stringify!($labels), "`].")]
+        #[derive(Clone)]
+        pub struct $family(GaugeVec);
+
+        impl $family {
+            fn new(name: &str, help: &str) -> prometheus::Result<Self> {
+                GaugeVec::new(Opts::new(name, help), $labels::NAMES).map(Self)
+            }
+
+            fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+                registry.register(Box::new(self.0.clone()))
+            }
+
+            pub fn set(&self, labels: &$labels<'_>, value: f64) {
+                self.0.with_label_values(&[$(labels.$field),+]).set(value);
+            }
+        }
+    };
+}
+
+/// Label of the stable process identity on per-process series (`proc_id_label`).
+pub const PROC_ID_LABEL: &str = "proc_id";
+
+/// Extra label names of per-process families: `proc_id` with
+/// `proc_id_label`, then the `extracted` label names.
+pub fn process_label_names(cfg: &Config, extracted: &[String]) -> Vec<String> {
+    cfg.proc_id_label
+        .unwrap_or(false)
+        .then(|| PROC_ID_LABEL.to_string())
+        .into_iter()
+        .chain(extracted.iter().cloned())
+        .collect()
+}
+
+/// Labels of per-process series.
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessLabels<'a> {
+    pub pid: &'a str,
+    pub name: &'a str,
+    pub group: &'a str,
+    pub subgroup: &'a str,
+    pub uptime_in_seconds: &'a str,
+    /// Values of `proc_id` (with `proc_id_label`) and the `label_extractors`
+    /// labels; missing values are exported empty
+    pub extra: &'a [String],
+}
+
+impl ProcessLabels<'_> {
+    pub const NAMES: &'static [&'static str] =
+        &["pid", "name", "group", "subgroup", "uptime_in_seconds"];
+}
+
+/// Gauge family labelled by [`ProcessLabels`], the extracted label names and
+/// optionally labels of its own.
+#[derive(Clone)]
+pub struct ProcessGauges {
+    vec: GaugeVec,
+    extra_labels: usize,
+}
+
+impl ProcessGauges {
+    fn new(name: &str, help: &str, extra_labels: &[String]) -> prometheus::Result<Self> {
+        Self::with_own_labels(name, help, extra_labels, &[])
+    }
+
+    /// A family with `own_labels` after the extracted labels, e.g. for info metrics.
+    fn with_own_labels(
+        name: &str,
+        help: &str,
+        extra_labels: &[String],
+        own_labels: &[&str],
+    ) -> prometheus::Result<Self> {
+        let mut names = ProcessLabels::NAMES.to_vec();
+        names.extend(extra_labels.iter().map(String::as_str));
+        names.extend(own_labels);
+        Ok(Self {
+            vec: GaugeVec::new(Opts::new(name, help), &names)?,
+            extra_labels: extra_labels.len(),
+        })
+    }
+
+    fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.vec.clone()))
+    }
+
+    pub fn set(&self, labels: &ProcessLabels<'_>, value: f64) {
+        self.set_with(labels, &[], value);
+    }
+
+    /// Sets a series of a family created with own labels; `own` are their values.
+    pub fn set_with(&self, labels: &ProcessLabels<'_>, own: &[&str], value: f64) {
+        let mut values =
+            Vec::with_capacity(ProcessLabels::NAMES.len() + self.extra_labels + own.len());
+        values.extend([
+            labels.pid,
+            labels.name,
+            labels.group,
+            labels.subgroup,
+            labels.uptime_in_seconds,
+        ]);
+        values
+            .extend((0..self.extra_labels).map(|i| labels.extra.get(i).map_or("", String::as_str)));
+        values.extend(own);
+        self.vec.with_label_values(&values).set(value);
+    }
+}
+
+gauge_family! {
+    /// Labels of per-subgroup sums.
+    SubgroupLabels => SubgroupGauges { group, subgroup, uptime_in_seconds }
+}
+
+/// Labels of Top-N series; `rank` starts at 1.
+#[derive(Debug, Clone, Copy)]
+pub struct TopLabels<'a> {
+    pub group: &'a str,
+    pub subgroup: &'a str,
+    pub rank: &'a str,
+    pub pid: &'a str,
+    pub name: &'a str,
+    pub uptime_in_seconds: &'a str,
+}
+
+impl TopLabels<'_> {
+    pub const NAMES: &'static [&'static str] = &[
+        "group",
+        "subgroup",
+        "rank",
+        "pid",
+        "name",
+        "uptime_in_seconds",
+    ];
+    /// Label names with `stable_topn`, where pid and name move to
+    /// `herakles_proc_mem_top_info`.
+    pub const STABLE_NAMES: &'static [&'static str] =
+        &["group", "subgroup", "rank", "uptime_in_seconds"];
+}
+
+/// Gauge family labelled by [`TopLabels`], without pid and name with
+/// `stable_topn`.
+#[derive(Clone)]
+pub struct TopGauges {
+    vec: GaugeVec,
+    stable: bool,
+}
+
+impl TopGauges {
+    fn new(name: &str, help: &str, stable: bool) -> prometheus::Result<Self> {
+        let names = if stable {
+            TopLabels::STABLE_NAMES
+        } else {
+            TopLabels::NAMES
+        };
+        Ok(Self {
+            vec: GaugeVec::new(Opts::new(name, help), names)?,
+            stable,
+        })
+    }
+
+    fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.vec.clone()))
+    }
+
+    pub fn set(&self, labels: &TopLabels<'_>, value: f64) {
+        let gauge = if self.stable {
+            self.vec.with_label_values(&[
+                labels.group,
+                labels.subgroup,
+                labels.rank,
+                labels.uptime_in_seconds,
+            ])
+        } else {
+            self.vec.with_label_values(&[
+                labels.group,
+                labels.subgroup,
+                labels.rank,
+                labels.pid,
+                labels.name,
+                labels.uptime_in_seconds,
+            ])
+        };
+        gauge.set(value);
+    }
+}
+
+gauge_family! {
+    /// Labels of the rank to process mapping of `stable_topn`.
+    TopInfoLabels => TopInfoGauges { group, subgroup, rank, pid, name }
+}
+
+gauge_family! {
+    /// Labels of the state of a Top-N process; `wchan` is empty unless the
+    /// process is in uninterruptible sleep (`state="D"`).
+    TopWaitLabels => TopWaitGauges { group, subgroup, rank, pid, name, state, wchan }
+}
+
+gauge_family! {
+    /// Labels of privileged process counts; `privilege` is `root` or
+    /// `full_capabilities`.
+    PrivilegeLabels => PrivilegeGauges { group, subgroup, privilege }
+}
+
+gauge_family! {
+    /// Labels of process counts by state; `state` is the state character of
+    /// /proc/<pid>/stat, one of R, S, D and Z.
+    StateLabels => StateGauges { group, subgroup, state }
+}
+
+gauge_family! {
+    /// Labels of per-session or per-process-group sums.
+    SessionLabels => SessionGauges { aggregation, id, leader }
+}
+
+gauge_family! {
+    /// Labels of per-CPU system series.
+    CpuLabels => CpuGauges { cpu }
+}
+
+gauge_family! {
+    /// Labels of the running kernel.
+    KernelLabels => KernelGauges { release, version }
+}
+
+gauge_family! {
+    /// Labels of per-zram-device series.
+    ZramLabels => ZramGauges { device }
+}
+
+gauge_family! {
+    /// Labels of per-cgroup series.
+    CgroupLabels => CgroupGauges { cgroup }
+}
+
+gauge_family! {
+    /// Labels of per-cgroup memory.stat series.
+    CgroupStatLabels => CgroupStatGauges { cgroup, field }
+}
+
+gauge_family! {
+    /// Labels of per-systemd-unit memory limit series.
+    UnitLimitLabels => UnitLimitGauges { unit, cgroup, limit }
+}
 
 /// Collection of Prometheus metrics for memory and CPU monitoring.
 #[derive(Clone)]
 pub struct MemoryMetrics {
-    pub rss: GaugeVec,
-    pub pss: GaugeVec,
-    pub uss: GaugeVec,
-    pub cpu_usage: GaugeVec,
-    pub cpu_time: GaugeVec,
+    pub rss: ProcessGauges,
+    pub pss: ProcessGauges,
+    pub uss: ProcessGauges,
+    pub locked: ProcessGauges,
+    // RSS by backing (`enable_anon_file_metrics`)
+    pub anon: ProcessGauges,
+    pub file: ProcessGauges,
+    pub shmem: ProcessGauges,
+    pub cpu_usage: ProcessGauges,
+    pub cpu_time: ProcessGauges,
+    pub cpu_guest: ProcessGauges,
+    // Scheduling priority (`enable_priority_metrics`)
+    pub nice: ProcessGauges,
+    pub sched_info: ProcessGauges,
+    pub io_info: ProcessGauges,
+    // Proximity to the cgroup memory limit (`cgroups.process_limits`)
+    pub limit_utilization: ProcessGauges,
+    // Delay accounting (`enable_delay_metrics`)
+    pub blkio_delay: ProcessGauges,
+    pub swapin_delay: ProcessGauges,
+    // Scheduler statistics (`enable_schedstat`)
+    pub sched_run: ProcessGauges,
+    pub sched_wait: ProcessGauges,
+    pub sched_timeslices: ProcessGauges,
+    // Resident memory per NUMA node (`enable_numa`)
+    pub numa_rss: ProcessGauges,
+    // Busiest threads (`per_thread`)
+    pub thread_cpu_percent: ProcessGauges,
+    pub thread_cpu_time: ProcessGauges,
+    // /proc/<pid>/io counters (`enable_io_metrics`)
+    pub io_read_bytes: ProcessGauges,
+    pub io_write_bytes: ProcessGauges,
+    pub io_read_syscalls: ProcessGauges,
+    pub io_write_syscalls: ProcessGauges,
+    // Process start time and age (`enable_start_time_metrics`)
+    pub start_time: ProcessGauges,
+    pub age: ProcessGauges,
 
     // Aggregated per-subgroup sums
-    pub agg_rss_sum: GaugeVec,
-    pub agg_pss_sum: GaugeVec,
-    pub agg_uss_sum: GaugeVec,
-    pub agg_cpu_percent_sum: GaugeVec,
-    pub agg_cpu_time_sum: GaugeVec,
+    pub agg_rss_sum: SubgroupGauges,
+    pub agg_pss_sum: SubgroupGauges,
+    pub agg_uss_sum: SubgroupGauges,
+    pub agg_cpu_percent_sum: SubgroupGauges,
+    pub agg_cpu_time_sum: SubgroupGauges,
 
     // Top-N metrics per subgroup
-    pub top_rss: GaugeVec,
-    pub top_pss: GaugeVec,
-    pub top_uss: GaugeVec,
-    pub top_cpu_percent: GaugeVec,
-    pub top_cpu_time: GaugeVec,
+    pub top_rss: TopGauges,
+    pub top_pss: TopGauges,
+    pub top_uss: TopGauges,
+    pub top_cpu_percent: TopGauges,
+    pub top_cpu_time: TopGauges,
 
     // Percentage-of-subgroup metrics for Top-N
-    pub top_cpu_percent_of_subgroup: GaugeVec,
-    pub top_rss_percent_of_subgroup: GaugeVec,
-    pub top_pss_percent_of_subgroup: GaugeVec,
-    pub top_uss_percent_of_subgroup: GaugeVec,
+    pub top_cpu_percent_of_subgroup: TopGauges,
+    pub top_rss_percent_of_subgroup: TopGauges,
+    pub top_pss_percent_of_subgroup: TopGauges,
+    pub top_uss_percent_of_subgroup: TopGauges,
+    /// Process behind each rank, only set with `stable_topn`
+    pub top_info: TopInfoGauges,
+    // State of the Top-N processes (`enable_wchan_metrics`)
+    pub top_wait_info: TopWaitGauges,
+
+    // Per-second rates between the last two scans
+    pub rate_cpu_seconds: ProcessGauges,
+    pub rate_read_bytes: ProcessGauges,
+    pub rate_write_bytes: ProcessGauges,
+    pub rate_minor_faults: ProcessGauges,
+    pub rate_major_faults: ProcessGauges,
+    pub rate_swapped_bytes: ProcessGauges,
+    pub agg_rate_cpu_seconds_sum: SubgroupGauges,
+    pub agg_rate_read_bytes_sum: SubgroupGauges,
+    pub agg_rate_write_bytes_sum: SubgroupGauges,
+    pub agg_rate_minor_faults_sum: SubgroupGauges,
+    pub agg_rate_major_faults_sum: SubgroupGauges,
+    pub agg_rate_swapped_bytes_sum: SubgroupGauges,
+
+    // Privileged processes per subgroup (`enable_privilege_metrics`)
+    pub privileged_processes: PrivilegeGauges,
+    // Processes per subgroup and state
+    pub processes_by_state: StateGauges,
+
+    // Sums per session or process group (`session_aggregation`)
+    pub session_uss_sum: SessionGauges,
 
     // System-wide metrics
     pub system_memory_total_bytes: Gauge,
     pub system_memory_available_bytes: Gauge,
     pub system_memory_used_ratio: Gauge,
-    pub system_cpu_usage_ratio: GaugeVec,
+    pub system_cpu_usage_ratio: CpuGauges,
+    pub system_cpu_iowait_ratio: CpuGauges,
+    pub system_cpu_steal_ratio: CpuGauges,
     pub system_load1: Gauge,
     pub system_load5: Gauge,
     pub system_load15: Gauge,
+    pub system_boot_time: Gauge,
+    pub system_kernel_info: KernelGauges,
+    pub system_file_handles: Gauge,
+    pub system_file_handles_max: Gauge,
+    pub system_file_handles_used_ratio: Gauge,
+    pub system_tasks: Gauge,
+    pub system_pid_max: Gauge,
+    pub system_pid_used_ratio: Gauge,
+    pub system_threads_max: Gauge,
+    pub system_threads_used_ratio: Gauge,
+    pub system_entropy_available_bits: Gauge,
+
+    // Compressed swap; zswap is a label-less vector so it is absent when unreadable
+    pub zram_disksize: ZramGauges,
+    pub zram_orig_data: ZramGauges,
+    pub zram_compr_data: ZramGauges,
+    pub zram_mem_used: ZramGauges,
+    pub zswap_pool: GaugeVec,
+    pub zswap_stored_pages: GaugeVec,
+    /// ZFS ARC; label-less so it is absent on hosts without ZFS
+    pub zfs_arc_size: GaugeVec,
+    pub zfs_arc_target: GaugeVec,
+    pub zfs_arc_min: GaugeVec,
+    pub zfs_arc_max: GaugeVec,
+    /// Label-less so it is absent outside of VMs with a memory balloon
+    pub system_memory_balloon_bytes: GaugeVec,
+
+    // Per-cgroup memory accounting (`cgroups`)
+    pub cgroup_memory_current: CgroupGauges,
+    pub cgroup_memory_swap_current: CgroupGauges,
+    pub cgroup_memory_stat: CgroupStatGauges,
+    // Systemd unit limits (`cgroups.unit_limits`)
+    pub unit_memory_limit: UnitLimitGauges,
+    pub unit_memory_limit_ratio: UnitLimitGauges,
 }
 
 impl MemoryMetrics {
     /// Creates and registers all Prometheus metrics with the registry.
-    pub fn new(registry: &Registry) -> Result<Self, Box<dyn std::error::Error>> {
-        let labels = &["pid", "name", "group", "subgroup", "uptime_in_seconds"];
+    ///
+    /// `extra_labels` are appended to the labels of the per-process families
+    /// (see `label_extractors`). With `stable_topn` the Top-N families carry
+    /// no pid and name labels; `top_info` maps their ranks to processes.
+    pub fn new(
+        registry: &Registry,
+        extra_labels: &[String],
+        stable_topn: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let rss = ProcessGauges::new(
+            "herakles_proc_mem_rss_bytes",
+            "Resident Set Size per process in bytes",
+            extra_labels,
+        )?;
+        let pss = ProcessGauges::new(
+            "herakles_proc_mem_pss_bytes",
+            "Proportional Set Size per process in bytes",
+            extra_labels,
+        )?;
+        let uss = ProcessGauges::new(
+            "herakles_proc_mem_uss_bytes",
+            "Unique Set Size per process in bytes",
+            extra_labels,
+        )?;
+        let locked = ProcessGauges::new(
+            "herakles_proc_mem_locked_bytes",
+            "Memory per process locked into RAM with mlock(2) in bytes",
+            extra_labels,
+        )?;
+        let anon = ProcessGauges::new(
+            "herakles_proc_mem_anon_bytes",
+            "Anonymous memory (heap, stacks, private mappings) of the RSS per process in bytes",
+            extra_labels,
+        )?;
+        let file = ProcessGauges::new(
+            "herakles_proc_mem_file_bytes",
+            "File-backed memory (page cache of mapped files) of the RSS per process in bytes",
+            extra_labels,
+        )?;
+        let shmem = ProcessGauges::new(
+            "herakles_proc_mem_shmem_bytes",
+            "Resident shared memory (tmpfs, System V and shared anonymous mappings) per process in bytes",
+            extra_labels,
+        )?;
+        let cpu_usage = ProcessGauges::new(
+            "herakles_proc_mem_cpu_percent",
+            "CPU usage per process in percent (delta over last scan)",
+            extra_labels,
+        )?;
+        let cpu_time = ProcessGauges::new(
+            "herakles_proc_mem_cpu_time_seconds",
+            "Total CPU time used per process",
+            extra_labels,
+        )?;
+        let cpu_guest = ProcessGauges::new(
+            "herakles_proc_mem_cpu_guest_seconds",
+            "CPU time per process spent running a virtual CPU of a guest, part of the total CPU time",
+            extra_labels,
+        )?;
 
-        let rss = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_rss_bytes",
-                "Resident Set Size per process in bytes",
-            ),
-            labels,
+        let nice = ProcessGauges::new(
+            "herakles_proc_mem_nice",
+            "Nice value per process (-20 to 19)",
+            extra_labels,
         )?;
-        let pss = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_pss_bytes",
-                "Proportional Set Size per process in bytes",
-            ),
-            labels,
+        let sched_info = ProcessGauges::with_own_labels(
+            "herakles_proc_mem_sched_info",
+            "Scheduling policy per process (always 1)",
+            extra_labels,
+            &["policy"],
         )?;
-        let uss = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_uss_bytes",
-                "Unique Set Size per process in bytes",
-            ),
-            labels,
+
+        let io_info = ProcessGauges::with_own_labels(
+            "herakles_proc_mem_io_info",
+            "I/O scheduling class and level per process (always 1)",
+            extra_labels,
+            &["class", "level"],
         )?;
-        let cpu_usage = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_cpu_percent",
-                "CPU usage per process in percent (delta over last scan)",
-            ),
-            labels,
+
+        let limit_utilization = ProcessGauges::with_own_labels(
+            "herakles_proc_mem_limit_utilization_ratio",
+            "Process USS or cgroup usage over the memory.max of the cgroup limiting the process",
+            extra_labels,
+            &["cgroup", "usage"],
         )?;
-        let cpu_time = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_cpu_time_seconds",
-                "Total CPU time used per process",
-            ),
-            labels,
+
+        let blkio_delay = ProcessGauges::new(
+            "herakles_proc_mem_blkio_delay_seconds",
+            "Total time the threads of a process waited for block IO (delay accounting)",
+            extra_labels,
+        )?;
+        let swapin_delay = ProcessGauges::new(
+            "herakles_proc_mem_swapin_delay_seconds",
+            "Total time the threads of a process waited for pages to be swapped in (delay accounting)",
+            extra_labels,
+        )?;
+
+        let sched_run = ProcessGauges::new(
+            "herakles_proc_mem_sched_run_seconds",
+            "Total time the main thread of a process ran on a CPU (schedstat)",
+            extra_labels,
+        )?;
+        let sched_wait = ProcessGauges::new(
+            "herakles_proc_mem_sched_wait_seconds",
+            "Total time the main thread of a process was runnable but waited on a runqueue (schedstat)",
+            extra_labels,
+        )?;
+        let sched_timeslices = ProcessGauges::new(
+            "herakles_proc_mem_sched_timeslices",
+            "Timeslices the main thread of a process ran on a CPU (schedstat)",
+            extra_labels,
+        )?;
+        let numa_rss = ProcessGauges::with_own_labels(
+            "herakles_proc_mem_numa_rss_bytes",
+            "Resident memory per process on a NUMA node (numa_maps)",
+            extra_labels,
+            &["numa_node"],
+        )?;
+        let thread_cpu_percent = ProcessGauges::with_own_labels(
+            "herakles_proc_mem_thread_cpu_percent",
+            "CPU usage per thread in percent (delta over last scan)",
+            extra_labels,
+            &["tid", "thread_name"],
+        )?;
+        let thread_cpu_time = ProcessGauges::with_own_labels(
+            "herakles_proc_mem_thread_cpu_time_seconds",
+            "Total CPU time used per thread",
+            extra_labels,
+            &["tid", "thread_name"],
+        )?;
+
+        let io_read_bytes = ProcessGauges::new(
+            "herakles_proc_mem_io_read_bytes",
+            "Total bytes the process caused to be read from storage",
+            extra_labels,
+        )?;
+        let io_write_bytes = ProcessGauges::new(
+            "herakles_proc_mem_io_write_bytes",
+            "Total bytes the process caused to be written to storage",
+            extra_labels,
+        )?;
+        let io_read_syscalls = ProcessGauges::new(
+            "herakles_proc_mem_io_read_syscalls",
+            "Total read syscalls of the process (syscr)",
+            extra_labels,
+        )?;
+        let io_write_syscalls = ProcessGauges::new(
+            "herakles_proc_mem_io_write_syscalls",
+            "Total write syscalls of the process (syscw)",
+            extra_labels,
+        )?;
+        let start_time = ProcessGauges::new(
+            "herakles_proc_mem_process_start_time_seconds",
+            "Start time of the process in seconds since the epoch",
+            extra_labels,
+        )?;
+        let age = ProcessGauges::new(
+            "herakles_proc_mem_process_age_seconds",
+            "Seconds since the process started",
+            extra_labels,
         )?;
 
         // Aggregated sums per subgroup
-        let agg_rss_sum = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_group_rss_bytes_sum",
-                "Sum of RSS bytes per subgroup",
-            ),
-            &["group", "subgroup", "uptime_in_seconds"],
+        let agg_rss_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_rss_bytes_sum",
+            "Sum of RSS bytes per subgroup",
         )?;
-        let agg_pss_sum = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_group_pss_bytes_sum",
-                "Sum of PSS bytes per subgroup",
-            ),
-            &["group", "subgroup", "uptime_in_seconds"],
+        let agg_pss_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_pss_bytes_sum",
+            "Sum of PSS bytes per subgroup",
         )?;
-        let agg_uss_sum = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_group_uss_bytes_sum",
-                "Sum of USS bytes per subgroup",
-            ),
-            &["group", "subgroup", "uptime_in_seconds"],
+        let agg_uss_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_uss_bytes_sum",
+            "Sum of USS bytes per subgroup",
         )?;
-        let agg_cpu_percent_sum = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_group_cpu_percent_sum",
-                "Sum of CPU percent per subgroup",
-            ),
-            &["group", "subgroup", "uptime_in_seconds"],
+        let agg_cpu_percent_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_cpu_percent_sum",
+            "Sum of CPU percent per subgroup",
         )?;
-        let agg_cpu_time_sum = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_group_cpu_time_seconds_sum",
-                "Sum of CPU time seconds per subgroup",
-            ),
-            &["group", "subgroup", "uptime_in_seconds"],
+        let agg_cpu_time_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_cpu_time_seconds_sum",
+            "Sum of CPU time seconds per subgroup",
         )?;
 
         // Top-N metrics per subgroup
-        let top_rss = GaugeVec::new(
-            Opts::new("herakles_proc_mem_top_rss_bytes", "Top-N RSS per subgroup"),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
-        )?;
-        let top_pss = GaugeVec::new(
-            Opts::new("herakles_proc_mem_top_pss_bytes", "Top-N PSS per subgroup"),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
-        )?;
-        let top_uss = GaugeVec::new(
-            Opts::new("herakles_proc_mem_top_uss_bytes", "Top-N USS per subgroup"),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
-        )?;
-        let top_cpu_percent = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_top_cpu_percent",
-                "Top-N CPU percent per subgroup",
-            ),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
-        )?;
-        let top_cpu_time = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_top_cpu_time_seconds",
-                "Top-N CPU time seconds per subgroup",
-            ),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
+        let top_rss = TopGauges::new(
+            "herakles_proc_mem_top_rss_bytes",
+            "Top-N RSS per subgroup",
+            stable_topn,
+        )?;
+        let top_pss = TopGauges::new(
+            "herakles_proc_mem_top_pss_bytes",
+            "Top-N PSS per subgroup",
+            stable_topn,
+        )?;
+        let top_uss = TopGauges::new(
+            "herakles_proc_mem_top_uss_bytes",
+            "Top-N USS per subgroup",
+            stable_topn,
+        )?;
+        let top_cpu_percent = TopGauges::new(
+            "herakles_proc_mem_top_cpu_percent",
+            "Top-N CPU percent per subgroup",
+            stable_topn,
+        )?;
+        let top_cpu_time = TopGauges::new(
+            "herakles_proc_mem_top_cpu_time_seconds",
+            "Top-N CPU time seconds per subgroup",
+            stable_topn,
         )?;
 
         // Percentage-of-subgroup metrics
-        let top_cpu_percent_of_subgroup = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_top_cpu_percent_of_subgroup",
-                "Top-N CPU time as percentage of subgroup total CPU time",
-            ),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
-        )?;
-        let top_rss_percent_of_subgroup = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_top_rss_percent_of_subgroup",
-                "Top-N RSS as percentage of subgroup total RSS",
-            ),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
-        )?;
-        let top_pss_percent_of_subgroup = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_top_pss_percent_of_subgroup",
-                "Top-N PSS as percentage of subgroup total PSS",
-            ),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
-        )?;
-        let top_uss_percent_of_subgroup = GaugeVec::new(
-            Opts::new(
-                "herakles_proc_mem_top_uss_percent_of_subgroup",
-                "Top-N USS as percentage of subgroup total USS",
-            ),
-            &[
-                "group",
-                "subgroup",
-                "rank",
-                "pid",
-                "name",
-                "uptime_in_seconds",
-            ],
+        let top_cpu_percent_of_subgroup = TopGauges::new(
+            "herakles_proc_mem_top_cpu_percent_of_subgroup",
+            "Top-N CPU time as percentage of subgroup total CPU time",
+            stable_topn,
+        )?;
+        let top_rss_percent_of_subgroup = TopGauges::new(
+            "herakles_proc_mem_top_rss_percent_of_subgroup",
+            "Top-N RSS as percentage of subgroup total RSS",
+            stable_topn,
+        )?;
+        let top_pss_percent_of_subgroup = TopGauges::new(
+            "herakles_proc_mem_top_pss_percent_of_subgroup",
+            "Top-N PSS as percentage of subgroup total PSS",
+            stable_topn,
+        )?;
+        let top_uss_percent_of_subgroup = TopGauges::new(
+            "herakles_proc_mem_top_uss_percent_of_subgroup",
+            "Top-N USS as percentage of subgroup total USS",
+            stable_topn,
+        )?;
+        let top_info = TopInfoGauges::new(
+            "herakles_proc_mem_top_info",
+            "Process behind a Top-N rank with stable_topn (always 1)",
+        )?;
+        let top_wait_info = TopWaitGauges::new(
+            "herakles_proc_mem_top_wait_info",
+            "Scheduler state and wait channel of a Top-N process (always 1)",
+        )?;
+
+        // Per-second rates between the last two scans
+        let rate_cpu_seconds = ProcessGauges::new(
+            "herakles_proc_mem_cpu_seconds_per_second",
+            "CPU seconds per second per process between the last two scans",
+            extra_labels,
+        )?;
+        let rate_read_bytes = ProcessGauges::new(
+            "herakles_proc_mem_read_bytes_per_second",
+            "Storage bytes read per second per process between the last two scans",
+            extra_labels,
+        )?;
+        let rate_write_bytes = ProcessGauges::new(
+            "herakles_proc_mem_write_bytes_per_second",
+            "Storage bytes written per second per process between the last two scans",
+            extra_labels,
+        )?;
+        let rate_minor_faults = ProcessGauges::new(
+            "herakles_proc_mem_minor_faults_per_second",
+            "Minor page faults per second per process between the last two scans",
+            extra_labels,
+        )?;
+        let rate_major_faults = ProcessGauges::new(
+            "herakles_proc_mem_major_faults_per_second",
+            "Major page faults per second per process between the last two scans",
+            extra_labels,
+        )?;
+        let rate_swapped_bytes = ProcessGauges::new(
+            "herakles_proc_mem_swapped_bytes_per_second",
+            "Bytes swapped out per second per process between the last two memory reads",
+            extra_labels,
+        )?;
+        let agg_rate_cpu_seconds_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_cpu_seconds_per_second_sum",
+            "Sum of CPU seconds per second per subgroup",
+        )?;
+        let agg_rate_read_bytes_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_read_bytes_per_second_sum",
+            "Sum of storage bytes read per second per subgroup",
+        )?;
+        let agg_rate_write_bytes_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_write_bytes_per_second_sum",
+            "Sum of storage bytes written per second per subgroup",
+        )?;
+        let agg_rate_minor_faults_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_minor_faults_per_second_sum",
+            "Sum of minor page faults per second per subgroup",
+        )?;
+        let agg_rate_major_faults_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_major_faults_per_second_sum",
+            "Sum of major page faults per second per subgroup",
+        )?;
+        let agg_rate_swapped_bytes_sum = SubgroupGauges::new(
+            "herakles_proc_mem_group_swapped_bytes_per_second_sum",
+            "Sum of bytes swapped out per second per subgroup",
+        )?;
+
+        let privileged_processes = PrivilegeGauges::new(
+            "herakles_proc_mem_group_privileged_processes",
+            "Processes per subgroup running as root or with full capabilities",
+        )?;
+        let processes_by_state = StateGauges::new(
+            "herakles_proc_mem_group_processes_by_state",
+            "Processes per subgroup by state: running (R), sleeping (S), uninterruptible sleep (D) or zombie (Z)",
+        )?;
+
+        let session_uss_sum = SessionGauges::new(
+            "herakles_proc_mem_session_uss_bytes_sum",
+            "Sum of USS bytes per session or process group",
         )?;
 
         // System-wide metrics
@@ -257,17 +734,20 @@ impl MemoryMetrics {
             "herakles_system_memory_used_ratio",
             "Memory used ratio: 1 - (available_bytes / total_bytes), value between 0.0 and 1.0",
         )?;
-        let system_cpu_usage_ratio = GaugeVec::new(
-            Opts::new(
-                "herakles_system_cpu_usage_ratio",
-                "CPU usage ratio per core and total, calculated from /proc/stat deltas",
-            ),
-            &["cpu"],
+        let system_cpu_usage_ratio = CpuGauges::new(
+            "herakles_system_cpu_usage_ratio",
+            "CPU usage ratio per core and total, calculated from /proc/stat deltas",
         )?;
-        let system_load1 = Gauge::new(
-            "herakles_system_load1",
-            "System load average over 1 minute",
+        let system_cpu_iowait_ratio = CpuGauges::new(
+            "herakles_system_cpu_iowait_ratio",
+            "Share of CPU time idle with outstanding disk I/O per core and total",
         )?;
+        let system_cpu_steal_ratio = CpuGauges::new(
+            "herakles_system_cpu_steal_ratio",
+            "Share of CPU time stolen by the hypervisor per core and total",
+        )?;
+        let system_load1 =
+            Gauge::new("herakles_system_load1", "System load average over 1 minute")?;
         let system_load5 = Gauge::new(
             "herakles_system_load5",
             "System load average over 5 minutes",
@@ -276,44 +756,260 @@ impl MemoryMetrics {
             "herakles_system_load15",
             "System load average over 15 minutes",
         )?;
+        let system_boot_time = Gauge::new(
+            "herakles_system_boot_time_seconds",
+            "System boot time in seconds since the epoch",
+        )?;
+        let system_kernel_info = KernelGauges::new(
+            "herakles_system_kernel_info",
+            "Release and version of the running kernel (always 1)",
+        )?;
+        let system_file_handles = Gauge::new(
+            "herakles_system_file_handles",
+            "Allocated file handles (/proc/sys/fs/file-nr)",
+        )?;
+        let system_file_handles_max = Gauge::new(
+            "herakles_system_file_handles_max",
+            "Maximum number of file handles (fs.file-max)",
+        )?;
+        let system_file_handles_used_ratio = Gauge::new(
+            "herakles_system_file_handles_used_ratio",
+            "Allocated file handles relative to fs.file-max (0.0 to 1.0)",
+        )?;
+        let system_tasks = Gauge::new(
+            "herakles_system_tasks",
+            "Processes and threads currently existing",
+        )?;
+        let system_pid_max = Gauge::new(
+            "herakles_system_pid_max",
+            "Highest PID plus one (kernel.pid_max)",
+        )?;
+        let system_pid_used_ratio = Gauge::new(
+            "herakles_system_pid_used_ratio",
+            "Existing tasks relative to kernel.pid_max (0.0 to 1.0)",
+        )?;
+        let system_threads_max = Gauge::new(
+            "herakles_system_threads_max",
+            "Maximum number of threads (kernel.threads-max)",
+        )?;
+        let system_threads_used_ratio = Gauge::new(
+            "herakles_system_threads_used_ratio",
+            "Existing tasks relative to kernel.threads-max (0.0 to 1.0)",
+        )?;
+        let system_entropy_available_bits = Gauge::new(
+            "herakles_system_entropy_available_bits",
+            "Entropy available in the kernel random pool",
+        )?;
+        let zram_disksize = ZramGauges::new(
+            "herakles_system_zram_disksize_bytes",
+            "Configured size of a zram device",
+        )?;
+        let zram_orig_data = ZramGauges::new(
+            "herakles_system_zram_original_data_bytes",
+            "Uncompressed size of the data stored in a zram device",
+        )?;
+        let zram_compr_data = ZramGauges::new(
+            "herakles_system_zram_compressed_data_bytes",
+            "Compressed size of the data stored in a zram device",
+        )?;
+        let zram_mem_used = ZramGauges::new(
+            "herakles_system_zram_memory_used_bytes",
+            "Memory used by a zram device including allocator overhead",
+        )?;
+        let zswap_pool = GaugeVec::new(
+            Opts::new(
+                "herakles_system_zswap_pool_bytes",
+                "Memory used by the zswap compressed pool",
+            ),
+            &[],
+        )?;
+        let zswap_stored_pages = GaugeVec::new(
+            Opts::new(
+                "herakles_system_zswap_stored_pages",
+                "Pages stored in the zswap compressed pool",
+            ),
+            &[],
+        )?;
+        let zfs_gauge = |name: &str, help: &str| GaugeVec::new(Opts::new(name, help), &[]);
+        let zfs_arc_size = zfs_gauge(
+            "herakles_system_zfs_arc_size_bytes",
+            "Current size of the ZFS adaptive replacement cache",
+        )?;
+        let zfs_arc_target = zfs_gauge(
+            "herakles_system_zfs_arc_target_bytes",
+            "Size the ZFS ARC currently adapts towards (c)",
+        )?;
+        let zfs_arc_min = zfs_gauge(
+            "herakles_system_zfs_arc_min_bytes",
+            "Lower bound of the ZFS ARC target (c_min)",
+        )?;
+        let zfs_arc_max = zfs_gauge(
+            "herakles_system_zfs_arc_max_bytes",
+            "Upper bound of the ZFS ARC target (c_max)",
+        )?;
+        let system_memory_balloon_bytes = GaugeVec::new(
+            Opts::new(
+                "herakles_system_memory_balloon_bytes",
+                "Memory taken from this VM by the hypervisor's memory balloon",
+            ),
+            &[],
+        )?;
+        let cgroup_memory_current = CgroupGauges::new(
+            "herakles_cgroup_memory_current_bytes",
+            "Memory charged to a cgroup (memory.current)",
+        )?;
+        let cgroup_memory_swap_current = CgroupGauges::new(
+            "herakles_cgroup_memory_swap_current_bytes",
+            "Swap charged to a cgroup (memory.swap.current)",
+        )?;
+        let cgroup_memory_stat = CgroupStatGauges::new(
+            "herakles_cgroup_memory_stat_bytes",
+            "Selected memory.stat fields of a cgroup",
+        )?;
+        let unit_memory_limit = UnitLimitGauges::new(
+            "herakles_cgroup_unit_memory_limit_bytes",
+            "Memory limit of a systemd unit: memory.high (MemoryHigh=) or memory.max (MemoryMax=)",
+        )?;
+        let unit_memory_limit_ratio = UnitLimitGauges::new(
+            "herakles_cgroup_unit_memory_limit_ratio",
+            "Memory charged to a systemd unit (memory.current) over its memory.high or memory.max",
+        )?;
+
+        rss.register(registry)?;
+        pss.register(registry)?;
+        uss.register(registry)?;
+        locked.register(registry)?;
+        anon.register(registry)?;
+        file.register(registry)?;
+        shmem.register(registry)?;
+        cpu_usage.register(registry)?;
+        cpu_time.register(registry)?;
+        cpu_guest.register(registry)?;
+        nice.register(registry)?;
+        sched_info.register(registry)?;
+        io_info.register(registry)?;
+        limit_utilization.register(registry)?;
+        blkio_delay.register(registry)?;
+        swapin_delay.register(registry)?;
+        sched_run.register(registry)?;
+        sched_wait.register(registry)?;
+        sched_timeslices.register(registry)?;
+        numa_rss.register(registry)?;
+        thread_cpu_percent.register(registry)?;
+        thread_cpu_time.register(registry)?;
+        io_read_bytes.register(registry)?;
+        io_write_bytes.register(registry)?;
+        io_read_syscalls.register(registry)?;
+        io_write_syscalls.register(registry)?;
+        start_time.register(registry)?;
+        age.register(registry)?;
 
-        registry.register(Box::new(rss.clone()))?;
-        registry.register(Box::new(pss.clone()))?;
-        registry.register(Box::new(uss.clone()))?;
-        registry.register(Box::new(cpu_usage.clone()))?;
-        registry.register(Box::new(cpu_time.clone()))?;
+        agg_rss_sum.register(registry)?;
+        agg_pss_sum.register(registry)?;
+        agg_uss_sum.register(registry)?;
+        agg_cpu_percent_sum.register(registry)?;
+        agg_cpu_time_sum.register(registry)?;
 
-        registry.register(Box::new(agg_rss_sum.clone()))?;
-        registry.register(Box::new(agg_pss_sum.clone()))?;
-        registry.register(Box::new(agg_uss_sum.clone()))?;
-        registry.register(Box::new(agg_cpu_percent_sum.clone()))?;
-        registry.register(Box::new(agg_cpu_time_sum.clone()))?;
+        top_rss.register(registry)?;
+        top_pss.register(registry)?;
+        top_uss.register(registry)?;
+        top_cpu_percent.register(registry)?;
+        top_cpu_time.register(registry)?;
 
-        registry.register(Box::new(top_rss.clone()))?;
-        registry.register(Box::new(top_pss.clone()))?;
-        registry.register(Box::new(top_uss.clone()))?;
-        registry.register(Box::new(top_cpu_percent.clone()))?;
-        registry.register(Box::new(top_cpu_time.clone()))?;
+        top_cpu_percent_of_subgroup.register(registry)?;
+        top_rss_percent_of_subgroup.register(registry)?;
+        top_pss_percent_of_subgroup.register(registry)?;
+        top_uss_percent_of_subgroup.register(registry)?;
+        top_info.register(registry)?;
+        top_wait_info.register(registry)?;
 
-        registry.register(Box::new(top_cpu_percent_of_subgroup.clone()))?;
-        registry.register(Box::new(top_rss_percent_of_subgroup.clone()))?;
-        registry.register(Box::new(top_pss_percent_of_subgroup.clone()))?;
-        registry.register(Box::new(top_uss_percent_of_subgroup.clone()))?;
+        rate_cpu_seconds.register(registry)?;
+        rate_read_bytes.register(registry)?;
+        rate_write_bytes.register(registry)?;
+        rate_minor_faults.register(registry)?;
+        rate_major_faults.register(registry)?;
+        rate_swapped_bytes.register(registry)?;
+        agg_rate_cpu_seconds_sum.register(registry)?;
+        agg_rate_read_bytes_sum.register(registry)?;
+        agg_rate_write_bytes_sum.register(registry)?;
+        agg_rate_minor_faults_sum.register(registry)?;
+        agg_rate_major_faults_sum.register(registry)?;
+        agg_rate_swapped_bytes_sum.register(registry)?;
+
+        privileged_processes.register(registry)?;
+        processes_by_state.register(registry)?;
+        session_uss_sum.register(registry)?;
 
         registry.register(Box::new(system_memory_total_bytes.clone()))?;
         registry.register(Box::new(system_memory_available_bytes.clone()))?;
         registry.register(Box::new(system_memory_used_ratio.clone()))?;
-        registry.register(Box::new(system_cpu_usage_ratio.clone()))?;
+        system_cpu_usage_ratio.register(registry)?;
+        system_cpu_iowait_ratio.register(registry)?;
+        system_cpu_steal_ratio.register(registry)?;
         registry.register(Box::new(system_load1.clone()))?;
         registry.register(Box::new(system_load5.clone()))?;
         registry.register(Box::new(system_load15.clone()))?;
+        registry.register(Box::new(system_boot_time.clone()))?;
+        system_kernel_info.register(registry)?;
+        for gauge in [
+            &system_file_handles,
+            &system_file_handles_max,
+            &system_file_handles_used_ratio,
+            &system_tasks,
+            &system_pid_max,
+            &system_pid_used_ratio,
+            &system_threads_max,
+            &system_threads_used_ratio,
+            &system_entropy_available_bits,
+        ] {
+            registry.register(Box::new(gauge.clone()))?;
+        }
+        zram_disksize.register(registry)?;
+        zram_orig_data.register(registry)?;
+        zram_compr_data.register(registry)?;
+        zram_mem_used.register(registry)?;
+        registry.register(Box::new(zswap_pool.clone()))?;
+        registry.register(Box::new(zswap_stored_pages.clone()))?;
+        registry.register(Box::new(system_memory_balloon_bytes.clone()))?;
+        registry.register(Box::new(zfs_arc_size.clone()))?;
+        registry.register(Box::new(zfs_arc_target.clone()))?;
+        registry.register(Box::new(zfs_arc_min.clone()))?;
+        registry.register(Box::new(zfs_arc_max.clone()))?;
+        cgroup_memory_current.register(registry)?;
+        cgroup_memory_swap_current.register(registry)?;
+        cgroup_memory_stat.register(registry)?;
+        unit_memory_limit.register(registry)?;
+        unit_memory_limit_ratio.register(registry)?;
 
         Ok(Self {
             rss,
             pss,
             uss,
+            locked,
+            anon,
+            file,
+            shmem,
             cpu_usage,
             cpu_time,
+            cpu_guest,
+            nice,
+            sched_info,
+            io_info,
+            limit_utilization,
+            blkio_delay,
+            swapin_delay,
+            sched_run,
+            sched_wait,
+            sched_timeslices,
+            numa_rss,
+            thread_cpu_percent,
+            thread_cpu_time,
+            io_read_bytes,
+            io_write_bytes,
+            io_read_syscalls,
+            io_write_syscalls,
+            start_time,
+            age,
             agg_rss_sum,
             agg_pss_sum,
             agg_uss_sum,
@@ -328,50 +1024,68 @@ impl MemoryMetrics {
             top_rss_percent_of_subgroup,
             top_pss_percent_of_subgroup,
             top_uss_percent_of_subgroup,
+            top_info,
+            top_wait_info,
+            rate_cpu_seconds,
+            rate_read_bytes,
+            rate_write_bytes,
+            rate_minor_faults,
+            rate_major_faults,
+            rate_swapped_bytes,
+            agg_rate_cpu_seconds_sum,
+            agg_rate_read_bytes_sum,
+            agg_rate_write_bytes_sum,
+            agg_rate_minor_faults_sum,
+            agg_rate_major_faults_sum,
+            agg_rate_swapped_bytes_sum,
+            privileged_processes,
+            processes_by_state,
+            session_uss_sum,
             system_memory_total_bytes,
             system_memory_available_bytes,
             system_memory_used_ratio,
             system_cpu_usage_ratio,
+            system_cpu_iowait_ratio,
+            system_cpu_steal_ratio,
             system_load1,
             system_load5,
             system_load15,
+            system_boot_time,
+            system_kernel_info,
+            system_file_handles,
+            system_file_handles_max,
+            system_file_handles_used_ratio,
+            system_tasks,
+            system_pid_max,
+            system_pid_used_ratio,
+            system_threads_max,
+            system_threads_used_ratio,
+            system_entropy_available_bits,
+            zram_disksize,
+            zram_orig_data,
+            zram_compr_data,
+            zram_mem_used,
+            zswap_pool,
+            zswap_stored_pages,
+            zfs_arc_size,
+            zfs_arc_target,
+            zfs_arc_min,
+            zfs_arc_max,
+            system_memory_balloon_bytes,
+            cgroup_memory_current,
+            cgroup_memory_swap_current,
+            cgroup_memory_stat,
+            unit_memory_limit,
+            unit_memory_limit_ratio,
         })
     }
 
-    /// Resets all metrics to zero (used before updating with fresh data).
-    pub fn reset(&self) {
-        self.rss.reset();
-        self.pss.reset();
-        self.uss.reset();
-        self.cpu_usage.reset();
-        self.cpu_time.reset();
-
-        self.agg_rss_sum.reset();
-        self.agg_pss_sum.reset();
-        self.agg_uss_sum.reset();
-        self.agg_cpu_percent_sum.reset();
-        self.agg_cpu_time_sum.reset();
-
-        self.top_rss.reset();
-        self.top_pss.reset();
-        self.top_uss.reset();
-        self.top_cpu_percent.reset();
-        self.top_cpu_time.reset();
-
-        self.top_cpu_percent_of_subgroup.reset();
-        self.top_rss_percent_of_subgroup.reset();
-        self.top_pss_percent_of_subgroup.reset();
-        self.top_uss_percent_of_subgroup.reset();
-
-        // Reset system metrics
-        self.system_cpu_usage_ratio.reset();
-    }
-
     /// Sets system memory metrics (total, available, used ratio).
     pub fn set_system_memory_metrics(&self, total_bytes: u64, available_bytes: u64) {
         self.system_memory_total_bytes.set(total_bytes as f64);
-        self.system_memory_available_bytes.set(available_bytes as f64);
-        
+        self.system_memory_available_bytes
+            .set(available_bytes as f64);
+
         // Calculate used ratio: 1 - (available / total)
         if total_bytes > 0 {
             let used_ratio = 1.0 - (available_bytes as f64 / total_bytes as f64);
@@ -381,12 +1095,141 @@ impl MemoryMetrics {
         }
     }
 
-    /// Sets CPU usage ratio metrics for each CPU core and total.
-    pub fn set_system_cpu_usage_ratios(&self, cpu_ratios: &std::collections::HashMap<String, f64>) {
-        for (cpu_name, ratio) in cpu_ratios {
-            self.system_cpu_usage_ratio
-                .with_label_values(&[cpu_name])
-                .set(*ratio);
+    /// Sets the ZFS ARC series.
+    pub fn set_system_zfs_metrics(&self, arc: &ZfsArcInfo) {
+        for (gauge, bytes) in [
+            (&self.zfs_arc_size, arc.size_bytes),
+            (&self.zfs_arc_target, arc.target_bytes),
+            (&self.zfs_arc_min, arc.min_bytes),
+            (&self.zfs_arc_max, arc.max_bytes),
+        ] {
+            gauge.with_label_values::<&str>(&[]).set(bytes as f64);
+        }
+    }
+
+    /// Sets the memory held by the memory balloon of a VM.
+    pub fn set_system_balloon_bytes(&self, bytes: u64) {
+        self.system_memory_balloon_bytes
+            .with_label_values::<&str>(&[])
+            .set(bytes as f64);
+    }
+
+    /// Sets CPU usage, iowait and steal ratios for each CPU core and total.
+    pub fn set_system_cpu_ratios(&self, cpu_ratios: &std::collections::HashMap<String, CpuRatios>) {
+        for (cpu_name, ratios) in cpu_ratios {
+            let labels = CpuLabels { cpu: cpu_name };
+            self.system_cpu_usage_ratio.set(&labels, ratios.usage);
+            self.system_cpu_iowait_ratio.set(&labels, ratios.iowait);
+            self.system_cpu_steal_ratio.set(&labels, ratios.steal);
+        }
+    }
+
+    /// Sets the boot time and kernel info series.
+    pub fn set_system_info(&self, boot_time: Option<u64>, kernel: Option<&KernelInfo>) {
+        if let Some(boot_time) = boot_time {
+            self.system_boot_time.set(boot_time as f64);
+        }
+        if let Some(kernel) = kernel {
+            let labels = KernelLabels {
+                release: &kernel.release,
+                version: &kernel.version,
+            };
+            self.system_kernel_info.set(&labels, 1.0);
+        }
+    }
+
+    /// Sets the kernel table usage and limits; `tasks` comes from /proc/loadavg.
+    pub fn set_system_limit_metrics(&self, limits: &KernelLimits, tasks: Option<u64>) {
+        let ratio = |used: u64, max: u64| {
+            if max > 0 {
+                used as f64 / max as f64
+            } else {
+                0.0
+            }
+        };
+        self.system_file_handles.set(limits.file_handles as f64);
+        self.system_file_handles_max.set(limits.file_max as f64);
+        self.system_file_handles_used_ratio
+            .set(ratio(limits.file_handles, limits.file_max));
+        self.system_pid_max.set(limits.pid_max as f64);
+        self.system_threads_max.set(limits.threads_max as f64);
+        if let Some(tasks) = tasks {
+            self.system_tasks.set(tasks as f64);
+            self.system_pid_used_ratio.set(ratio(tasks, limits.pid_max));
+            self.system_threads_used_ratio
+                .set(ratio(tasks, limits.threads_max));
+        }
+        if let Some(bits) = limits.entropy_bits {
+            self.system_entropy_available_bits.set(bits as f64);
+        }
+    }
+
+    /// Sets the zram device and zswap pool series.
+    pub fn set_system_swap_metrics(&self, zram: &[ZramDevice], zswap: Option<ZswapInfo>) {
+        for device in zram {
+            let labels = ZramLabels {
+                device: &device.name,
+            };
+            self.zram_disksize
+                .set(&labels, device.disksize_bytes as f64);
+            self.zram_orig_data
+                .set(&labels, device.orig_data_bytes as f64);
+            self.zram_compr_data
+                .set(&labels, device.compr_data_bytes as f64);
+            self.zram_mem_used
+                .set(&labels, device.mem_used_bytes as f64);
+        }
+        if let Some(zswap) = zswap {
+            self.zswap_pool
+                .with_label_values::<&str>(&[])
+                .set(zswap.pool_bytes as f64);
+            self.zswap_stored_pages
+                .with_label_values::<&str>(&[])
+                .set(zswap.stored_pages as f64);
+        }
+    }
+
+    /// Sets the per-cgroup memory series of the last scan, and with
+    /// `unit_limits` the limits of systemd units.
+    pub fn set_cgroup_metrics(&self, cgroups: &[CgroupMemory], unit_limits: bool) {
+        for cgroup in cgroups {
+            let labels = CgroupLabels {
+                cgroup: &cgroup.path,
+            };
+            if let Some(bytes) = cgroup.current_bytes {
+                self.cgroup_memory_current.set(&labels, bytes as f64);
+            }
+            if let Some(bytes) = cgroup.swap_current_bytes {
+                self.cgroup_memory_swap_current.set(&labels, bytes as f64);
+            }
+            for (field, bytes) in &cgroup.stat {
+                self.cgroup_memory_stat.set(
+                    &CgroupStatLabels {
+                        cgroup: &cgroup.path,
+                        field,
+                    },
+                    *bytes as f64,
+                );
+            }
+
+            let Some(unit) = systemd_unit(&cgroup.path).filter(|_| unit_limits) else {
+                continue;
+            };
+            for (limit, bytes) in [("high", cgroup.high_bytes), ("max", cgroup.max_bytes)] {
+                let Some(bytes) = bytes.filter(|&b| b > 0) else {
+                    continue;
+                };
+                let labels = UnitLimitLabels {
+                    unit,
+                    cgroup: &cgroup.path,
+                    limit,
+                };
+                self.unit_memory_limit.set(&labels, bytes as f64);
+                if let Some(current) = cgroup.current_bytes {
+                    self.unit_memory_limit_ratio
+                        .set(&labels, current as f64 / bytes as f64);
+                }
+            }
         }
     }
 
@@ -398,42 +1241,144 @@ impl MemoryMetrics {
     }
 
     /// Sets metric values for a specific process with classification.
-    #[allow(clippy::too_many_arguments)]
-    pub fn set_for_process(
-        &self,
-        pid: &str,
-        name: &str,
-        group: &str,
-        subgroup: &str,
-        rss: u64,
-        pss: u64,
-        uss: u64,
-        cpu_percent: f64,
-        cpu_time_seconds: f64,
-        cfg: &Config,
-        uptime_in_seconds: &str,
-    ) {
-        let labels = &[pid, name, group, subgroup, uptime_in_seconds];
-
-        let enable_rss = cfg.enable_rss.unwrap_or(true);
-        let enable_pss = cfg.enable_pss.unwrap_or(true);
-        let enable_uss = cfg.enable_uss.unwrap_or(true);
-        let enable_cpu = cfg.enable_cpu.unwrap_or(true);
-
-        if enable_rss {
-            self.rss.with_label_values(labels).set(rss as f64);
-        }
-        if enable_pss {
-            self.pss.with_label_values(labels).set(pss as f64);
-        }
-        if enable_uss {
-            self.uss.with_label_values(labels).set(uss as f64);
-        }
-        if enable_cpu {
-            self.cpu_usage.with_label_values(labels).set(cpu_percent);
-            self.cpu_time
-                .with_label_values(labels)
-                .set(cpu_time_seconds);
+    pub fn set_for_process(&self, labels: &ProcessLabels, p: &ProcMem, cfg: &Config) {
+        if cfg.enable_rss.unwrap_or(true) {
+            self.rss.set(labels, p.rss as f64);
+        }
+        if cfg.enable_pss.unwrap_or(true) {
+            self.pss.set(labels, p.pss as f64);
+        }
+        if cfg.enable_uss.unwrap_or(true) {
+            self.uss.set(labels, p.uss as f64);
+        }
+        // Most processes lock nothing, they get no series
+        if p.locked > 0 {
+            self.locked.set(labels, p.locked as f64);
+        }
+        if cfg.enable_anon_file_metrics.unwrap_or(false) {
+            self.anon.set(labels, p.anonymous as f64);
+            self.file
+                .set(labels, p.rss.saturating_sub(p.anonymous) as f64);
+        }
+        if let Some(shmem) = p.shmem {
+            self.shmem.set(labels, shmem as f64);
+        }
+        if cfg.enable_cpu.unwrap_or(true) {
+            self.cpu_usage.set(labels, p.cpu_percent as f64);
+            self.cpu_time.set(labels, p.cpu_time_seconds as f64);
+            // Only hypervisor processes such as qemu run guest code
+            if p.guest_ticks > 0 {
+                self.cpu_guest.set(labels, p.guest_ticks as f64 / *CLK_TCK);
+            }
+        }
+        if cfg.enable_priority_metrics.unwrap_or(false) {
+            self.nice.set(labels, p.nice as f64);
+            self.sched_info
+                .set_with(labels, &[sched_policy_name(p.sched_policy)], 1.0);
+            if let Some(io) = p.io_priority {
+                self.io_info
+                    .set_with(labels, &[io.class_name(), &io.level.to_string()], 1.0);
+            }
+        }
+        if let Some(limit) = &p.memory_limit {
+            let max = limit.max_bytes as f64;
+            self.limit_utilization
+                .set_with(labels, &[&limit.cgroup, "uss"], p.uss as f64 / max);
+            self.limit_utilization.set_with(
+                labels,
+                &[&limit.cgroup, "cgroup"],
+                limit.current_bytes as f64 / max,
+            );
+        }
+        if let Some(delays) = p.delays {
+            self.blkio_delay
+                .set(labels, delays.blkio_delay_ns as f64 / 1e9);
+            self.swapin_delay
+                .set(labels, delays.swapin_delay_ns as f64 / 1e9);
+        }
+        if let Some(sched) = p.schedstat {
+            self.sched_run.set(labels, sched.run_ns as f64 / 1e9);
+            self.sched_wait.set(labels, sched.wait_ns as f64 / 1e9);
+            self.sched_timeslices.set(labels, sched.timeslices as f64);
+        }
+        for &(node, bytes) in p.numa.iter().flat_map(|nodes| nodes.iter()) {
+            self.numa_rss
+                .set_with(labels, &[&node.to_string()], bytes as f64);
         }
+        for t in p.threads.iter().flat_map(|threads| threads.iter()) {
+            let tid = t.tid.to_string();
+            let own = [tid.as_str(), &t.name];
+            self.thread_cpu_percent
+                .set_with(labels, &own, t.cpu_percent as f64);
+            self.thread_cpu_time
+                .set_with(labels, &own, t.cpu_time_seconds as f64);
+        }
+        if cfg.enable_io_metrics.unwrap_or(false) {
+            if let (Some(read), Some(write)) = (p.read_bytes, p.write_bytes) {
+                self.io_read_bytes.set(labels, read as f64);
+                self.io_write_bytes.set(labels, write as f64);
+            }
+            if let (Some(read), Some(write)) = (p.read_syscalls, p.write_syscalls) {
+                self.io_read_syscalls.set(labels, read as f64);
+                self.io_write_syscalls.set(labels, write as f64);
+            }
+        }
+        if cfg.enable_start_time_metrics.unwrap_or(false) {
+            if let Some(boot_time) = *BOOT_TIME {
+                let start = boot_time as f64 + p.start_time_ticks as f64 / *CLK_TCK;
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0.0, |d| d.as_secs_f64());
+                self.start_time.set(labels, start);
+                self.age.set(labels, (now - start).max(0.0));
+            }
+        }
+    }
+
+    /// Sets per-second rate metrics for a specific process.
+    pub fn set_rates_for_process(&self, labels: &ProcessLabels, rates: &ProcRates) {
+        self.rate_cpu_seconds.set(labels, rates.cpu_seconds);
+        self.rate_minor_faults.set(labels, rates.minor_faults);
+        self.rate_major_faults.set(labels, rates.major_faults);
+        if let Some(v) = rates.read_bytes {
+            self.rate_read_bytes.set(labels, v);
+        }
+        if let Some(v) = rates.write_bytes {
+            self.rate_write_bytes.set(labels, v);
+        }
+        if let Some(v) = rates.swapped_bytes {
+            self.rate_swapped_bytes.set(labels, v);
+        }
+    }
+
+    /// Sets per-second rate sums for a subgroup.
+    pub fn set_rate_sums(&self, labels: &SubgroupLabels, sums: &ProcRates) {
+        self.agg_rate_cpu_seconds_sum.set(labels, sums.cpu_seconds);
+        self.agg_rate_minor_faults_sum
+            .set(labels, sums.minor_faults);
+        self.agg_rate_major_faults_sum
+            .set(labels, sums.major_faults);
+        if let Some(v) = sums.read_bytes {
+            self.agg_rate_read_bytes_sum.set(labels, v);
+        }
+        if let Some(v) = sums.write_bytes {
+            self.agg_rate_write_bytes_sum.set(labels, v);
+        }
+        if let Some(v) = sums.swapped_bytes {
+            self.agg_rate_swapped_bytes_sum.set(labels, v);
+        }
+    }
+}
+
+/// Name of a SCHED_* policy as found in /proc/<pid>/stat.
+pub fn sched_policy_name(policy: u32) -> &'static str {
+    match policy {
+        0 => "other",
+        1 => "fifo",
+        2 => "rr",
+        3 => "batch",
+        5 => "idle",
+        6 => "deadline",
+        _ => "unknown",
     }
 }
@@ -0,0 +1,200 @@
+//! Startup probe for other exporters covering the same host data.
+//!
+//! With `overlap_probe` configured, the exporter scrapes the given endpoints
+//! (typically node_exporter and process-exporter) once before it starts and
+//! looks for families describing the same data as one of its collectors,
+//! e.g. `node_load1` and `herakles_system_load1`. Overlaps are logged; with
+//! `action: disable` the overlapping system collectors are removed from
+//! `system_collectors`. Per-process metrics overlapping with process-exporter
+//! are only reported, they are what this exporter is for.
+
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::{Config, OverlapProbeConfig};
+use crate::federation::parse_exposition;
+use crate::system::SYSTEM_COLLECTORS;
+
+/// Default timeout of a probe request.
+pub const DEFAULT_TIMEOUT_SECONDS: u64 = 5;
+/// Name of the per-process families in overlap reports.
+pub const PROCESSES: &str = "processes";
+
+/// Families of other exporters describing the same data as a collector of
+/// this exporter.
+const FOREIGN_FAMILIES: &[(&str, &[&str])] = &[
+    ("loadavg", &["node_load1", "node_load5", "node_load15"]),
+    (
+        "meminfo",
+        &[
+            "node_memory_MemTotal_bytes",
+            "node_memory_MemAvailable_bytes",
+        ],
+    ),
+    ("cpustat", &["node_cpu_seconds_total"]),
+    ("kernel", &["node_boot_time_seconds", "node_uname_info"]),
+    (
+        "limits",
+        &[
+            "node_filefd_allocated",
+            "node_filefd_maximum",
+            "node_processes_pids",
+            "node_processes_threads",
+            "node_entropy_available_bits",
+        ],
+    ),
+    (
+        "zfs",
+        &["node_zfs_arc_size", "node_zfs_arc_c", "node_zfs_arc_c_max"],
+    ),
+    (
+        PROCESSES,
+        &[
+            "namedprocess_namegroup_memory_bytes",
+            "namedprocess_namegroup_cpu_seconds_total",
+        ],
+    ),
+];
+
+/// Collectors overlapping with the given foreign family names, each with the
+/// families that overlap, in collector order.
+pub fn overlapping_collectors(names: &HashSet<String>) -> Vec<(&'static str, Vec<&'static str>)> {
+    FOREIGN_FAMILIES
+        .iter()
+        .filter_map(|(collector, families)| {
+            let found: Vec<&str> = families
+                .iter()
+                .copied()
+                .filter(|f| names.contains(*f))
+                .collect();
+            (!found.is_empty()).then_some((*collector, found))
+        })
+        .collect()
+}
+
+/// Removes `collectors` from the enabled system collectors. Returns the ones
+/// that were enabled before.
+pub fn disable_collectors(cfg: &mut Config, collectors: &[&str]) -> Vec<&'static str> {
+    let enabled: Vec<&'static str> = SYSTEM_COLLECTORS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| {
+            cfg.system_collectors
+                .as_ref()
+                .is_none_or(|list| list.iter().any(|n| n == name))
+        })
+        .collect();
+    let (disabled, kept): (Vec<&str>, Vec<&str>) = enabled
+        .into_iter()
+        .partition(|name| collectors.contains(name));
+    cfg.system_collectors = Some(kept.into_iter().map(str::to_string).collect());
+    disabled
+}
+
+/// Fetches the family names exported at `url`.
+async fn probe(client: &reqwest::Client, url: &str) -> Result<HashSet<String>, String> {
+    let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    Ok(parse_exposition(&body)
+        .into_iter()
+        .map(|family| family.name)
+        .collect())
+}
+
+/// Probes all configured endpoints and reports or disables overlaps.
+///
+/// Unreachable endpoints are logged and skipped; startup never fails here.
+pub async fn run(cfg: &mut Config, probe_cfg: &OverlapProbeConfig) {
+    let timeout = Duration::from_secs(probe_cfg.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS));
+    let client = match reqwest::Client::builder().timeout(timeout).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to build overlap probe HTTP client: {}", e);
+            return;
+        }
+    };
+    let disable = probe_cfg.action.as_deref() == Some("disable");
+
+    let mut overlapping = Vec::new();
+    for url in &probe_cfg.urls {
+        let names = match probe(&client, url).await {
+            Ok(names) => names,
+            Err(e) => {
+                warn!("Overlap probe of {} failed: {}", url, e);
+                continue;
+            }
+        };
+        for (collector, families) in overlapping_collectors(&names) {
+            warn!(
+                "{} exports {}, overlapping the '{}' collector",
+                url,
+                families.join(", "),
+                collector
+            );
+            if collector == PROCESSES {
+                warn!("Per-process metrics are never disabled by overlap_probe");
+            } else {
+                overlapping.push(collector);
+            }
+        }
+    }
+
+    if disable && !overlapping.is_empty() {
+        let disabled = disable_collectors(cfg, &overlapping);
+        if !disabled.is_empty() {
+            info!(
+                "Disabled system collectors overlapping with other exporters: {}",
+                disabled.join(", ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping_collectors() {
+        let text = "# HELP node_load1 1m load average.\n# TYPE node_load1 gauge\nnode_load1 0.5\n\
+                    # TYPE node_cpu_seconds_total counter\n\
+                    node_cpu_seconds_total{cpu=\"0\",mode=\"idle\"} 100\n\
+                    namedprocess_namegroup_memory_bytes{groupname=\"nginx\",memtype=\"resident\"} 1024\n";
+        let names: HashSet<String> = parse_exposition(text).into_iter().map(|f| f.name).collect();
+        assert_eq!(
+            overlapping_collectors(&names),
+            [
+                ("loadavg", vec!["node_load1"]),
+                ("cpustat", vec!["node_cpu_seconds_total"]),
+                (PROCESSES, vec!["namedprocess_namegroup_memory_bytes"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disable_collectors() {
+        let mut cfg = Config::default();
+        assert_eq!(
+            disable_collectors(&mut cfg, &["loadavg", "cpustat"]),
+            ["loadavg", "cpustat"]
+        );
+        assert_eq!(
+            cfg.system_collectors.as_deref().unwrap(),
+            ["meminfo", "kernel", "limits", "zram", "zfs"]
+        );
+
+        // Already disabled collectors stay disabled and are not reported
+        assert_eq!(
+            disable_collectors(&mut cfg, &["loadavg", "meminfo"]),
+            ["meminfo"]
+        );
+        assert_eq!(
+            cfg.system_collectors.as_deref().unwrap(),
+            ["kernel", "limits", "zram", "zfs"]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! PID file with single-instance locking.
+//!
+//! With `pid_file` set, the server takes an exclusive `flock` on the file and
+//! writes its PID into it before binding the port. A second instance fails
+//! immediately with the PID of the running one instead of an "address in
+//! use" error. The lock is released by the kernel when the process dies, so
+//! a file left behind by a crash is recognized as stale and taken over.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// A locked PID file; removed when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    // Holds the lock for the lifetime of the process
+    _file: File,
+}
+
+impl PidFile {
+    /// Locks `path` and writes the current PID into it.
+    ///
+    /// Fails if another process holds the lock.
+    pub fn acquire(path: &Path) -> Result<Self, String> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o644)
+            .open(path)
+            .map_err(|e| format!("Failed to open PID file {}: {}", path.display(), e))?;
+
+        let mut previous = String::new();
+        let _ = file.read_to_string(&mut previous);
+        let previous = previous.trim().parse::<u32>().ok();
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(match previous {
+                    Some(pid) => format!(
+                        "Another instance is already running (pid {}, PID file {})",
+                        pid,
+                        path.display()
+                    ),
+                    None => format!(
+                        "Another instance is already running (PID file {} is locked)",
+                        path.display()
+                    ),
+                });
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(format!("Failed to lock PID file {}: {}", path.display(), e));
+            }
+        }
+
+        // The holder may have removed the file between our open and lock
+        let locked_inode = file.metadata().map(|m| (m.dev(), m.ino())).ok();
+        let current_inode = fs::metadata(path).map(|m| (m.dev(), m.ino())).ok();
+        if locked_inode.is_none() || locked_inode != current_inode {
+            return Err(format!(
+                "PID file {} was replaced while starting, another instance may be starting",
+                path.display()
+            ));
+        }
+
+        if let Some(pid) = previous.filter(|&pid| pid != std::process::id()) {
+            warn!(
+                "Taking over stale PID file {} (pid {} is no longer running)",
+                path.display(),
+                pid
+            );
+        }
+
+        file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| writeln!(file, "{}", std::process::id()))
+            .and_then(|_| file.sync_data())
+            .map_err(|e| format!("Failed to write PID file {}: {}", path.display(), e))?;
+        info!("PID {} written to {}", std::process::id(), path.display());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            _file: file,
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Removed while still locked, so a starting instance cannot lock the old inode
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove PID file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_single_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exporter.pid");
+
+        // Stale file of a dead process
+        fs::write(&path, "999999\n").unwrap();
+        let pid_file = PidFile::acquire(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+
+        let err = PidFile::acquire(&path).unwrap_err();
+        assert!(
+            err.contains(&format!("pid {}", std::process::id())),
+            "{}",
+            err
+        );
+
+        drop(pid_file);
+        assert!(!path.exists());
+        assert!(PidFile::acquire(&path).is_ok());
+    }
+}
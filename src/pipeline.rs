@@ -0,0 +1,837 @@
+//! Exposition pipeline of the metrics endpoints.
+//!
+//! A scrape runs the cache snapshot through
+//!
+//! 1. process stages, which filter or rewrite the snapshot,
+//! 2. aggregate stages over the classified snapshot, which select the
+//!    processes exported as series and write the per-process, session,
+//!    subgroup and Top-N families (see [`crate::aggregate`]),
+//! 3. family stages, which drop, reorder or relabel the gathered families,
+//! 4. encoding.
+//!
+//! Each stage is a trait object, so views like the per-tenant endpoints and
+//! options like the export caps are a different list of stages instead of
+//! another branch in the handler.
+//! [`Pipeline::for_metrics`] and [`Pipeline::for_tenant`] build the lists
+//! from the configuration.
+//!
+//! Families are built with their v1 names; [`SchemaV2`] renames them for
+//! expositions in metric schema v2.
+
+use prometheus::proto::{Counter, LabelPair, MetricFamily, MetricType};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::instrument;
+
+use crate::aggregate::{
+    Classified, EnabledValues, HideMonitoring, MaxExported, MinSubgroupShare, PrivilegeCounts,
+    ProcessSeries, SessionSums, StateCounts, SubgroupSums, TopInfo, TopN, TopWait,
+};
+use crate::cache::ProcMem;
+use crate::config::{Config, DEFAULT_MONITORING_PROCESSES};
+use crate::metrics::MemoryMetrics;
+use crate::process::should_include_process;
+use crate::system;
+use crate::topn_hysteresis::TopNHysteresis;
+
+/// Process families describing the scrape itself, never timestamped.
+const LIVE_FAMILIES: &[&str] = &[
+    "herakles_proc_mem_cache_restored",
+    "herakles_proc_mem_cache_updating",
+    "herakles_proc_mem_scrape_duration_seconds",
+];
+
+/// Families labelled with `scan_id` when `scan_id_label` is enabled.
+const AGGREGATE_PREFIXES: &[&str] = &[
+    "herakles_proc_mem_group_",
+    "herakles_proc_mem_top_",
+    "herakles_proc_mem_session_",
+];
+
+/// Families renamed by schema v2: v1 name, v2 name and whether the family
+/// becomes a counter.
+const V2_RENAMES: &[(&str, &str, bool)] = &[
+    // A gauge, `_total` is reserved for counters
+    (
+        "herakles_proc_mem_processes_total",
+        "herakles_proc_mem_processes",
+        false,
+    ),
+    // Cumulative per process, only reset when the PID is reused
+    (
+        "herakles_proc_mem_cpu_time_seconds",
+        "herakles_proc_mem_cpu_seconds_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_cpu_guest_seconds",
+        "herakles_proc_mem_cpu_guest_seconds_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_blkio_delay_seconds",
+        "herakles_proc_mem_blkio_delay_seconds_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_swapin_delay_seconds",
+        "herakles_proc_mem_swapin_delay_seconds_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_sched_run_seconds",
+        "herakles_proc_mem_sched_run_seconds_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_sched_wait_seconds",
+        "herakles_proc_mem_sched_wait_seconds_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_sched_timeslices",
+        "herakles_proc_mem_sched_timeslices_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_thread_cpu_time_seconds",
+        "herakles_proc_mem_thread_cpu_seconds_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_io_read_bytes",
+        "herakles_proc_mem_io_read_bytes_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_io_write_bytes",
+        "herakles_proc_mem_io_write_bytes_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_io_read_syscalls",
+        "herakles_proc_mem_io_read_syscalls_total",
+        true,
+    ),
+    (
+        "herakles_proc_mem_io_write_syscalls",
+        "herakles_proc_mem_io_write_syscalls_total",
+        true,
+    ),
+];
+
+/// Label dropped by schema v2; its value changes on every scrape, so every
+/// series of v1 is a new series each time.
+const V2_DROPPED_LABEL: &str = "uptime_in_seconds";
+
+/// Metric naming schema of an exposition (`metric_schema`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetricSchema {
+    /// Names of the first releases, kept for existing dashboards
+    #[default]
+    V1,
+    /// Fixed names, types and labels, see [`SchemaV2`]
+    V2,
+}
+
+impl MetricSchema {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "v1" => Some(Self::V1),
+            "v2" => Some(Self::V2),
+            _ => None,
+        }
+    }
+
+    /// The schema of /metrics and the tenant endpoints.
+    pub fn from_config(cfg: &Config) -> Self {
+        cfg.metric_schema
+            .as_deref()
+            .and_then(Self::parse)
+            .unwrap_or_default()
+    }
+}
+
+/// A step over the process snapshot, before aggregation.
+pub trait ProcessStage: Send + Sync {
+    /// Name in the stage timings of /debug/exposition.
+    fn name(&self) -> &'static str;
+    fn apply(&self, processes: &mut Vec<ProcMem>);
+}
+
+/// A step over the classified snapshot, writing into the families of
+/// `metrics`.
+pub trait AggregateStage: Send + Sync {
+    /// Name in the stage timings of /debug/exposition.
+    fn name(&self) -> &'static str;
+    fn apply(&self, snapshot: &mut Classified<'_>, metrics: &MemoryMetrics);
+}
+
+/// A step over the gathered families, before encoding.
+pub trait FamilyStage: Send + Sync {
+    /// Name in the stage timings of /debug/exposition.
+    fn name(&self) -> &'static str;
+    fn apply(&self, families: &mut Vec<MetricFamily>);
+}
+
+/// Keeps the processes passing the name filters of a configuration.
+pub struct NameFilter<'a> {
+    pub cfg: &'a Config,
+}
+
+impl ProcessStage for NameFilter<'_> {
+    fn name(&self) -> &'static str {
+        "name_filter"
+    }
+
+    fn apply(&self, processes: &mut Vec<ProcMem>) {
+        processes.retain(|p| should_include_process(&p.name, self.cfg));
+    }
+}
+
+/// Drops the families whose names start with `prefix`.
+pub struct DropPrefix(pub &'static str);
+
+impl FamilyStage for DropPrefix {
+    fn name(&self) -> &'static str {
+        "drop_prefix"
+    }
+
+    fn apply(&self, families: &mut Vec<MetricFamily>) {
+        families.retain(|f| !f.name().starts_with(self.0));
+    }
+}
+
+/// Drops the families of system collectors missing from `system_collectors`,
+/// so disabled collectors leave no empty or zero families behind.
+pub struct DisabledCollectors<'a> {
+    pub allowlist: Option<&'a [String]>,
+}
+
+impl FamilyStage for DisabledCollectors<'_> {
+    fn name(&self) -> &'static str {
+        "disabled_collectors"
+    }
+
+    fn apply(&self, families: &mut Vec<MetricFamily>) {
+        families.retain(|f| !system::family_disabled(self.allowlist, f.name()));
+    }
+}
+
+/// Orders the families by name.
+pub struct SortByName;
+
+impl FamilyStage for SortByName {
+    fn name(&self) -> &'static str {
+        "sort"
+    }
+
+    fn apply(&self, families: &mut Vec<MetricFamily>) {
+        families.sort_by(|a, b| a.name().cmp(b.name()));
+    }
+}
+
+/// Rewrites families to metric schema v2: gauges lose a `_total` suffix,
+/// cumulative gauges become `_total` counters and the `uptime_in_seconds`
+/// label is dropped.
+pub struct SchemaV2;
+
+impl FamilyStage for SchemaV2 {
+    fn name(&self) -> &'static str {
+        "schema_v2"
+    }
+
+    fn apply(&self, families: &mut Vec<MetricFamily>) {
+        for family in families.iter_mut() {
+            if let Some((_, name, counter)) =
+                V2_RENAMES.iter().find(|(v1, ..)| *v1 == family.name())
+            {
+                family.set_name(name.to_string());
+                if *counter {
+                    family.set_field_type(MetricType::COUNTER);
+                    for metric in family.mut_metric() {
+                        let mut value = Counter::new();
+                        value.set_value(metric.get_gauge().value());
+                        metric.gauge.clear();
+                        metric.set_counter(value);
+                    }
+                }
+            }
+            for metric in family.mut_metric() {
+                let mut labels = metric.take_label();
+                labels.retain(|l| l.name() != V2_DROPPED_LABEL);
+                metric.set_label(labels);
+            }
+        }
+    }
+}
+
+/// Sets the sample timestamp of all families derived from the cache to the
+/// time of the last cache update (`sample_timestamps`).
+pub struct SampleTimestamps {
+    pub timestamp_ms: i64,
+}
+
+impl FamilyStage for SampleTimestamps {
+    fn name(&self) -> &'static str {
+        "sample_timestamps"
+    }
+
+    fn apply(&self, families: &mut Vec<MetricFamily>) {
+        for family in families.iter_mut().filter(|f| {
+            (f.name().starts_with("herakles_proc_mem_") || f.name().starts_with("herakles_cgroup_"))
+                && !LIVE_FAMILIES.contains(&f.name())
+        }) {
+            for metric in family.mut_metric() {
+                metric.set_timestamp_ms(self.timestamp_ms);
+            }
+        }
+    }
+}
+
+/// Adds the `scan_id` label to the aggregate families, so sums scraped
+/// separately can be matched to the same scan (`scan_id_label`).
+pub struct ScanIdLabel {
+    pub scan_id: u64,
+}
+
+impl FamilyStage for ScanIdLabel {
+    fn name(&self) -> &'static str {
+        "scan_id_label"
+    }
+
+    fn apply(&self, families: &mut Vec<MetricFamily>) {
+        let scan_id = self.scan_id.to_string();
+        for family in families
+            .iter_mut()
+            .filter(|f| AGGREGATE_PREFIXES.iter().any(|p| f.name().starts_with(p)))
+        {
+            for metric in family.mut_metric() {
+                let mut labels = metric.take_label();
+                let mut label = LabelPair::new();
+                label.set_name("scan_id".to_string());
+                label.set_value(scan_id.clone());
+                labels.push(label);
+                metric.set_label(labels);
+            }
+        }
+    }
+}
+
+/// Adds the static labels of `group_labels` to every series with a `group`
+/// label; a "group/subgroup" entry wins over a plain group entry.
+pub struct GroupLabels<'a> {
+    pub labels: &'a BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl GroupLabels<'_> {
+    /// The labels of `group` and `subgroup`, sorted by name.
+    pub fn for_series(&self, group: &str, subgroup: Option<&str>) -> BTreeMap<&str, &str> {
+        let mut labels: BTreeMap<&str, &str> = BTreeMap::new();
+        let group_entry = self.labels.get(group);
+        let subgroup_entry =
+            subgroup.and_then(|subgroup| self.labels.get(&format!("{}/{}", group, subgroup)));
+        for entry in [group_entry, subgroup_entry].into_iter().flatten() {
+            labels.extend(entry.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        }
+        labels
+    }
+}
+
+impl FamilyStage for GroupLabels<'_> {
+    fn name(&self) -> &'static str {
+        "group_labels"
+    }
+
+    fn apply(&self, families: &mut Vec<MetricFamily>) {
+        for metric in families.iter_mut().flat_map(|f| f.mut_metric().iter_mut()) {
+            let value = |name: &str| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|l| l.name() == name)
+                    .map(|l| l.value().to_string())
+            };
+            let Some(group) = value("group") else {
+                continue;
+            };
+            let subgroup = value("subgroup");
+            let extra = self.for_series(&group, subgroup.as_deref());
+            if extra.is_empty() {
+                continue;
+            }
+            let mut labels = metric.take_label();
+            for (name, value) in extra {
+                let mut label = LabelPair::new();
+                label.set_name(name.to_string());
+                label.set_value(value.to_string());
+                labels.push(label);
+            }
+            metric.set_label(labels);
+        }
+    }
+}
+
+/// The stages of one scrape, in order.
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    process_stages: Vec<Box<dyn ProcessStage + 'a>>,
+    aggregate_stages: Vec<Box<dyn AggregateStage + 'a>>,
+    family_stages: Vec<Box<dyn FamilyStage + 'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_process_stage(mut self, stage: impl ProcessStage + 'a) -> Self {
+        self.process_stages.push(Box::new(stage));
+        self
+    }
+
+    pub fn with_aggregate_stage(mut self, stage: impl AggregateStage + 'a) -> Self {
+        self.aggregate_stages.push(Box::new(stage));
+        self
+    }
+
+    pub fn with_family_stage(mut self, stage: impl FamilyStage + 'a) -> Self {
+        self.family_stages.push(Box::new(stage));
+        self
+    }
+
+    /// Adds the renames of `schema`; before sorting, which they affect.
+    fn with_schema(self, schema: MetricSchema) -> Self {
+        match schema {
+            MetricSchema::V1 => self,
+            MetricSchema::V2 => self.with_family_stage(SchemaV2),
+        }
+    }
+
+    /// Adds the stages shared by all endpoints: the `group_labels`, sample
+    /// timestamps (when `timestamp_ms` is given) and the `scan_id` label.
+    fn with_scan_stages(
+        mut self,
+        cfg: &'a Config,
+        timestamp_ms: Option<i64>,
+        scan_id: u64,
+    ) -> Self {
+        if let Some(labels) = cfg.group_labels.as_ref().filter(|l| !l.is_empty()) {
+            self = self.with_family_stage(GroupLabels { labels });
+        }
+        if let Some(timestamp_ms) = timestamp_ms {
+            self = self.with_family_stage(SampleTimestamps { timestamp_ms });
+        }
+        if cfg.scan_id_label.unwrap_or(false) {
+            self = self.with_family_stage(ScanIdLabel { scan_id });
+        }
+        self
+    }
+
+    /// Adds the aggregate stages of `cfg`. Without `per_process` (or with
+    /// `per_process_metrics: false`) only the aggregated, Top-N and session
+    /// families are filled. With `top_n`, the Top-N of the scan with the
+    /// given ID is selected with hysteresis.
+    pub fn with_aggregation(
+        mut self,
+        cfg: &'a Config,
+        per_process: bool,
+        top_n: Option<(&'a TopNHysteresis, u64)>,
+    ) -> Self {
+        let enabled = EnabledValues::from_config(cfg);
+        // The exporter and monitoring agents count in the sums but get no series
+        if cfg.hide_monitoring_processes.unwrap_or(false) {
+            let names = cfg
+                .monitoring_processes
+                .as_ref()
+                .map(|names| names.iter().map(String::as_str).collect())
+                .unwrap_or_else(|| DEFAULT_MONITORING_PROCESSES.to_vec());
+            self = self.with_aggregate_stage(HideMonitoring { names });
+        }
+        if per_process && cfg.per_process_metrics.unwrap_or(true) {
+            // Capped processes still count in the aggregates
+            if let Some(max) = cfg.max_exported_processes {
+                self = self.with_aggregate_stage(MaxExported(max));
+            }
+            // Only heavy hitters of the subgroup get their own series
+            if let Some(percent) = cfg.min_uss_percent_of_subgroup.filter(|p| *p > 0.0) {
+                self = self.with_aggregate_stage(MinSubgroupShare { percent });
+            }
+            self = self.with_aggregate_stage(ProcessSeries { cfg });
+        }
+        if let Some(by) = cfg
+            .session_aggregation
+            .as_deref()
+            .filter(|by| enabled.uss && *by != "off")
+        {
+            self = self.with_aggregate_stage(SessionSums { by, cfg });
+        }
+        self = self.with_aggregate_stage(SubgroupSums { enabled });
+        if cfg.enable_privilege_metrics.unwrap_or(false) {
+            self = self.with_aggregate_stage(PrivilegeCounts);
+        }
+        self = self
+            .with_aggregate_stage(StateCounts)
+            .with_aggregate_stage(TopN {
+                cfg,
+                enabled,
+                hysteresis: top_n,
+            });
+        if cfg.stable_topn.unwrap_or(false) {
+            self = self.with_aggregate_stage(TopInfo { cfg });
+        }
+        if cfg.enable_wchan_metrics.unwrap_or(false) {
+            self = self.with_aggregate_stage(TopWait { cfg });
+        }
+        self
+    }
+
+    /// Stages of /metrics in `schema`; `per_process` as for
+    /// [`Self::with_aggregation`], the Top-N with `hysteresis`.
+    pub fn for_metrics(
+        cfg: &'a Config,
+        schema: MetricSchema,
+        timestamp_ms: Option<i64>,
+        scan_id: u64,
+        per_process: bool,
+        hysteresis: &'a TopNHysteresis,
+    ) -> Self {
+        Self::new()
+            .with_aggregation(cfg, per_process, Some((hysteresis, scan_id)))
+            .with_family_stage(DisabledCollectors {
+                allowlist: cfg.system_collectors.as_deref(),
+            })
+            .with_schema(schema)
+            .with_family_stage(SortByName)
+            .with_scan_stages(cfg, timestamp_ms, scan_id)
+    }
+
+    /// Stages of /metrics/<tenant>: the tenant's name filters and no
+    /// host-wide families. `tenant_cfg` is the tenant's view of `cfg`.
+    /// Tenants see part of the processes, so the Top-N memberships are kept
+    /// for the full view only.
+    pub fn for_tenant(
+        cfg: &'a Config,
+        tenant_cfg: &'a Config,
+        timestamp_ms: Option<i64>,
+        scan_id: u64,
+        per_process: bool,
+    ) -> Self {
+        Self::new()
+            .with_process_stage(NameFilter { cfg: tenant_cfg })
+            .with_aggregation(tenant_cfg, per_process, None)
+            .with_family_stage(DropPrefix("herakles_system_"))
+            .with_schema(MetricSchema::from_config(cfg))
+            .with_scan_stages(cfg, timestamp_ms, scan_id)
+    }
+
+    /// Runs the process stages over the snapshot. Returns the time taken
+    /// by each stage.
+    pub fn filter(&self, processes: &mut Vec<ProcMem>) -> Vec<(&'static str, Duration)> {
+        self.process_stages
+            .iter()
+            .map(|stage| {
+                let start = Instant::now();
+                stage.apply(processes);
+                (stage.name(), start.elapsed())
+            })
+            .collect()
+    }
+
+    /// Runs the aggregate stages over the classified snapshot. Returns the
+    /// time taken by each stage.
+    #[instrument(skip_all, fields(processes = snapshot.processes.len()))]
+    pub fn aggregate(
+        &self,
+        snapshot: &mut Classified<'_>,
+        metrics: &MemoryMetrics,
+    ) -> Vec<(&'static str, Duration)> {
+        self.aggregate_stages
+            .iter()
+            .map(|stage| {
+                let start = Instant::now();
+                stage.apply(snapshot, metrics);
+                (stage.name(), start.elapsed())
+            })
+            .collect()
+    }
+
+    /// Runs the family stages over the gathered families. Returns the time
+    /// taken by each stage.
+    pub fn rewrite(&self, families: &mut Vec<MetricFamily>) -> Vec<(&'static str, Duration)> {
+        self.family_stages
+            .iter()
+            .map(|stage| {
+                let start = Instant::now();
+                stage.apply(families);
+                (stage.name(), start.elapsed())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::generate::TestProcess;
+    use prometheus::core::Collector;
+    use prometheus::{Gauge, GaugeVec, Opts};
+
+    fn process(pid: u32, name: &str, uss: u64) -> ProcMem {
+        ProcMem::from(TestProcess {
+            pid,
+            name: name.to_string(),
+            group: String::new(),
+            subgroup: String::new(),
+            rss: uss,
+            pss: uss,
+            uss,
+            cpu_percent: 0.0,
+            cpu_time_seconds: 0.0,
+        })
+    }
+
+    fn families() -> Vec<MetricFamily> {
+        let load = Gauge::new("herakles_system_load1", "Load").unwrap();
+        let sum = GaugeVec::new(
+            Opts::new("herakles_proc_mem_group_uss_bytes_sum", "USS"),
+            &["group"],
+        )
+        .unwrap();
+        sum.with_label_values(&["db"]).set(1.0);
+        let updating = Gauge::new("herakles_proc_mem_cache_updating", "Updating").unwrap();
+        [load.collect(), sum.collect(), updating.collect()]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn test_metrics_pipeline() {
+        let cfg = Config {
+            system_collectors: Some(vec!["meminfo".to_string()]),
+            scan_id_label: Some(true),
+            ..Default::default()
+        };
+        let mut families = families();
+        let hysteresis = TopNHysteresis::new(1);
+        let timings =
+            Pipeline::for_metrics(&cfg, MetricSchema::V1, Some(1_000), 7, true, &hysteresis)
+                .rewrite(&mut families);
+        let stages: Vec<&str> = timings.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            stages,
+            [
+                "disabled_collectors",
+                "sort",
+                "sample_timestamps",
+                "scan_id_label"
+            ]
+        );
+
+        let names: Vec<&str> = families.iter().map(|f| f.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "herakles_proc_mem_cache_updating",
+                "herakles_proc_mem_group_uss_bytes_sum"
+            ]
+        );
+        assert_eq!(families[0].get_metric()[0].timestamp_ms(), 0);
+        let sum = &families[1].get_metric()[0];
+        assert_eq!(sum.timestamp_ms(), 1_000);
+        assert!(sum
+            .get_label()
+            .iter()
+            .any(|l| l.name() == "scan_id" && l.value() == "7"));
+    }
+
+    #[test]
+    fn test_schema_v2() {
+        let cpu_time = GaugeVec::new(
+            Opts::new("herakles_proc_mem_cpu_time_seconds", "CPU"),
+            &["pid", "uptime_in_seconds"],
+        )
+        .unwrap();
+        cpu_time.with_label_values(&["1", "42"]).set(12.5);
+        let processes = Gauge::new("herakles_proc_mem_processes_total", "Processes").unwrap();
+        let mut families: Vec<MetricFamily> = families()
+            .into_iter()
+            .chain(cpu_time.collect())
+            .chain(processes.collect())
+            .collect();
+
+        let cfg = Config::default();
+        let hysteresis = TopNHysteresis::new(1);
+        let mut v1 = families.clone();
+        Pipeline::for_metrics(&cfg, MetricSchema::V1, None, 7, true, &hysteresis).rewrite(&mut v1);
+        assert!(v1
+            .iter()
+            .any(|f| f.name() == "herakles_proc_mem_cpu_time_seconds"));
+
+        Pipeline::for_metrics(&cfg, MetricSchema::V2, None, 7, true, &hysteresis)
+            .rewrite(&mut families);
+        let names: Vec<&str> = families.iter().map(|f| f.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "herakles_proc_mem_cache_updating",
+                "herakles_proc_mem_cpu_seconds_total",
+                "herakles_proc_mem_group_uss_bytes_sum",
+                "herakles_proc_mem_processes",
+                "herakles_system_load1"
+            ]
+        );
+        let cpu = &families[1];
+        assert_eq!(cpu.get_field_type(), MetricType::COUNTER);
+        let metric = &cpu.get_metric()[0];
+        assert_eq!(metric.get_counter().value(), 12.5);
+        let labels: Vec<&str> = metric.get_label().iter().map(|l| l.name()).collect();
+        assert_eq!(labels, ["pid"]);
+        assert_eq!(families[3].get_field_type(), MetricType::GAUGE);
+    }
+
+    #[test]
+    fn test_group_labels() {
+        let subgroup_sum = GaugeVec::new(
+            Opts::new("herakles_proc_mem_group_rss_bytes_sum", "RSS"),
+            &["group", "subgroup"],
+        )
+        .unwrap();
+        subgroup_sum.with_label_values(&["db", "postgres"]).set(1.0);
+        subgroup_sum.with_label_values(&["db", "mysql"]).set(1.0);
+        subgroup_sum.with_label_values(&["web", "nginx"]).set(1.0);
+        let mut families: Vec<MetricFamily> = subgroup_sum.collect();
+
+        let labels = BTreeMap::from([
+            (
+                "db".to_string(),
+                BTreeMap::from([
+                    ("team".to_string(), "dba".to_string()),
+                    ("tier".to_string(), "backend".to_string()),
+                ]),
+            ),
+            (
+                "db/mysql".to_string(),
+                BTreeMap::from([("team".to_string(), "legacy".to_string())]),
+            ),
+        ]);
+        GroupLabels { labels: &labels }.apply(&mut families);
+
+        let series: Vec<Vec<(&str, &str)>> = families[0]
+            .get_metric()
+            .iter()
+            .map(|m| {
+                m.get_label()
+                    .iter()
+                    .map(|l| (l.name(), l.value()))
+                    .collect()
+            })
+            .collect();
+        assert!(series.contains(&vec![
+            ("group", "db"),
+            ("subgroup", "mysql"),
+            ("team", "legacy"),
+            ("tier", "backend")
+        ]));
+        assert!(series.contains(&vec![
+            ("group", "db"),
+            ("subgroup", "postgres"),
+            ("team", "dba"),
+            ("tier", "backend")
+        ]));
+        assert!(series.contains(&vec![("group", "web"), ("subgroup", "nginx")]));
+    }
+
+    #[test]
+    fn test_tenant_pipeline() {
+        let cfg = Config::default();
+        let tenant_cfg = Config {
+            include_names: Some(vec!["postgres".to_string()]),
+            ..Default::default()
+        };
+        let pipeline = Pipeline::for_tenant(&cfg, &tenant_cfg, None, 7, true);
+
+        let mut processes = vec![process(1, "postgres", 0), process(1, "nginx", 0)];
+        pipeline.filter(&mut processes);
+        assert_eq!(processes.len(), 1);
+        assert_eq!(&*processes[0].name, "postgres");
+
+        let mut families = families();
+        pipeline.rewrite(&mut families);
+        assert_eq!(families.len(), 2);
+        assert!(families
+            .iter()
+            .all(|f| !f.name().starts_with("herakles_system_")));
+        // No scan_id label without scan_id_label
+        assert_eq!(families[0].get_metric()[0].get_label().len(), 1);
+    }
+
+    #[test]
+    fn test_aggregation() {
+        let cfg = Config {
+            hide_monitoring_processes: Some(true),
+            monitoring_processes: Some(vec!["nginx".to_string()]),
+            max_exported_processes: Some(1),
+            ..Default::default()
+        };
+        let pipeline = Pipeline::new().with_aggregation(&cfg, true, None);
+        let processes = vec![
+            process(1, "postgres", 100),
+            process(2, "postgres", 300),
+            process(3, "nginx", 1000),
+        ];
+        let rates = ahash::AHashMap::new();
+        let mut snapshot = Classified::new(&processes, &rates, &cfg, "0");
+        let registry = prometheus::Registry::new();
+        let metrics = MemoryMetrics::new(&registry, &[], false).unwrap();
+        let timings = pipeline.aggregate(&mut snapshot, &metrics);
+
+        let stages: Vec<&str> = timings.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            stages,
+            [
+                "hide_monitoring",
+                "max_exported",
+                "process_series",
+                "subgroup_sums",
+                "state_counts",
+                "top_n"
+            ]
+        );
+        // The largest visible process is exported, the hidden one still
+        // counts in the sums of its subgroup
+        assert_eq!(snapshot.exported_count, 1);
+        let families = registry.gather();
+        let series = |name: &str| -> Vec<(String, f64)> {
+            families
+                .iter()
+                .filter(|f| f.name() == name)
+                .flat_map(|f| f.get_metric())
+                .map(|m| {
+                    let labels: Vec<String> = m
+                        .get_label()
+                        .iter()
+                        .filter(|l| l.name() == "pid" || l.name() == "subgroup")
+                        .map(|l| l.value().to_string())
+                        .collect();
+                    (labels.join("/"), m.get_gauge().value())
+                })
+                .collect()
+        };
+        assert_eq!(
+            series("herakles_proc_mem_uss_bytes"),
+            [("2/postgres".to_string(), 300.0)]
+        );
+        let mut sums = series("herakles_proc_mem_group_uss_bytes_sum");
+        sums.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            sums,
+            [
+                ("nginx".to_string(), 1000.0),
+                ("postgres".to_string(), 400.0)
+            ]
+        );
+        assert_eq!(series("herakles_proc_mem_top_uss_bytes").len(), 2);
+    }
+}
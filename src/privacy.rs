@@ -0,0 +1,131 @@
+//! Privacy mode: exclusion of processes by owner and hashed process names.
+//!
+//! With `name_hash: true` every exported process name (metric labels,
+//! `/export.csv`, the Grafana and unclassified APIs, the history and dumps)
+//! and every value extracted from command lines or environments is replaced
+//! by a short hash. The hash only depends on the value and `name_hash_salt`,
+//! so series keep their identity across scans, restarts and hosts.
+//! Classification and name filters still see the real names.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::Config;
+
+/// Prefix of hashed names, so they cannot be mistaken for real ones.
+const HASH_PREFIX: &str = "h_";
+
+/// Returns a process name or extracted value as exported.
+pub fn public_name<'a>(value: &'a str, cfg: &Config) -> Cow<'a, str> {
+    if cfg.name_hash.unwrap_or(false) && !value.is_empty() {
+        Cow::Owned(hash_name(
+            value,
+            cfg.name_hash_salt.as_deref().unwrap_or(""),
+        ))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Extracted label values as exported (see [`public_name`]).
+pub fn public_values(values: Arc<[String]>, cfg: &Config) -> Arc<[String]> {
+    if !cfg.name_hash.unwrap_or(false) {
+        return values;
+    }
+    values
+        .iter()
+        .map(|v| public_name(v, cfg).into_owned())
+        .collect()
+}
+
+/// FNV-1a over salt and value, truncated to 48 bits (12 hex digits).
+fn hash_name(value: &str, salt: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in salt.bytes().chain([0]).chain(value.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{}{:012x}", HASH_PREFIX, hash >> 16)
+}
+
+/// Resolves `exclude_users` entries (user names or numeric UIDs) to UIDs.
+///
+/// Names are looked up in /etc/passwd, so this must run before the sandbox
+/// restricts filesystem access.
+pub fn resolve_users(users: &[String]) -> Result<HashSet<u32>, String> {
+    if users.iter().all(|u| u.parse::<u32>().is_ok()) {
+        return Ok(users.iter().filter_map(|u| u.parse().ok()).collect());
+    }
+    let passwd = fs::read_to_string("/etc/passwd")
+        .map_err(|e| format!("Failed to read /etc/passwd for exclude_users: {}", e))?;
+    resolve_users_in(users, &passwd)
+}
+
+fn resolve_users_in(users: &[String], passwd: &str) -> Result<HashSet<u32>, String> {
+    users
+        .iter()
+        .map(|user| {
+            if let Ok(uid) = user.parse() {
+                return Ok(uid);
+            }
+            passwd
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.split(':');
+                    let name = fields.next()?;
+                    let uid = fields.nth(1)?.parse::<u32>().ok()?;
+                    Some((name, uid))
+                })
+                .find(|(name, _)| name == user)
+                .map(|(_, uid)| uid)
+                .ok_or_else(|| format!("Unknown user '{}' in exclude_users", user))
+        })
+        .collect()
+}
+
+/// Owner UID of a process (the owner of /proc/<pid>), None if it exited.
+pub fn process_owner(proc_path: &Path) -> Option<u32> {
+    fs::metadata(proc_path).ok().map(|m| m.uid())
+}
+
+/// Whether a process is excluded by owner; processes that exited are excluded too.
+pub fn excluded_by_owner(proc_path: &Path, uids: &HashSet<u32>) -> bool {
+    !uids.is_empty() && process_owner(proc_path).is_none_or(|uid| uids.contains(&uid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_name() {
+        let mut cfg = Config::default();
+        assert_eq!(public_name("postgres", &cfg), "postgres");
+
+        cfg.name_hash = Some(true);
+        let hashed = public_name("postgres", &cfg).into_owned();
+        assert_eq!(hashed.len(), HASH_PREFIX.len() + 12);
+        assert_eq!(public_name("postgres", &cfg), hashed);
+        assert_ne!(public_name("postgrex", &cfg), hashed);
+        assert_eq!(public_name("", &cfg), "");
+
+        cfg.name_hash_salt = Some("secret".to_string());
+        assert_ne!(public_name("postgres", &cfg), hashed);
+    }
+
+    #[test]
+    fn test_resolve_users() {
+        let passwd =
+            "root:x:0:0:root:/root:/bin/bash\npostgres:x:114:120::/var/lib/postgresql:/bin/sh\n";
+        let users = vec!["postgres".to_string(), "1000".to_string()];
+        assert_eq!(
+            resolve_users_in(&users, passwd).unwrap(),
+            HashSet::from([114, 1000])
+        );
+        assert!(resolve_users_in(&["nobody".to_string()], passwd).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Probing of optional /proc features.
+//!
+//! Kernels, containers and mount options differ in what they expose under
+//! /proc. This module probes the features the collectors depend on and keeps
+//! the result in a global table so the scan code can pick the right parser
+//! up front instead of failing (and logging) once per process.
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Probed /proc features relevant for collection.
+pub struct Capabilities {
+    smaps_rollup: AtomicBool,
+    smaps_pss: AtomicBool,
+    hidepid: AtomicBool,
+    proc_io: AtomicBool,
+    foreign_processes: AtomicBool,
+}
+
+/// Snapshot of probed capabilities at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapabilitySnapshot {
+    /// /proc/<pid>/smaps_rollup is available (Linux >= 4.14).
+    pub smaps_rollup: bool,
+    /// smaps reports Pss lines.
+    pub smaps_pss: bool,
+    /// /proc is mounted with hidepid, foreign processes may be invisible.
+    pub hidepid: bool,
+    /// /proc/<pid>/io of foreign processes is readable.
+    pub proc_io: bool,
+    /// Processes of other users can be read, i.e. hidepid does not apply
+    /// to the exporter (root, CAP_SYS_PTRACE or the hidepid `gid`).
+    pub foreign_processes: bool,
+}
+
+impl CapabilitySnapshot {
+    /// Feature name/value pairs as exported in `herakles_exporter_capability`.
+    pub fn features(&self) -> [(&'static str, bool); 5] {
+        [
+            ("smaps_rollup", self.smaps_rollup),
+            ("smaps_pss", self.smaps_pss),
+            ("hidepid", self.hidepid),
+            ("proc_io", self.proc_io),
+            ("foreign_processes", self.foreign_processes),
+        ]
+    }
+
+    /// Degraded mode: hidepid hides other users' processes from the
+    /// exporter, so only its own user's processes are scanned.
+    pub fn own_processes_only(&self) -> bool {
+        self.hidepid && !self.foreign_processes
+    }
+}
+
+/// Global capability table, optimistic until the first probe has run.
+pub static CAPABILITIES: Capabilities = Capabilities {
+    smaps_rollup: AtomicBool::new(true),
+    smaps_pss: AtomicBool::new(true),
+    hidepid: AtomicBool::new(false),
+    proc_io: AtomicBool::new(true),
+    foreign_processes: AtomicBool::new(true),
+};
+
+impl Capabilities {
+    pub fn smaps_rollup(&self) -> bool {
+        self.smaps_rollup.load(Ordering::Relaxed)
+    }
+
+    pub fn smaps_pss(&self) -> bool {
+        self.smaps_pss.load(Ordering::Relaxed)
+    }
+
+    pub fn hidepid(&self) -> bool {
+        self.hidepid.load(Ordering::Relaxed)
+    }
+
+    pub fn proc_io(&self) -> bool {
+        self.proc_io.load(Ordering::Relaxed)
+    }
+
+    pub fn foreign_processes(&self) -> bool {
+        self.foreign_processes.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> CapabilitySnapshot {
+        CapabilitySnapshot {
+            smaps_rollup: self.smaps_rollup(),
+            smaps_pss: self.smaps_pss(),
+            hidepid: self.hidepid(),
+            proc_io: self.proc_io(),
+            foreign_processes: self.foreign_processes(),
+        }
+    }
+
+    /// Stores a probe result and returns the previous snapshot.
+    pub fn store(&self, snap: CapabilitySnapshot) -> CapabilitySnapshot {
+        let previous = self.snapshot();
+        self.smaps_rollup
+            .store(snap.smaps_rollup, Ordering::Relaxed);
+        self.smaps_pss.store(snap.smaps_pss, Ordering::Relaxed);
+        self.hidepid.store(snap.hidepid, Ordering::Relaxed);
+        self.proc_io.store(snap.proc_io, Ordering::Relaxed);
+        self.foreign_processes
+            .store(snap.foreign_processes, Ordering::Relaxed);
+        previous
+    }
+}
+
+/// Probes /proc for the optional features used by the collectors.
+pub fn probe_capabilities(proc_root: &str) -> CapabilitySnapshot {
+    let root = Path::new(proc_root);
+    let own = root.join("self");
+
+    // Pss is checked in the rollup when possible, it is much smaller than smaps
+    let rollup = fs::read_to_string(own.join("smaps_rollup")).ok();
+    let smaps_rollup = rollup.is_some();
+    let smaps_pss = match rollup {
+        Some(content) => smaps_has_pss(&content),
+        None => fs::read_to_string(own.join("smaps"))
+            .map(|s| smaps_has_pss(&s))
+            .unwrap_or(false),
+    };
+    let hidepid = fs::read_to_string(root.join("mounts"))
+        .map(|s| mounts_have_hidepid(&s, proc_root))
+        .unwrap_or(false);
+    // PID 1 is always a foreign process unless we run as init in a container
+    let proc_io = fs::read(root.join("1").join("io")).is_ok();
+    // stat is world-readable unless hidepid hides the process
+    let foreign_processes = fs::read(root.join("1").join("stat")).is_ok();
+
+    CapabilitySnapshot {
+        smaps_rollup,
+        smaps_pss,
+        hidepid,
+        proc_io,
+        foreign_processes,
+    }
+}
+
+/// Returns true if an smaps dump contains at least one Pss line.
+fn smaps_has_pss(content: &str) -> bool {
+    content.lines().any(|l| l.starts_with("Pss:"))
+}
+
+/// Returns true if the proc mount at `mount_point` uses a restrictive hidepid.
+fn mounts_have_hidepid(mounts: &str, mount_point: &str) -> bool {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            (fields.len() >= 4 && fields[2] == "proc" && fields[1] == mount_point)
+                .then(|| fields[3])
+        })
+        .any(|opts| {
+            opts.split(',')
+                .any(|opt| match opt.strip_prefix("hidepid=") {
+                    Some(v) => !matches!(v, "0" | "off"),
+                    None => false,
+                })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mounts_have_hidepid() {
+        let plain = "proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0\n";
+        assert!(!mounts_have_hidepid(plain, "/proc"));
+
+        let hidden = "sysfs /sys sysfs rw 0 0\nproc /proc proc rw,relatime,hidepid=2 0 0\n";
+        assert!(mounts_have_hidepid(hidden, "/proc"));
+
+        let named = "proc /proc proc rw,relatime,hidepid=invisible 0 0\n";
+        assert!(mounts_have_hidepid(named, "/proc"));
+
+        let off = "proc /proc proc rw,relatime,hidepid=0 0 0\n";
+        assert!(!mounts_have_hidepid(off, "/proc"));
+
+        // hidepid on a different proc mount must not count
+        assert!(!mounts_have_hidepid(hidden, "/host/proc"));
+    }
+
+    #[test]
+    fn test_own_processes_only() {
+        let snap = CapabilitySnapshot {
+            smaps_rollup: true,
+            smaps_pss: true,
+            hidepid: true,
+            proc_io: false,
+            foreign_processes: false,
+        };
+        assert!(snap.own_processes_only());
+        // Root or the hidepid gid see every process
+        let privileged = CapabilitySnapshot {
+            foreign_processes: true,
+            ..snap
+        };
+        assert!(!privileged.own_processes_only());
+    }
+
+    #[test]
+    fn test_smaps_has_pss() {
+        assert!(smaps_has_pss("Rss:  100 kB\nPss:  50 kB\n"));
+        assert!(!smaps_has_pss("Rss:  100 kB\nShared_Clean: 0 kB\n"));
+    }
+}
@@ -104,6 +104,54 @@ pub fn classify_process_raw(process_name: &str) -> (Arc<str>, Arc<str>) {
         .unwrap_or_else(|| (Arc::clone(&OTHER_STR), Arc::clone(&UNKNOWN_STR)))
 }
 
+/// Number of unclassified process names kept in [`RuleStats`].
+pub const TOP_UNCLASSIFIED: usize = 20;
+
+/// Classification rule statistics of one scan, for tuning the subgroups table.
+#[derive(Debug, Clone, Default)]
+pub struct RuleStats {
+    /// Processes matched per pattern (patterns without hits are absent)
+    pub hits: HashMap<Arc<str>, usize>,
+    /// Most common unclassified names with their process count, descending
+    pub unclassified: Vec<(String, usize)>,
+    /// Number of processes not matched by any pattern
+    pub unclassified_total: usize,
+}
+
+/// Counts pattern hits and unclassified names over the given process names.
+pub fn rule_stats<'a>(names: impl IntoIterator<Item = &'a str>) -> RuleStats {
+    rule_stats_with(&SUBGROUPS, names, TOP_UNCLASSIFIED)
+}
+
+fn rule_stats_with<'a>(
+    map: &SubgroupsMap,
+    names: impl IntoIterator<Item = &'a str>,
+    top: usize,
+) -> RuleStats {
+    let mut stats = RuleStats::default();
+    let mut unclassified: HashMap<&str, usize> = HashMap::new();
+
+    for name in names {
+        match map.get_key_value(name) {
+            Some((pattern, _)) => *stats.hits.entry(Arc::clone(pattern)).or_default() += 1,
+            None => {
+                *unclassified.entry(name).or_default() += 1;
+                stats.unclassified_total += 1;
+            }
+        }
+    }
+
+    let mut unclassified: Vec<(String, usize)> = unclassified
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
+    unclassified.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    unclassified.truncate(top);
+    stats.unclassified = unclassified;
+
+    stats
+}
+
 /// Classification including config rules (include/exclude, disable_others).
 pub fn classify_process_with_config(
     process_name: &str,
@@ -169,4 +217,32 @@ mod tests {
         assert_eq!(group.as_ref(), "other");
         assert_eq!(subgroup.as_ref(), "unknown");
     }
+
+    #[test]
+    fn test_rule_stats() {
+        let mut map = SubgroupsMap::new();
+        load_subgroups_from_str(
+            r#"subgroups = [{ group = "db", subgroup = "postgres", matches = ["postgres", "postmaster"] }]"#,
+            &mut map,
+        );
+
+        let names = [
+            "postgres",
+            "postgres",
+            "postmaster",
+            "bash",
+            "bash",
+            "zsh",
+            "vim",
+        ];
+        let stats = rule_stats_with(&map, names, 2);
+
+        assert_eq!(stats.hits.get("postgres"), Some(&2));
+        assert_eq!(stats.hits.get("postmaster"), Some(&1));
+        assert_eq!(stats.unclassified_total, 4);
+        assert_eq!(
+            stats.unclassified,
+            vec![("bash".to_string(), 2), ("vim".to_string(), 1)]
+        );
+    }
 }
@@ -5,11 +5,14 @@
 
 use ahash::AHashMap as HashMap;
 use once_cell::sync::Lazy;
-use std::fs;
 use std::path::Path;
 use std::sync::RwLock as StdRwLock;
 use std::time::Instant;
-use tracing::debug;
+
+use herakles_proc_mem_exporter::CollectError;
+
+use super::stat::read_proc_stat;
+use crate::cache::ProcKey;
 
 /// Get system clock ticks per second (usually 100, but can vary).
 fn get_clk_tck() -> f64 {
@@ -45,43 +48,29 @@ pub struct CpuEntry {
 }
 
 /// Parse total CPU time (user+system) in seconds from /proc/<pid>/stat.
-pub fn parse_cpu_time_seconds(proc_path: &Path) -> Result<f64, std::io::Error> {
-    let stat_path = proc_path.join("stat");
-    let content = fs::read_to_string(stat_path)?;
-
-    let parts: Vec<&str> = content.split_whitespace().collect();
-    if parts.len() <= 14 {
-        return Err(std::io::Error::other("Invalid stat format"));
-    }
-
-    let utime: f64 = parts[13].parse().unwrap_or(0.0);
-    let stime: f64 = parts[14].parse().unwrap_or(0.0);
+pub fn parse_cpu_time_seconds(proc_path: &Path) -> Result<f64, CollectError> {
+    let stat = read_proc_stat(proc_path)?;
 
     // Use system-detected clock ticks per second
-    Ok((utime + stime) / *CLK_TCK)
+    Ok(stat.cpu_ticks() as f64 / *CLK_TCK)
 }
 
-/// Returns CPU stats for a PID using delta between samples.
-pub fn get_cpu_stat_for_pid(
-    pid: u32,
-    proc_path: &Path,
-    cache: &StdRwLock<HashMap<u32, CpuEntry>>,
+/// Computes CPU percent from an already read CPU time and updates the cache.
+///
+/// The cache is keyed by [`ProcKey`], so a reused PID starts without a
+/// previous CPU time instead of being compared to the old process.
+pub fn update_cpu_stat(
+    key: ProcKey,
+    cpu_time_seconds: f64,
+    cache: &StdRwLock<HashMap<ProcKey, CpuEntry>>,
 ) -> CpuStat {
     let now = Instant::now();
-    let cpu_time_seconds = match parse_cpu_time_seconds(proc_path) {
-        Ok(v) => v,
-        Err(e) => {
-            debug!("Failed to read CPU time for pid {}: {}", pid, e);
-            0.0
-        }
-    };
-
     let mut cpu_percent = 0.0;
 
     // Use delta between last and current CPU time to compute percent
     {
         let cache_read = cache.read().expect("cpu_cache read lock poisoned");
-        if let Some(entry) = cache_read.get(&pid) {
+        if let Some(entry) = cache_read.get(&key) {
             let dt = now.duration_since(entry.last_updated).as_secs_f64();
             if dt > 0.0 {
                 let delta_cpu = cpu_time_seconds - entry.stat.cpu_time_seconds;
@@ -101,7 +90,7 @@ pub fn get_cpu_stat_for_pid(
     {
         let mut cache_write = cache.write().expect("cpu_cache write lock poisoned");
         cache_write.insert(
-            pid,
+            key,
             CpuEntry {
                 stat,
                 last_updated: now,
@@ -161,6 +150,7 @@ mod tests {
 
         let result = parse_cpu_time_seconds(dir.path());
         assert!(result.is_err());
+        assert_eq!(result.unwrap_err().category(), "parse");
     }
 
     #[test]
@@ -170,6 +160,7 @@ mod tests {
         // No stat file exists
         let result = parse_cpu_time_seconds(dir.path());
         assert!(result.is_err());
+        assert!(result.unwrap_err().is_not_found());
     }
 
     #[test]
@@ -185,4 +176,25 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 0.0);
     }
+
+    #[test]
+    fn test_update_cpu_stat_pid_reuse() {
+        let cache = StdRwLock::new(HashMap::new());
+        let old = ProcKey {
+            pid: 42,
+            start_time_ticks: 100,
+        };
+        update_cpu_stat(old, 50.0, &cache);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(update_cpu_stat(old, 50.1, &cache).cpu_percent > 0.0);
+
+        // A new process with the same PID is not compared to the old one
+        let new = ProcKey {
+            start_time_ticks: 900,
+            ..old
+        };
+        assert_eq!(update_cpu_stat(new, 60.0, &cache).cpu_percent, 0.0);
+        assert_eq!(cache.read().unwrap().len(), 2);
+        assert_eq!(new.to_string(), "42.384");
+    }
 }
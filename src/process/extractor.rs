@@ -0,0 +1,226 @@
+//! Label extraction from process names, command lines and environments.
+//!
+//! Each `label_extractors` entry is a regex whose named capture groups become
+//! extra labels of the per-process series, e.g. the database of a PostgreSQL
+//! backend from its command line `postgres: app orders 10.0.0.5(5432) idle`.
+//! A label takes its value from the first extractor that captures it; labels
+//! no extractor captured are exported empty, which Prometheus treats as absent.
+//! The `environ` variables follow as lowercase labels after the extractor labels.
+
+use regex::Regex;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::config::Config;
+
+/// One compiled `label_extractors` entry.
+struct Extractor {
+    regex: Regex,
+    /// Match the command line instead of the name
+    cmdline: bool,
+    /// Capture group name and index of the label in `LabelExtractors::names`
+    labels: Vec<(String, usize)>,
+}
+
+/// The compiled `label_extractors` of a config.
+#[derive(Default)]
+pub struct LabelExtractors {
+    extractors: Vec<Extractor>,
+    names: Vec<String>,
+    /// Number of `environ` variables exported as labels, at the end of `names`
+    environ_labels: usize,
+}
+
+impl LabelExtractors {
+    /// Compiles the extractors of `cfg`; invalid entries are skipped with a
+    /// warning (config validation rejects them before this point).
+    pub fn from_config(cfg: &Config) -> Self {
+        let mut names: Vec<String> = Vec::new();
+        let mut extractors = Vec::new();
+
+        for entry in cfg.label_extractors.iter().flatten() {
+            let regex = match Regex::new(&entry.pattern) {
+                Ok(regex) => regex,
+                Err(e) => {
+                    warn!("Skipping label extractor '{}': {}", entry.pattern, e);
+                    continue;
+                }
+            };
+            let labels = entry
+                .labels
+                .iter()
+                .map(|label| {
+                    let index = names.iter().position(|n| n == label).unwrap_or_else(|| {
+                        names.push(label.clone());
+                        names.len() - 1
+                    });
+                    (label.clone(), index)
+                })
+                .collect();
+            extractors.push(Extractor {
+                regex,
+                cmdline: entry.source.as_deref() != Some("name"),
+                labels,
+            });
+        }
+
+        let environ_labels = match &cfg.environ {
+            Some(environ) if environ.labels.unwrap_or(true) => {
+                names.extend(environ.variables.iter().map(|v| v.to_ascii_lowercase()));
+                environ.variables.len()
+            }
+            _ => 0,
+        };
+
+        Self {
+            extractors,
+            names,
+            environ_labels,
+        }
+    }
+
+    /// Names of the extra labels, in the order of the extracted values.
+    pub fn label_names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Whether any extractor matches command lines, which costs an extra read.
+    pub fn needs_cmdline(&self) -> bool {
+        self.extractors.iter().any(|e| e.cmdline)
+    }
+
+    /// Extracts the label values of a process, None if nothing was captured.
+    ///
+    /// Extractors for command lines match the name if `cmdline` is None (e.g.
+    /// for test data or kernel threads, whose command line is empty).
+    /// `environ` holds the values of the `environ` variables, in order.
+    pub fn extract(
+        &self,
+        name: &str,
+        cmdline: Option<&str>,
+        environ: Option<&[String]>,
+    ) -> Option<Arc<[String]>> {
+        if self.names.is_empty() {
+            return None;
+        }
+
+        let mut values = vec![String::new(); self.names.len()];
+        let mut captured = false;
+        if self.environ_labels > 0 {
+            let offset = self.names.len() - self.environ_labels;
+            for (i, value) in environ.unwrap_or_default().iter().enumerate() {
+                if i < self.environ_labels && !value.is_empty() {
+                    values[offset + i] = value.clone();
+                    captured = true;
+                }
+            }
+        }
+        for extractor in &self.extractors {
+            let text = if extractor.cmdline {
+                cmdline.unwrap_or(name)
+            } else {
+                name
+            };
+            let Some(caps) = extractor.regex.captures(text) else {
+                continue;
+            };
+            for (label, index) in &extractor.labels {
+                if let Some(m) = caps.name(label) {
+                    if values[*index].is_empty() && !m.as_str().is_empty() {
+                        values[*index] = m.as_str().to_string();
+                        captured = true;
+                    }
+                }
+            }
+        }
+
+        captured.then(|| values.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EnvironConfig, LabelExtractorConfig};
+
+    fn extractor(pattern: &str, labels: &[&str], source: Option<&str>) -> LabelExtractorConfig {
+        LabelExtractorConfig {
+            pattern: pattern.to_string(),
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            source: source.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_extract_labels() {
+        let config = Config {
+            label_extractors: Some(vec![
+                extractor(
+                    r"^postgres: (?P<user>\w+) (?P<db>\w+)",
+                    &["db", "user"],
+                    None,
+                ),
+                extractor(r"^java .*-Dapp=(?P<app>[\w-]+)", &["app"], None),
+                extractor(r"^(?P<db>\w+)-worker$", &["db"], Some("name")),
+            ]),
+            ..Default::default()
+        };
+        let extractors = LabelExtractors::from_config(&config);
+        assert_eq!(extractors.label_names(), ["db", "user", "app"]);
+        assert!(extractors.needs_cmdline());
+
+        let values = extractors
+            .extract(
+                "postgres",
+                Some("postgres: app orders 10.0.0.5(5432) idle"),
+                None,
+            )
+            .unwrap();
+        assert_eq!(&*values, ["orders", "app", ""]);
+
+        let values = extractors
+            .extract(
+                "java",
+                Some("java -Xmx2g -Dapp=billing-api -jar app.jar"),
+                None,
+            )
+            .unwrap();
+        assert_eq!(&*values, ["", "", "billing-api"]);
+
+        // Name extractors see the name even when the command line is known
+        let values = extractors
+            .extract("orders-worker", Some("/usr/bin/worker"), None)
+            .unwrap();
+        assert_eq!(&*values, ["orders", "", ""]);
+
+        assert!(extractors
+            .extract("nginx", Some("nginx: worker process"), None)
+            .is_none());
+        assert!(LabelExtractors::default()
+            .extract("postgres", None, None)
+            .is_none());
+    }
+
+    #[test]
+    fn test_environ_labels() {
+        let config = Config {
+            label_extractors: Some(vec![extractor(r"^(?P<app>\w+)", &["app"], Some("name"))]),
+            environ: Some(EnvironConfig {
+                variables: vec!["SERVICE_NAME".to_string(), "POD_NAME".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let extractors = LabelExtractors::from_config(&config);
+        assert_eq!(
+            extractors.label_names(),
+            ["app", "service_name", "pod_name"]
+        );
+        assert!(!extractors.needs_cmdline());
+
+        let environ = ["billing".to_string(), String::new()];
+        let values = extractors.extract("-", None, Some(&environ)).unwrap();
+        assert_eq!(&*values, ["", "billing", ""]);
+        assert!(extractors.extract("-", None, None).is_none());
+    }
+}
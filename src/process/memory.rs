@@ -1,13 +1,20 @@
 //! Memory parsing utilities for reading process memory metrics from /proc.
 //!
 //! This module provides functions to parse memory information from
-//! `/proc/<pid>/smaps` and `/proc/<pid>/smaps_rollup` files.
+//! `/proc/<pid>/smaps` and `/proc/<pid>/smaps_rollup` files, and the
+//! distribution of resident memory over NUMA nodes from `/proc/<pid>/numa_maps`.
 
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{BufRead, BufReader};
+use std::io::{ErrorKind, Read};
 use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use herakles_proc_mem_exporter::CollectError;
+
+use super::capabilities::CAPABILITIES;
+
 /// Static atomics for tracking maximum buffer usage across parse operations.
 /// These track the actual bytes read through each buffer type.
 pub static MAX_IO_BUFFER_BYTES: AtomicU64 = AtomicU64::new(0);
@@ -38,71 +45,161 @@ pub fn update_max_buffer_usage(current_max: &AtomicU64, new_value: u64) {
     }
 }
 
-/// Fast parser for /proc/<pid>/smaps_rollup (Linux >= 4.14).
-/// Much faster than reading the full smaps file.
-pub fn parse_smaps_rollup(path: &Path, buf_kb: usize) -> Result<(u64, u64, u64), std::io::Error> {
-    let file = fs::File::open(path)?;
-    let reader = BufReader::with_capacity(buf_kb * 1024, file);
-
-    let mut rss_kb = 0;
-    let mut pss_kb = 0;
-    let mut private_clean_kb = 0;
-    let mut private_dirty_kb = 0;
-    let mut bytes_read: u64 = 0;
-
-    for line in reader.lines() {
-        let l = line?;
-        bytes_read += l.len() as u64 + 1; // +1 for newline
-        if let Some(v) = l.strip_prefix("Rss:") {
-            rss_kb += parse_kb_value(v).unwrap_or(0);
+/// Reusable read buffers of one thread.
+#[derive(Default)]
+struct LineBuffers {
+    /// Raw file chunks; grows to the largest configured buffer size and stays there
+    chunk: Vec<u8>,
+    /// Current line, for lines spanning two chunks
+    line: Vec<u8>,
+}
+
+thread_local! {
+    /// Scans run on long-lived rayon threads, so each thread allocates its
+    /// buffers once instead of once per process.
+    static LINE_BUFFERS: RefCell<LineBuffers> = RefCell::new(LineBuffers::default());
+}
+
+/// Calls `f` for every line of the file at `path`, reading `buf_bytes` at a
+/// time through this thread's reusable buffers. Lines that are not valid
+/// UTF-8 (e.g. mapping paths) are skipped. Returns the number of bytes read.
+fn for_each_line(
+    path: &Path,
+    buf_bytes: usize,
+    mut f: impl FnMut(&str),
+) -> Result<u64, CollectError> {
+    let mut file = fs::File::open(path).map_err(|e| CollectError::io(path, e))?;
+
+    LINE_BUFFERS.with_borrow_mut(|buffers| {
+        let LineBuffers { chunk, line } = buffers;
+        let len = buf_bytes.max(1);
+        if chunk.len() < len {
+            chunk.resize(len, 0);
+        }
+        line.clear();
+
+        let mut emit = |line: &[u8]| {
+            if let Ok(l) = std::str::from_utf8(line) {
+                f(l);
+            }
+        };
+
+        let mut bytes_read: u64 = 0;
+        loop {
+            let n = match file.read(&mut chunk[..len]) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(CollectError::io(path, e)),
+            };
+            bytes_read += n as u64;
+
+            let mut rest = &chunk[..n];
+            while let Some(pos) = rest.iter().position(|&b| b == b'\n') {
+                if line.is_empty() {
+                    emit(&rest[..pos]);
+                } else {
+                    line.extend_from_slice(&rest[..pos]);
+                    emit(line);
+                    line.clear();
+                }
+                rest = &rest[pos + 1..];
+            }
+            line.extend_from_slice(rest);
+        }
+        if !line.is_empty() {
+            emit(line);
+        }
+        Ok(bytes_read)
+    })
+}
+
+/// Memory of a process in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryValues {
+    pub rss: u64,
+    pub pss: u64,
+    /// Private_Clean + Private_Dirty
+    pub uss: u64,
+    pub swap: u64,
+    pub locked: u64,
+    /// Anonymous pages, the rest of the RSS is file-backed
+    pub anonymous: u64,
+}
+
+/// Sums the Rss, Pss, Private_*, Swap, Locked and Anonymous fields of an
+/// smaps or smaps_rollup file.
+///
+/// Values are summed in bytes with overflow checks: some kernels report an
+/// underflowed Pss close to `u64::MAX` kB, which used to wrap around into
+/// nonsense. Such files are rejected as parse errors.
+///
+/// Returns the memory values and the number of bytes read.
+fn sum_memory_fields(path: &Path, buf_kb: usize) -> Result<(MemoryValues, u64), CollectError> {
+    // rss, pss, private (clean + dirty), swap, locked, anonymous
+    let mut sums = [0u64; 6];
+    let mut overflow = false;
+
+    let bytes_read = for_each_line(path, buf_kb.saturating_mul(1024), |l| {
+        let (field, v) = if let Some(v) = l.strip_prefix("Rss:") {
+            (0, v)
         } else if let Some(v) = l.strip_prefix("Pss:") {
-            pss_kb += parse_kb_value(v).unwrap_or(0);
-        } else if let Some(v) = l.strip_prefix("Private_Clean:") {
-            private_clean_kb += parse_kb_value(v).unwrap_or(0);
-        } else if let Some(v) = l.strip_prefix("Private_Dirty:") {
-            private_dirty_kb += parse_kb_value(v).unwrap_or(0);
+            (1, v)
+        } else if let Some(v) = l
+            .strip_prefix("Private_Clean:")
+            .or_else(|| l.strip_prefix("Private_Dirty:"))
+        {
+            (2, v)
+        } else if let Some(v) = l.strip_prefix("Swap:") {
+            (3, v)
+        } else if let Some(v) = l.strip_prefix("Locked:") {
+            (4, v)
+        } else if let Some(v) = l.strip_prefix("Anonymous:") {
+            (5, v)
+        } else {
+            return;
+        };
+        let bytes = parse_kb_value(v).unwrap_or(0).checked_mul(1024);
+        match bytes.and_then(|b| sums[field].checked_add(b)) {
+            Some(sum) => sums[field] = sum,
+            None => overflow = true,
         }
+    })?;
+
+    if overflow {
+        return Err(CollectError::parse(path, "memory value out of range"));
     }
+    let [rss, pss, uss, swap, locked, anonymous] = sums;
+    let memory = MemoryValues {
+        rss,
+        pss,
+        uss,
+        swap,
+        locked,
+        anonymous,
+    };
+    Ok((memory, bytes_read))
+}
+
+/// Fast parser for /proc/<pid>/smaps_rollup (Linux >= 4.14).
+/// Much faster than reading the full smaps file.
+pub fn parse_smaps_rollup(path: &Path, buf_kb: usize) -> Result<MemoryValues, CollectError> {
+    let (memory, bytes_read) = sum_memory_fields(path, buf_kb)?;
 
     // Update maximum buffer usage for smaps_rollup
     update_max_buffer_usage(&MAX_SMAPS_ROLLUP_BUFFER_BYTES, bytes_read);
 
-    Ok((
-        rss_kb * 1024,
-        pss_kb * 1024,
-        (private_clean_kb + private_dirty_kb) * 1024,
-    ))
+    Ok(memory)
 }
 
 /// Parses memory metrics from /proc/pid/smaps file.
-pub fn parse_smaps(path: &Path, buf_kb: usize) -> Result<(u64, u64, u64), std::io::Error> {
-    let file = fs::File::open(path)?;
-    let reader = BufReader::with_capacity(buf_kb * 1024, file);
-
-    let mut rss = 0;
-    let mut pss = 0;
-    let mut pc = 0;
-    let mut pd = 0;
-    let mut bytes_read: u64 = 0;
-
-    for line in reader.lines() {
-        let l = line?;
-        bytes_read += l.len() as u64 + 1; // +1 for newline
-        if let Some(kb) = l.strip_prefix("Rss:") {
-            rss += parse_kb_value(kb).unwrap_or(0);
-        } else if let Some(kb) = l.strip_prefix("Pss:") {
-            pss += parse_kb_value(kb).unwrap_or(0);
-        } else if let Some(kb) = l.strip_prefix("Private_Clean:") {
-            pc += parse_kb_value(kb).unwrap_or(0);
-        } else if let Some(kb) = l.strip_prefix("Private_Dirty:") {
-            pd += parse_kb_value(kb).unwrap_or(0);
-        }
-    }
+pub fn parse_smaps(path: &Path, buf_kb: usize) -> Result<MemoryValues, CollectError> {
+    let (memory, bytes_read) = sum_memory_fields(path, buf_kb)?;
 
     // Update maximum buffer usage for smaps
     update_max_buffer_usage(&MAX_SMAPS_BUFFER_BYTES, bytes_read);
 
-    Ok((rss * 1024, pss * 1024, (pc + pd) * 1024))
+    Ok(memory)
 }
 
 /// Parses kilobyte values from smaps file lines.
@@ -110,15 +207,44 @@ pub fn parse_kb_value(v: &str) -> Option<u64> {
     v.split_whitespace().next()?.parse().ok()
 }
 
+/// Sums the resident pages of each NUMA node over the mappings of a
+/// /proc/<pid>/numa_maps file, in bytes by node number.
+///
+/// Each mapping lists its pages per node as `N<node>=<pages>`, in units of
+/// its `kernelpagesize_kB`, so huge page mappings are weighted correctly.
+pub fn parse_numa_maps(path: &Path, buf_kb: usize) -> Result<Vec<(u32, u64)>, CollectError> {
+    let mut nodes: BTreeMap<u32, u64> = BTreeMap::new();
+    for_each_line(path, buf_kb.saturating_mul(1024), |l| {
+        let page_kb = l
+            .split_whitespace()
+            .find_map(|t| t.strip_prefix("kernelpagesize_kB="))
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(4);
+        for (node, pages) in l.split_whitespace().filter_map(|t| {
+            let (node, pages) = t.strip_prefix('N')?.split_once('=')?;
+            Some((node.parse::<u32>().ok()?, pages.parse::<u64>().ok()?))
+        }) {
+            let bytes = pages.saturating_mul(page_kb).saturating_mul(1024);
+            let sum = nodes.entry(node).or_default();
+            *sum = sum.saturating_add(bytes);
+        }
+    })?;
+    Ok(nodes.into_iter().collect())
+}
+
 /// Wrapper that selects the fastest available memory parser.
-/// Uses smaps_rollup when available, otherwise falls back to full smaps.
+/// Uses smaps_rollup when the kernel provides it (see `capabilities`),
+/// otherwise falls back to full smaps.
 pub fn parse_memory_for_process(
     proc_path: &Path,
     buffers: &BufferConfig,
-) -> Result<(u64, u64, u64), std::io::Error> {
-    let rollup = proc_path.join("smaps_rollup");
-    if rollup.exists() {
-        return parse_smaps_rollup(&rollup, buffers.smaps_rollup_kb);
+) -> Result<MemoryValues, CollectError> {
+    if CAPABILITIES.smaps_rollup() {
+        let rollup = proc_path.join("smaps_rollup");
+        match parse_smaps_rollup(&rollup, buffers.smaps_rollup_kb) {
+            Err(e) if e.is_not_found() => {}
+            result => return result,
+        }
     }
 
     let smaps = proc_path.join("smaps");
@@ -129,10 +255,211 @@ pub fn parse_memory_for_process(
 mod tests {
     use super::*;
 
+    // -------------------------------------------------------------------------
+    // Tests for parse_smaps
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_parse_smaps_reuses_buffers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smaps");
+        let mapping = "7f0000000000-7f0000001000 r-xp 00000000 08:01 42 /usr/lib/libc.so\n\
+                       Rss:                 8 kB\n\
+                       Pss:                 4 kB\n\
+                       Private_Clean:       1 kB\n\
+                       Private_Dirty:       2 kB\n\
+                       Swap:                5 kB\n\
+                       SwapPss:             3 kB\n\
+                       Locked:              2 kB\n";
+        // No trailing newline on the last line
+        let content = format!("{}{}", mapping.repeat(50), mapping.trim_end());
+        fs::write(&path, &content).unwrap();
+
+        // Lines span many chunks with a tiny buffer; the result must not depend on it
+        for buf_bytes in [7, 64, 1024 * 1024] {
+            let mut lines = 0;
+            let bytes = for_each_line(&path, buf_bytes, |_| lines += 1).unwrap();
+            assert_eq!(bytes, content.len() as u64);
+            assert_eq!(lines, 51 * 8);
+        }
+        assert_eq!(
+            parse_smaps(&path, 1).unwrap(),
+            MemoryValues {
+                rss: 51 * 8 * 1024,
+                pss: 51 * 4 * 1024,
+                uss: 51 * 3 * 1024,
+                swap: 51 * 5 * 1024,
+                locked: 51 * 2 * 1024,
+                anonymous: 0,
+            }
+        );
+    }
+
+    /// smaps of a process on a 32-bit ARM kernel (armv7l).
+    const SMAPS_ARMV7: &str = "\
+00010000-00011000 r-xp 00000000 b3:02 1234       /usr/bin/gateway
+Size:                  4 kB
+Rss:                   4 kB
+Pss:                   2 kB
+Shared_Clean:          4 kB
+Shared_Dirty:          0 kB
+Private_Clean:         0 kB
+Private_Dirty:         0 kB
+Referenced:            4 kB
+Anonymous:             0 kB
+Swap:                  0 kB
+SwapPss:               0 kB
+KernelPageSize:        4 kB
+MMUPageSize:           4 kB
+Locked:                0 kB
+VmFlags: rd ex mr mw me dw
+b6f00000-b6f21000 rw-p 00000000 00:00 0          [heap]
+Size:                132 kB
+Rss:                 120 kB
+Pss:                 120 kB
+Private_Clean:         0 kB
+Private_Dirty:       120 kB
+Anonymous:           120 kB
+Swap:                 12 kB
+ffff0000-ffff1000 r-xp 00000000 00:00 0          [vectors]
+Rss:                   4 kB
+Pss:                   0 kB
+Private_Clean:         0 kB
+Private_Dirty:         0 kB
+Swap:                  0 kB
+";
+
+    /// smaps of a 32-bit (compat) process on an arm64 kernel: 32-bit
+    /// addresses, but the fields of a current kernel.
+    const SMAPS_ARM64_COMPAT: &str = "\
+00400000-00401000 r-xp 00000000 b3:02 1234       /usr/bin/gateway
+Size:                  4 kB
+KernelPageSize:        4 kB
+MMUPageSize:           4 kB
+Rss:                   4 kB
+Pss:                   4 kB
+Pss_Dirty:             0 kB
+Shared_Clean:          0 kB
+Shared_Dirty:          0 kB
+Private_Clean:         4 kB
+Private_Dirty:         0 kB
+Referenced:            4 kB
+Anonymous:             0 kB
+KSM:                   0 kB
+LazyFree:              0 kB
+AnonHugePages:         0 kB
+Swap:                  0 kB
+SwapPss:               0 kB
+Locked:                0 kB
+THPeligible:           0
+VmFlags: rd ex mr mw me
+f7d00000-f7e00000 rw-p 00000000 00:00 0
+Size:               1024 kB
+KernelPageSize:        4 kB
+MMUPageSize:           4 kB
+Rss:                 512 kB
+Pss:                 512 kB
+Pss_Dirty:           512 kB
+Private_Clean:         0 kB
+Private_Dirty:       512 kB
+Anonymous:           512 kB
+Swap:                 64 kB
+SwapPss:              64 kB
+THPeligible:           1
+";
+
+    /// smaps_rollup of a 64-bit process on x86_64.
+    const SMAPS_ROLLUP_X86_64: &str = "\
+55d0c0a00000-7ffd3e5f2000 ---p 00000000 00:00 0                          [rollup]
+Rss:               51200 kB
+Pss:               20480 kB
+Pss_Dirty:         10240 kB
+Pss_Anon:          10240 kB
+Pss_File:          10240 kB
+Pss_Shmem:             0 kB
+Shared_Clean:      30720 kB
+Shared_Dirty:          0 kB
+Private_Clean:      2048 kB
+Private_Dirty:      8192 kB
+Referenced:        51200 kB
+Anonymous:         10240 kB
+LazyFree:              0 kB
+AnonHugePages:         0 kB
+ShmemPmdMapped:        0 kB
+FilePmdMapped:         0 kB
+Shared_Hugetlb:        0 kB
+Private_Hugetlb:       0 kB
+Swap:               1024 kB
+SwapPss:            1024 kB
+Locked:              512 kB
+";
+
+    #[test]
+    fn test_parse_smaps_architectures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smaps");
+        for (arch, content, expected) in [
+            ("armv7", SMAPS_ARMV7, (128, 122, 120, 12, 0, 120)),
+            (
+                "arm64 compat",
+                SMAPS_ARM64_COMPAT,
+                (516, 516, 516, 64, 0, 512),
+            ),
+            (
+                "x86_64 rollup",
+                SMAPS_ROLLUP_X86_64,
+                (51200, 20480, 10240, 1024, 512, 10240),
+            ),
+        ] {
+            fs::write(&path, content).unwrap();
+            let kb = |m: MemoryValues| {
+                (
+                    m.rss / 1024,
+                    m.pss / 1024,
+                    m.uss / 1024,
+                    m.swap / 1024,
+                    m.locked / 1024,
+                    m.anonymous / 1024,
+                )
+            };
+            assert_eq!(kb(parse_smaps(&path, 4).unwrap()), expected, "{arch}");
+        }
+
+        // An underflowed Pss must not wrap around into a small value
+        fs::write(&path, "Rss: 8 kB\nPss: 18446744073709551615 kB\n").unwrap();
+        assert_eq!(parse_smaps(&path, 4).unwrap_err().category(), "parse");
+        fs::write(&path, "Pss: 18014398509481983 kB\nPss: 1 kB\n").unwrap();
+        assert_eq!(parse_smaps(&path, 4).unwrap_err().category(), "parse");
+    }
+
     // -------------------------------------------------------------------------
     // Tests for parse_kb_value
     // -------------------------------------------------------------------------
 
+    #[test]
+    fn test_parse_numa_maps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("numa_maps");
+        fs::write(
+            &path,
+            "55d4c8a00000 default file=/usr/bin/postgres mapped=120 active=0 N0=100 N1=20 kernelpagesize_kB=4\n\
+             7f2a00000000 bind:1 anon=512 dirty=512 N1=512 kernelpagesize_kB=4\n\
+             7f4000000000 default file=/anon_hugepage\\040(deleted) huge dirty=2 N0=2 kernelpagesize_kB=2048\n\
+             7ffc1e000000 default stack anon=3 dirty=3 N0=3\n\
+             7ffc1f000000 default\n",
+        )
+        .unwrap();
+
+        let nodes = parse_numa_maps(&path, 4).unwrap();
+        assert_eq!(
+            nodes,
+            vec![
+                (0, (100 + 3) * 4096 + 2 * 2048 * 1024),
+                (1, (20 + 512) * 4096),
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_kb_value() {
         // Standard smaps format with trailing "kB"
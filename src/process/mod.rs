@@ -3,19 +3,41 @@
 //! This module provides:
 //! - `memory`: Memory parsing from /proc/<pid>/smaps
 //! - `cpu`: CPU time parsing and statistics
+//! - `stat`: /proc/<pid>/stat, /proc/<pid>/io, /proc/<pid>/environ,
+//!   /proc/<pid>/status and /proc/<pid>/wchan parsing, I/O priority
 //! - `scanner`: Process discovery and filtering
 //! - `classifier`: Process grouping and classification
+//! - `extractor`: Extra labels parsed from process names and command lines
+//! - `capabilities`: Probing of optional /proc features
+//! - `taskstats`: Block IO and swap-in delays over taskstats netlink
 
+pub mod capabilities;
 pub mod classifier;
 pub mod cpu;
+pub mod extractor;
 pub mod memory;
 pub mod scanner;
+pub mod stat;
+pub mod taskstats;
 
 // Re-export commonly used types
-pub use classifier::{classify_process_raw, classify_process_with_config, SUBGROUPS};
-pub use cpu::{get_cpu_stat_for_pid, CpuEntry, CpuStat, CLK_TCK};
+pub use capabilities::{probe_capabilities, CapabilitySnapshot, CAPABILITIES};
+pub use classifier::{
+    classify_process_raw, classify_process_with_config, rule_stats, RuleStats, SUBGROUPS,
+};
+pub use cpu::{update_cpu_stat, CpuEntry, CpuStat, CLK_TCK};
+pub use extractor::LabelExtractors;
 pub use memory::{
-    parse_memory_for_process, BufferConfig, MAX_IO_BUFFER_BYTES, MAX_SMAPS_BUFFER_BYTES,
-    MAX_SMAPS_ROLLUP_BUFFER_BYTES,
+    parse_memory_for_process, parse_numa_maps, BufferConfig, MemoryValues, MAX_IO_BUFFER_BYTES,
+    MAX_SMAPS_BUFFER_BYTES, MAX_SMAPS_ROLLUP_BUFFER_BYTES,
+};
+pub use scanner::{
+    collect_proc_entries, read_process_cmdline, read_process_name, should_include_process,
+    MaxProcessesBy, ProcEntryChunks,
+};
+pub use stat::{
+    read_io_priority, read_proc_environ, read_proc_io, read_proc_privileges, read_proc_schedstat,
+    read_proc_shmem, read_proc_stat, read_proc_threads, read_proc_wchan, IoPriority, Privileges,
+    ProcStat, SchedStat, ThreadStat,
 };
-pub use scanner::{collect_proc_entries, read_process_name, should_include_process};
+pub use taskstats::{delayacct_enabled, DelayStats, TaskstatsPool};
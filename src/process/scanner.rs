@@ -5,9 +5,14 @@
 
 use crate::config::Config;
 use crate::process::memory::{update_max_buffer_usage, MAX_IO_BUFFER_BYTES};
+use rayon::prelude::*;
+use std::cmp::Reverse;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Number of /proc entries handed to a scan worker at once.
+pub const PROC_CHUNK_SIZE: usize = 32;
+
 /// Process entry representing a directory in /proc filesystem.
 #[derive(Debug, Clone)]
 pub struct ProcEntry {
@@ -15,35 +20,168 @@ pub struct ProcEntry {
     pub proc_path: PathBuf,
 }
 
-/// Scans /proc directory for process entries with numeric PIDs.
-pub fn collect_proc_entries(root: &str, max: Option<usize>) -> Vec<ProcEntry> {
-    let mut out = Vec::new();
-    if let Ok(entries) = fs::read_dir(root) {
-        for entry in entries.flatten() {
-            let p = entry.path();
-            let name = match p.file_name().and_then(|s| s.to_str()) {
-                Some(v) => v,
-                None => continue,
-            };
-            if !name.chars().all(|c| c.is_ascii_digit()) {
-                continue;
-            }
-            if !p.join("smaps").exists() && !p.join("smaps_rollup").exists() {
-                continue;
+impl ProcEntry {
+    /// True if the memory maps of the process can be scanned.
+    pub fn is_scannable(&self) -> bool {
+        self.proc_path.join("smaps").exists() || self.proc_path.join("smaps_rollup").exists()
+    }
+}
+
+/// Which processes `max_processes` keeps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MaxProcessesBy {
+    /// Lowest PIDs first
+    #[default]
+    Pid,
+    /// Largest resident set size from /proc/<pid>/statm first
+    Rss,
+}
+
+impl MaxProcessesBy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pid" => Some(Self::Pid),
+            "rss" => Some(Self::Rss),
+            _ => None,
+        }
+    }
+
+    /// The configured selection, lowest PIDs if unset.
+    pub fn from_config(cfg: &Config) -> Self {
+        cfg.max_processes_by
+            .as_deref()
+            .and_then(Self::parse)
+            .unwrap_or_default()
+    }
+}
+
+/// PID of a /proc directory entry name, None for non-process entries.
+fn parse_pid(name: &str) -> Option<u32> {
+    if name.is_empty() || !name.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    name.parse().ok()
+}
+
+/// Resident pages from /proc/<pid>/statm, 0 if unreadable.
+fn read_statm_resident(proc_path: &Path) -> u64 {
+    fs::read_to_string(proc_path.join("statm"))
+        .ok()
+        .and_then(|s| s.split_whitespace().nth(1)?.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Keeps the `max` processes selected by `by`, in PID order.
+fn select_pids(root: &Path, mut pids: Vec<u32>, max: usize, by: MaxProcessesBy) -> Vec<u32> {
+    pids.sort_unstable();
+    if pids.len() <= max {
+        return pids;
+    }
+    match by {
+        MaxProcessesBy::Pid => pids.truncate(max),
+        MaxProcessesBy::Rss => {
+            let mut sized: Vec<(u64, u32)> = pids
+                .par_iter()
+                .map(|&pid| (read_statm_resident(&root.join(pid.to_string())), pid))
+                .collect();
+            sized.sort_unstable_by_key(|&(rss, pid)| (Reverse(rss), pid));
+            pids = sized.into_iter().take(max).map(|(_, pid)| pid).collect();
+            pids.sort_unstable();
+        }
+    }
+    pids
+}
+
+enum ChunkSource {
+    /// Unbounded scan, streamed from the directory listing
+    Listing(fs::ReadDir),
+    /// `max_processes` selection, made from the full PID list
+    Selected(std::vec::IntoIter<u32>),
+    Empty,
+}
+
+/// Process entries of /proc in chunks of [`PROC_CHUNK_SIZE`].
+///
+/// Without a limit the directory is read as the chunks are consumed, so scan
+/// workers start on the first chunk while the rest is still listed. Entries
+/// are not checked with [`ProcEntry::is_scannable`]; workers do that in
+/// parallel.
+pub struct ProcEntryChunks {
+    root: PathBuf,
+    source: ChunkSource,
+    chunk_size: usize,
+    /// Processes left out by `max`
+    capped: usize,
+}
+
+impl ProcEntryChunks {
+    /// Lists `root`. With `max`, all PIDs are listed first (names only, no
+    /// per-process reads) and the `max` selected by `by` are kept.
+    pub fn new(root: &str, max: Option<usize>, by: MaxProcessesBy) -> Self {
+        let root = PathBuf::from(root);
+        let mut capped = 0;
+        let source = match (fs::read_dir(&root), max) {
+            (Err(_), _) => ChunkSource::Empty,
+            (Ok(listing), None) => ChunkSource::Listing(listing),
+            (Ok(listing), Some(max)) => {
+                let pids: Vec<u32> = listing
+                    .flatten()
+                    .filter_map(|e| parse_pid(e.file_name().to_str()?))
+                    .collect();
+                capped = pids.len().saturating_sub(max);
+                ChunkSource::Selected(select_pids(&root, pids, max, by).into_iter())
             }
-            let pid: u32 = match name.parse() {
-                Ok(v) => v,
-                Err(_) => continue,
+        };
+        Self {
+            root,
+            source,
+            chunk_size: PROC_CHUNK_SIZE,
+            capped,
+        }
+    }
+
+    /// Number of processes left out by `max_processes`.
+    pub fn capped(&self) -> usize {
+        self.capped
+    }
+
+    fn next_pid(&mut self) -> Option<u32> {
+        match &mut self.source {
+            ChunkSource::Listing(listing) => listing
+                .flatten()
+                .find_map(|e| parse_pid(e.file_name().to_str()?)),
+            ChunkSource::Selected(pids) => pids.next(),
+            ChunkSource::Empty => None,
+        }
+    }
+}
+
+impl Iterator for ProcEntryChunks {
+    type Item = Vec<ProcEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+        while chunk.len() < self.chunk_size {
+            let Some(pid) = self.next_pid() else {
+                break;
             };
-            out.push(ProcEntry { pid, proc_path: p });
-            if let Some(maxp) = max {
-                if out.len() >= maxp {
-                    break;
-                }
-            }
+            chunk.push(ProcEntry {
+                pid,
+                proc_path: self.root.join(pid.to_string()),
+            });
         }
+        (!chunk.is_empty()).then_some(chunk)
     }
-    out
+}
+
+/// Scans /proc directory for process entries with numeric PIDs.
+///
+/// Use [`ProcEntryChunks`] to overlap listing with scanning.
+pub fn collect_proc_entries(root: &str, max: Option<usize>, by: MaxProcessesBy) -> Vec<ProcEntry> {
+    ProcEntryChunks::new(root, max, by)
+        .flatten()
+        .filter(ProcEntry::is_scannable)
+        .collect()
 }
 
 /// Reads process name from comm file or extracts from cmdline.
@@ -77,6 +215,17 @@ pub fn read_process_name(proc_path: &Path) -> Option<String> {
     None
 }
 
+/// Reads /proc/<pid>/cmdline with arguments separated by spaces.
+///
+/// Returns None for kernel threads and unreadable or non-UTF-8 command lines.
+pub fn read_process_cmdline(proc_path: &Path) -> Option<String> {
+    let content = fs::read(proc_path.join("cmdline")).ok()?;
+    update_max_buffer_usage(&MAX_IO_BUFFER_BYTES, content.len() as u64);
+    let cmdline = String::from_utf8(content).ok()?;
+    let cmdline = cmdline.trim_end_matches('\0').replace('\0', " ");
+    (!cmdline.is_empty()).then_some(cmdline)
+}
+
 /// Determines if a process should be included based on configuration filters.
 pub fn should_include_process(name: &str, cfg: &Config) -> bool {
     if let Some(ex) = &cfg.exclude_names {
@@ -96,6 +245,69 @@ pub fn should_include_process(name: &str, cfg: &Config) -> bool {
 mod tests {
     use super::*;
 
+    fn fake_proc(processes: &[(u32, u64)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (pid, rss) in processes {
+            let path = dir.path().join(pid.to_string());
+            fs::create_dir(&path).unwrap();
+            fs::write(path.join("smaps_rollup"), "").unwrap();
+            fs::write(path.join("statm"), format!("1000 {} 0 0 0 0 0\n", rss)).unwrap();
+        }
+        fs::create_dir(dir.path().join("sys")).unwrap();
+        fs::create_dir(dir.path().join("77")).unwrap();
+        dir
+    }
+
+    fn pids(root: &Path, max: Option<usize>, by: MaxProcessesBy) -> Vec<u32> {
+        let mut pids: Vec<u32> = ProcEntryChunks::new(root.to_str().unwrap(), max, by)
+            .flatten()
+            .filter(ProcEntry::is_scannable)
+            .map(|e| e.pid)
+            .collect();
+        pids.sort_unstable();
+        pids
+    }
+
+    #[test]
+    fn test_proc_entry_chunks() {
+        let processes: Vec<(u32, u64)> = (1..=100).map(|pid| (pid * 10, 0)).collect();
+        let dir = fake_proc(&processes);
+        let root = dir.path().to_str().unwrap();
+
+        let chunks: Vec<Vec<ProcEntry>> =
+            ProcEntryChunks::new(root, None, MaxProcessesBy::Pid).collect();
+        assert!(chunks.iter().all(|c| c.len() <= PROC_CHUNK_SIZE));
+        // 77 is listed but has no memory maps; "sys" is not a process
+        assert_eq!(chunks.iter().map(Vec::len).sum::<usize>(), 101);
+        assert_eq!(pids(dir.path(), None, MaxProcessesBy::Pid).len(), 100);
+        assert_eq!(
+            collect_proc_entries(root, None, MaxProcessesBy::Pid).len(),
+            100
+        );
+    }
+
+    #[test]
+    fn test_max_processes_selection() {
+        let dir = fake_proc(&[(300, 5), (20, 1), (1000, 50), (4, 0), (55, 50)]);
+        assert_eq!(pids(dir.path(), Some(3), MaxProcessesBy::Pid), [4, 20, 55]);
+        // Equal RSS is decided by the lower PID
+        assert_eq!(
+            pids(dir.path(), Some(3), MaxProcessesBy::Rss),
+            [55, 300, 1000]
+        );
+        assert_eq!(pids(dir.path(), Some(10), MaxProcessesBy::Rss).len(), 5);
+
+        let root = dir.path().to_str().unwrap();
+        assert_eq!(
+            ProcEntryChunks::new(root, Some(3), MaxProcessesBy::Rss).capped(),
+            3
+        );
+        assert_eq!(
+            ProcEntryChunks::new(root, None, MaxProcessesBy::Pid).capped(),
+            0
+        );
+    }
+
     // -------------------------------------------------------------------------
     // Tests for should_include_process
     // -------------------------------------------------------------------------
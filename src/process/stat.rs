@@ -0,0 +1,548 @@
+//! Parsing of /proc/<pid>/stat, /proc/<pid>/io, /proc/<pid>/environ and the
+//! privileges in /proc/<pid>/status, and the I/O priority, which /proc does
+//! not expose.
+//!
+//! The command name in /proc/<pid>/stat is enclosed in parentheses and may
+//! itself contain spaces and parentheses, so fields are counted from the last
+//! closing parenthesis instead of splitting the whole line.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use herakles_proc_mem_exporter::CollectError;
+
+use super::memory::{update_max_buffer_usage, MAX_IO_BUFFER_BYTES};
+
+/// Selected fields of /proc/<pid>/stat.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcStat {
+    pub state: char,
+    pub ppid: i32,
+    /// Process group ID
+    pub pgrp: i32,
+    /// Session ID
+    pub session: i32,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    /// User time in clock ticks
+    pub utime: u64,
+    /// System time in clock ticks
+    pub stime: u64,
+    /// Nice value (-20 to 19)
+    pub nice: i32,
+    pub num_threads: i64,
+    /// Start time after boot in clock ticks
+    pub start_time: u64,
+    /// Scheduling policy (SCHED_* constant), 0 on kernels before 2.5.19
+    pub policy: u32,
+    /// Time spent running a virtual CPU of a guest in clock ticks, part of
+    /// utime; 0 on kernels before 2.6.24
+    pub guest_time: u64,
+}
+
+impl ProcStat {
+    /// Total CPU time (user + system) in clock ticks.
+    pub fn cpu_ticks(&self) -> u64 {
+        self.utime + self.stime
+    }
+}
+
+/// Parses the content of /proc/<pid>/stat.
+///
+/// Returns None if the line is truncated before the stime field.
+pub fn parse_stat(content: &str) -> Option<ProcStat> {
+    let after_comm = &content[content.rfind(')')? + 1..];
+    // Field 3 (state) is the first entry after the command name
+    let fields: Vec<&str> = after_comm.split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3).copied();
+
+    Some(ProcStat {
+        state: field(3)?.chars().next()?,
+        ppid: field(4)?.parse().ok()?,
+        pgrp: field(5)?.parse().ok()?,
+        session: field(6)?.parse().ok()?,
+        minor_faults: field(10)?.parse().ok()?,
+        major_faults: field(12)?.parse().ok()?,
+        utime: field(14)?.parse().ok()?,
+        stime: field(15)?.parse().ok()?,
+        nice: field(19).and_then(|v| v.parse().ok()).unwrap_or(0),
+        num_threads: field(20).and_then(|v| v.parse().ok()).unwrap_or(0),
+        start_time: field(22).and_then(|v| v.parse().ok()).unwrap_or(0),
+        policy: field(41).and_then(|v| v.parse().ok()).unwrap_or(0),
+        guest_time: field(43).and_then(|v| v.parse().ok()).unwrap_or(0),
+    })
+}
+
+/// Reads and parses /proc/<pid>/stat.
+pub fn read_proc_stat(proc_path: &Path) -> Result<ProcStat, CollectError> {
+    let path = proc_path.join("stat");
+    let content = fs::read_to_string(&path).map_err(|e| CollectError::io(&path, e))?;
+    parse_stat(&content).ok_or_else(|| CollectError::parse(path, "invalid stat format"))
+}
+
+/// Storage and syscall counters from /proc/<pid>/io.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcIo {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    /// read(2)-like syscalls (`syscr`)
+    pub read_syscalls: u64,
+    /// write(2)-like syscalls (`syscw`)
+    pub write_syscalls: u64,
+}
+
+/// Reads storage read/write byte and syscall counters from /proc/<pid>/io.
+pub fn read_proc_io(proc_path: &Path, buf_kb: usize) -> Result<ProcIo, CollectError> {
+    let path = proc_path.join("io");
+    let file = fs::File::open(&path).map_err(|e| CollectError::io(&path, e))?;
+    let reader = BufReader::with_capacity(buf_kb.saturating_mul(1024), file);
+
+    let mut read_bytes = None;
+    let mut write_bytes = None;
+    let mut read_syscalls = None;
+    let mut write_syscalls = None;
+    let mut bytes_read: u64 = 0;
+
+    for line in reader.lines() {
+        let l = line.map_err(|e| CollectError::io(&path, e))?;
+        bytes_read += l.len() as u64 + 1;
+        if let Some(v) = l.strip_prefix("read_bytes:") {
+            read_bytes = v.trim().parse().ok();
+        } else if let Some(v) = l.strip_prefix("write_bytes:") {
+            write_bytes = v.trim().parse().ok();
+        } else if let Some(v) = l.strip_prefix("syscr:") {
+            read_syscalls = v.trim().parse().ok();
+        } else if let Some(v) = l.strip_prefix("syscw:") {
+            write_syscalls = v.trim().parse().ok();
+        }
+    }
+
+    update_max_buffer_usage(&MAX_IO_BUFFER_BYTES, bytes_read);
+
+    match (read_bytes, write_bytes, read_syscalls, write_syscalls) {
+        (Some(read_bytes), Some(write_bytes), Some(read_syscalls), Some(write_syscalls)) => {
+            Ok(ProcIo {
+                read_bytes,
+                write_bytes,
+                read_syscalls,
+                write_syscalls,
+            })
+        }
+        _ => Err(CollectError::parse(
+            path,
+            "missing read_bytes, write_bytes, syscr or syscw",
+        )),
+    }
+}
+
+/// Reads the values of `variables` from /proc/<pid>/environ, in order.
+///
+/// Unset variables are empty. Other variables are discarded while parsing and
+/// never logged. Reading another user's environment needs CAP_SYS_PTRACE.
+pub fn read_proc_environ(
+    proc_path: &Path,
+    variables: &[String],
+) -> Result<Vec<String>, CollectError> {
+    let path = proc_path.join("environ");
+    let content = fs::read(&path).map_err(|e| CollectError::io(&path, e))?;
+    update_max_buffer_usage(&MAX_IO_BUFFER_BYTES, content.len() as u64);
+    Ok(parse_environ(&content, variables))
+}
+
+/// Effective UID and capabilities of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Privileges {
+    pub euid: u32,
+    /// CapEff bit mask
+    pub cap_eff: u64,
+}
+
+/// All capabilities known to the running kernel.
+static FULL_CAPABILITIES: Lazy<u64> = Lazy::new(|| {
+    let last_cap = fs::read_to_string("/proc/sys/kernel/cap_last_cap")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        // CAP_CHECKPOINT_RESTORE, the last one since Linux 5.9
+        .unwrap_or(40);
+    capability_mask(last_cap)
+});
+
+fn capability_mask(last_cap: u32) -> u64 {
+    u64::MAX >> (63 - last_cap.min(63))
+}
+
+impl Privileges {
+    pub fn is_root(self) -> bool {
+        self.euid == 0
+    }
+
+    /// Whether every capability of the running kernel is effective, as for
+    /// root or a privileged container.
+    pub fn has_full_capabilities(self) -> bool {
+        self.cap_eff & *FULL_CAPABILITIES == *FULL_CAPABILITIES
+    }
+}
+
+/// Reads the effective UID and CapEff from /proc/<pid>/status.
+pub fn read_proc_privileges(proc_path: &Path) -> Result<Privileges, CollectError> {
+    let path = proc_path.join("status");
+    let content = fs::read_to_string(&path).map_err(|e| CollectError::io(&path, e))?;
+    parse_privileges(&content).ok_or_else(|| CollectError::parse(&path, "missing Uid or CapEff"))
+}
+
+fn parse_privileges(content: &str) -> Option<Privileges> {
+    let mut euid = None;
+    let mut cap_eff = None;
+    for line in content.lines() {
+        if let Some(uids) = line.strip_prefix("Uid:") {
+            // Real, effective, saved set and filesystem UID
+            euid = uids.split_whitespace().nth(1).and_then(|u| u.parse().ok());
+        } else if let Some(caps) = line.strip_prefix("CapEff:") {
+            cap_eff = u64::from_str_radix(caps.trim(), 16).ok();
+        }
+    }
+    Some(Privileges {
+        euid: euid?,
+        cap_eff: cap_eff?,
+    })
+}
+
+/// Reads the resident shared memory (RssShmem) from /proc/<pid>/status, in
+/// bytes; 0 for kernel threads, which have no memory fields.
+pub fn read_proc_shmem(proc_path: &Path) -> Result<u64, CollectError> {
+    let path = proc_path.join("status");
+    let content = fs::read_to_string(&path).map_err(|e| CollectError::io(&path, e))?;
+    Ok(parse_shmem(&content).unwrap_or(0))
+}
+
+fn parse_shmem(content: &str) -> Option<u64> {
+    let kb: u64 = content
+        .lines()
+        .find_map(|line| line.strip_prefix("RssShmem:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// Scheduler statistics of the main thread from /proc/<pid>/schedstat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedStat {
+    /// Time spent running on a CPU
+    pub run_ns: u64,
+    /// Time spent runnable, waiting on a runqueue
+    pub wait_ns: u64,
+    /// Timeslices run on a CPU
+    pub timeslices: u64,
+}
+
+/// Reads run time, runqueue wait time and timeslices from /proc/<pid>/schedstat.
+pub fn read_proc_schedstat(proc_path: &Path) -> Result<SchedStat, CollectError> {
+    let path = proc_path.join("schedstat");
+    let content = fs::read_to_string(&path).map_err(|e| CollectError::io(&path, e))?;
+    parse_schedstat(&content).ok_or_else(|| CollectError::parse(&path, "expected 3 counters"))
+}
+
+fn parse_schedstat(content: &str) -> Option<SchedStat> {
+    let mut fields = content.split_whitespace().map(|f| f.parse::<u64>().ok());
+    Some(SchedStat {
+        run_ns: fields.next()??,
+        wait_ns: fields.next()??,
+        timeslices: fields.next()??,
+    })
+}
+
+/// A thread of a process from /proc/<pid>/task/<tid>/stat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadStat {
+    pub tid: u32,
+    /// Thread name (comm), as set with prctl(PR_SET_NAME)
+    pub name: String,
+    pub stat: ProcStat,
+}
+
+/// Reads the stat file of every thread of a process.
+///
+/// Threads that exit while the task directory is listed are left out.
+pub fn read_proc_threads(proc_path: &Path) -> Result<Vec<ThreadStat>, CollectError> {
+    let task_path = proc_path.join("task");
+    let entries = fs::read_dir(&task_path).map_err(|e| CollectError::io(&task_path, e))?;
+    let mut threads = Vec::new();
+    for entry in entries.flatten() {
+        let Some(tid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+            continue;
+        };
+        let Ok(content) = fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        if let (Some(name), Some(stat)) = (parse_stat_comm(&content), parse_stat(&content)) {
+            threads.push(ThreadStat {
+                tid,
+                name: name.to_string(),
+                stat,
+            });
+        }
+    }
+    Ok(threads)
+}
+
+/// Command name of /proc/<pid>/stat content, between the first opening and
+/// the last closing parenthesis.
+fn parse_stat_comm(content: &str) -> Option<&str> {
+    content.get(content.find('(')? + 1..content.rfind(')')?)
+}
+
+/// Reads the kernel function a process sleeps in from /proc/<pid>/wchan.
+///
+/// None if the process is running or the kernel hides the symbol, which it
+/// does ("0") without ptrace access to the process.
+pub fn read_proc_wchan(proc_path: &Path) -> Result<Option<String>, CollectError> {
+    let path = proc_path.join("wchan");
+    let content = fs::read_to_string(&path).map_err(|e| CollectError::io(&path, e))?;
+    let wchan = content.trim();
+    Ok((!wchan.is_empty() && wchan != "0").then(|| wchan.to_string()))
+}
+
+/// I/O scheduling class and level of a process, as set with `ionice`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoPriority {
+    pub class: u8,
+    pub level: u8,
+}
+
+impl IoPriority {
+    /// Decodes an ioprio value: class in bits 13-15, level in bits 0-2.
+    fn from_raw(raw: i32) -> Self {
+        Self {
+            class: ((raw >> 13) & 0x7) as u8,
+            level: (raw & 0x7) as u8,
+        }
+    }
+
+    pub fn class_name(self) -> &'static str {
+        match self.class {
+            // Derived from the nice value by the kernel
+            0 => "none",
+            1 => "realtime",
+            2 => "best-effort",
+            3 => "idle",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Reads the I/O priority of `pid` with ioprio_get(2); None if the process
+/// is gone.
+pub fn read_io_priority(pid: u32) -> Option<IoPriority> {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    // SAFETY: ioprio_get takes two integers and touches no memory
+    let raw = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_get,
+            IOPRIO_WHO_PROCESS,
+            pid as libc::c_long,
+        )
+    };
+    (raw >= 0).then(|| IoPriority::from_raw(raw as i32))
+}
+
+fn parse_environ(content: &[u8], variables: &[String]) -> Vec<String> {
+    let mut values = vec![String::new(); variables.len()];
+    for entry in content.split(|&b| b == 0) {
+        let Some(eq) = entry.iter().position(|&b| b == b'=') else {
+            continue;
+        };
+        let (key, value) = (&entry[..eq], &entry[eq + 1..]);
+        if let Some(i) = variables.iter().position(|v| v.as_bytes() == key) {
+            // The first definition wins, as with getenv()
+            if values[i].is_empty() {
+                values[i] = String::from_utf8_lossy(value).into_owned();
+            }
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const STAT: &str = "1234 (test_process) S 1 1234 1234 0 -1 4194304 100 0 7 0 1000 500 0 0 20 0 4 0 12345 12345678 1234 18446744073709551615";
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = parse_stat(STAT).expect("valid stat");
+        assert_eq!(stat.state, 'S');
+        assert_eq!(stat.ppid, 1);
+        assert_eq!(stat.pgrp, 1234);
+        assert_eq!(stat.session, 1234);
+        assert_eq!(stat.minor_faults, 100);
+        assert_eq!(stat.major_faults, 7);
+        assert_eq!(stat.utime, 1000);
+        assert_eq!(stat.stime, 500);
+        assert_eq!(stat.cpu_ticks(), 1500);
+        assert_eq!(stat.nice, 0);
+        assert_eq!(stat.num_threads, 4);
+        assert_eq!(stat.start_time, 12345);
+        assert_eq!(stat.policy, 0);
+        assert_eq!(stat.guest_time, 0);
+
+        // Up to cguest_time (field 44)
+        let full = format!("{}{} 300 0", STAT, " 0".repeat(17));
+        let stat = parse_stat(&full).expect("valid stat");
+        assert_eq!(stat.guest_time, 300);
+    }
+
+    #[test]
+    fn test_parse_privileges() {
+        let status = "Name:\tsshd\nUid:\t1000\t0\t0\t0\nGid:\t0\t0\t0\t0\n\
+                      CapInh:\t0000000000000000\nCapEff:\t000001ffffffffff\n";
+        let privileges = parse_privileges(status).expect("valid status");
+        assert_eq!(privileges.euid, 0);
+        assert!(privileges.is_root());
+        assert_eq!(privileges.cap_eff, capability_mask(40));
+        assert_eq!(capability_mask(63), u64::MAX);
+
+        let status = "Uid:\t33\t33\t33\t33\nCapEff:\t0000000000000400\n";
+        let privileges = parse_privileges(status).expect("valid status");
+        assert!(!privileges.is_root());
+        assert!(!privileges.has_full_capabilities());
+        assert_eq!(parse_privileges("Uid:\t0\t0\t0\t0\n"), None);
+    }
+
+    #[test]
+    fn test_parse_schedstat() {
+        assert_eq!(
+            parse_schedstat("2147483648 500000000 1234\n"),
+            Some(SchedStat {
+                run_ns: 2_147_483_648,
+                wait_ns: 500_000_000,
+                timeslices: 1234,
+            })
+        );
+        assert_eq!(parse_schedstat("2147483648 500000000\n"), None);
+        assert_eq!(parse_schedstat("run wait slices\n"), None);
+    }
+
+    #[test]
+    fn test_read_proc_threads() {
+        let dir = tempfile::tempdir().unwrap();
+        for (tid, comm) in [("100", "postgres"), ("101", "bg (writer)")] {
+            let task = dir.path().join("task").join(tid);
+            fs::create_dir_all(&task).unwrap();
+            fs::write(
+                task.join("stat"),
+                format!("{tid} ({comm}) S 1 100 100 0 -1 0 10 0 0 0 250 50 0 0 20 0 2 0 4242"),
+            )
+            .unwrap();
+        }
+        // Exited between listing and reading
+        fs::create_dir_all(dir.path().join("task/102")).unwrap();
+
+        let mut threads = read_proc_threads(dir.path()).unwrap();
+        threads.sort_by_key(|t| t.tid);
+        assert_eq!(threads.len(), 2);
+        assert_eq!(
+            (threads[0].tid, threads[0].name.as_str()),
+            (100, "postgres")
+        );
+        assert_eq!(threads[1].name, "bg (writer)");
+        assert_eq!(threads[1].stat.cpu_ticks(), 300);
+        assert_eq!(threads[1].stat.start_time, 4242);
+    }
+
+    #[test]
+    fn test_parse_shmem() {
+        let status = "Name:\tpostgres\nVmRSS:\t  145000 kB\nRssAnon:\t    5000 kB\n\
+                      RssFile:\t   12000 kB\nRssShmem:\t  128000 kB\n";
+        assert_eq!(parse_shmem(status), Some(128000 * 1024));
+        assert_eq!(parse_shmem("Name:\tkthreadd\n"), None);
+    }
+
+    #[test]
+    fn test_io_priority() {
+        // ionice -c 2 -n 7
+        let prio = IoPriority::from_raw((2 << 13) | 7);
+        assert_eq!((prio.class, prio.level), (2, 7));
+        assert_eq!(prio.class_name(), "best-effort");
+        assert_eq!(IoPriority::from_raw(3 << 13).class_name(), "idle");
+
+        let own = read_io_priority(std::process::id()).expect("own process");
+        assert!(own.class <= 3);
+    }
+
+    #[test]
+    fn test_parse_stat_comm_with_spaces_and_parens() {
+        let content = STAT.replace("(test_process)", "(tmux: server) (1))");
+        let stat = parse_stat(&content).expect("valid stat");
+        assert_eq!(stat.state, 'S');
+        assert_eq!(stat.utime, 1000);
+        assert_eq!(stat.start_time, 12345);
+    }
+
+    #[test]
+    fn test_parse_stat_priority() {
+        // Full line of a SCHED_BATCH process at nice 10
+        let content = "77 (backup) S 1 77 77 0 -1 4194560 50 0 0 0 10 5 0 0 30 10 1 0 900 \
+                       1000000 200 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 3 0 3 0 0 0";
+        let stat = parse_stat(content).expect("valid stat");
+        assert_eq!(stat.nice, 10);
+        assert_eq!(stat.start_time, 900);
+        assert_eq!(stat.policy, 3);
+    }
+
+    #[test]
+    fn test_parse_stat_truncated() {
+        assert!(parse_stat("1234 (test) S 1 2 3").is_none());
+        assert!(parse_stat("garbage").is_none());
+    }
+
+    #[test]
+    fn test_read_proc_io() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        std::fs::write(
+            dir.path().join("io"),
+            "rchar: 10\nwchar: 20\nsyscr: 1\nsyscw: 2\nread_bytes: 4096\nwrite_bytes: 8192\ncancelled_write_bytes: 0\n",
+        )
+        .expect("Failed to write io file");
+
+        assert_eq!(
+            read_proc_io(dir.path(), 4).unwrap(),
+            ProcIo {
+                read_bytes: 4096,
+                write_bytes: 8192,
+                read_syscalls: 1,
+                write_syscalls: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_proc_wchan() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let wchan = dir.path().join("wchan");
+        std::fs::write(&wchan, "folio_wait_bit_common").expect("Failed to write wchan file");
+        assert_eq!(
+            read_proc_wchan(dir.path()).unwrap().as_deref(),
+            Some("folio_wait_bit_common")
+        );
+        std::fs::write(&wchan, "0").expect("Failed to write wchan file");
+        assert_eq!(read_proc_wchan(dir.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_environ() {
+        let variables = vec!["SERVICE_NAME".to_string(), "POD_NAME".to_string()];
+        let content = b"PATH=/usr/bin\0SERVICE_NAME=billing=v2\0SECRET=x\0SERVICE_NAME=other\0";
+        assert_eq!(
+            parse_environ(content, &variables),
+            vec!["billing=v2".to_string(), String::new()]
+        );
+        assert_eq!(parse_environ(b"", &variables), vec![String::new(); 2]);
+    }
+}
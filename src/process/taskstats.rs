@@ -0,0 +1,353 @@
+//! Per-process delay accounting over the taskstats netlink interface.
+//!
+//! The kernel accounts the time tasks wait for block IO and for pages to be
+//! swapped in. Unlike CPU numbers, this tells a process slowed down by memory
+//! pressure from one slowed down by the disk. The values are only available
+//! from the TASKSTATS generic netlink family, which needs CAP_NET_ADMIN, and
+//! stay at zero unless delay accounting is enabled (`kernel.task_delayacct`
+//! or the `delayacct` boot parameter).
+//!
+//! A request can wait up to a second for the kernel, so the scan threads do
+//! not share one socket: each request takes an idle socket from a pool, or
+//! opens another one, and returns it afterwards.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Mutex;
+
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+const TASKSTATS_CMD_GET: u8 = 1;
+const TASKSTATS_CMD_ATTR_TGID: u16 = 2;
+const TASKSTATS_TYPE_STATS: u16 = 3;
+const TASKSTATS_TYPE_AGGR_TGID: u16 = 5;
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 1;
+const NLMSG_HDRLEN: usize = 16;
+const GENL_HDRLEN: usize = 4;
+const NLA_HDRLEN: usize = 4;
+/// Attribute type bits, without the nested and byte order flags.
+const NLA_TYPE_MASK: u16 = 0x3fff;
+const RECV_BUFFER: usize = 8192;
+
+/// Offsets of the delay totals in struct taskstats, stable since version 1.
+const BLKIO_DELAY_TOTAL: usize = 40;
+const SWAPIN_DELAY_TOTAL: usize = 56;
+
+/// Whether the kernel collects delays; None on kernels without the sysctl
+/// (before 5.14), where it depends on the `delayacct` boot parameter.
+pub fn delayacct_enabled() -> Option<bool> {
+    let value = fs::read_to_string("/proc/sys/kernel/task_delayacct").ok()?;
+    Some(value.trim() != "0")
+}
+
+/// Accumulated delays of all threads of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct DelayStats {
+    pub blkio_delay_ns: u64,
+    pub swapin_delay_ns: u64,
+}
+
+/// Taskstats sockets shared by the scan threads, opened on demand.
+pub struct TaskstatsPool {
+    idle: Mutex<Vec<TaskstatsClient>>,
+}
+
+impl TaskstatsPool {
+    /// Opens the first socket, which checks that taskstats is available.
+    pub fn open() -> io::Result<Self> {
+        Ok(Self {
+            idle: Mutex::new(vec![TaskstatsClient::open()?]),
+        })
+    }
+
+    /// Delays of the process (thread group) `tgid`, on a socket no other
+    /// thread uses meanwhile.
+    pub fn delays(&self, tgid: u32) -> io::Result<DelayStats> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let mut client = match idle {
+            Some(client) => client,
+            None => TaskstatsClient::open()?,
+        };
+        let result = client.delays(tgid);
+        self.idle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(client);
+        result
+    }
+}
+
+/// A generic netlink socket bound to the TASKSTATS family.
+struct TaskstatsClient {
+    fd: OwnedFd,
+    family: u16,
+    seq: u32,
+    buf: Vec<u8>,
+}
+
+impl TaskstatsClient {
+    /// Opens the socket and resolves the TASKSTATS family id.
+    pub fn open() -> io::Result<Self> {
+        // SAFETY: plain socket(2) call, the descriptor is owned right below
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_GENERIC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd is a fresh descriptor nobody else owns
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // A kernel that never answers must not hang the scan
+        let timeout = libc::timeval {
+            tv_sec: 1,
+            tv_usec: 0,
+        };
+        // SAFETY: timeout outlives the call and its size is passed
+        let rc = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut client = Self {
+            fd,
+            family: 0,
+            seq: 0,
+            buf: vec![0; RECV_BUFFER],
+        };
+        let payload = client.request(
+            GENL_ID_CTRL,
+            CTRL_CMD_GETFAMILY,
+            &[(CTRL_ATTR_FAMILY_NAME, b"TASKSTATS\0")],
+        )?;
+        let family = attrs(payload)
+            .find(|(kind, _)| *kind == CTRL_ATTR_FAMILY_ID)
+            .and_then(|(_, value)| Some(u16::from_ne_bytes(value.get(..2)?.try_into().ok()?)))
+            .ok_or_else(|| invalid("no TASKSTATS family id in reply"))?;
+        client.family = family;
+        Ok(client)
+    }
+
+    /// Delays of the process (thread group) `tgid`.
+    pub fn delays(&mut self, tgid: u32) -> io::Result<DelayStats> {
+        let payload = self
+            .request(
+                self.family,
+                TASKSTATS_CMD_GET,
+                &[(TASKSTATS_CMD_ATTR_TGID, &tgid.to_ne_bytes())],
+            )
+            .map_err(|e| match e.raw_os_error() {
+                // Counted like other files of processes that exited
+                Some(libc::ESRCH) => io::Error::new(io::ErrorKind::NotFound, e),
+                _ => e,
+            })?;
+        parse_delays(payload).ok_or_else(|| invalid("no taskstats in reply"))
+    }
+
+    /// Sends one request and returns the attributes of its reply.
+    fn request(&mut self, family: u16, cmd: u8, attrs: &[(u16, &[u8])]) -> io::Result<&[u8]> {
+        self.seq = self.seq.wrapping_add(1);
+        let msg = encode_request(family, self.seq, cmd, attrs);
+        // SAFETY: sockaddr_nl is plain data, all zero addresses the kernel
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        // SAFETY: msg and addr outlive the call and their sizes are passed
+        let sent = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                msg.as_ptr() as *const libc::c_void,
+                msg.len(),
+                0,
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let range = loop {
+            // SAFETY: buf is valid for writes of its length
+            let len = unsafe {
+                libc::recv(
+                    self.fd.as_raw_fd(),
+                    self.buf.as_mut_ptr() as *mut libc::c_void,
+                    self.buf.len(),
+                    0,
+                )
+            };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Replies to earlier, timed out requests are skipped
+            if let Some(range) = find_reply(&self.buf[..len as usize], self.seq)? {
+                break range;
+            }
+        };
+        Ok(&self.buf[range])
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+const fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// A generic netlink request message.
+fn encode_request(family: u16, seq: u32, cmd: u8, attrs: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut msg = vec![0u8; NLMSG_HDRLEN];
+    msg.extend([cmd, 1, 0, 0]);
+    for (kind, value) in attrs {
+        msg.extend(((NLA_HDRLEN + value.len()) as u16).to_ne_bytes());
+        msg.extend(kind.to_ne_bytes());
+        msg.extend(*value);
+        msg.resize(align4(msg.len()), 0);
+    }
+    let len = msg.len() as u32;
+    msg[0..4].copy_from_slice(&len.to_ne_bytes());
+    msg[4..6].copy_from_slice(&family.to_ne_bytes());
+    msg[6..8].copy_from_slice(&NLM_F_REQUEST.to_ne_bytes());
+    msg[8..12].copy_from_slice(&seq.to_ne_bytes());
+    msg
+}
+
+/// Range of the attributes of the reply to `seq` in received `data`, None if
+/// the datagram holds no such reply. Netlink errors become `io::Error`s.
+fn find_reply(data: &[u8], seq: u32) -> io::Result<Option<Range<usize>>> {
+    let mut offset = 0;
+    while offset + NLMSG_HDRLEN <= data.len() {
+        let header = &data[offset..];
+        let len = u32::from_ne_bytes(header[0..4].try_into().unwrap()) as usize;
+        if len < NLMSG_HDRLEN || offset + len > data.len() {
+            return Err(invalid("truncated netlink message"));
+        }
+        let kind = u16::from_ne_bytes(header[4..6].try_into().unwrap());
+        let msg_seq = u32::from_ne_bytes(header[8..12].try_into().unwrap());
+        if msg_seq == seq {
+            if kind == NLMSG_ERROR {
+                let errno = header
+                    .get(NLMSG_HDRLEN..NLMSG_HDRLEN + 4)
+                    .map(|v| i32::from_ne_bytes(v.try_into().unwrap()))
+                    .ok_or_else(|| invalid("truncated netlink error"))?;
+                if errno != 0 {
+                    return Err(io::Error::from_raw_os_error(-errno));
+                }
+            } else if len >= NLMSG_HDRLEN + GENL_HDRLEN {
+                return Ok(Some(offset + NLMSG_HDRLEN + GENL_HDRLEN..offset + len));
+            }
+        }
+        offset += align4(len);
+    }
+    Ok(None)
+}
+
+/// Netlink attributes as (type, value).
+fn attrs(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < NLA_HDRLEN {
+            return None;
+        }
+        let len = u16::from_ne_bytes([data[0], data[1]]) as usize;
+        let kind = u16::from_ne_bytes([data[2], data[3]]) & NLA_TYPE_MASK;
+        if len < NLA_HDRLEN || len > data.len() {
+            return None;
+        }
+        let value = &data[NLA_HDRLEN..len];
+        data = &data[align4(len).min(data.len())..];
+        Some((kind, value))
+    })
+}
+
+/// Delays from the attributes of a TASKSTATS_CMD_GET reply.
+fn parse_delays(payload: &[u8]) -> Option<DelayStats> {
+    let (_, aggregate) = attrs(payload).find(|(kind, _)| *kind == TASKSTATS_TYPE_AGGR_TGID)?;
+    let (_, stats) = attrs(aggregate).find(|(kind, _)| *kind == TASKSTATS_TYPE_STATS)?;
+    let u64_at = |offset: usize| {
+        Some(u64::from_ne_bytes(
+            stats.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    };
+    Some(DelayStats {
+        blkio_delay_ns: u64_at(BLKIO_DELAY_TOTAL)?,
+        swapin_delay_ns: u64_at(SWAPIN_DELAY_TOTAL)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attr(kind: u16, value: &[u8]) -> Vec<u8> {
+        let mut out = ((NLA_HDRLEN + value.len()) as u16).to_ne_bytes().to_vec();
+        out.extend(kind.to_ne_bytes());
+        out.extend(value);
+        out.resize(align4(out.len()), 0);
+        out
+    }
+
+    #[test]
+    fn test_taskstats_messages() {
+        let request = encode_request(0x17, 7, TASKSTATS_CMD_GET, &[(2, &42u32.to_ne_bytes())]);
+        assert_eq!(request.len(), NLMSG_HDRLEN + GENL_HDRLEN + 8);
+        assert_eq!(&request[0..4], &(request.len() as u32).to_ne_bytes());
+
+        // struct taskstats up to swapin_delay_total
+        let mut stats = vec![0u8; 64];
+        stats[BLKIO_DELAY_TOTAL..BLKIO_DELAY_TOTAL + 8]
+            .copy_from_slice(&1_500_000u64.to_ne_bytes());
+        stats[SWAPIN_DELAY_TOTAL..SWAPIN_DELAY_TOTAL + 8].copy_from_slice(&250u64.to_ne_bytes());
+        let mut aggregate = attr(2, &42u32.to_ne_bytes());
+        aggregate.extend(attr(TASKSTATS_TYPE_STATS, &stats));
+        let payload = attr(TASKSTATS_TYPE_AGGR_TGID | 0x8000, &aggregate);
+
+        let mut reply = vec![0u8; NLMSG_HDRLEN];
+        reply.extend([2, 1, 0, 0]);
+        reply.extend(&payload);
+        let len = reply.len() as u32;
+        reply[0..4].copy_from_slice(&len.to_ne_bytes());
+        reply[4..6].copy_from_slice(&0x17u16.to_ne_bytes());
+        reply[8..12].copy_from_slice(&7u32.to_ne_bytes());
+
+        assert_eq!(find_reply(&reply, 6).unwrap(), None);
+        let range = find_reply(&reply, 7).unwrap().unwrap();
+        assert_eq!(
+            parse_delays(&reply[range]),
+            Some(DelayStats {
+                blkio_delay_ns: 1_500_000,
+                swapin_delay_ns: 250,
+            })
+        );
+
+        // ESRCH for a process that exited
+        let mut error = vec![0u8; NLMSG_HDRLEN + 4];
+        error[0..4].copy_from_slice(&((NLMSG_HDRLEN + 4) as u32).to_ne_bytes());
+        error[4..6].copy_from_slice(&NLMSG_ERROR.to_ne_bytes());
+        error[8..12].copy_from_slice(&7u32.to_ne_bytes());
+        error[16..20].copy_from_slice(&(-libc::ESRCH).to_ne_bytes());
+        assert_eq!(
+            find_reply(&error, 7).unwrap_err().raw_os_error(),
+            Some(libc::ESRCH)
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Self-registration in Consul or etcd.
+//!
+//! With `registration` configured, the exporter registers itself once the
+//! listener is bound (so an OS-assigned port is known) and keeps the entry
+//! alive while it runs:
+//!
+//! - Consul: the service is registered with the local agent together with a
+//!   TTL check that is marked passing every third of the TTL. Prometheus
+//!   `consul_sd_configs` then finds it like any other service.
+//! - etcd: the service is written as JSON under `key_prefix` + service ID,
+//!   attached to a lease that is kept alive the same way.
+//!
+//! On shutdown the entry is removed; if the exporter dies instead, the check
+//! turns critical (Consul) or the lease expires (etcd) after the TTL.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::config::RegistrationConfig;
+
+pub const DEFAULT_SERVICE_NAME: &str = "herakles-proc-mem-exporter";
+pub const DEFAULT_TTL_SECONDS: u64 = 30;
+pub const DEFAULT_ETCD_PREFIX: &str = "/services/herakles-proc-mem-exporter/";
+/// Timeout of a single request to the catalog.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What is registered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    pub id: String,
+    pub name: String,
+    pub address: String,
+    pub port: u16,
+    pub tls: bool,
+    pub tags: Vec<String>,
+}
+
+impl Service {
+    /// Describes this exporter listening on `addr`.
+    pub fn new(cfg: &RegistrationConfig, addr: SocketAddr, tls: bool) -> Self {
+        let name = cfg
+            .service_name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string());
+        let address = cfg.advertise_address.clone().unwrap_or_else(|| {
+            if addr.ip().is_unspecified() {
+                host_name()
+            } else {
+                addr.ip().to_string()
+            }
+        });
+        let id = cfg
+            .service_id
+            .clone()
+            .unwrap_or_else(|| format!("{}-{}-{}", name, address, addr.port()));
+        Self {
+            id,
+            name,
+            address,
+            port: addr.port(),
+            tls,
+            tags: cfg.tags.clone().unwrap_or_default(),
+        }
+    }
+
+    fn scheme(&self) -> &'static str {
+        if self.tls {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    fn check_id(&self) -> String {
+        format!("service:{}", self.id)
+    }
+
+    /// Body of the Consul agent service registration.
+    fn consul_registration(&self, ttl: u64) -> Value {
+        json!({
+            "ID": self.id,
+            "Name": self.name,
+            "Address": self.address,
+            "Port": self.port,
+            "Tags": self.tags,
+            "Meta": {
+                "scheme": self.scheme(),
+                "metrics_path": "/metrics",
+                "health_path": "/health",
+            },
+            "Check": {
+                "CheckID": self.check_id(),
+                "Name": format!("{} TTL", self.name),
+                "TTL": format!("{}s", ttl),
+                "DeregisterCriticalServiceAfter": format!("{}s", (ttl * 10).max(60)),
+            },
+        })
+    }
+
+    /// Value stored in etcd.
+    fn etcd_value(&self) -> Value {
+        json!({
+            "name": self.name,
+            "address": format!("{}:{}", self.address, self.port),
+            "scheme": self.scheme(),
+            "metrics_path": "/metrics",
+            "health_path": "/health",
+            "tags": self.tags,
+        })
+    }
+}
+
+/// Host name from the kernel, "localhost" if unreadable.
+pub fn host_name() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .ok()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// A catalog client for the configured backend.
+struct Catalog {
+    client: reqwest::Client,
+    cfg: RegistrationConfig,
+    service: Service,
+    /// Current etcd lease
+    lease: Mutex<Option<String>>,
+}
+
+impl Catalog {
+    fn ttl(&self) -> u64 {
+        self.cfg.ttl_seconds.unwrap_or(DEFAULT_TTL_SECONDS)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.cfg.address.trim_end_matches('/'), path)
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value, String> {
+        let mut request = self
+            .client
+            .request(method, self.url(path))
+            .header("Content-Type", "application/json");
+        if let Some(token) = &self.cfg.token {
+            request = match self.cfg.backend.as_str() {
+                "consul" => request.header("X-Consul-Token", token),
+                _ => request.header("Authorization", token),
+            };
+        }
+        if let Some(body) = body {
+            request = request.body(body.to_string());
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let text = response.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("{} {}: {}", status, path, text.trim()));
+        }
+        Ok(serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    async fn register(&self) -> Result<(), String> {
+        match self.cfg.backend.as_str() {
+            "consul" => {
+                let body = self.service.consul_registration(self.ttl());
+                self.send(
+                    reqwest::Method::PUT,
+                    "/v1/agent/service/register",
+                    Some(&body),
+                )
+                .await?;
+                self.refresh().await
+            }
+            _ => {
+                let grant = self
+                    .send(
+                        reqwest::Method::POST,
+                        "/v3/lease/grant",
+                        Some(&json!({ "TTL": self.ttl() })),
+                    )
+                    .await?;
+                let lease = grant["ID"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or("lease grant returned no ID")?;
+                let prefix = self
+                    .cfg
+                    .key_prefix
+                    .as_deref()
+                    .unwrap_or(DEFAULT_ETCD_PREFIX);
+                let key = format!("{}{}", prefix, self.service.id);
+                let put = json!({
+                    "key": BASE64.encode(key),
+                    "value": BASE64.encode(self.service.etcd_value().to_string()),
+                    "lease": lease,
+                });
+                self.send(reqwest::Method::POST, "/v3/kv/put", Some(&put))
+                    .await?;
+                *self.lease.lock().await = Some(lease);
+                Ok(())
+            }
+        }
+    }
+
+    /// Keeps the registration alive; an error means it has to be redone.
+    async fn refresh(&self) -> Result<(), String> {
+        match self.cfg.backend.as_str() {
+            "consul" => {
+                let path = format!("/v1/agent/check/pass/{}", self.service.check_id());
+                self.send(reqwest::Method::PUT, &path, None).await?;
+                Ok(())
+            }
+            _ => {
+                let lease = self.lease.lock().await.clone().ok_or("no lease")?;
+                let response = self
+                    .send(
+                        reqwest::Method::POST,
+                        "/v3/lease/keepalive",
+                        Some(&json!({ "ID": lease })),
+                    )
+                    .await?;
+                // An expired lease is answered without TTL
+                let ttl = response["result"]["TTL"].as_str().unwrap_or("0");
+                if ttl == "0" {
+                    return Err(format!("lease {} expired", lease));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn deregister(&self) -> Result<(), String> {
+        match self.cfg.backend.as_str() {
+            "consul" => {
+                let path = format!("/v1/agent/service/deregister/{}", self.service.id);
+                self.send(reqwest::Method::PUT, &path, None).await?;
+            }
+            _ => {
+                if let Some(lease) = self.lease.lock().await.take() {
+                    self.send(
+                        reqwest::Method::POST,
+                        "/v3/lease/revoke",
+                        Some(&json!({ "ID": lease })),
+                    )
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A running registration; removed from the catalog by [`Registration::stop`].
+pub struct Registration {
+    catalog: Arc<Catalog>,
+    task: JoinHandle<()>,
+}
+
+impl Registration {
+    /// Registers `service` and keeps it alive in the background.
+    pub fn start(cfg: &RegistrationConfig, service: Service) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to build registration HTTP client: {}", e))?;
+        let catalog = Arc::new(Catalog {
+            client,
+            cfg: cfg.clone(),
+            service,
+            lease: Mutex::new(None),
+        });
+        info!(
+            "Registering {} as {} ({}:{}) in {} at {}",
+            catalog.service.name,
+            catalog.service.id,
+            catalog.service.address,
+            catalog.service.port,
+            cfg.backend,
+            cfg.address
+        );
+
+        let task_catalog = catalog.clone();
+        let task = tokio::spawn(async move {
+            let catalog = task_catalog;
+            let interval = Duration::from_secs((catalog.ttl() / 3).max(1));
+            let mut registered = false;
+            loop {
+                if registered {
+                    if let Err(e) = catalog.refresh().await {
+                        warn!("Registration refresh failed, registering again: {}", e);
+                        registered = false;
+                    }
+                }
+                if !registered {
+                    match catalog.register().await {
+                        Ok(()) => {
+                            info!(
+                                "Registered {} in {}",
+                                catalog.service.id, catalog.cfg.backend
+                            );
+                            registered = true;
+                        }
+                        Err(e) => warn!("Registration in {} failed: {}", catalog.cfg.backend, e),
+                    }
+                } else {
+                    debug!("Registration of {} refreshed", catalog.service.id);
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+
+        Ok(Self { catalog, task })
+    }
+
+    /// Stops the refresh and removes the entry from the catalog.
+    pub async fn stop(self) {
+        self.task.abort();
+        let _ = self.task.await;
+        match self.catalog.deregister().await {
+            Ok(()) => info!(
+                "Deregistered {} from {}",
+                self.catalog.service.id, self.catalog.cfg.backend
+            ),
+            Err(e) => warn!(
+                "Failed to deregister {} from {}: {}",
+                self.catalog.service.id, self.catalog.cfg.backend, e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(backend: &str) -> RegistrationConfig {
+        RegistrationConfig {
+            backend: backend.to_string(),
+            address: "http://127.0.0.1:8500".to_string(),
+            tags: Some(vec!["prod".to_string()]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_service_defaults() {
+        let cfg = config("consul");
+        let service = Service::new(&cfg, "10.0.0.5:9215".parse().unwrap(), true);
+        assert_eq!(service.id, "herakles-proc-mem-exporter-10.0.0.5-9215");
+        assert_eq!(service.address, "10.0.0.5");
+
+        let service = Service::new(&cfg, "0.0.0.0:9215".parse().unwrap(), false);
+        assert_eq!(service.address, host_name());
+
+        let cfg = RegistrationConfig {
+            service_id: Some("exporter-1".to_string()),
+            advertise_address: Some("node1.example.com".to_string()),
+            ..config("consul")
+        };
+        let service = Service::new(&cfg, "0.0.0.0:9300".parse().unwrap(), false);
+        assert_eq!(service.id, "exporter-1");
+        assert_eq!(service.address, "node1.example.com");
+        assert_eq!(service.port, 9300);
+    }
+
+    #[test]
+    fn test_registration_bodies() {
+        let service = Service::new(&config("consul"), "10.0.0.5:9215".parse().unwrap(), true);
+        let body = service.consul_registration(30);
+        assert_eq!(body["Port"], 9215);
+        assert_eq!(body["Tags"][0], "prod");
+        assert_eq!(body["Meta"]["scheme"], "https");
+        assert_eq!(body["Check"]["CheckID"], format!("service:{}", service.id));
+        assert_eq!(body["Check"]["TTL"], "30s");
+        assert_eq!(body["Check"]["DeregisterCriticalServiceAfter"], "300s");
+
+        let value = service.etcd_value();
+        assert_eq!(value["address"], "10.0.0.5:9215");
+        assert_eq!(value["health_path"], "/health");
+    }
+}
@@ -0,0 +1,217 @@
+//! Request IDs for HTTP requests.
+//!
+//! Every request runs inside a `request` span carrying its request ID, so all
+//! log lines of the handlers below it can be matched to one scrape. The ID is
+//! the trace ID of an incoming W3C `traceparent` header if there is one, then
+//! an incoming `X-Request-Id`, and a random ID otherwise. It is returned in the
+//! `X-Request-Id` response header; Prometheus and proxies that log it can be
+//! correlated with the exporter's logs.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+#[cfg(feature = "otlp")]
+use tracing::Span;
+use tracing::{debug, info_span, warn, Instrument};
+
+/// Header carrying the request ID, in requests and responses.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// W3C trace context header.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Longest incoming X-Request-Id that is reused as is.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Request ID of the current request, in the request extensions.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Parsed W3C `traceparent` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceParent {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// 16 lowercase hex digits, span ID of the caller
+    pub parent_id: String,
+    pub flags: u8,
+}
+
+impl TraceParent {
+    /// Parses `version-traceid-parentid-flags`.
+    ///
+    /// Returns None for malformed headers and all-zero IDs, which the spec
+    /// defines as invalid. Future versions may append fields after flags.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let parent_id = parts.next()?;
+        let flags = parts.next()?;
+        if version == "00" && parts.next().is_some() {
+            return None;
+        }
+
+        let is_hex = |s: &str, len: usize| {
+            s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        if !is_hex(version, 2) || version == "ff" || !is_hex(flags, 2) {
+            return None;
+        }
+        if !is_hex(trace_id, 32) || trace_id.bytes().all(|b| b == b'0') {
+            return None;
+        }
+        if !is_hex(parent_id, 16) || parent_id.bytes().all(|b| b == b'0') {
+            return None;
+        }
+
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            flags: u8::from_str_radix(flags, 16).ok()?,
+        })
+    }
+}
+
+/// Selects the request ID for a request, see the module documentation.
+pub fn request_id_for(headers: &HeaderMap) -> (String, Option<TraceParent>) {
+    let traceparent = headers
+        .get(TRACEPARENT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(TraceParent::parse);
+    if let Some(tp) = &traceparent {
+        return (tp.trace_id.clone(), traceparent);
+    }
+
+    let incoming = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        });
+    match incoming {
+        Some(id) => (id.to_string(), None),
+        None => (format!("{:032x}", rand::random::<u128>()), None),
+    }
+}
+
+/// Makes the caller's span the parent of `span` in exported traces.
+#[cfg(feature = "otlp")]
+fn set_remote_parent(span: &Span, tp: &TraceParent) {
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let (Ok(trace_id), Ok(span_id)) = (
+        TraceId::from_hex(&tp.trace_id),
+        SpanId::from_hex(&tp.parent_id),
+    ) else {
+        return;
+    };
+    let remote = SpanContext::new(
+        trace_id,
+        span_id,
+        TraceFlags::new(tp.flags),
+        true,
+        TraceState::default(),
+    );
+    // Fails only without the OpenTelemetry layer, i.e. when otlp is not configured
+    let _ = span.set_parent(opentelemetry::Context::new().with_remote_span_context(remote));
+}
+
+/// Middleware wrapping every request in a `request` span with its request ID.
+pub async fn track_request(mut req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let (request_id, _traceparent) = request_id_for(req.headers());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+
+    let span = info_span!("request", request_id = %request_id, %method, path = %path);
+    #[cfg(feature = "otlp")]
+    if let Some(tp) = &_traceparent {
+        set_remote_parent(&span, tp);
+    }
+
+    let mut response = next.run(req).instrument(span.clone()).await;
+
+    let status = response.status();
+    let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+    span.in_scope(|| log_request(status, duration_ms));
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Access log line (method and path are fields of the enclosing span),
+/// raised to a warning for server errors.
+fn log_request(status: StatusCode, duration_ms: f64) {
+    if status.is_server_error() {
+        warn!(
+            "Request failed: {} in {:.2}ms",
+            status.as_u16(),
+            duration_ms
+        );
+    } else {
+        debug!(
+            "Request completed: {} in {:.2}ms",
+            status.as_u16(),
+            duration_ms
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_traceparent() {
+        let tp = TraceParent::parse(TRACEPARENT).expect("valid traceparent");
+        assert_eq!(tp.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(tp.parent_id, "00f067aa0ba902b7");
+        assert_eq!(tp.flags, 1);
+
+        // Later versions may carry additional fields
+        assert!(TraceParent::parse(&format!("01{}-extra", &TRACEPARENT[2..])).is_some());
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert_eq!(TraceParent::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_request_id_for() {
+        let mut headers = HeaderMap::new();
+        let (generated, tp) = request_id_for(&headers);
+        assert_eq!(generated.len(), 32);
+        assert!(tp.is_none());
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("prom-42"));
+        assert_eq!(request_id_for(&headers).0, "prom-42");
+
+        headers.insert(TRACEPARENT_HEADER, HeaderValue::from_static(TRACEPARENT));
+        let (id, tp) = request_id_for(&headers);
+        assert_eq!(id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(tp.is_some());
+    }
+}
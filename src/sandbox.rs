@@ -0,0 +1,513 @@
+//! Optional sandboxing after initialization.
+//!
+//! The exporter parses attacker-influenced data (process names and command
+//! lines) while usually running as root. Once the initial scan has run and all
+//! files needed at startup are loaded, it can restrict itself:
+//!
+//! - Landlock limits filesystem access to /proc and the zram/zswap statistics
+//!   in /sys (read), the config, TLS and test data files (read) and the
+//!   textfile/dump destinations (write).
+//! - seccomp limits the process to the system calls the exporter needs.
+//!
+//! seccomp filters are installed on all threads at once (TSYNC). Landlock has
+//! no such mode, so the ruleset is applied on the calling thread, on every
+//! rayon scan thread and on every tokio worker; threads spawned later inherit
+//! the restriction from their parent. Threads of tokio's blocking pool started
+//! before that are reused for later tasks, so the exporter's blocking tasks go
+//! through [`spawn_blocking`], which restricts their thread first.
+
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::sync::{Arc, OnceLock};
+
+use crate::cgroup::DEFAULT_CGROUP_ROOT;
+use crate::config::{Config, SandboxConfig};
+
+/// Which sandbox mechanisms ended up active.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SandboxStatus {
+    pub landlock: bool,
+    pub seccomp: bool,
+}
+
+impl SandboxStatus {
+    /// Returns (mechanism, active) pairs for metrics and logging.
+    pub fn mechanisms(&self) -> [(&'static str, bool); 2] {
+        [("landlock", self.landlock), ("seccomp", self.seccomp)]
+    }
+}
+
+/// Paths the sandboxed exporter may still access.
+#[derive(Debug, Default, PartialEq)]
+pub struct SandboxPaths {
+    pub read: Vec<PathBuf>,
+    pub write: Vec<PathBuf>,
+}
+
+/// Collects the paths the exporter needs after initialization.
+pub fn sandbox_paths(cfg: &Config, config_path: Option<&Path>) -> SandboxPaths {
+    let mut read: Vec<PathBuf> = vec!["/proc".into(), "/etc/localtime".into()];
+    let mut write: Vec<PathBuf> = Vec::new();
+
+    read.extend(config_path.map(Path::to_path_buf));
+    read.extend(cfg.tls_cert_path.as_ref().map(PathBuf::from));
+    read.extend(cfg.tls_key_path.as_ref().map(PathBuf::from));
+    // Test data is re-read on every scan
+    read.extend(cfg.test_data_file.clone());
+    // zram devices (/sys/block links into /sys/devices), zswap and the
+    // virtio balloon, if present
+    read.extend(
+        [
+            "/sys/block",
+            "/sys/devices/virtual/block",
+            "/sys/kernel/debug/zswap",
+            "/sys/kernel/debug/virtio-balloon",
+            "/sys/bus/virtio/drivers/virtio_balloon",
+        ]
+        .map(PathBuf::from)
+        .into_iter()
+        .filter(|path| path.exists()),
+    );
+    if let Some(cgroups) = &cfg.cgroups {
+        read.push(
+            cgroups
+                .root
+                .clone()
+                .unwrap_or_else(|| DEFAULT_CGROUP_ROOT.into()),
+        );
+    }
+
+    if cfg.federation.is_some() {
+        // Name resolution for downstream targets
+        read.extend(
+            [
+                "/etc/resolv.conf",
+                "/etc/hosts",
+                "/etc/nsswitch.conf",
+                "/etc/gai.conf",
+                "/etc/host.conf",
+            ]
+            .map(PathBuf::from),
+        );
+    }
+
+    // Textfile output, the ready file and the cache snapshot are written as
+    // a temp file plus rename in their directory
+    for path in [&cfg.textfile_output, &cfg.ready_file, &cfg.cache_snapshot]
+        .into_iter()
+        .flatten()
+    {
+        write.extend(path.parent().map(Path::to_path_buf));
+    }
+    // SQLite creates its -wal and -shm files next to the history database
+    write.extend(
+        cfg.history_db
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf),
+    );
+    // The PID file is removed from its directory on shutdown
+    write.extend(
+        cfg.pid_file
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf),
+    );
+    if cfg.diagnostics_dump.as_deref() == Some("file") {
+        write.push(std::env::temp_dir());
+    }
+    if cfg.enable_file_logging.unwrap_or(false) {
+        write.extend(cfg.log_file.clone());
+    }
+    write.extend(cfg.audit_log.clone());
+
+    SandboxPaths { read, write }
+}
+
+/// Landlock paths once the ruleset is enforced, for threads restricted later.
+#[cfg(target_os = "linux")]
+static LANDLOCK_PATHS: OnceLock<Arc<SandboxPaths>> = OnceLock::new();
+
+/// Applies the active Landlock ruleset to the calling thread, once per thread.
+///
+/// Does nothing before the sandbox is applied or without Landlock.
+#[cfg(target_os = "linux")]
+pub fn restrict_thread() {
+    if let Some(paths) = LANDLOCK_PATHS.get() {
+        if let Err(e) = landlock_rules::restrict_thread_once(paths) {
+            tracing::warn!("Failed to apply Landlock sandbox to thread: {}", e);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn restrict_thread() {}
+
+/// Returns true once the Landlock ruleset is enforced.
+#[cfg(target_os = "linux")]
+pub fn landlock_active() -> bool {
+    LANDLOCK_PATHS.get().is_some()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn landlock_active() -> bool {
+    false
+}
+
+/// Runs `f` on tokio's blocking pool, on a thread restricted by the sandbox.
+pub fn spawn_blocking<F, R>(f: F) -> tokio::task::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        restrict_thread();
+        f()
+    })
+}
+
+/// Applies the configured sandbox mechanisms.
+///
+/// Failures are logged and leave the mechanism inactive instead of aborting,
+/// since kernels without Landlock or seccomp support are common.
+#[cfg(target_os = "linux")]
+pub async fn apply_sandbox(
+    cfg: &Config,
+    sandbox: &SandboxConfig,
+    config_path: Option<&Path>,
+) -> SandboxStatus {
+    use tracing::{info, warn};
+
+    let mut status = SandboxStatus::default();
+
+    if sandbox.landlock.unwrap_or(false) {
+        let paths = Arc::new(sandbox_paths(cfg, config_path));
+        match landlock_rules::restrict_all_threads(paths.clone()).await {
+            Ok(enforced) => {
+                status.landlock = enforced;
+                if enforced {
+                    let _ = LANDLOCK_PATHS.set(paths);
+                    info!("Landlock filesystem sandbox active");
+                } else {
+                    warn!("Landlock not supported by this kernel, filesystem sandbox inactive");
+                }
+            }
+            Err(e) => warn!("Failed to apply Landlock sandbox: {}", e),
+        }
+    }
+
+    if sandbox.seccomp.unwrap_or(false) {
+        let action = sandbox.seccomp_action.as_deref().unwrap_or("errno");
+        match seccomp_filter::apply(action) {
+            Ok(()) => {
+                status.seccomp = true;
+                info!(
+                    "seccomp syscall filter active (action on violation: {})",
+                    action
+                );
+            }
+            Err(e) => warn!("Failed to apply seccomp filter: {}", e),
+        }
+    }
+
+    status
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn apply_sandbox(
+    _cfg: &Config,
+    _sandbox: &SandboxConfig,
+    _config_path: Option<&Path>,
+) -> SandboxStatus {
+    tracing::warn!("Sandboxing is only supported on Linux");
+    SandboxStatus::default()
+}
+
+#[cfg(target_os = "linux")]
+mod landlock_rules {
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr,
+        RulesetError, RulesetStatus, ABI,
+    };
+    use std::cell::Cell;
+    use std::sync::{Arc, Barrier};
+
+    use super::SandboxPaths;
+
+    /// Highest ABI the ruleset is written for; older kernels degrade gracefully.
+    const LANDLOCK_ABI: ABI = ABI::V2;
+
+    thread_local! {
+        /// Set once the ruleset was applied on this thread.
+        static RESTRICTED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// Restricts the calling thread unless it already was.
+    pub fn restrict_thread_once(paths: &SandboxPaths) -> Result<(), RulesetError> {
+        if !RESTRICTED.get() {
+            restrict_current_thread(paths)?;
+        }
+        Ok(())
+    }
+
+    /// Restricts the calling thread. Returns false if Landlock is unsupported.
+    fn restrict_current_thread(paths: &SandboxPaths) -> Result<bool, RulesetError> {
+        let status = Ruleset::default()
+            .handle_access(AccessFs::from_all(LANDLOCK_ABI))?
+            .create()?
+            .add_rules(path_beneath_rules(
+                &paths.read,
+                AccessFs::from_read(LANDLOCK_ABI),
+            ))?
+            .add_rules(path_beneath_rules(
+                &paths.write,
+                AccessFs::from_all(LANDLOCK_ABI),
+            ))?
+            .restrict_self()?;
+        RESTRICTED.set(true);
+        Ok(status.ruleset != RulesetStatus::NotEnforced)
+    }
+
+    /// Restricts the calling thread, all rayon threads and all tokio workers.
+    pub async fn restrict_all_threads(paths: Arc<SandboxPaths>) -> Result<bool, RulesetError> {
+        let mut enforced = restrict_current_thread(&paths)?;
+
+        for result in rayon::broadcast(|_| restrict_current_thread(&paths)) {
+            enforced &= result?;
+        }
+
+        // One task per worker; the barrier keeps each worker busy until all
+        // tasks started, so every worker runs exactly one of them
+        let workers = tokio::runtime::Handle::current().metrics().num_workers();
+        let barrier = Arc::new(Barrier::new(workers));
+        let tasks: Vec<_> = (0..workers)
+            .map(|_| {
+                let paths = paths.clone();
+                let barrier = barrier.clone();
+                tokio::spawn(async move {
+                    let result = restrict_current_thread(&paths);
+                    barrier.wait();
+                    result
+                })
+            })
+            .collect();
+        for task in tasks {
+            if let Ok(result) = task.await {
+                enforced &= result?;
+            }
+        }
+
+        Ok(enforced)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod seccomp_filter {
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter};
+    use std::collections::BTreeMap;
+
+    /// System calls used by the exporter, tokio, rayon, hyper and rustls.
+    pub(super) const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+        // Files and /proc
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_readv,
+        libc::SYS_writev,
+        libc::SYS_pread64,
+        libc::SYS_pwrite64,
+        libc::SYS_openat,
+        libc::SYS_close,
+        libc::SYS_fstat,
+        libc::SYS_newfstatat,
+        libc::SYS_statx,
+        libc::SYS_statfs,
+        libc::SYS_fstatfs,
+        libc::SYS_lseek,
+        libc::SYS_getdents64,
+        libc::SYS_readlinkat,
+        libc::SYS_faccessat,
+        libc::SYS_faccessat2,
+        libc::SYS_fcntl,
+        libc::SYS_ioctl,
+        libc::SYS_fsync,
+        libc::SYS_fdatasync,
+        // Shrinking the history database and its write-ahead log
+        libc::SYS_ftruncate,
+        libc::SYS_renameat,
+        libc::SYS_renameat2,
+        libc::SYS_unlinkat,
+        libc::SYS_getcwd,
+        libc::SYS_dup,
+        libc::SYS_dup3,
+        libc::SYS_pipe2,
+        // Memory
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_mprotect,
+        libc::SYS_madvise,
+        libc::SYS_brk,
+        // Threads, scheduling and time
+        libc::SYS_clone,
+        libc::SYS_clone3,
+        libc::SYS_futex,
+        libc::SYS_set_robust_list,
+        libc::SYS_rseq,
+        libc::SYS_sched_yield,
+        libc::SYS_sched_getaffinity,
+        libc::SYS_getpriority,
+        libc::SYS_setpriority,
+        libc::SYS_ioprio_get,
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_getres,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        libc::SYS_gettid,
+        libc::SYS_getpid,
+        libc::SYS_getppid,
+        libc::SYS_getuid,
+        libc::SYS_geteuid,
+        libc::SYS_getgid,
+        libc::SYS_getegid,
+        libc::SYS_getrusage,
+        libc::SYS_prlimit64,
+        libc::SYS_sysinfo,
+        libc::SYS_uname,
+        libc::SYS_prctl,
+        libc::SYS_getrandom,
+        libc::SYS_membarrier,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+        // Signals
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack,
+        libc::SYS_tgkill,
+        // Event loop
+        libc::SYS_epoll_create1,
+        libc::SYS_epoll_ctl,
+        libc::SYS_epoll_pwait,
+        libc::SYS_epoll_pwait2,
+        libc::SYS_eventfd2,
+        libc::SYS_ppoll,
+        libc::SYS_pselect6,
+        // Network (HTTP server, federation)
+        libc::SYS_socket,
+        libc::SYS_socketpair,
+        libc::SYS_bind,
+        libc::SYS_listen,
+        libc::SYS_accept4,
+        libc::SYS_connect,
+        libc::SYS_shutdown,
+        libc::SYS_getsockname,
+        libc::SYS_getpeername,
+        libc::SYS_setsockopt,
+        libc::SYS_getsockopt,
+        libc::SYS_sendto,
+        libc::SYS_recvfrom,
+        libc::SYS_sendmsg,
+        libc::SYS_recvmsg,
+        libc::SYS_sendmmsg,
+        libc::SYS_recvmmsg,
+    ];
+
+    /// Legacy system calls that only exist on x86_64.
+    #[cfg(target_arch = "x86_64")]
+    const ALLOWED_SYSCALLS_ARCH: &[libc::c_long] = &[
+        libc::SYS_open,
+        libc::SYS_stat,
+        libc::SYS_lstat,
+        libc::SYS_access,
+        libc::SYS_readlink,
+        libc::SYS_rename,
+        libc::SYS_unlink,
+        libc::SYS_poll,
+        libc::SYS_epoll_wait,
+        libc::SYS_arch_prctl,
+        libc::SYS_accept,
+    ];
+
+    #[cfg(not(target_arch = "x86_64"))]
+    const ALLOWED_SYSCALLS_ARCH: &[libc::c_long] = &[];
+
+    /// Installs the allowlist on all threads.
+    pub fn apply(action: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mismatch_action = match action {
+            "kill" => SeccompAction::KillProcess,
+            "log" => SeccompAction::Log,
+            _ => SeccompAction::Errno(libc::EPERM as u32),
+        };
+
+        let rules: BTreeMap<i64, Vec<_>> = ALLOWED_SYSCALLS
+            .iter()
+            .chain(ALLOWED_SYSCALLS_ARCH)
+            .map(|&nr| (nr, Vec::new()))
+            .collect();
+
+        let filter = SeccompFilter::new(
+            rules,
+            mismatch_action,
+            SeccompAction::Allow,
+            std::env::consts::ARCH.try_into()?,
+        )?;
+        let program: BpfProgram = filter.try_into()?;
+        seccompiler::apply_filter_all_threads(&program)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox_paths() {
+        let cfg = Config {
+            tls_cert_path: Some("/etc/herakles/cert.pem".into()),
+            textfile_output: Some("/var/lib/node_exporter/textfile/herakles.prom".into()),
+            ..Config::default()
+        };
+        let paths = sandbox_paths(&cfg, Some(Path::new("/etc/herakles/config.yaml")));
+
+        assert!(paths.read.contains(&PathBuf::from("/proc")));
+        assert!(paths
+            .read
+            .contains(&PathBuf::from("/etc/herakles/config.yaml")));
+        assert!(paths
+            .read
+            .contains(&PathBuf::from("/etc/herakles/cert.pem")));
+        assert!(!paths.read.contains(&PathBuf::from("/etc/resolv.conf")));
+        assert_eq!(
+            paths.write,
+            vec![PathBuf::from("/var/lib/node_exporter/textfile")]
+        );
+    }
+
+    #[test]
+    fn test_sandbox_paths_history_db() {
+        let cfg = Config {
+            history_db: Some("/var/lib/herakles/history.db".into()),
+            ..Config::default()
+        };
+        let paths = sandbox_paths(&cfg, None);
+        assert_eq!(paths.write, vec![PathBuf::from("/var/lib/herakles")]);
+    }
+
+    #[test]
+    fn test_sandbox_paths_pid_file() {
+        let cfg = Config {
+            pid_file: Some("/run/herakles/exporter.pid".into()),
+            ..Config::default()
+        };
+        let paths = sandbox_paths(&cfg, None);
+        assert_eq!(paths.write, vec![PathBuf::from("/run/herakles")]);
+    }
+
+    #[cfg(all(target_os = "linux", feature = "sqlite"))]
+    #[test]
+    fn test_seccomp_allows_history_compaction() {
+        // incremental_vacuum and wal_checkpoint(TRUNCATE) shrink the files
+        assert!(seccomp_filter::ALLOWED_SYSCALLS.contains(&libc::SYS_ftruncate));
+    }
+}
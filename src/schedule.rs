@@ -0,0 +1,125 @@
+//! Time-of-day scan windows.
+//!
+//! `scan_windows` overrides cache_ttl for parts of the day (and optionally
+//! only on some weekdays), so e.g. batch hosts can be scanned every 30s during
+//! business hours and every 300s at night from a single config. The first
+//! matching window wins; outside all windows the plain cache_ttl applies.
+
+use chrono::{Datelike, Local, NaiveTime, Timelike, Weekday};
+
+use crate::config::{Config, ScanWindow, DEFAULT_CACHE_TTL};
+
+/// Parses a "HH:MM" time of day into minutes since midnight.
+pub fn parse_time_of_day(value: &str) -> Option<u32> {
+    let time = NaiveTime::parse_from_str(value.trim(), "%H:%M").ok()?;
+    Some(time.hour() * 60 + time.minute())
+}
+
+/// Parses a weekday name ("mon", "monday", ...).
+pub fn parse_weekday(value: &str) -> Option<Weekday> {
+    value.trim().parse().ok()
+}
+
+impl ScanWindow {
+    /// Returns true if the window covers the given weekday and minute of day.
+    ///
+    /// Windows with `end` before `start` wrap past midnight; the early-morning
+    /// part belongs to the weekday the window started on.
+    pub fn contains(&self, weekday: Weekday, minute: u32) -> bool {
+        let (Some(start), Some(end)) =
+            (parse_time_of_day(&self.start), parse_time_of_day(&self.end))
+        else {
+            return false;
+        };
+
+        let day_matches = |day: Weekday| {
+            self.days.as_ref().is_none_or(|days| {
+                days.iter()
+                    .filter_map(|d| parse_weekday(d))
+                    .any(|d| d == day)
+            })
+        };
+
+        if start <= end {
+            (start..end).contains(&minute) && day_matches(weekday)
+        } else if minute >= start {
+            day_matches(weekday)
+        } else {
+            minute < end && day_matches(weekday.pred())
+        }
+    }
+}
+
+/// Returns the cache TTL in seconds that applies at the given local time.
+pub fn cache_ttl_at(cfg: &Config, weekday: Weekday, minute: u32) -> u64 {
+    cfg.scan_windows
+        .iter()
+        .flatten()
+        .find(|w| w.contains(weekday, minute))
+        .map(|w| w.cache_ttl)
+        .unwrap_or_else(|| cfg.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL))
+}
+
+/// Returns the cache TTL in seconds that applies right now.
+pub fn current_cache_ttl(cfg: &Config) -> u64 {
+    let now = Local::now();
+    cache_ttl_at(cfg, now.weekday(), now.hour() * 60 + now.minute())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(start: &str, end: &str, ttl: u64, days: Option<&[&str]>) -> ScanWindow {
+        ScanWindow {
+            start: start.into(),
+            end: end.into(),
+            cache_ttl: ttl,
+            days: days.map(|d| d.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_parse_time_of_day() {
+        assert_eq!(parse_time_of_day("00:00"), Some(0));
+        assert_eq!(parse_time_of_day("08:30"), Some(510));
+        assert_eq!(parse_time_of_day("23:59"), Some(1439));
+        assert_eq!(parse_time_of_day("24:00"), None);
+        assert_eq!(parse_time_of_day("8"), None);
+    }
+
+    #[test]
+    fn test_window_contains() {
+        let day = window("08:00", "18:00", 30, None);
+        assert!(day.contains(Weekday::Wed, 8 * 60));
+        assert!(!day.contains(Weekday::Wed, 18 * 60));
+
+        let night = window("22:00", "06:00", 300, Some(&["fri"]));
+        assert!(night.contains(Weekday::Fri, 23 * 60));
+        assert!(night.contains(Weekday::Sat, 2 * 60));
+        assert!(!night.contains(Weekday::Fri, 2 * 60));
+        assert!(!night.contains(Weekday::Sat, 23 * 60));
+    }
+
+    #[test]
+    fn test_cache_ttl_at() {
+        let cfg = Config {
+            cache_ttl: Some(60),
+            scan_windows: Some(vec![
+                window(
+                    "08:00",
+                    "18:00",
+                    30,
+                    Some(&["mon", "tue", "wed", "thu", "fri"]),
+                ),
+                window("22:00", "06:00", 300, None),
+            ]),
+            ..Config::default()
+        };
+
+        assert_eq!(cache_ttl_at(&cfg, Weekday::Mon, 9 * 60), 30);
+        assert_eq!(cache_ttl_at(&cfg, Weekday::Sun, 9 * 60), 60);
+        assert_eq!(cache_ttl_at(&cfg, Weekday::Sun, 3 * 60), 300);
+        assert_eq!(cache_ttl_at(&cfg, Weekday::Mon, 20 * 60), 60);
+    }
+}
@@ -0,0 +1,302 @@
+//! Detection of scrape intervals that do not match the cache refresh.
+//!
+//! Every scrape of `/metrics` or `/metrics/<tenant>` records the time since
+//! the previous scrape of the same client (source IP) and path. When scrapes
+//! arrive [`MISMATCH_FACTOR`] times more often than the cache refreshes, most
+//! of them return the same snapshot; when they arrive that much less often,
+//! most scans are never scraped and short-lived processes fall between the
+//! samples. Both cases are exported per client and listed on `/health`.
+
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use prometheus::core::Collector;
+use prometheus::proto::Metric;
+use prometheus::GaugeVec;
+use std::fmt::Write as FmtWrite;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Ratio between scrape interval and cache_ttl reported as a mismatch.
+pub const MISMATCH_FACTOR: f64 = 4.0;
+/// Maximum number of client/path pairs tracked.
+pub const MAX_TRACKED_CLIENTS: usize = 64;
+/// Clients not seen for this long are forgotten.
+pub const CLIENT_EXPIRY: Duration = Duration::from_secs(3600);
+/// Weight of the newest interval in the smoothed interval, so a single
+/// manual request does not flip the result.
+const SMOOTHING: f64 = 0.3;
+
+/// How a scrape interval relates to the cache refresh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Several scrapes per refresh return the same snapshot
+    TooFrequent,
+    /// Several refreshes per scrape are never exported
+    TooRare,
+}
+
+impl Mismatch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::TooFrequent => "too_frequent",
+            Self::TooRare => "too_rare",
+        }
+    }
+
+    /// Classifies a scrape interval against the cache refresh interval.
+    ///
+    /// In `on_scrape` mode scans follow the scrapes, so rare scrapes are fine.
+    pub fn classify(interval_secs: f64, cache_ttl_secs: f64, on_scrape: bool) -> Option<Self> {
+        if cache_ttl_secs <= 0.0 {
+            return None;
+        }
+        if interval_secs * MISMATCH_FACTOR < cache_ttl_secs {
+            Some(Self::TooFrequent)
+        } else if !on_scrape && interval_secs > cache_ttl_secs * MISMATCH_FACTOR {
+            Some(Self::TooRare)
+        } else {
+            None
+        }
+    }
+}
+
+struct Client {
+    last_scrape: Instant,
+    /// Smoothed interval, None until the second scrape
+    interval_secs: Option<f64>,
+}
+
+/// Scrape intervals observed per client and path.
+#[derive(Default)]
+pub struct ScrapeIntervals {
+    clients: Mutex<HashMap<(String, String), Client>>,
+}
+
+impl ScrapeIntervals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a scrape of `path` by `client` (unknown without connect info).
+    pub fn record(&self, client: Option<IpAddr>, path: &str) {
+        self.record_at(client, path, Instant::now());
+    }
+
+    fn record_at(&self, client: Option<IpAddr>, path: &str, now: Instant) {
+        let client = client.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.retain(|_, c| now.duration_since(c.last_scrape) < CLIENT_EXPIRY);
+
+        let key = (client, path.to_string());
+        if let Some(entry) = clients.get_mut(&key) {
+            let interval = now.duration_since(entry.last_scrape).as_secs_f64();
+            entry.interval_secs = Some(match entry.interval_secs {
+                Some(previous) => previous + SMOOTHING * (interval - previous),
+                None => interval,
+            });
+            entry.last_scrape = now;
+        } else if clients.len() < MAX_TRACKED_CLIENTS {
+            clients.insert(
+                key,
+                Client {
+                    last_scrape: now,
+                    interval_secs: None,
+                },
+            );
+        }
+    }
+
+    /// Client, path, smoothed interval and mismatch of every client seen at
+    /// least twice, sorted by client and path.
+    pub fn snapshot(
+        &self,
+        cache_ttl_secs: f64,
+        on_scrape: bool,
+    ) -> Vec<(String, String, f64, Option<Mismatch>)> {
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let mut rows: Vec<_> = clients
+            .iter()
+            .filter_map(|((client, path), c)| {
+                let interval = c.interval_secs?;
+                Some((
+                    client.clone(),
+                    path.clone(),
+                    interval,
+                    Mismatch::classify(interval, cache_ttl_secs, on_scrape),
+                ))
+            })
+            .collect();
+        rows.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        rows
+    }
+
+    /// Sets the interval and mismatch gauges from the current observations.
+    ///
+    /// Series of forgotten clients are removed after the others are set, so
+    /// a concurrent scrape never sees the families empty.
+    pub fn export(
+        &self,
+        interval: &GaugeVec,
+        mismatch: &GaugeVec,
+        cache_ttl_secs: f64,
+        on_scrape: bool,
+    ) {
+        let mut current = HashSet::new();
+        for (client, path, secs, result) in self.snapshot(cache_ttl_secs, on_scrape) {
+            interval.with_label_values(&[&client, &path]).set(secs);
+            for kind in [Mismatch::TooFrequent, Mismatch::TooRare] {
+                mismatch
+                    .with_label_values(&[&client, &path, kind.as_str()])
+                    .set(if result == Some(kind) { 1.0 } else { 0.0 });
+            }
+            current.insert((client, path));
+        }
+        remove_stale(interval, &current);
+        remove_stale(mismatch, &current);
+    }
+
+    /// Plain-text notes for /health, empty when all intervals match.
+    pub fn render_notes(&self, cache_ttl_secs: f64, on_scrape: bool) -> String {
+        let mut out = String::new();
+        for (client, path, secs, result) in self.snapshot(cache_ttl_secs, on_scrape) {
+            let Some(result) = result else {
+                continue;
+            };
+            if out.is_empty() {
+                writeln!(out, "SCRAPE INTERVAL WARNINGS").ok();
+                writeln!(out, "========================").ok();
+                writeln!(out).ok();
+            }
+            let advice = match result {
+                Mismatch::TooFrequent => "raise scrape_interval or lower cache_ttl",
+                Mismatch::TooRare => "lower scrape_interval or raise cache_ttl",
+            };
+            writeln!(
+                out,
+                "{} {}: scraped every {:.0}s, cache_ttl {:.0}s ({}) - {}",
+                client,
+                path,
+                secs,
+                cache_ttl_secs,
+                result.as_str(),
+                advice
+            )
+            .ok();
+        }
+        if !out.is_empty() {
+            writeln!(out).ok();
+        }
+        out
+    }
+}
+
+/// Removes the series of `vec` whose client and path are not in `current`.
+fn remove_stale(vec: &GaugeVec, current: &HashSet<(String, String)>) {
+    for family in vec.collect() {
+        for metric in family.get_metric() {
+            let label = |name: &str| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|l| l.name() == name)
+                    .map_or("", |l| l.value())
+            };
+            let key = (label("client").to_string(), label("path").to_string());
+            if !current.contains(&key) {
+                let _ = vec.remove(&metric_labels(metric));
+            }
+        }
+    }
+}
+
+/// Label names and values of a collected series.
+fn metric_labels(metric: &Metric) -> std::collections::HashMap<&str, &str> {
+    metric
+        .get_label()
+        .iter()
+        .map(|l| (l.name(), l.value()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            Mismatch::classify(5.0, 30.0, false),
+            Some(Mismatch::TooFrequent)
+        );
+        assert_eq!(Mismatch::classify(15.0, 30.0, false), None);
+        assert_eq!(Mismatch::classify(60.0, 30.0, false), None);
+        assert_eq!(
+            Mismatch::classify(300.0, 30.0, false),
+            Some(Mismatch::TooRare)
+        );
+        assert_eq!(Mismatch::classify(300.0, 30.0, true), None);
+    }
+
+    #[test]
+    fn test_record_intervals() {
+        let intervals = ScrapeIntervals::new();
+        let client: Option<IpAddr> = Some("10.0.0.5".parse().unwrap());
+        let start = Instant::now();
+
+        intervals.record_at(client, "/metrics", start);
+        assert!(intervals.snapshot(30.0, false).is_empty());
+
+        for i in 1..=3 {
+            intervals.record_at(client, "/metrics", start + Duration::from_secs(5 * i));
+        }
+        intervals.record_at(None, "/metrics", start);
+        let rows = intervals.snapshot(30.0, false);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].0, "10.0.0.5");
+        assert!((rows[0].2 - 5.0).abs() < 1e-9);
+        assert_eq!(rows[0].3, Some(Mismatch::TooFrequent));
+        assert!(intervals
+            .render_notes(30.0, false)
+            .contains("10.0.0.5 /metrics: scraped every 5s"));
+        assert!(intervals.render_notes(10.0, false).is_empty());
+
+        // Idle clients expire
+        intervals.record_at(
+            None,
+            "/metrics",
+            start + CLIENT_EXPIRY + Duration::from_secs(20),
+        );
+        assert!(intervals.snapshot(30.0, false).is_empty());
+    }
+
+    #[test]
+    fn test_export_removes_forgotten_clients() {
+        let interval = GaugeVec::new(prometheus::Opts::new("i", "i"), &["client", "path"]).unwrap();
+        let mismatch = GaugeVec::new(
+            prometheus::Opts::new("m", "m"),
+            &["client", "path", "reason"],
+        )
+        .unwrap();
+        let intervals = ScrapeIntervals::new();
+        let client: Option<IpAddr> = Some("10.0.0.5".parse().unwrap());
+        let start = Instant::now();
+        intervals.record_at(client, "/metrics", start);
+        intervals.record_at(client, "/metrics", start + Duration::from_secs(15));
+
+        intervals.export(&interval, &mismatch, 30.0, false);
+        assert_eq!(
+            interval.with_label_values(&["10.0.0.5", "/metrics"]).get(),
+            15.0
+        );
+        assert_eq!(mismatch.collect()[0].get_metric().len(), 2);
+
+        intervals.record_at(
+            None,
+            "/metrics",
+            start + CLIENT_EXPIRY + Duration::from_secs(20),
+        );
+        intervals.export(&interval, &mismatch, 30.0, false);
+        assert!(interval.collect()[0].get_metric().is_empty());
+        assert!(mismatch.collect()[0].get_metric().is_empty());
+    }
+}
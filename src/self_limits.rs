@@ -0,0 +1,241 @@
+//! Self resource limits for the exporter process.
+//!
+//! This module reads the exporter's own resource usage from `/proc/self` and
+//! enforces the optional `self_limits` configuration: scan thread niceness,
+//! an address-space rlimit, a scan CPU duty cycle and an RSS cap that aborts
+//! or truncates a running scan.
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::config::SelfLimitsConfig;
+use crate::process::cpu::parse_cpu_time_seconds;
+
+/// Number of processed entries between two RSS checks during a scan.
+const RSS_CHECK_INTERVAL: usize = 64;
+
+/// Resource usage of the exporter process itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SelfUsage {
+    pub rss_bytes: u64,
+    /// User + system CPU time since start
+    pub cpu_seconds: f64,
+    pub open_fds: u64,
+    pub threads: u64,
+}
+
+/// Reads RSS, CPU time, open file descriptors and threads from /proc/self.
+/// Unreadable values are zero.
+pub fn read_self_usage() -> SelfUsage {
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let field = |name: &str| -> u64 {
+        status
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .and_then(|v| v.split_whitespace().next()?.parse().ok())
+            .unwrap_or(0)
+    };
+    SelfUsage {
+        rss_bytes: field("VmRSS:") * 1024,
+        cpu_seconds: parse_cpu_time_seconds(Path::new("/proc/self")).unwrap_or(0.0),
+        // Includes the descriptor used for the listing itself
+        open_fds: fs::read_dir("/proc/self/fd").map_or(0, |d| d.count() as u64),
+        threads: field("Threads:"),
+    }
+}
+
+/// Reads the exporter's own memory and CPU usage from /proc/self.
+pub fn read_self_resources() -> (f64, f64) {
+    let memory_mb = read_self_memory_mb().unwrap_or(0.0);
+    let cpu_percent = read_self_cpu_percent().unwrap_or(0.0);
+    (memory_mb, cpu_percent)
+}
+
+/// Reads the exporter's RSS memory usage from /proc/self/status.
+pub fn read_self_memory_mb() -> Option<f64> {
+    let content = fs::read_to_string("/proc/self/status").ok()?;
+    for line in content.lines() {
+        if let Some(value) = line.strip_prefix("VmRSS:") {
+            let kb: u64 = value.split_whitespace().next()?.parse().ok()?;
+            return Some(kb as f64 / 1024.0);
+        }
+    }
+    None
+}
+
+/// Reads the exporter's CPU usage from /proc/self/stat.
+pub fn read_self_cpu_percent() -> Option<f64> {
+    let cpu_time_seconds = parse_cpu_time_seconds(Path::new("/proc/self")).ok()?;
+
+    let uptime_content = fs::read_to_string("/proc/uptime").ok()?;
+    let uptime_seconds: f64 = uptime_content.split_whitespace().next()?.parse().ok()?;
+
+    if uptime_seconds > 0.0 {
+        Some((cpu_time_seconds / uptime_seconds) * 100.0)
+    } else {
+        None
+    }
+}
+
+/// Applies process-wide limits (currently the address-space rlimit).
+pub fn apply_process_limits(limits: &SelfLimitsConfig) {
+    let Some(mb) = limits.rlimit_as_mb else {
+        return;
+    };
+
+    let bytes = mb.saturating_mul(1024 * 1024) as libc::rlim_t;
+    let rlim = libc::rlimit {
+        rlim_cur: bytes,
+        rlim_max: bytes,
+    };
+
+    // SAFETY: setrlimit only reads the provided struct.
+    let rc = unsafe { libc::setrlimit(libc::RLIMIT_AS, &rlim) };
+    if rc == 0 {
+        debug!("Address space rlimit set to {} MB", mb);
+    } else {
+        warn!(
+            "Failed to set address space rlimit to {} MB: {}",
+            mb,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Sets the nice value of the calling thread.
+///
+/// On Linux `setpriority(PRIO_PROCESS, tid)` only affects the given thread,
+/// so this is used as the rayon start handler to renice scan workers without
+/// touching the HTTP runtime threads.
+pub fn renice_current_thread(nice: i32) {
+    // SAFETY: gettid has no preconditions.
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+
+    // SAFETY: setpriority has no memory safety preconditions.
+    let rc = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) };
+    if rc != 0 {
+        warn!(
+            "Failed to set nice {} for scan thread {}: {}",
+            nice,
+            tid,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Per-scan guard enforcing the RSS cap and the scan CPU duty cycle.
+pub struct ScanGuard {
+    max_rss_mb: Option<f64>,
+    max_cpu_percent: Option<f64>,
+    processed: AtomicUsize,
+    exceeded: AtomicBool,
+}
+
+impl ScanGuard {
+    /// Creates a guard for a single scan from the configured limits.
+    pub fn new(limits: Option<&SelfLimitsConfig>) -> Self {
+        Self {
+            max_rss_mb: limits.and_then(|l| l.max_rss_mb).map(|mb| mb as f64),
+            max_cpu_percent: limits
+                .and_then(|l| l.max_scan_cpu_percent)
+                .filter(|p| *p > 0.0 && *p < 100.0),
+            processed: AtomicUsize::new(0),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns true if the exporter is already above its RSS cap.
+    pub fn rss_exceeded_now(&self) -> bool {
+        match (self.max_rss_mb, read_self_memory_mb()) {
+            (Some(max), Some(current)) => current > max,
+            _ => false,
+        }
+    }
+
+    /// Checks the RSS cap every few processed entries.
+    ///
+    /// Returns true once the cap has been exceeded; the remaining entries of
+    /// the scan should then be skipped.
+    pub fn should_stop(&self) -> bool {
+        if self.max_rss_mb.is_none() {
+            return false;
+        }
+        if self.exceeded.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        let n = self.processed.fetch_add(1, Ordering::Relaxed);
+        if n.is_multiple_of(RSS_CHECK_INTERVAL) && self.rss_exceeded_now() {
+            self.exceeded.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    /// Whether the RSS cap was hit during this scan.
+    pub fn was_truncated(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+
+    /// Sleeps long enough after `busy` of work to honor the CPU duty cycle.
+    pub fn throttle(&self, busy: Duration) {
+        if let Some(pct) = self.max_cpu_percent {
+            std::thread::sleep(throttle_delay(busy, pct));
+        }
+    }
+}
+
+/// Computes the idle time needed after `busy` to stay at `max_percent` CPU.
+fn throttle_delay(busy: Duration, max_percent: f64) -> Duration {
+    if max_percent <= 0.0 || max_percent >= 100.0 {
+        return Duration::ZERO;
+    }
+    busy.mul_f64(100.0 / max_percent - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_self_usage() {
+        let usage = read_self_usage();
+        assert!(usage.rss_bytes > 0);
+        assert!(usage.open_fds >= 3);
+        assert!(usage.threads >= 1);
+        assert!(usage.cpu_seconds >= 0.0);
+    }
+
+    #[test]
+    fn test_throttle_delay() {
+        let busy = Duration::from_millis(10);
+        assert_eq!(throttle_delay(busy, 50.0), Duration::from_millis(10));
+        assert_eq!(throttle_delay(busy, 25.0), Duration::from_millis(30));
+        assert_eq!(throttle_delay(busy, 100.0), Duration::ZERO);
+        assert_eq!(throttle_delay(busy, 0.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_scan_guard_without_limits() {
+        let guard = ScanGuard::new(None);
+        for _ in 0..1000 {
+            assert!(!guard.should_stop());
+        }
+        assert!(!guard.was_truncated());
+        assert!(!guard.rss_exceeded_now());
+    }
+
+    #[test]
+    fn test_scan_guard_tiny_rss_cap() {
+        let limits = SelfLimitsConfig {
+            max_rss_mb: Some(0),
+            ..Default::default()
+        };
+        let guard = ScanGuard::new(Some(&limits));
+        assert!(guard.should_stop());
+        assert!(guard.was_truncated());
+    }
+}
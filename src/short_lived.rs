@@ -0,0 +1,236 @@
+//! Accounting of processes that start and exit between two scans.
+//!
+//! The periodic scan only sees processes alive at scan time, so on cron and
+//! CI hosts most of the memory and CPU is spent by processes that never show
+//! up. With `short_lived` configured, a poller lists `/proc` every
+//! `poll_interval_ms`, follows every PID born since the last scan and keeps
+//! its peak RSS (`VmHWM`) and CPU time. Processes that exit before a scan sees
+//! them are handed to the collector, which attributes them to the
+//! `short_lived` pseudo-subgroup of their group. Processes living shorter
+//! than one poll interval are still missed.
+
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::process::{collect_proc_entries, read_proc_stat, read_process_name, MaxProcessesBy};
+
+/// Poll interval of the /proc listing by default.
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+/// Subgroup label of short-lived process metrics.
+pub const SHORT_LIVED_SUBGROUP: &str = "short_lived";
+/// Upper bound of followed processes, so a fork bomb cannot grow the tracker.
+pub const MAX_TRACKED: usize = 10_000;
+
+/// A process that exited before any scan saw it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShortLivedProcess {
+    pub name: String,
+    /// Highest observed VmHWM in bytes
+    pub peak_rss_bytes: u64,
+    /// Last observed user + system CPU time in clock ticks
+    pub cpu_ticks: u64,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    start_time_ticks: u64,
+    process: ShortLivedProcess,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// PIDs alive at the last scan (or at the first poll), not followed
+    seen_by_scan: HashSet<u32>,
+    tracked: HashMap<u32, Tracked>,
+    /// Exited since the last scan, drained by the collector
+    exited: Vec<ShortLivedProcess>,
+    initialized: bool,
+}
+
+/// Follows processes born between scans.
+#[derive(Default)]
+pub struct ShortLivedTracker {
+    inner: Mutex<Inner>,
+}
+
+impl ShortLivedTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lists /proc once; `include` filters processes by name.
+    pub fn poll(&self, include: impl Fn(&str) -> bool) {
+        self.poll_root("/proc", include);
+    }
+
+    fn poll_root(&self, root: &str, include: impl Fn(&str) -> bool) {
+        let entries = collect_proc_entries(root, None, MaxProcessesBy::Pid);
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Everything alive at startup counts as seen by a scan
+        if !inner.initialized {
+            inner.seen_by_scan = entries.iter().map(|e| e.pid).collect();
+            inner.initialized = true;
+            return;
+        }
+
+        let alive: HashSet<u32> = entries.iter().map(|e| e.pid).collect();
+        inner.seen_by_scan.retain(|pid| alive.contains(pid));
+        let gone: Vec<u32> = inner
+            .tracked
+            .keys()
+            .filter(|pid| !alive.contains(pid))
+            .copied()
+            .collect();
+        for pid in gone {
+            inner.finish(pid);
+        }
+
+        for entry in &entries {
+            if inner.seen_by_scan.contains(&entry.pid) {
+                continue;
+            }
+            let Some((start_time_ticks, cpu_ticks, peak_rss_bytes)) = read_usage(&entry.proc_path)
+            else {
+                // Exited between the listing and the read
+                inner.finish(entry.pid);
+                continue;
+            };
+
+            match inner.tracked.get_mut(&entry.pid) {
+                Some(t) if t.start_time_ticks == start_time_ticks => {
+                    t.process.peak_rss_bytes = t.process.peak_rss_bytes.max(peak_rss_bytes);
+                    t.process.cpu_ticks = t.process.cpu_ticks.max(cpu_ticks);
+                    continue;
+                }
+                // PID reused since the last poll
+                Some(_) => inner.finish(entry.pid),
+                None => {}
+            }
+
+            let Some(name) = read_process_name(&entry.proc_path) else {
+                continue;
+            };
+            if !include(&name) {
+                // Not followed until the PID disappears
+                inner.seen_by_scan.insert(entry.pid);
+                continue;
+            }
+            if inner.tracked.len() < MAX_TRACKED {
+                inner.tracked.insert(
+                    entry.pid,
+                    Tracked {
+                        start_time_ticks,
+                        process: ShortLivedProcess {
+                            name,
+                            peak_rss_bytes,
+                            cpu_ticks,
+                        },
+                    },
+                );
+            }
+        }
+    }
+
+    /// Called after every scan: processes still alive were seen by it and
+    /// are no longer followed. Returns the processes that exited before.
+    pub fn scan_completed(&self) -> Vec<ShortLivedProcess> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let tracked: Vec<u32> = inner.tracked.drain().map(|(pid, _)| pid).collect();
+        inner.seen_by_scan.extend(tracked);
+        std::mem::take(&mut inner.exited)
+    }
+}
+
+impl Inner {
+    fn finish(&mut self, pid: u32) {
+        if let Some(t) = self.tracked.remove(&pid) {
+            self.exited.push(t.process);
+        }
+    }
+}
+
+/// Start time, CPU ticks and peak RSS in bytes of a process; None for
+/// kernel threads (no VmHWM) and processes that are gone.
+fn read_usage(proc_path: &Path) -> Option<(u64, u64, u64)> {
+    let stat = read_proc_stat(proc_path).ok()?;
+    let status = fs::read_to_string(proc_path.join("status")).ok()?;
+    let peak_kb: u64 = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmHWM:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    Some((stat.start_time, stat.cpu_ticks(), peak_kb * 1024))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(root: &Path, pid: u32, name: &str, start: u64, cpu: u64, hwm_kb: u64) {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("smaps_rollup"), "").unwrap();
+        fs::write(dir.join("comm"), format!("{}\n", name)).unwrap();
+        fs::write(
+            dir.join("stat"),
+            format!(
+                "{} ({}) S 1 {} {} 0 0 0 0 0 0 0 {} 0 0 0 20 0 1 0 {} 0 0",
+                pid, name, pid, pid, cpu, start
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("status"),
+            format!("Name:\t{}\nVmHWM:\t{} kB\n", name, hwm_kb),
+        )
+        .unwrap();
+    }
+
+    fn exit(root: &Path, pid: u32) {
+        fs::remove_dir_all(root.join(pid.to_string())).unwrap();
+    }
+
+    #[test]
+    fn test_short_lived_tracking() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let root_str = root.to_str().unwrap();
+        let tracker = ShortLivedTracker::new();
+        let all = |_: &str| true;
+
+        // Alive at startup: never short-lived
+        spawn(root, 10, "sshd", 100, 5, 4000);
+        tracker.poll_root(root_str, all);
+
+        spawn(root, 20, "gcc", 200, 10, 1000);
+        spawn(root, 21, "make", 210, 1, 500);
+        tracker.poll_root(root_str, all);
+        spawn(root, 20, "gcc", 200, 30, 3000);
+        tracker.poll_root(root_str, all);
+        exit(root, 20);
+        exit(root, 10);
+        tracker.poll_root(root_str, all);
+
+        assert_eq!(
+            tracker.scan_completed(),
+            [ShortLivedProcess {
+                name: "gcc".to_string(),
+                peak_rss_bytes: 3000 * 1024,
+                cpu_ticks: 30,
+            }]
+        );
+
+        // make was alive at the scan
+        exit(root, 21);
+        spawn(root, 22, "cron", 220, 2, 700);
+        tracker.poll_root(root_str, |name| name != "cron");
+        exit(root, 22);
+        tracker.poll_root(root_str, all);
+        assert!(tracker.scan_completed().is_empty());
+    }
+}
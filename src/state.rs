@@ -5,16 +5,27 @@
 
 use ahash::AHashMap as HashMap;
 use herakles_proc_mem_exporter::HealthState;
-use prometheus::{Gauge, Registry};
+use prometheus::{Counter, CounterVec, Gauge, GaugeVec, IntCounterVec, Registry};
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, RwLock as StdRwLock};
-use tokio::sync::{Notify, RwLock};
+use tokio::sync::{Mutex, Notify, RwLock};
 
-use crate::cache::MetricsCache;
+use crate::audit::AuditLog;
+use crate::autotune::ScanTuner;
+use crate::cache::{MetricsCache, ProcKey};
 use crate::config::Config;
+use crate::exposition_stats::ExpositionStats;
+use crate::federation::FederationCache;
 use crate::health_stats::HealthStats;
-use crate::metrics::MemoryMetrics;
-use crate::process::{BufferConfig, CpuEntry};
+use crate::history::SnapshotHistory;
+use crate::logging::LogControl;
+use crate::process::{BufferConfig, CpuEntry, LabelExtractors, TaskstatsPool};
+use crate::scrape_intervals::ScrapeIntervals;
+use crate::short_lived::ShortLivedTracker;
 use crate::system::CpuStatsCache;
+use crate::topn_hysteresis::TopNHysteresis;
+use crate::unclassified::UnclassifiedTracker;
 
 /// Type alias for shared application state.
 pub type SharedState = Arc<AppState>;
@@ -22,21 +33,107 @@ pub type SharedState = Arc<AppState>;
 /// Global application state shared across requests and background tasks.
 pub struct AppState {
     pub registry: Registry,
-    pub metrics: MemoryMetrics,
     pub scrape_duration: Gauge,
     pub processes_total: Gauge,
+    /// Sequence number of the snapshot behind the last scrape.
+    pub scan_id: Gauge,
     pub cache_update_duration: Gauge,
     pub cache_update_success: Gauge,
     pub cache_updating: Gauge,
+    /// Whether the served processes were restored from `cache_snapshot`.
+    pub cache_restored: Gauge,
+    /// Scans aborted/truncated by the exporter's self limits.
+    pub self_limit_events: IntCounterVec,
+    /// The exporter's own RSS, CPU time, open file descriptors and threads,
+    /// updated after every cache update.
+    pub self_rss_bytes: Gauge,
+    pub self_cpu_seconds: Counter,
+    pub self_open_fds: Gauge,
+    pub self_threads: Gauge,
+    /// Threads used by the last scan.
+    pub scan_threads: Gauge,
+    /// Statistics of the last full exposition, for /debug/exposition.
+    pub exposition_stats: StdRwLock<Option<ExpositionStats>>,
+    /// 1 while serving without process metrics because the initial scan
+    /// exceeded `startup_scan_timeout_seconds`.
+    pub initializing: Gauge,
+    /// Processes left out of the last scan by `max_processes`.
+    pub processes_capped: Gauge,
+    /// Zombie processes seen by the last scan, filtered ones included.
+    pub zombie_processes: Gauge,
+    /// Share of CPU time stolen by the hypervisor between the last two scans.
+    pub cpu_steal_ratio: Gauge,
+    /// Adjusts the scan thread pool between scans (`scan_autotune`).
+    pub scan_tuner: Option<ScanTuner>,
+    /// Failed /proc reads by error kind and source.
+    pub collect_errors: IntCounterVec,
+    /// Processes appearing between consecutive scans per subgroup.
+    pub process_starts: IntCounterVec,
+    /// Processes disappearing between consecutive scans per subgroup.
+    pub process_exits: IntCounterVec,
+    /// Processes that started and exited between scans per group (`short_lived`).
+    pub short_lived_processes: IntCounterVec,
+    /// CPU seconds of those processes.
+    pub short_lived_cpu_seconds: CounterVec,
+    /// Largest peak RSS among those that exited before the last scan.
+    pub short_lived_peak_rss: GaugeVec,
+    /// Processes born since the last scan, followed by the `short_lived` poller.
+    pub short_lived: ShortLivedTracker,
+    /// Classification rule hits of the last scan (`enable_rule_hit_metrics`).
+    pub rule_hits: GaugeVec,
+    /// Probed /proc features (1 = available).
+    pub capability: GaugeVec,
+    /// Whether the last scrape of a federation target succeeded.
+    pub federation_up: GaugeVec,
+    /// Duration of the last scrape of a federation target.
+    pub federation_scrape_duration: GaugeVec,
+    /// Smoothed scrape interval per client and path.
+    pub scrape_interval: GaugeVec,
+    /// Scrape intervals that do not match cache_ttl, by reason.
+    pub scrape_interval_mismatch: GaugeVec,
+    /// Scrape times per client, source of the two gauges above.
+    pub scrape_intervals: ScrapeIntervals,
+    /// Scrapes answered with less than the full exposition to meet their timeout.
+    pub scrapes_truncated: IntCounterVec,
+    /// Size of the SQLite history, absent without `history_db`.
+    pub history_db_bytes: GaugeVec,
+    /// Render time of the last full /metrics exposition in microseconds,
+    /// the estimate for whether the next one fits into a scrape timeout.
+    pub full_render_micros: AtomicU64,
+    /// Last scrape results of federation targets.
+    pub federation_cache: FederationCache,
     pub cache: Arc<RwLock<MetricsCache>>,
     pub config: Arc<Config>,
     pub buffer_config: BufferConfig,
-    pub cpu_cache: StdRwLock<HashMap<u32, CpuEntry>>,
+    pub cpu_cache: StdRwLock<HashMap<ProcKey, CpuEntry>>,
+    /// CPU times of threads by TID and start time (`per_thread`).
+    pub thread_cpu_cache: StdRwLock<HashMap<ProcKey, CpuEntry>>,
     pub health_stats: Arc<HealthStats>,
     /// Health state for buffer monitoring.
     pub health_state: Arc<HealthState>,
     /// Notification for cache update completion.
     pub cache_ready: Arc<Notify>,
+    /// Serializes on-demand scans in `collection_mode: on_scrape`.
+    pub scan_lock: Mutex<()>,
+    /// Reload handle for the active log level.
+    pub log_control: LogControl,
     /// CPU statistics cache for calculating usage ratios.
     pub system_cpu_cache: CpuStatsCache,
+    /// /proc/stat of the last scan, for `cpu_steal_ratio`.
+    pub scan_cpu_cache: CpuStatsCache,
+    /// Top-N memberships across scans (`top_n_hysteresis_scans`).
+    pub topn_hysteresis: TopNHysteresis,
+    /// Unclassified process names seen since startup.
+    pub unclassified: UnclassifiedTracker,
+    /// Subgroup totals of recent scans (see `history::history_enabled`).
+    pub history: SnapshotHistory,
+    /// Compiled `label_extractors`, applied to every scanned process.
+    pub label_extractors: LabelExtractors,
+    /// UIDs of `exclude_users`, resolved at startup.
+    pub excluded_uids: HashSet<u32>,
+    /// Taskstats sockets of `enable_delay_metrics`, None if disabled or
+    /// unavailable.
+    pub taskstats: Option<TaskstatsPool>,
+    /// Audit log of state-changing requests (`audit_log`).
+    pub audit: AuditLog,
 }
@@ -1,18 +1,39 @@
 //! System-wide metrics collection from /proc filesystem.
 //!
 //! This module provides functions to read system-wide metrics such as
-//! load average, total RAM, and total SWAP from the /proc filesystem.
+//! load average, total RAM, total SWAP, boot time, kernel version and kernel
+//! table limits from the /proc filesystem, and the compressed swap pools of zram devices and
+//! zswap and the memory balloon of virtual machines from /sys, and the ZFS ARC.
 
 use std::collections::HashMap;
 use std::fs;
+use std::io;
+use std::path::Path;
 use std::sync::RwLock;
 
+use herakles_proc_mem_exporter::CollectError;
+use once_cell::sync::Lazy;
+
 /// System load averages for 1, 5, and 15 minute intervals.
 #[derive(Debug, Clone, Copy)]
 pub struct LoadAverage {
     pub one_min: f64,
     pub five_min: f64,
     pub fifteen_min: f64,
+    /// Kernel scheduling entities (processes and threads) currently existing
+    pub tasks: Option<u64>,
+}
+
+/// Usage and limits of kernel tables that can run full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelLimits {
+    /// Allocated file handles (first minus second field of file-nr)
+    pub file_handles: u64,
+    pub file_max: u64,
+    pub pid_max: u64,
+    pub threads_max: u64,
+    /// Available entropy in bits, None if not readable
+    pub entropy_bits: Option<u64>,
 }
 
 /// Extended memory information including available memory.
@@ -38,7 +59,14 @@ pub struct CpuStat {
 impl CpuStat {
     /// Calculate total CPU time (all fields).
     pub fn total(&self) -> u64 {
-        self.user + self.nice + self.system + self.idle + self.iowait + self.irq + self.softirq + self.steal
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
     }
 
     /// Calculate non-active time (idle + iowait).
@@ -46,47 +74,201 @@ impl CpuStat {
     pub fn idle_total(&self) -> u64 {
         self.idle + self.iowait
     }
+
+    /// Time shares since `previous`; all zero if no time elapsed.
+    ///
+    /// iowait may go backwards on some kernels, so deltas saturate at zero.
+    pub fn ratios_since(&self, previous: &CpuStat) -> CpuRatios {
+        let delta_total = self.total().saturating_sub(previous.total());
+        if delta_total == 0 {
+            return CpuRatios::default();
+        }
+        let share = |delta: u64| delta as f64 / delta_total as f64;
+        let delta_non_active = self.idle_total().saturating_sub(previous.idle_total());
+        CpuRatios {
+            usage: share(delta_total.saturating_sub(delta_non_active)),
+            iowait: share(self.iowait.saturating_sub(previous.iowait)),
+            steal: share(self.steal.saturating_sub(previous.steal)),
+        }
+    }
+}
+
+/// CPU time shares between two /proc/stat readings, each 0.0 to 1.0.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuRatios {
+    /// Busy time (everything except idle and iowait)
+    pub usage: f64,
+    /// Idle time with outstanding disk I/O
+    pub iowait: f64,
+    /// Time the hypervisor ran other guests
+    pub steal: f64,
+}
+
+/// Release and build version of the running kernel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelInfo {
+    /// e.g. "6.8.0-45-generic"
+    pub release: String,
+    /// e.g. "#45-Ubuntu SMP PREEMPT_DYNAMIC Fri Aug 30 12:02:04 UTC 2024"
+    pub version: String,
+}
+
+/// Word sizes of the exporter build and of the kernel it runs on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataModel {
+    /// "ILP32" or "LP64", the data model the exporter was built for
+    pub model: &'static str,
+    /// Machine as reported by uname(2), e.g. "armv7l" or "aarch64"
+    pub machine: String,
+    /// Word size of the kernel, 32 or 64
+    pub kernel_bits: u32,
+}
+
+/// Compressed RAM disk usage of one zram device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZramDevice {
+    /// Device name, e.g. "zram0"
+    pub name: String,
+    /// Configured size of the device
+    pub disksize_bytes: u64,
+    /// Uncompressed size of the stored data
+    pub orig_data_bytes: u64,
+    /// Compressed size of the stored data
+    pub compr_data_bytes: u64,
+    /// Memory used including allocator overhead
+    pub mem_used_bytes: u64,
 }
 
+/// Usage of the zswap compressed cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZswapInfo {
+    /// Memory used by the compressed pool
+    pub pool_bytes: u64,
+    /// Pages stored in the pool
+    pub stored_pages: u64,
+}
+
+/// Size and targets of the ZFS adaptive replacement cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZfsArcInfo {
+    /// Current size (`size`)
+    pub size_bytes: u64,
+    /// Size the ARC adapts towards (`c`)
+    pub target_bytes: u64,
+    /// Bounds of the target (`c_min`, `c_max`)
+    pub min_bytes: u64,
+    pub max_bytes: u64,
+}
+
+/// System collectors that `system_collectors` can select, with the
+/// prefixes of the metric families each one produces.
+pub const SYSTEM_COLLECTORS: &[(&str, &[&str])] = &[
+    ("loadavg", &["herakles_system_load"]),
+    ("meminfo", &["herakles_system_memory_"]),
+    ("cpustat", &["herakles_system_cpu_"]),
+    (
+        "kernel",
+        &["herakles_system_boot_time_", "herakles_system_kernel_info"],
+    ),
+    (
+        "limits",
+        &[
+            "herakles_system_file_handles",
+            "herakles_system_tasks",
+            "herakles_system_pid_",
+            "herakles_system_threads_",
+            "herakles_system_entropy_",
+        ],
+    ),
+    ("zram", &["herakles_system_zram_", "herakles_system_zswap_"]),
+    ("zfs", &["herakles_system_zfs_"]),
+];
+
+/// Whether `name` is enabled; all collectors are without an allowlist.
+pub fn collector_enabled(allowlist: Option<&[String]>, name: &str) -> bool {
+    allowlist.is_none_or(|names| names.iter().any(|n| n == name))
+}
+
+/// Whether a metric family belongs to a system collector not in the allowlist.
+pub fn family_disabled(allowlist: Option<&[String]>, family: &str) -> bool {
+    SYSTEM_COLLECTORS.iter().any(|(name, prefixes)| {
+        !collector_enabled(allowlist, name) && prefixes.iter().any(|p| family.starts_with(p))
+    })
+}
+
+/// Path of the kernel load average file.
+const LOADAVG_PATH: &str = "/proc/loadavg";
+/// Path of the kernel memory statistics file.
+const MEMINFO_PATH: &str = "/proc/meminfo";
+/// Path of the kernel CPU statistics file.
+const STAT_PATH: &str = "/proc/stat";
+/// Paths of the kernel release and version strings.
+const OSRELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+const KERNEL_VERSION_PATH: &str = "/proc/sys/kernel/version";
+/// Paths of the kernel table limits.
+const FILE_NR_PATH: &str = "/proc/sys/fs/file-nr";
+const PID_MAX_PATH: &str = "/proc/sys/kernel/pid_max";
+const THREADS_MAX_PATH: &str = "/proc/sys/kernel/threads-max";
+const ENTROPY_AVAIL_PATH: &str = "/proc/sys/kernel/random/entropy_avail";
+/// Directory of block devices, including zram devices.
+const SYS_BLOCK_PATH: &str = "/sys/block";
+/// Directory of the zswap statistics (debugfs, usually root only).
+const ZSWAP_DEBUG_PATH: &str = "/sys/kernel/debug/zswap";
+/// Statistics of the virtio memory balloon (debugfs, Linux 6.2+, usually root only).
+const VIRTIO_BALLOON_DEBUG_PATH: &str = "/sys/kernel/debug/virtio-balloon";
+/// Directory of the virtio balloon driver, present in guests with a balloon device.
+const VIRTIO_BALLOON_DRIVER_PATH: &str = "/sys/bus/virtio/drivers/virtio_balloon";
+/// Path of the kernel virtual memory event counters.
+const VMSTAT_PATH: &str = "/proc/vmstat";
+/// Statistics of the ZFS ARC, present while the zfs module is loaded.
+const ZFS_ARCSTATS_PATH: &str = "/proc/spl/kstat/zfs/arcstats";
+
 /// Reads load average from /proc/loadavg.
 ///
 /// Returns the 1, 5, and 15 minute load averages.
-/// Format: "0.00 0.01 0.05 1/234 5678"
-pub fn read_load_average() -> Result<LoadAverage, String> {
-    let content = fs::read_to_string("/proc/loadavg")
-        .map_err(|e| format!("Failed to read /proc/loadavg: {}", e))?;
+/// Format: "0.00 0.01 0.05 1/234 5678", where 234 is the number of tasks.
+pub fn read_load_average() -> Result<LoadAverage, CollectError> {
+    let content =
+        fs::read_to_string(LOADAVG_PATH).map_err(|e| CollectError::io(LOADAVG_PATH, e))?;
+    parse_load_average_line(&content)
+}
 
-    let parts: Vec<&str> = content.split_whitespace().collect();
+/// Parses the content of /proc/loadavg.
+fn parse_load_average_line(line: &str) -> Result<LoadAverage, CollectError> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
     if parts.len() < 3 {
-        return Err(format!(
-            "Invalid /proc/loadavg format: expected at least 3 fields, got {}",
-            parts.len()
+        return Err(CollectError::parse(
+            LOADAVG_PATH,
+            format!("expected at least 3 fields, got {}", parts.len()),
         ));
     }
 
-    let one_min = parts[0]
-        .parse::<f64>()
-        .map_err(|e| format!("Failed to parse 1min load average: {}", e))?;
-    let five_min = parts[1]
-        .parse::<f64>()
-        .map_err(|e| format!("Failed to parse 5min load average: {}", e))?;
-    let fifteen_min = parts[2]
-        .parse::<f64>()
-        .map_err(|e| format!("Failed to parse 15min load average: {}", e))?;
+    let field = |idx: usize, name: &str| {
+        parts[idx].parse::<f64>().map_err(|e| {
+            CollectError::parse(
+                LOADAVG_PATH,
+                format!("failed to parse {} load average: {}", name, e),
+            )
+        })
+    };
 
     Ok(LoadAverage {
-        one_min,
-        five_min,
-        fifteen_min,
+        one_min: field(0, "1min")?,
+        five_min: field(1, "5min")?,
+        fifteen_min: field(2, "15min")?,
+        tasks: parts
+            .get(3)
+            .and_then(|f| f.split_once('/'))
+            .and_then(|(_, total)| total.parse().ok()),
     })
 }
 
 /// Reads extended memory information from /proc/meminfo including MemAvailable.
 ///
 /// Returns total and available memory in bytes.
-pub fn read_extended_memory_info() -> Result<ExtendedMemoryInfo, String> {
-    let content = fs::read_to_string("/proc/meminfo")
-        .map_err(|e| format!("Failed to read /proc/meminfo: {}", e))?;
+pub fn read_extended_memory_info() -> Result<ExtendedMemoryInfo, CollectError> {
+    let content =
+        fs::read_to_string(MEMINFO_PATH).map_err(|e| CollectError::io(MEMINFO_PATH, e))?;
 
     let mut total_bytes: Option<u64> = None;
     let mut available_bytes: Option<u64> = None;
@@ -118,7 +300,10 @@ pub fn read_extended_memory_info() -> Result<ExtendedMemoryInfo, String> {
             total_bytes: total,
             available_bytes: available,
         }),
-        _ => Err("Failed to parse MemTotal or MemAvailable from /proc/meminfo".to_string()),
+        _ => Err(CollectError::parse(
+            MEMINFO_PATH,
+            "missing MemTotal or MemAvailable",
+        )),
     }
 }
 
@@ -126,9 +311,8 @@ pub fn read_extended_memory_info() -> Result<ExtendedMemoryInfo, String> {
 ///
 /// Returns a HashMap with CPU name as key and CpuStat as value.
 /// "cpu" represents total across all cores, "cpu0", "cpu1", etc. are individual cores.
-pub fn read_cpu_stats() -> Result<HashMap<String, CpuStat>, String> {
-    let content = fs::read_to_string("/proc/stat")
-        .map_err(|e| format!("Failed to read /proc/stat: {}", e))?;
+pub fn read_cpu_stats() -> Result<HashMap<String, CpuStat>, CollectError> {
+    let content = fs::read_to_string(STAT_PATH).map_err(|e| CollectError::io(STAT_PATH, e))?;
 
     let mut stats = HashMap::new();
 
@@ -140,7 +324,7 @@ pub fn read_cpu_stats() -> Result<HashMap<String, CpuStat>, String> {
             }
 
             let cpu_name = parts[0].to_string();
-            
+
             // Parse CPU time fields
             let user = parts[1].parse::<u64>().unwrap_or(0);
             let nice = parts[2].parse::<u64>().unwrap_or(0);
@@ -149,28 +333,322 @@ pub fn read_cpu_stats() -> Result<HashMap<String, CpuStat>, String> {
             let iowait = parts[5].parse::<u64>().unwrap_or(0);
             let irq = parts[6].parse::<u64>().unwrap_or(0);
             let softirq = parts[7].parse::<u64>().unwrap_or(0);
-            let steal = if parts.len() > 8 { parts[8].parse::<u64>().unwrap_or(0) } else { 0 };
-
-            stats.insert(cpu_name, CpuStat {
-                user,
-                nice,
-                system,
-                idle,
-                iowait,
-                irq,
-                softirq,
-                steal,
-            });
+            let steal = if parts.len() > 8 {
+                parts[8].parse::<u64>().unwrap_or(0)
+            } else {
+                0
+            };
+
+            stats.insert(
+                cpu_name,
+                CpuStat {
+                    user,
+                    nice,
+                    system,
+                    idle,
+                    iowait,
+                    irq,
+                    softirq,
+                    steal,
+                },
+            );
         }
     }
 
     if stats.is_empty() {
-        return Err("No CPU statistics found in /proc/stat".to_string());
+        return Err(CollectError::parse(STAT_PATH, "no cpu lines found"));
     }
 
     Ok(stats)
 }
 
+/// Reads all zram devices from /sys/block; empty without zram.
+pub fn read_zram_devices() -> Result<Vec<ZramDevice>, CollectError> {
+    read_zram_devices_from(Path::new(SYS_BLOCK_PATH))
+}
+
+fn read_zram_devices_from(root: &Path) -> Result<Vec<ZramDevice>, CollectError> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(CollectError::io(root, e)),
+    };
+    let mut devices = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with("zram") {
+            continue;
+        }
+        let disksize_bytes = fs::read_to_string(entry.path().join("disksize"))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0);
+        // Not initialized (no disksize set)
+        if disksize_bytes == 0 {
+            continue;
+        }
+        let mm_stat_path = entry.path().join("mm_stat");
+        let mm_stat = match fs::read_to_string(&mm_stat_path) {
+            Ok(content) => content,
+            // Device removed meanwhile
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(CollectError::io(&mm_stat_path, e)),
+        };
+        // orig_data_size compr_data_size mem_used_total mem_limit ...
+        let fields: Vec<u64> = mm_stat
+            .split_whitespace()
+            .take(3)
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| CollectError::parse(&mm_stat_path, "expected byte counts"))?;
+        if fields.len() < 3 {
+            return Err(CollectError::parse(&mm_stat_path, "expected 3 fields"));
+        }
+        devices.push(ZramDevice {
+            name,
+            disksize_bytes,
+            orig_data_bytes: fields[0],
+            compr_data_bytes: fields[1],
+            mem_used_bytes: fields[2],
+        });
+    }
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(devices)
+}
+
+/// Reads the zswap pool from debugfs, falling back to the `Zswap` and
+/// `Zswapped` fields of /proc/meminfo (Linux 5.19+). None if neither is
+/// readable.
+pub fn read_zswap() -> Option<ZswapInfo> {
+    let debug = Path::new(ZSWAP_DEBUG_PATH);
+    let read = |file: &str| -> Option<u64> {
+        fs::read_to_string(debug.join(file))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+    if let (Some(pool_bytes), Some(stored_pages)) = (read("pool_total_size"), read("stored_pages"))
+    {
+        return Some(ZswapInfo {
+            pool_bytes,
+            stored_pages,
+        });
+    }
+    parse_meminfo_zswap(&fs::read_to_string(MEMINFO_PATH).ok()?, page_size())
+}
+
+fn page_size() -> u64 {
+    // SAFETY: sysconf is safe to call with _SC_PAGESIZE
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size > 0 {
+        page_size as u64
+    } else {
+        4096
+    }
+}
+
+fn parse_meminfo_zswap(content: &str, page_size: u64) -> Option<ZswapInfo> {
+    let kb = |field: &str| -> Option<u64> {
+        content
+            .lines()
+            .find_map(|l| l.strip_prefix(field))?
+            .split_whitespace()
+            .next()?
+            .parse::<u64>()
+            .ok()
+            .map(|kb| kb * 1024)
+    };
+    Some(ZswapInfo {
+        pool_bytes: kb("Zswap:")?,
+        stored_pages: kb("Zswapped:")? / page_size,
+    })
+}
+
+/// Memory currently taken from the guest by a memory balloon, in bytes.
+///
+/// Reads `inflated_kb` of the virtio balloon from debugfs, falling back to
+/// the balloon pages of /proc/vmstat. None outside of VMs with a balloon
+/// device.
+pub fn read_balloon_bytes() -> Option<u64> {
+    if let Some(bytes) = fs::read_to_string(VIRTIO_BALLOON_DEBUG_PATH)
+        .ok()
+        .and_then(|content| parse_balloon_debugfs(&content))
+    {
+        return Some(bytes);
+    }
+    let pages = parse_vmstat_balloon(&fs::read_to_string(VMSTAT_PATH).ok()?)?;
+    // Without a bound virtio balloon, a balloon only shows by holding pages
+    if pages == 0 && !virtio_balloon_bound() {
+        return None;
+    }
+    Some(pages * page_size())
+}
+
+/// Whether a virtio balloon device is bound to its driver.
+fn virtio_balloon_bound() -> bool {
+    fs::read_dir(VIRTIO_BALLOON_DRIVER_PATH).is_ok_and(|entries| {
+        entries
+            .filter_map(Result::ok)
+            .any(|e| e.file_name().to_string_lossy().starts_with("virtio"))
+    })
+}
+
+/// Parses `inflated_kb` of the virtio balloon debugfs file.
+fn parse_balloon_debugfs(content: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "inflated_kb")
+            .then(|| value.trim().parse::<u64>().ok())
+            .flatten()
+            .map(|kb| kb * 1024)
+    })
+}
+
+/// Parses the pages held by balloons from /proc/vmstat: `nr_balloon_pages`
+/// (Linux 6.16+), else the `balloon_inflate` minus `balloon_deflate` events
+/// since boot. None on kernels without balloon support.
+fn parse_vmstat_balloon(content: &str) -> Option<u64> {
+    let counter = |name: &str| {
+        content.lines().find_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            (key == name).then(|| value.trim().parse::<u64>().ok())?
+        })
+    };
+    if let Some(pages) = counter("nr_balloon_pages") {
+        return Some(pages);
+    }
+    Some(counter("balloon_inflate")?.saturating_sub(counter("balloon_deflate")?))
+}
+
+/// Reads the ZFS ARC statistics; None on hosts without ZFS.
+pub fn read_zfs_arc() -> Result<Option<ZfsArcInfo>, CollectError> {
+    match fs::read_to_string(ZFS_ARCSTATS_PATH) {
+        Ok(content) => parse_arcstats(&content).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(CollectError::io(ZFS_ARCSTATS_PATH, e)),
+    }
+}
+
+/// Parses arcstats: a kstat header line, then "name type data" per line.
+fn parse_arcstats(content: &str) -> Result<ZfsArcInfo, CollectError> {
+    let value = |name: &str| {
+        content
+            .lines()
+            .find_map(|line| {
+                let mut fields = line.split_whitespace();
+                (fields.next() == Some(name)).then(|| fields.nth(1)?.parse::<u64>().ok())?
+            })
+            .ok_or_else(|| CollectError::parse(ZFS_ARCSTATS_PATH, format!("missing {}", name)))
+    };
+    Ok(ZfsArcInfo {
+        size_bytes: value("size")?,
+        target_bytes: value("c")?,
+        min_bytes: value("c_min")?,
+        max_bytes: value("c_max")?,
+    })
+}
+
+/// Boot time in seconds since the epoch, read once; None if unreadable.
+pub static BOOT_TIME: Lazy<Option<u64>> = Lazy::new(|| read_boot_time().ok());
+
+/// Reads the boot time in seconds since the epoch (`btime` in /proc/stat).
+pub fn read_boot_time() -> Result<u64, CollectError> {
+    let content = fs::read_to_string(STAT_PATH).map_err(|e| CollectError::io(STAT_PATH, e))?;
+    parse_boot_time(&content)
+}
+
+fn parse_boot_time(content: &str) -> Result<u64, CollectError> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| CollectError::parse(STAT_PATH, "missing btime"))
+}
+
+/// Reads file handle, PID and thread limits and the available entropy.
+pub fn read_kernel_limits() -> Result<KernelLimits, CollectError> {
+    let read = |path: &str| fs::read_to_string(path).map_err(|e| CollectError::io(path, e));
+    let number = |path: &str| {
+        read(path)?
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| CollectError::parse(path, "expected a number"))
+    };
+    let (file_handles, file_max) = parse_file_nr(&read(FILE_NR_PATH)?)?;
+    Ok(KernelLimits {
+        file_handles,
+        file_max,
+        pid_max: number(PID_MAX_PATH)?,
+        threads_max: number(THREADS_MAX_PATH)?,
+        entropy_bits: number(ENTROPY_AVAIL_PATH).ok(),
+    })
+}
+
+/// Parses file-nr ("allocated unused max") into used handles and maximum.
+fn parse_file_nr(content: &str) -> Result<(u64, u64), CollectError> {
+    let fields: Vec<u64> = content
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| CollectError::parse(FILE_NR_PATH, "expected numbers"))?;
+    match fields[..] {
+        [allocated, unused, max] => Ok((allocated.saturating_sub(unused), max)),
+        _ => Err(CollectError::parse(FILE_NR_PATH, "expected 3 fields")),
+    }
+}
+
+/// Reads the release and version of the running kernel.
+pub fn read_kernel_info() -> Result<KernelInfo, CollectError> {
+    let read = |path: &str| {
+        fs::read_to_string(path)
+            .map(|s| s.trim().to_string())
+            .map_err(|e| CollectError::io(path, e))
+    };
+    Ok(KernelInfo {
+        release: read(OSRELEASE_PATH)?,
+        version: read(KERNEL_VERSION_PATH)?,
+    })
+}
+
+/// Detects the data model of the exporter and the word size of the kernel,
+/// e.g. an armv7 build on an arm64 kernel.
+pub fn read_data_model() -> DataModel {
+    // SAFETY: utsname is plain data and uname only writes into it
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    // SAFETY: uts is a valid, writable utsname
+    let machine = if unsafe { libc::uname(&mut uts) } == 0 {
+        // SAFETY: uname NUL-terminates the fields it fills
+        unsafe { std::ffi::CStr::from_ptr(uts.machine.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        String::new()
+    };
+    DataModel {
+        model: if cfg!(target_pointer_width = "64") {
+            "LP64"
+        } else {
+            "ILP32"
+        },
+        kernel_bits: kernel_bits(&machine),
+        machine,
+    }
+}
+
+/// Word size of a kernel from its uname machine. 32-bit processes on arm64
+/// see "armv8l", on other 64-bit kernels the native machine.
+fn kernel_bits(machine: &str) -> u32 {
+    let is_64 = machine.ends_with("64")
+        || machine.starts_with("ppc64")
+        || matches!(machine, "armv8l" | "s390x");
+    if is_64 {
+        64
+    } else {
+        32
+    }
+}
+
 /// CPU statistics cache for calculating deltas.
 pub struct CpuStatsCache {
     previous: RwLock<Option<HashMap<String, CpuStat>>>,
@@ -183,40 +661,31 @@ impl CpuStatsCache {
         }
     }
 
-    /// Calculate CPU usage ratios by comparing current and previous stats.
-    /// Returns a HashMap with CPU name as key and usage ratio (0.0 to 1.0) as value.
-    pub fn calculate_usage_ratios(&self) -> Result<HashMap<String, f64>, String> {
+    /// Calculate CPU time ratios by comparing current and previous stats.
+    /// Returns a HashMap with CPU name as key; empty on the first call.
+    pub fn calculate_usage_ratios(&self) -> Result<HashMap<String, CpuRatios>, CollectError> {
         let current_stats = read_cpu_stats()?;
-        
+
         let mut ratios = HashMap::new();
-        
+
         // Try to get previous stats
-        let prev_guard = self.previous.read().map_err(|e| format!("Failed to acquire lock: {}", e))?;
-        
+        let prev_guard = self.previous.read().unwrap_or_else(|e| e.into_inner());
+
         if let Some(prev_stats) = prev_guard.as_ref() {
             // Calculate deltas for each CPU
             for (cpu_name, current) in &current_stats {
                 if let Some(previous) = prev_stats.get(cpu_name) {
-                    let delta_total = current.total().saturating_sub(previous.total());
-                    let delta_non_active = current.idle_total().saturating_sub(previous.idle_total());
-                    
-                    let ratio = if delta_total > 0 {
-                        (delta_total - delta_non_active) as f64 / delta_total as f64
-                    } else {
-                        0.0
-                    };
-                    
-                    ratios.insert(cpu_name.clone(), ratio);
+                    ratios.insert(cpu_name.clone(), current.ratios_since(previous));
                 }
             }
         }
-        
+
         drop(prev_guard);
-        
+
         // Update cache with current stats
-        let mut cache_guard = self.previous.write().map_err(|e| format!("Failed to acquire write lock: {}", e))?;
+        let mut cache_guard = self.previous.write().unwrap_or_else(|e| e.into_inner());
         *cache_guard = Some(current_stats);
-        
+
         Ok(ratios)
     }
 }
@@ -234,6 +703,45 @@ mod tests {
         assert!((load.one_min - 0.52).abs() < 0.001);
         assert!((load.five_min - 0.58).abs() < 0.001);
         assert!((load.fifteen_min - 0.59).abs() < 0.001);
+        assert_eq!(load.tasks, Some(1190));
+        assert_eq!(
+            parse_load_average_line("0.52 0.58 0.59").unwrap().tasks,
+            None
+        );
+    }
+
+    #[test]
+    fn test_cpu_ratios_since() {
+        let previous = CpuStat {
+            user: 100,
+            nice: 0,
+            system: 50,
+            idle: 800,
+            iowait: 30,
+            irq: 0,
+            softirq: 0,
+            steal: 20,
+        };
+        let current = CpuStat {
+            user: 160,
+            idle: 820,
+            iowait: 40,
+            steal: 30,
+            ..previous
+        };
+        let ratios = current.ratios_since(&previous);
+        assert!((ratios.usage - 0.7).abs() < 1e-9);
+        assert!((ratios.iowait - 0.1).abs() < 1e-9);
+        assert!((ratios.steal - 0.1).abs() < 1e-9);
+
+        // iowait going backwards is clamped
+        let current = CpuStat {
+            iowait: 25,
+            idle: 900,
+            ..previous
+        };
+        assert_eq!(current.ratios_since(&previous).iowait, 0.0);
+        assert_eq!(previous.ratios_since(&previous), CpuRatios::default());
     }
 
     #[test]
@@ -247,27 +755,134 @@ mod tests {
         assert!(result.is_err());
     }
 
-    // Helper functions for testing
-    fn parse_load_average_line(line: &str) -> Result<LoadAverage, String> {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 3 {
-            return Err(format!("Invalid format: expected at least 3 fields"));
+    #[test]
+    fn test_system_collector_allowlist() {
+        assert!(collector_enabled(None, "meminfo"));
+        assert!(!family_disabled(None, "herakles_system_load1"));
+
+        let allowlist = ["cpustat".to_string(), "limits".to_string()];
+        let allowlist = Some(&allowlist[..]);
+        assert!(collector_enabled(allowlist, "cpustat"));
+        assert!(!collector_enabled(allowlist, "meminfo"));
+        assert!(family_disabled(allowlist, "herakles_system_load15"));
+        assert!(family_disabled(
+            allowlist,
+            "herakles_system_memory_used_ratio"
+        ));
+        assert!(!family_disabled(
+            allowlist,
+            "herakles_system_cpu_usage_ratio"
+        ));
+        assert!(!family_disabled(allowlist, "herakles_system_tasks"));
+        assert!(!family_disabled(allowlist, "herakles_proc_mem_rss_bytes"));
+    }
+
+    #[test]
+    fn test_parse_file_nr() {
+        assert_eq!(
+            parse_file_nr("10432\t0\t9223372036854775807\n").unwrap(),
+            (10432, i64::MAX as u64)
+        );
+        assert_eq!(parse_file_nr("2048 48 65536").unwrap(), (2000, 65536));
+        assert!(parse_file_nr("2048 0").is_err());
+    }
+
+    #[test]
+    fn test_parse_boot_time() {
+        let stat =
+            "cpu  100 0 50 800 30 0 0 20 0 0\nctxt 123456\nbtime 1718000000\nprocesses 4242\n";
+        assert_eq!(parse_boot_time(stat).unwrap(), 1718000000);
+        assert!(parse_boot_time("cpu  100 0 50 800 30 0 0 20 0 0\n").is_err());
+    }
+
+    #[test]
+    fn test_kernel_bits() {
+        for machine in ["x86_64", "aarch64", "armv8l", "ppc64le", "s390x", "riscv64"] {
+            assert_eq!(kernel_bits(machine), 64, "{machine}");
+        }
+        for machine in ["armv7l", "i686", "mips", ""] {
+            assert_eq!(kernel_bits(machine), 32, "{machine}");
         }
+    }
 
-        let one_min = parts[0]
-            .parse::<f64>()
-            .map_err(|e| format!("Failed to parse 1min: {}", e))?;
-        let five_min = parts[1]
-            .parse::<f64>()
-            .map_err(|e| format!("Failed to parse 5min: {}", e))?;
-        let fifteen_min = parts[2]
-            .parse::<f64>()
-            .map_err(|e| format!("Failed to parse 15min: {}", e))?;
-
-        Ok(LoadAverage {
-            one_min,
-            five_min,
-            fifteen_min,
-        })
+    #[test]
+    fn test_read_zram_devices() {
+        let tmp = tempfile::tempdir().unwrap();
+        let zram0 = tmp.path().join("zram0");
+        fs::create_dir_all(&zram0).unwrap();
+        fs::write(
+            zram0.join("mm_stat"),
+            "  8388608  2097152  2359296        0  2359296        0        0        0        0\n",
+        )
+        .unwrap();
+        fs::write(zram0.join("disksize"), "4294967296\n").unwrap();
+        let zram1 = tmp.path().join("zram1");
+        fs::create_dir_all(&zram1).unwrap();
+        fs::write(zram1.join("mm_stat"), "0 0 0 0 0 0 0 0 0\n").unwrap();
+        fs::write(zram1.join("disksize"), "0\n").unwrap();
+        fs::create_dir_all(tmp.path().join("sda")).unwrap();
+
+        let devices = read_zram_devices_from(tmp.path()).unwrap();
+        assert_eq!(
+            devices,
+            [ZramDevice {
+                name: "zram0".to_string(),
+                disksize_bytes: 4294967296,
+                orig_data_bytes: 8388608,
+                compr_data_bytes: 2097152,
+                mem_used_bytes: 2359296,
+            }]
+        );
+        assert!(read_zram_devices_from(&tmp.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parse_meminfo_zswap() {
+        let meminfo = "MemTotal: 16000000 kB\nZswap:  2048 kB\nZswapped:  8192 kB\n";
+        assert_eq!(
+            parse_meminfo_zswap(meminfo, 4096),
+            Some(ZswapInfo {
+                pool_bytes: 2048 * 1024,
+                stored_pages: 2048,
+            })
+        );
+        assert_eq!(parse_meminfo_zswap("MemTotal: 16000000 kB\n", 4096), None);
+    }
+
+    #[test]
+    fn test_parse_arcstats() {
+        let arcstats = "13 1 0x01 147 39984 5523616391 1233187891418606\n\
+                        name                            type data\n\
+                        hits                            4    8419837\n\
+                        c                               4    4294967296\n\
+                        c_min                           4    1051984896\n\
+                        c_max                           4    16831758336\n\
+                        size                            4    4271589904\n";
+        assert_eq!(
+            parse_arcstats(arcstats).unwrap(),
+            ZfsArcInfo {
+                size_bytes: 4_271_589_904,
+                target_bytes: 4_294_967_296,
+                min_bytes: 1_051_984_896,
+                max_bytes: 16_831_758_336,
+            }
+        );
+        assert!(parse_arcstats("name type data\nhits 4 1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_balloon() {
+        let debugfs = "inflated_kb           : 524288\nfree_page_hint_kb     : 0\n";
+        assert_eq!(parse_balloon_debugfs(debugfs), Some(512 * 1024 * 1024));
+        assert_eq!(parse_balloon_debugfs("free_page_hint_kb : 0\n"), None);
+
+        let vmstat =
+            "nr_free_pages 1000\nballoon_inflate 300\nballoon_deflate 100\nballoon_migrate 7\n";
+        assert_eq!(parse_vmstat_balloon(vmstat), Some(200));
+        let current = format!("nr_balloon_pages 150\n{vmstat}");
+        assert_eq!(parse_vmstat_balloon(&current), Some(150));
+        assert_eq!(parse_vmstat_balloon("nr_free_pages 1000\n"), None);
     }
 }
@@ -0,0 +1,87 @@
+//! node_exporter textfile collector output.
+//!
+//! After every cache update the full exposition is written to a temporary
+//! file next to the target and renamed over it, so node_exporter never reads
+//! a partially written .prom file.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{debug, error};
+
+use crate::handlers::render_metrics;
+use crate::pipeline::MetricSchema;
+use crate::state::SharedState;
+
+/// Renders the current exposition and writes it to `path`.
+pub async fn write_textfile(state: &SharedState, path: &Path) {
+    let schema = MetricSchema::from_config(&state.config);
+    let body = match render_metrics(state, schema, false, None).await {
+        Ok(body) => body,
+        Err(_) => {
+            error!("Failed to encode metrics for {}", path.display());
+            return;
+        }
+    };
+
+    match write_atomic(path, body.as_bytes()) {
+        Ok(()) => debug!(
+            "Metrics written to {} ({} bytes)",
+            path.display(),
+            body.len()
+        ),
+        Err(e) => error!("Failed to write {}: {}", path.display(), e),
+    }
+}
+
+/// Writes `data` to a sibling temp file and renames it over `path`.
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let tmp = tmp_path(path);
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Temp file name that the textfile collector ignores (not ending in .prom).
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("herakles.prom");
+
+        write_atomic(&path, b"first\n").unwrap();
+        write_atomic(&path, b"second\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+        let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1, "temp file must not be left behind");
+    }
+
+    #[test]
+    fn test_tmp_path_is_not_prom() {
+        let tmp = tmp_path(Path::new("/var/lib/node_exporter/textfile/herakles.prom"));
+        assert_eq!(
+            tmp.parent(),
+            Some(Path::new("/var/lib/node_exporter/textfile"))
+        );
+        assert!(tmp.to_string_lossy().ends_with(".tmp"));
+    }
+}
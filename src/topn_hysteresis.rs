@@ -0,0 +1,166 @@
+//! Hysteresis of the Top-N selection.
+//!
+//! Two processes of similar size swap places in the Top-N of their subgroup
+//! on every other scan, and each swap ends one series and starts another.
+//! With `top_n_hysteresis_scans: K`, a process joins the Top-N only after it
+//! ranked within the top N for K consecutive scans, and leaves only after it
+//! ranked below for K consecutive scans. Until then a subgroup may export
+//! more or fewer than N processes. Exited processes leave at once.
+//!
+//! Membership changes once per scan; further renders of the same scan
+//! (other schemas, repeated scrapes) see the same members.
+
+use ahash::AHashMap as HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use crate::cache::{ProcKey, ProcMem};
+
+/// Top-N membership of a process.
+#[derive(Debug, Clone, Copy)]
+struct Membership {
+    member: bool,
+    /// Consecutive scans ranked on the other side of the cut
+    streak: u32,
+}
+
+/// Memberships of one subgroup.
+#[derive(Default)]
+struct Subgroup {
+    scan_id: u64,
+    processes: HashMap<ProcKey, Membership>,
+}
+
+#[derive(Default)]
+struct Inner {
+    scan_id: u64,
+    subgroups: HashMap<(Arc<str>, Arc<str>), Subgroup>,
+}
+
+/// Top-N memberships of all subgroups across scans.
+pub struct TopNHysteresis {
+    scans: u32,
+    inner: StdMutex<Inner>,
+}
+
+impl TopNHysteresis {
+    /// Hysteresis of `scans` scans; 1 and below select the current Top-N.
+    pub fn new(scans: u32) -> Self {
+        Self {
+            scans,
+            inner: StdMutex::new(Inner::default()),
+        }
+    }
+
+    /// Top-N processes of the subgroup `key` in scan `scan_id`. `ranked` are
+    /// all processes of the subgroup, largest first; the result keeps that
+    /// order.
+    pub fn select<'a>(
+        &self,
+        scan_id: u64,
+        key: &(Arc<str>, Arc<str>),
+        ranked: &[&'a ProcMem],
+        limit: usize,
+    ) -> Vec<&'a ProcMem> {
+        if self.scans <= 1 {
+            return ranked.iter().take(limit).copied().collect();
+        }
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if inner.scan_id != scan_id {
+            // Subgroups without processes in the previous scan start over
+            let previous = inner.scan_id;
+            inner.subgroups.retain(|_, s| s.scan_id == previous);
+            inner.scan_id = scan_id;
+        }
+
+        let subgroup = inner.subgroups.entry(key.clone()).or_default();
+        if subgroup.scan_id != scan_id {
+            let first = subgroup.processes.is_empty();
+            let mut processes = HashMap::with_capacity(ranked.len());
+            for (rank, p) in ranked.iter().enumerate() {
+                let in_top = rank < limit;
+                let membership = match subgroup.processes.get(&p.key()) {
+                    Some(&m) if m.member == in_top => Membership {
+                        member: m.member,
+                        streak: 0,
+                    },
+                    Some(&m) if m.streak + 1 >= self.scans => Membership {
+                        member: in_top,
+                        streak: 0,
+                    },
+                    Some(&m) => Membership {
+                        member: m.member,
+                        streak: m.streak + 1,
+                    },
+                    // The first scan takes the Top-N as it is
+                    None if first => Membership {
+                        member: in_top,
+                        streak: 0,
+                    },
+                    None => Membership {
+                        member: false,
+                        streak: u32::from(in_top),
+                    },
+                };
+                processes.insert(p.key(), membership);
+            }
+            subgroup.processes = processes;
+            subgroup.scan_id = scan_id;
+        }
+
+        ranked
+            .iter()
+            .filter(|p| subgroup.processes.get(&p.key()).is_some_and(|m| m.member))
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::generate::TestProcess;
+
+    fn process(pid: u32, uss: u64) -> ProcMem {
+        ProcMem::from(TestProcess {
+            pid,
+            name: "worker".to_string(),
+            group: String::new(),
+            subgroup: String::new(),
+            rss: uss,
+            pss: uss,
+            uss,
+            cpu_percent: 0.0,
+            cpu_time_seconds: 0.0,
+        })
+    }
+
+    #[test]
+    fn test_topn_hysteresis() {
+        let key: (Arc<str>, Arc<str>) = ("app".into(), "worker".into());
+        let hysteresis = TopNHysteresis::new(2);
+        let pids = |selected: Vec<&ProcMem>| selected.iter().map(|p| p.pid).collect::<Vec<_>>();
+        let (a, b, c) = (process(1, 300), process(2, 200), process(3, 100));
+
+        // The first scan takes the Top-2 as it is
+        assert_eq!(pids(hysteresis.select(1, &key, &[&a, &b, &c], 2)), [1, 2]);
+        // Repeated renders of a scan do not count
+        assert_eq!(pids(hysteresis.select(1, &key, &[&a, &c, &b], 2)), [1, 2]);
+
+        // 3 passes 2 for one scan: no change yet
+        assert_eq!(pids(hysteresis.select(2, &key, &[&a, &c, &b], 2)), [1, 2]);
+        // and back: the streak starts over
+        assert_eq!(pids(hysteresis.select(3, &key, &[&a, &b, &c], 2)), [1, 2]);
+        // Two scans in a row: 3 joins, 2 leaves
+        assert_eq!(pids(hysteresis.select(4, &key, &[&a, &c, &b], 2)), [1, 2]);
+        assert_eq!(pids(hysteresis.select(5, &key, &[&a, &c, &b], 2)), [1, 3]);
+
+        // An exited member leaves at once, its place is taken after 2 scans
+        assert_eq!(pids(hysteresis.select(6, &key, &[&c, &b], 2)), [3]);
+        assert_eq!(pids(hysteresis.select(7, &key, &[&c, &b], 2)), [3, 2]);
+
+        // Without hysteresis the current Top-N is returned
+        let off = TopNHysteresis::new(1);
+        assert_eq!(pids(off.select(1, &key, &[&a, &c, &b], 2)), [1, 3]);
+    }
+}
@@ -0,0 +1,229 @@
+//! Sampling of unclassified process names.
+//!
+//! Every scan adds the processes that no subgroup rule matched to a running
+//! tally per name, so `/api/v1/unclassified` can show which rules are worth
+//! adding next. The number of tracked names is capped to keep memory bounded
+//! on hosts with many short-lived, uniquely named processes.
+
+use ahash::AHashMap as HashMap;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cache::ProcMem;
+
+/// Maximum number of distinct names tracked since startup.
+pub const MAX_TRACKED_NAMES: usize = 1000;
+/// Number of entries returned when the request has no `limit`.
+pub const DEFAULT_LIMIT: usize = 20;
+/// Upper bound for `limit`.
+pub const MAX_LIMIT: usize = 100;
+/// Minimum interval between two answered requests.
+pub const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Running totals for one unclassified process name.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct UnclassifiedEntry {
+    pub name: String,
+    /// Process observations summed over all scans
+    pub processes: u64,
+    /// Number of scans the name appeared in
+    pub scans: u64,
+    /// RSS/USS of all observations, divide by `scans` for a per-scan average
+    pub rss_bytes_sum: u64,
+    pub uss_bytes_sum: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, UnclassifiedEntry>,
+    /// Names not tracked because the cap was reached
+    dropped: u64,
+    scans: u64,
+}
+
+/// Snapshot returned by [`UnclassifiedTracker::top`].
+#[derive(Debug, Serialize)]
+pub struct UnclassifiedReport {
+    pub scans: u64,
+    pub tracked_names: usize,
+    pub dropped_names: u64,
+    pub entries: Vec<UnclassifiedEntry>,
+}
+
+/// Tally of unclassified names since startup.
+#[derive(Default)]
+pub struct UnclassifiedTracker {
+    inner: Mutex<Inner>,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl UnclassifiedTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the unclassified processes of one scan.
+    pub fn record_scan<'a>(&self, processes: impl IntoIterator<Item = &'a ProcMem>) {
+        let mut scan: HashMap<&str, (u64, u64, u64)> = HashMap::new();
+        for p in processes {
+            let totals = scan.entry(&*p.name).or_default();
+            totals.0 += 1;
+            totals.1 += p.rss;
+            totals.2 += p.uss;
+        }
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.scans += 1;
+        for (name, (count, rss, uss)) in scan {
+            if !inner.entries.contains_key(name) {
+                if inner.entries.len() >= MAX_TRACKED_NAMES {
+                    inner.dropped += 1;
+                    continue;
+                }
+                inner.entries.insert(
+                    name.to_string(),
+                    UnclassifiedEntry {
+                        name: name.to_string(),
+                        ..Default::default()
+                    },
+                );
+            }
+            if let Some(entry) = inner.entries.get_mut(name) {
+                entry.processes = entry.processes.saturating_add(count);
+                entry.scans += 1;
+                entry.rss_bytes_sum = entry.rss_bytes_sum.saturating_add(rss);
+                entry.uss_bytes_sum = entry.uss_bytes_sum.saturating_add(uss);
+            }
+        }
+    }
+
+    /// Returns the most frequently seen names, largest memory first on ties.
+    pub fn top(&self, limit: usize) -> UnclassifiedReport {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries: Vec<UnclassifiedEntry> = inner.entries.values().cloned().collect();
+        entries.sort_by(|a, b| {
+            b.processes
+                .cmp(&a.processes)
+                .then_with(|| b.rss_bytes_sum.cmp(&a.rss_bytes_sum))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        entries.truncate(limit.min(MAX_LIMIT));
+
+        UnclassifiedReport {
+            scans: inner.scans,
+            tracked_names: inner.entries.len(),
+            dropped_names: inner.dropped,
+            entries,
+        }
+    }
+
+    /// Returns false if the previous request was answered less than
+    /// `MIN_REQUEST_INTERVAL` ago.
+    pub fn try_acquire_request(&self) -> bool {
+        let mut last = self.last_request.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if last.is_some_and(|t| now.duration_since(t) < MIN_REQUEST_INTERVAL) {
+            return false;
+        }
+        *last = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proc_mem(name: &str, rss: u64) -> ProcMem {
+        ProcMem {
+            pid: 1,
+            name: name.into(),
+            rss,
+            pss: rss,
+            uss: rss / 2,
+            swap: 0,
+            locked: 0,
+            anonymous: 0,
+            shmem: None,
+            cpu_percent: 0.0,
+            cpu_time_seconds: 0.0,
+            start_time_ticks: 0,
+            cpu_ticks: 0,
+            guest_ticks: 0,
+            minor_faults: 0,
+            major_faults: 0,
+            pgrp: 0,
+            session: 0,
+            nice: 0,
+            sched_policy: 0,
+            state: 'S',
+            io_priority: None,
+            privileges: None,
+            delays: None,
+            schedstat: None,
+            numa: None,
+            wchan: None,
+            read_bytes: None,
+            write_bytes: None,
+            read_syscalls: None,
+            write_syscalls: None,
+            memory_updated: Instant::now(),
+            extra_labels: None,
+            environ: None,
+            classify_as: None,
+            memory_limit: None,
+            threads: None,
+        }
+    }
+
+    #[test]
+    fn test_record_scan_and_top() {
+        let tracker = UnclassifiedTracker::new();
+        let first = [
+            proc_mem("bash", 100),
+            proc_mem("bash", 300),
+            proc_mem("vim", 50),
+        ];
+        let second = [proc_mem("bash", 200)];
+        tracker.record_scan(&first);
+        tracker.record_scan(&second);
+
+        let report = tracker.top(10);
+        assert_eq!(report.scans, 2);
+        assert_eq!(report.tracked_names, 2);
+        assert_eq!(
+            report.entries[0],
+            UnclassifiedEntry {
+                name: "bash".into(),
+                processes: 3,
+                scans: 2,
+                rss_bytes_sum: 600,
+                uss_bytes_sum: 300,
+            }
+        );
+        assert_eq!(report.entries[1].name, "vim");
+        assert_eq!(tracker.top(1).entries.len(), 1);
+    }
+
+    #[test]
+    fn test_tracked_names_are_capped() {
+        let tracker = UnclassifiedTracker::new();
+        let procs: Vec<ProcMem> = (0..MAX_TRACKED_NAMES + 5)
+            .map(|i| proc_mem(&format!("job-{}", i), 1))
+            .collect();
+        tracker.record_scan(&procs);
+
+        let report = tracker.top(MAX_LIMIT + 50);
+        assert_eq!(report.tracked_names, MAX_TRACKED_NAMES);
+        assert_eq!(report.dropped_names, 5);
+        assert_eq!(report.entries.len(), MAX_LIMIT);
+    }
+
+    #[test]
+    fn test_requests_are_rate_limited() {
+        let tracker = UnclassifiedTracker::new();
+        assert!(tracker.try_acquire_request());
+        assert!(!tracker.try_acquire_request());
+    }
+}
@@ -0,0 +1,370 @@
+//! Warm start of the cache from a disk snapshot.
+//!
+//! On hosts where the first scan takes tens of seconds, a restart leaves
+//! /metrics without process data until it completes. With `cache_snapshot`
+//! set, the process snapshot is written to that file on shutdown and read
+//! back at startup. The restored cache is served, marked by
+//! `herakles_proc_mem_cache_restored`, while the first scan runs in the
+//! background. Snapshots taken before the last boot or older than
+//! `cache_snapshot_max_age_seconds` are ignored, their PIDs are meaningless.
+//!
+//! Processes are identified by [`ProcKey`], which stays valid across a
+//! restart. The first scan after a warm start therefore computes CPU percent
+//! and rates against the restored scan instead of starting from zero.
+
+use ahash::AHashMap as HashMap;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::cache::{MetricsCache, ProcKey, ProcMem};
+use crate::config::Config;
+use crate::process::{CpuEntry, CpuStat, DelayStats, IoPriority, Privileges, SchedStat, CLK_TCK};
+use crate::state::SharedState;
+use crate::system::read_boot_time;
+use crate::textfile::write_atomic;
+
+/// Default maximum age of a snapshot restored at startup.
+pub const DEFAULT_MAX_AGE_SECONDS: u64 = 3600;
+/// Format version, snapshots of other versions are ignored.
+const FORMAT_VERSION: u32 = 9;
+
+/// A process of the snapshot file; `ProcMem` without the scan-time `Instant`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SnapshotProcess {
+    pid: u32,
+    name: String,
+    rss: u64,
+    pss: u64,
+    uss: u64,
+    swap: u64,
+    locked: u64,
+    anonymous: u64,
+    shmem: Option<u64>,
+    cpu_percent: f32,
+    cpu_time_seconds: f32,
+    start_time_ticks: u64,
+    cpu_ticks: u64,
+    guest_ticks: u64,
+    minor_faults: u64,
+    major_faults: u64,
+    pgrp: u32,
+    session: u32,
+    nice: i32,
+    sched_policy: u32,
+    state: char,
+    io_priority: Option<IoPriority>,
+    privileges: Option<Privileges>,
+    delays: Option<DelayStats>,
+    schedstat: Option<SchedStat>,
+    numa: Option<Vec<(u32, u64)>>,
+    wchan: Option<String>,
+    read_bytes: Option<u64>,
+    write_bytes: Option<u64>,
+    read_syscalls: Option<u64>,
+    write_syscalls: Option<u64>,
+    extra_labels: Option<Vec<String>>,
+    environ: Option<Vec<String>>,
+    classify_as: Option<String>,
+}
+
+impl From<&ProcMem> for SnapshotProcess {
+    fn from(p: &ProcMem) -> Self {
+        Self {
+            pid: p.pid,
+            name: p.name.to_string(),
+            rss: p.rss,
+            pss: p.pss,
+            uss: p.uss,
+            swap: p.swap,
+            locked: p.locked,
+            anonymous: p.anonymous,
+            shmem: p.shmem,
+            cpu_percent: p.cpu_percent,
+            cpu_time_seconds: p.cpu_time_seconds,
+            start_time_ticks: p.start_time_ticks,
+            cpu_ticks: p.cpu_ticks,
+            guest_ticks: p.guest_ticks,
+            minor_faults: p.minor_faults,
+            major_faults: p.major_faults,
+            pgrp: p.pgrp,
+            session: p.session,
+            nice: p.nice,
+            sched_policy: p.sched_policy,
+            state: p.state,
+            io_priority: p.io_priority,
+            privileges: p.privileges,
+            delays: p.delays,
+            schedstat: p.schedstat,
+            numa: p.numa.as_deref().map(<[_]>::to_vec),
+            wchan: p.wchan.as_deref().map(str::to_string),
+            read_bytes: p.read_bytes,
+            write_bytes: p.write_bytes,
+            read_syscalls: p.read_syscalls,
+            write_syscalls: p.write_syscalls,
+            extra_labels: p.extra_labels.as_deref().map(<[String]>::to_vec),
+            environ: p.environ.as_deref().map(<[String]>::to_vec),
+            classify_as: p.classify_as.as_deref().map(str::to_string),
+        }
+    }
+}
+
+impl SnapshotProcess {
+    fn into_proc_mem(self, now: Instant) -> ProcMem {
+        ProcMem {
+            pid: self.pid,
+            name: Arc::from(self.name),
+            rss: self.rss,
+            pss: self.pss,
+            uss: self.uss,
+            swap: self.swap,
+            locked: self.locked,
+            anonymous: self.anonymous,
+            shmem: self.shmem,
+            cpu_percent: self.cpu_percent,
+            cpu_time_seconds: self.cpu_time_seconds,
+            start_time_ticks: self.start_time_ticks,
+            cpu_ticks: self.cpu_ticks,
+            guest_ticks: self.guest_ticks,
+            minor_faults: self.minor_faults,
+            major_faults: self.major_faults,
+            pgrp: self.pgrp,
+            session: self.session,
+            nice: self.nice,
+            sched_policy: self.sched_policy,
+            state: self.state,
+            io_priority: self.io_priority,
+            privileges: self.privileges,
+            delays: self.delays,
+            schedstat: self.schedstat,
+            numa: self.numa.map(Arc::from),
+            wchan: self.wchan.map(Arc::from),
+            read_bytes: self.read_bytes,
+            write_bytes: self.write_bytes,
+            read_syscalls: self.read_syscalls,
+            write_syscalls: self.write_syscalls,
+            memory_updated: now,
+            extra_labels: self.extra_labels.map(Arc::from),
+            environ: self.environ.map(Arc::from),
+            classify_as: self.classify_as.map(Arc::from),
+            // Limits are resolved against the cgroups of a scan
+            memory_limit: None,
+            // Thread CPU needs two scans of the threads themselves
+            threads: None,
+        }
+    }
+}
+
+/// Contents of the `cache_snapshot` file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheSnapshot {
+    version: u32,
+    /// Boot time (`btime`) of the boot the snapshot was taken in
+    boot_time: u64,
+    /// Unix time of the start of the scan in milliseconds
+    updated_unix_ms: i64,
+    update_duration_seconds: f64,
+    processes: Vec<SnapshotProcess>,
+}
+
+impl CacheSnapshot {
+    /// Snapshot of the processes of `cache`, None before the first scan.
+    pub fn from_cache(cache: &MetricsCache, boot_time: u64) -> Option<Self> {
+        let mut processes: Vec<SnapshotProcess> = cache
+            .processes
+            .values()
+            .map(SnapshotProcess::from)
+            .collect();
+        processes.sort_by_key(|p| p.pid);
+        Some(Self {
+            version: FORMAT_VERSION,
+            boot_time,
+            updated_unix_ms: cache.last_updated_unix_ms?,
+            update_duration_seconds: cache.update_duration_seconds,
+            processes,
+        })
+    }
+
+    /// A cache marked as restored, or why the snapshot cannot be used.
+    ///
+    /// The cache has no `last_updated`, so it never counts as fresh and
+    /// process churn is not computed against it. Its `restored_updated` is
+    /// the time of the snapshot's scan, the baseline of the next rates.
+    pub fn into_cache(
+        self,
+        boot_time: u64,
+        now_unix_ms: i64,
+        max_age: Duration,
+    ) -> Result<MetricsCache, String> {
+        if self.version != FORMAT_VERSION {
+            return Err(format!("unsupported format version {}", self.version));
+        }
+        if self.boot_time != boot_time {
+            return Err("taken before the last boot".to_string());
+        }
+        let age_ms = now_unix_ms.saturating_sub(self.updated_unix_ms);
+        if age_ms > max_age.as_millis() as i64 {
+            return Err(format!("{}s old", age_ms / 1000));
+        }
+
+        let now = Instant::now();
+        let scanned = now.checked_sub(Duration::from_millis(age_ms.max(0) as u64));
+        Ok(MetricsCache {
+            processes: self
+                .processes
+                .into_iter()
+                .map(|p| {
+                    let p = p.into_proc_mem(scanned.unwrap_or(now));
+                    (p.key(), p)
+                })
+                .collect(),
+            restored_updated: scanned,
+            last_updated_unix_ms: Some(self.updated_unix_ms),
+            update_duration_seconds: self.update_duration_seconds,
+            restored: true,
+            ..Default::default()
+        })
+    }
+}
+
+/// CPU cache entries of a restored cache, so the first scan after a restart
+/// computes CPU percent against the restored CPU times.
+pub fn cpu_entries(cache: &MetricsCache) -> HashMap<ProcKey, CpuEntry> {
+    let Some(last_updated) = cache.restored_updated else {
+        return HashMap::new();
+    };
+    cache
+        .processes
+        .iter()
+        .map(|(key, p)| {
+            let stat = CpuStat {
+                cpu_percent: p.cpu_percent as f64,
+                cpu_time_seconds: p.cpu_ticks as f64 / *CLK_TCK,
+            };
+            (*key, CpuEntry { stat, last_updated })
+        })
+        .collect()
+}
+
+/// Reads the snapshot at `path`. Missing, unreadable or unusable snapshots
+/// are logged and yield None; startup never fails here.
+pub fn load(cfg: &Config, path: &Path) -> Option<MetricsCache> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            debug!("No cache snapshot at {}", path.display());
+            return None;
+        }
+        Err(e) => {
+            warn!("Failed to read cache snapshot {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let snapshot: CacheSnapshot = match serde_json::from_slice(&content) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            warn!("Ignoring invalid cache snapshot {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let boot_time = match read_boot_time() {
+        Ok(boot_time) => boot_time,
+        Err(e) => {
+            warn!("Ignoring cache snapshot, boot time unknown: {}", e);
+            return None;
+        }
+    };
+    let max_age = Duration::from_secs(
+        cfg.cache_snapshot_max_age_seconds
+            .unwrap_or(DEFAULT_MAX_AGE_SECONDS),
+    );
+    match snapshot.into_cache(boot_time, chrono::Utc::now().timestamp_millis(), max_age) {
+        Ok(cache) => {
+            info!(
+                "Restored {} processes from cache snapshot {}",
+                cache.processes.len(),
+                path.display()
+            );
+            Some(cache)
+        }
+        Err(reason) => {
+            info!("Ignoring cache snapshot {}: {}", path.display(), reason);
+            None
+        }
+    }
+}
+
+/// Writes the current process snapshot to `path`.
+pub async fn save(state: &SharedState, path: &Path) {
+    let boot_time = match read_boot_time() {
+        Ok(boot_time) => boot_time,
+        Err(e) => {
+            warn!("Not writing cache snapshot, boot time unknown: {}", e);
+            return;
+        }
+    };
+    let Some(snapshot) = CacheSnapshot::from_cache(&*state.cache.read().await, boot_time) else {
+        debug!("No completed scan, not writing cache snapshot");
+        return;
+    };
+    let result = serde_json::to_vec(&snapshot)
+        .map_err(std::io::Error::other)
+        .and_then(|data| write_atomic(path, &data));
+    match result {
+        Ok(()) => info!(
+            "Cache snapshot of {} processes written to {}",
+            snapshot.processes.len(),
+            path.display()
+        ),
+        Err(e) => warn!("Failed to write cache snapshot {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::generate::TestProcess;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let process = ProcMem::from(TestProcess {
+            pid: 42,
+            name: "postgres".to_string(),
+            group: "db".to_string(),
+            subgroup: "postgres".to_string(),
+            rss: 4096,
+            pss: 2048,
+            uss: 1024,
+            cpu_percent: 1.5,
+            cpu_time_seconds: 10.0,
+        });
+        let mut cache = MetricsCache::default();
+        assert!(CacheSnapshot::from_cache(&cache, 1_000).is_none());
+        cache.processes.insert(process.key(), process);
+        cache.last_updated = Some(Instant::now());
+        cache.last_updated_unix_ms = Some(1_000_000);
+        cache.scan_cycle = 7;
+
+        let json = serde_json::to_vec(&CacheSnapshot::from_cache(&cache, 1_000).unwrap()).unwrap();
+        let read = || serde_json::from_slice::<CacheSnapshot>(&json).unwrap();
+        let max_age = Duration::from_secs(60);
+
+        let restored = read().into_cache(1_000, 1_030_000, max_age).unwrap();
+        assert!(restored.restored);
+        assert!(restored.last_updated.is_none());
+        assert_eq!(restored.last_updated_unix_ms, Some(1_000_000));
+        assert_eq!(restored.scan_cycle, 0);
+        let (key, p) = restored.processes.iter().next().unwrap();
+        assert_eq!(key.pid, 42);
+        assert_eq!((&*p.name, p.rss, p.uss), ("postgres", 4096, 1024));
+        assert!(restored.restored_updated.is_some());
+        assert!(cpu_entries(&restored).contains_key(key));
+
+        // Another boot or too old
+        assert!(read().into_cache(2_000, 1_030_000, max_age).is_err());
+        assert!(read().into_cache(1_000, 1_061_000, max_age).is_err());
+    }
+}